@@ -0,0 +1,30 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Kernel for the hosted simulator.
+//!
+//! This runs a Hubris image as an ordinary Linux program; see
+//! `kern::arch::sim` for how. It takes the path to the image's `final.elf` as
+//! its only argument, and is normally run by `cargo xtask sim`.
+
+fn main() {
+    // The simulated core clock runs at 1 GHz, so this gives the usual 1 ms
+    // tick.
+    const CYCLES_PER_MS: u32 = 1_000_000;
+
+    let Some(path) = std::env::args_os().nth(1) else {
+        eprintln!("usage: sim FINAL.ELF");
+        std::process::exit(2);
+    };
+    let image = match std::fs::read(&path) {
+        Ok(image) => image,
+        Err(e) => {
+            eprintln!("can't read {}: {e}", path.to_string_lossy());
+            std::process::exit(2);
+        }
+    };
+    kern::arch::load_image(&image);
+
+    unsafe { kern::startup::start_kernel(CYCLES_PER_MS) }
+}
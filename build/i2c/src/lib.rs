@@ -1740,6 +1740,8 @@ pub fn codegen(disposition: Disposition) -> Result<()> {
 
 pub struct I2cDeviceDescription {
     pub device: String,
+    pub name: Option<String>,
+    pub refdes: Option<String>,
     pub description: String,
     pub sensors: Vec<DeviceSensor>,
 }
@@ -1761,6 +1763,8 @@ pub fn device_descriptions() -> impl Iterator<Item = I2cDeviceDescription> {
     g.devices.into_iter().zip(sensors.device_sensors).map(
         |(device, sensors)| I2cDeviceDescription {
             device: device.device,
+            name: device.name,
+            refdes: device.refdes,
             description: device.description,
             sensors,
         },
@@ -18,6 +18,13 @@ pub struct KernelConfig {
 
     /// Interrupts hooked by the application, keyed by IRQ number.
     pub irqs: BTreeMap<u32, InterruptConfig>,
+
+    /// Names of kernel locks, in index order.
+    pub locks: Vec<String>,
+
+    /// Hardware watchdog timeout in milliseconds, if the kernel should arm the
+    /// watchdog at boot.
+    pub watchdog_ms: Option<u32>,
 }
 
 /// Configuration for a single hooked interrupt.
@@ -71,6 +78,10 @@ pub struct TaskConfig {
 
     /// Should this task be started automatically on boot?
     pub start_at_boot: bool,
+
+    /// Indices of kernel locks (in the app-level `locks`) that this task may
+    /// acquire.
+    pub locks: BTreeSet<usize>,
 }
 
 /// An address within an owned region of memory.
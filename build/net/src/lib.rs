@@ -28,19 +28,33 @@ pub struct NetConfig {
     /// during the `net` build, so it must be present iff the `vlan` feature
     /// is turned on.
     pub vlan: Option<VLanConfig>,
+
+    /// IPv4 configuration, or None. This is checked against enabled features
+    /// during the `net` build, so it must be present iff the `ipv4` feature
+    /// is turned on.
+    pub ipv4: Option<Ipv4Config>,
+
+    /// Tasks other than a socket's owner that may read its counters with
+    /// `get_socket_counters`.
+    #[serde(default)]
+    pub counter_readers: Vec<String>,
 }
 
 /// TODO: this type really wants to be an enum, but the toml crate's enum
-/// handling is really, really fragile, and currently it would be an enum with a
-/// single variant anyway.
+/// handling is really, really fragile.
 #[derive(Deserialize)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct SocketConfig {
+    /// Either `"udp"` or `"tcp"`.
     pub kind: String,
     pub owner: TaskNote,
     pub port: u16,
     pub tx: BufSize,
     pub rx: BufSize,
+    /// Whether the socket also talks IPv4; otherwise, it's bound to the
+    /// interface's IPv6 link-local address. Requires the `ipv4` feature.
+    #[serde(default)]
+    pub ipv4: bool,
 }
 
 #[derive(Copy, Clone, Debug, Deserialize)]
@@ -52,9 +66,26 @@ pub struct VLanConfig {
     pub count: usize,
 }
 
+/// How the `net` task should get its IPv4 address. Exactly one of `address`
+/// and `dhcp` must be given.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Ipv4Config {
+    /// Static address and prefix length, in CIDR notation (`"10.0.0.2/24"`)
+    pub address: Option<String>,
+    /// Default gateway to use with a static `address`
+    pub gateway: Option<String>,
+    /// Get an address (and gateway) from a DHCP server instead
+    #[serde(default)]
+    pub dhcp: bool,
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct BufSize {
+    /// Number of packets the buffer can hold. Required for UDP sockets;
+    /// meaningless for TCP sockets, which buffer a byte stream.
+    #[serde(default)]
     pub packets: usize,
     pub bytes: usize,
 }
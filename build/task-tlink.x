@@ -93,6 +93,8 @@ SECTIONS
     *(.ARM.exidx);
     *(.ARM.exidx.*);
     *(.ARM.extab.*);
+    /* The same, as emitted for the simulator's x86 target */
+    *(.eh_frame .eh_frame_hdr);
     *(.got .got.*);
   }
 }
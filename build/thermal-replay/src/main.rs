@@ -0,0 +1,165 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Replays a capture of `thermal` control loop telemetry through the
+//! firmware's own controllers, with new tuning, to see what they would have
+//! done.
+//!
+//! A capture is any concatenation of the records returned by
+//! `Thermal::get_telemetry`; records that appear more than once (e.g. from
+//! overlapping reads) are only replayed once.
+//!
+//! Note that this is open-loop: the recorded temperatures don't respond to
+//! the replayed fan speeds, so this is good for spotting tuning that
+//! oscillates or reacts sluggishly, but not for predicting temperatures.
+
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use thermal_control::{
+    telemetry::{state, Record},
+    Controller, ControllerConfig, PidConfig, ZoneController, ZoneInput,
+};
+
+#[derive(Debug, Parser)]
+#[clap(
+    max_term_width = 80,
+    about = "replay thermal loop telemetry through new PID gains"
+)]
+struct Args {
+    /// Capture of telemetry records
+    capture: PathBuf,
+    /// PID gains to replay with, as zero, P, I and D
+    #[clap(
+        long,
+        number_of_values = 4,
+        value_names = &["ZERO", "P", "I", "D"],
+        required = true
+    )]
+    pid: Vec<f32>,
+    /// Target margin to replay with, instead of the one recorded
+    #[clap(long)]
+    margin: Option<f32>,
+    /// Only replay this zone
+    #[clap(long)]
+    zone: Option<usize>,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let config = ControllerConfig::Pid(PidConfig {
+        zero: args.pid[0],
+        gain_p: args.pid[1],
+        gain_i: args.pid[2],
+        gain_d: args.pid[3],
+    });
+
+    let bytes = std::fs::read(&args.capture)
+        .with_context(|| format!("reading {}", args.capture.display()))?;
+    let mut records = vec![];
+    let mut rest = bytes.as_slice();
+    while !rest.is_empty() {
+        let Some((r, next)) = Record::parse(rest) else {
+            bail!(
+                "truncated record at offset {}",
+                bytes.len() - rest.len()
+            );
+        };
+        records.push(r);
+        rest = next;
+    }
+    records.sort_by_key(|r| r.header.seq.get());
+    records.dedup_by_key(|r| r.header.seq.get());
+
+    let Some(first) = records.first() else {
+        bail!("no records in capture");
+    };
+    let nzones = usize::from(first.header.nzones);
+    if records
+        .iter()
+        .any(|r| usize::from(r.header.nzones) != nzones)
+    {
+        bail!("capture mixes records with different numbers of zones");
+    }
+    if let Some(z) = args.zone {
+        if z >= nzones {
+            bail!("zone {z} out of range; capture has {nzones} zones");
+        }
+    }
+
+    let mut controllers = (0..nzones)
+        .map(|_| ZoneController::new(&config))
+        .collect::<Vec<_>>();
+    let mut diffs = vec![(0.0f32, 0usize); nzones];
+    let mut prev: Option<(u32, u8)> = None;
+
+    println!("seq,time,zone,error,recorded,replayed,p,i,d");
+    for r in &records {
+        let seq = r.header.seq.get();
+        let run_state = r.header.state;
+        let prev_state = match prev {
+            Some((s, st)) if s.wrapping_add(1) == seq => Some(st),
+            Some((s, _)) => {
+                eprintln!("warning: records {} to {} missing", s + 1, seq - 1);
+                None
+            }
+            None => None,
+        };
+        prev = Some((seq, run_state));
+
+        // The firmware resets its controllers on the way into `Running`
+        if run_state == state::RUNNING && prev_state != Some(state::RUNNING) {
+            for c in &mut controllers {
+                c.reset();
+            }
+        }
+
+        for (zone, (z, c)) in r.zones.iter().zip(&mut controllers).enumerate() {
+            if args.zone.map(|a| a != zone).unwrap_or(false) {
+                continue;
+            }
+            let error = z.error.get();
+            if error.is_nan() {
+                // The controllers didn't run on this iteration
+                continue;
+            }
+            let error = match args.margin {
+                Some(m) => error - r.header.target_margin.get() + m,
+                None => error,
+            };
+            let power = z.power.get();
+            let input = ZoneInput {
+                error,
+                power: (!power.is_nan()).then_some(power),
+            };
+            let pwm = c.run(&input) as u8;
+            let terms = c.pid_terms();
+            println!(
+                "{seq},{},{zone},{error},{},{pwm},{},{},{}",
+                r.header.time.get(),
+                z.pwm,
+                terms.p,
+                terms.i,
+                terms.d
+            );
+
+            let d = &mut diffs[zone];
+            d.0 += (f32::from(pwm) - f32::from(z.pwm)).abs();
+            d.1 += 1;
+        }
+    }
+
+    for (zone, (total, n)) in diffs.iter().enumerate() {
+        if *n > 0 {
+            eprintln!(
+                "zone {zone}: {n} iterations, mean |replayed - recorded| PWM \
+                 {:.1}%",
+                total / *n as f32
+            );
+        }
+    }
+
+    Ok(())
+}
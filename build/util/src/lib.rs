@@ -66,6 +66,8 @@ pub fn expose_m_profile() {
         println!("cargo:rustc-cfg=armv7m");
     } else if target.starts_with("thumbv8m") {
         println!("cargo:rustc-cfg=armv8m");
+    } else if target.starts_with("i686") {
+        // The hosted simulator; there's no M-profile flavor to expose.
     } else {
         println!("Don't know the target {}", target);
         std::process::exit(1);
@@ -218,6 +220,104 @@ impl TaskIds {
     }
 }
 
+/// Length of an [`HmacKey`], in bytes. This matches `net_auth::KEY_LEN`.
+pub const HMAC_KEY_LEN: usize = 32;
+
+/// An HMAC-SHA256 key in a task's config, with which network clients
+/// authenticate their requests. Exactly one of `hex` and `env` must be given.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct HmacKey {
+    /// The key itself, as hex. Anything in an app.toml is public, so this is
+    /// only for keys that needn't be secret, e.g. on dev images.
+    pub hex: Option<String>,
+    /// Name of an environment variable holding the key, as hex, at build
+    /// time.
+    pub env: Option<String>,
+}
+
+impl HmacKey {
+    /// Returns the key's bytes, reading them from the environment if need be.
+    pub fn resolve(&self) -> Result<[u8; HMAC_KEY_LEN]> {
+        let hex = match (&self.hex, &self.env) {
+            (Some(hex), None) => hex.clone(),
+            (None, Some(var)) => env_var(var)?,
+            _ => bail!("key needs exactly one of hex and env"),
+        };
+        let hex = hex.trim();
+        if hex.len() != HMAC_KEY_LEN * 2 {
+            bail!("key must be {} hex digits", HMAC_KEY_LEN * 2);
+        }
+        let mut key = [0; HMAC_KEY_LEN];
+        for (byte, digits) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            let digits = std::str::from_utf8(digits)?;
+            *byte = u8::from_str_radix(digits, 16)
+                .with_context(|| format!("bad hex digits {digits:?}"))?;
+        }
+        Ok(key)
+    }
+}
+
+/// An entry in a task's allow-list of the messages that network clients may
+/// have it send.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct AllowSend {
+    pub task: String,
+    /// Operation numbers that may be sent to `task`; if omitted, any may.
+    pub ops: Option<Vec<u16>>,
+}
+
+/// Writes the config for a task that authenticates network requests with
+/// `net-auth`: `KEY_COUNT` and `KEYS` for its `Authenticator`, and `ALLOWED`
+/// for `is_allowed`. `name` is used in error messages.
+pub fn write_net_auth_config(
+    out: &mut impl Write,
+    name: &str,
+    keys: &[HmacKey],
+    allow: &[AllowSend],
+) -> Result<()> {
+    if keys.is_empty() {
+        bail!("{name} needs at least one key");
+    }
+    // Requests name their key with a `u8`.
+    if keys.len() > usize::from(u8::MAX) + 1 {
+        bail!("{name} can't have more than 256 keys");
+    }
+
+    writeln!(out, "pub(crate) const KEY_COUNT: usize = {};", keys.len())?;
+    writeln!(
+        out,
+        "pub(crate) static KEYS: [net_auth::Key; KEY_COUNT] = ["
+    )?;
+    for (i, key) in keys.iter().enumerate() {
+        let bytes = key
+            .resolve()
+            .with_context(|| format!("parsing {name} key {i}"))?;
+        writeln!(out, "    {bytes:?},")?;
+    }
+    writeln!(out, "];")?;
+
+    let task_ids = task_ids();
+    writeln!(
+        out,
+        "pub(crate) static ALLOWED: [net_auth::Allow; {}] = [",
+        allow.len()
+    )?;
+    for allow in allow {
+        let task = task_ids
+            .get(&allow.task)
+            .ok_or_else(|| anyhow!("unknown task `{}`", allow.task))?;
+        match &allow.ops {
+            Some(ops) => writeln!(out, "    ({task}, Some(&{ops:?})),")?,
+            None => writeln!(out, "    ({task}, None),")?,
+        }
+    }
+    writeln!(out, "];")?;
+
+    Ok(())
+}
+
 /// Parse the contents of an environment variable as toml.
 ///
 /// Returns:
@@ -299,3 +399,30 @@ fn write_task_notifications<W: Write>(out: &mut W, t: &[String]) -> Result<()> {
     }
     Ok(())
 }
+
+/// Generates `locks.rs` in `OUT_DIR`, containing a `locks` module with the
+/// number of each kernel lock the current task may use, for passing to
+/// `userlib::hl::lock` and friends.
+pub fn build_locks() -> Result<()> {
+    let out_dir = out_dir();
+    let dest_path = out_dir.join("locks.rs");
+    let mut out = std::fs::File::create(dest_path)?;
+
+    let task_config = task_full_config_toml()?;
+    let all_tasks = toml_from_env::<
+        IndexMap<String, toml_task::Task<ordered_toml::Value>>,
+    >("HUBRIS_ALL_TASK_CONFIGS")?
+    .ok_or_else(|| anyhow!("HUBRIS_ALL_TASK_CONFIGS is not defined"))?;
+    let all_locks = toml_task::all_locks(all_tasks.values());
+
+    writeln!(&mut out, "#[allow(dead_code)]")?;
+    writeln!(&mut out, "pub mod locks {{")?;
+    for name in &task_config.locks {
+        let index = all_locks.iter().position(|l| l == name).unwrap();
+        let name = name.to_uppercase().replace('-', "_");
+        writeln!(&mut out, "pub const {name}: u32 = {index};")?;
+    }
+    writeln!(&mut out, "}}")?;
+
+    Ok(())
+}
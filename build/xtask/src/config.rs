@@ -13,6 +13,9 @@ use serde::{Deserialize, Serialize};
 
 use crate::auxflash::{build_auxflash, AuxFlash, AuxFlashData};
 
+/// Target triple for images that run in the hosted simulator.
+const SIM_TARGET: &str = "i686-unknown-linux-gnu";
+
 /// A `RawConfig` represents an `app.toml` file that has been deserialized,
 /// but may not be ready for use.  In particular, we use the `chip` field
 /// to load a second file containing peripheral register addresses.
@@ -36,6 +39,8 @@ struct RawConfig {
     external_images: Vec<String>,
     #[serde(default)]
     signing: Option<RoTMfgSettings>,
+    #[serde(default)]
+    image_signing: Option<ImageSigning>,
     stacksize: Option<u32>,
     kernel: Kernel,
     tasks: IndexMap<String, Task>,
@@ -58,6 +63,7 @@ pub struct Config {
     pub image_names: Vec<String>,
     pub external_images: Vec<String>,
     pub signing: Option<RoTMfgSettings>,
+    pub image_signing: Option<ImageSigning>,
     pub stacksize: Option<u32>,
     pub kernel: Kernel,
     pub outputs: IndexMap<String, Vec<Output>>,
@@ -176,6 +182,7 @@ impl Config {
             version: toml.version,
             fwid: toml.fwid,
             signing: toml.signing,
+            image_signing: toml.image_signing,
             stacksize: toml.stacksize,
             kernel: toml.kernel,
             outputs,
@@ -451,10 +458,19 @@ impl Config {
             "thumbv7em-none-eabihf" | "thumbv6m-none-eabi" => {
                 MpuAlignment::PowerOfTwo
             }
+            // The simulator has no MPU, but keep regions aligned as on
+            // ARMv8-M so that region checks behave the same.
+            SIM_TARGET => MpuAlignment::Chunk(32),
             t => panic!("Unknown mpu requirements for target '{}'", t),
         }
     }
 
+    /// Checks whether this image runs in the hosted simulator rather than on
+    /// hardware.
+    pub fn is_sim(&self) -> bool {
+        self.target == SIM_TARGET
+    }
+
     /// Checks whether the given chip's MPU requires power-of-two sized regions
     pub fn mpu_power_of_two_required(&self) -> bool {
         self.mpu_alignment() == MpuAlignment::PowerOfTwo
@@ -616,6 +632,24 @@ pub struct RoTMfgSettings {
     pub certs: lpc55_sign::signed_image::CertConfig,
 }
 
+/// Key for signing SP images, which `stm32h7-update-server` checks before it
+/// swaps banks to a new image
+///
+/// Production keys are kept out of the repo, and named by `private-key-env`;
+/// development images set `private-key` to the checked-in development key,
+/// which takes precedence.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ImageSigning {
+    /// File holding the hex-encoded Ed25519 private key (seed), relative to
+    /// the app's TOML file
+    pub private_key: Option<PathBuf>,
+    /// Environment variable holding the path of a file with the hex-encoded
+    /// Ed25519 private key (seed). If it isn't set, the image is left
+    /// unsigned, and `stm32h7-update-server` will refuse to swap to it.
+    pub private_key_env: Option<String>,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct Kernel {
@@ -624,6 +658,9 @@ pub struct Kernel {
     pub stacksize: Option<u32>,
     #[serde(default)]
     pub features: Vec<String>,
+    /// Hardware watchdog timeout, in milliseconds. If present, the kernel arms
+    /// the watchdog at boot and the supervisor must keep it fed.
+    pub watchdog_ms: Option<u32>,
 }
 
 fn default_name() -> String {
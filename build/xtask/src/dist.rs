@@ -14,6 +14,7 @@ use std::process::{Command, Stdio};
 
 use anyhow::{anyhow, bail, Context, Result};
 use atty::Stream;
+use colored::Colorize;
 use indexmap::IndexMap;
 use lpc55_rom_data::FLASH_PAGE_SIZE as LPC55_FLASH_PAGE_SIZE;
 use multimap::MultiMap;
@@ -23,7 +24,7 @@ use zerocopy::AsBytes;
 
 use crate::{
     caboose_pos,
-    config::{BuildConfig, CabooseConfig, Config},
+    config::{BuildConfig, CabooseConfig, Config, ImageSigning},
     elf,
     sizes::load_task_size,
     task_slot,
@@ -34,6 +35,10 @@ use crate::{
 /// padded that a bit.
 pub const DEFAULT_KERNEL_STACK: u32 = 1024;
 
+/// Shortest kernel watchdog timeout we'll accept, in milliseconds. Jefe pets
+/// the watchdog every 100 ms, and we want to tolerate it being a bit late.
+const MIN_WATCHDOG_MS: u32 = 500;
+
 /// Humility will (gracefully) refuse to load an archive version that is later
 /// than its defined version, so this version number should be be used to
 /// enforce flag days across Hubris and Humility.  To increase this version,
@@ -614,6 +619,21 @@ pub fn package(
             }
         }
 
+        // Post-build modifications: sign the caboose'd SP image if requested
+        if let Some(signing) = &cfg.toml.image_signing {
+            match image_signing_seed(&cfg.app_src_dir, signing)? {
+                Some(seed) => sign_sp_image(&cfg.toml, &seed, &archive_name)?,
+                None => {
+                    eprintln!(
+                        "{}: ${} isn't set, so the image is unsigned; \
+                         stm32h7-update-server will refuse to swap to it",
+                        "warning".bold().yellow(),
+                        signing.private_key_env.as_deref().unwrap_or_default(),
+                    );
+                }
+            }
+        }
+
         // Post-build modifications: sign the image if requested
         if let Some(signing) = &cfg.toml.signing {
             let mut archive = hubtools::RawHubrisArchive::load(&archive_name)
@@ -675,6 +695,180 @@ pub fn package(
     Ok(allocated)
 }
 
+/// Signs (or re-signs) the SP image in a build archive, for use after
+/// anything that rewrites its caboose; see `sign_sp_image`.
+pub fn resign_sp_image(
+    cfg: &Path,
+    image_name: Option<String>,
+    archive: Option<&Path>,
+) -> Result<()> {
+    let config = PackageConfig::new(cfg, false, false)
+        .context("could not create build configuration")?;
+    let Some(signing) = &config.toml.image_signing else {
+        bail!("{} has no `image-signing` key", cfg.display());
+    };
+    let Some(seed) = image_signing_seed(&config.app_src_dir, signing)? else {
+        bail!(
+            "${} isn't set; it must name the image-signing key",
+            signing.private_key_env.as_deref().unwrap_or_default()
+        );
+    };
+
+    let archive = match archive {
+        Some(archive) => archive.to_owned(),
+        None => {
+            let image_name = image_name.unwrap_or_else(|| "default".into());
+            if !config.toml.check_image_name(&image_name) {
+                bail!("image name {image_name} not declared in TOML");
+            }
+            config.img_file(config.toml.archive_name(&image_name), &image_name)
+        }
+    };
+    sign_sp_image(&config.toml, &seed, &archive)
+}
+
+/// Reads the private key (seed) for signing SP images, from `private-key` if
+/// it's given, or else from the file named by `private-key-env`, returning
+/// `None` if that variable isn't set.
+fn image_signing_seed(
+    app_src_dir: &Path,
+    signing: &ImageSigning,
+) -> Result<Option<[u8; 32]>> {
+    let path = match (&signing.private_key, &signing.private_key_env) {
+        (Some(path), _) => app_src_dir.join(path),
+        (None, Some(var)) => match std::env::var_os(var) {
+            Some(path) => PathBuf::from(path),
+            None => return Ok(None),
+        },
+        (None, None) => bail!(
+            "`image-signing` needs one of `private-key` or `private-key-env`"
+        ),
+    };
+    let seed = std::fs::read_to_string(&path).with_context(|| {
+        format!("could not read private key {}", path.display())
+    })?;
+    let seed = hex::decode(seed.trim())
+        .ok()
+        .and_then(|seed| seed.try_into().ok())
+        .ok_or_else(|| {
+            anyhow!("private key {} is not 32 hex bytes", path.display())
+        })?;
+    Ok(Some(seed))
+}
+
+/// Adds a `SIGN` chunk to the caboose of an SP image, replacing any that's
+/// already there, holding an Ed25519 signature over the SHA3-256 digest of
+/// the whole image with that chunk zeroed, which `stm32h7-update-server`
+/// checks before swapping banks to the image.
+///
+/// Because the signature covers the caboose, anything that rewrites the
+/// caboose afterwards must sign the image again, with `cargo xtask sign-sp`;
+/// until then, `stm32h7-update-server` refuses the image with
+/// `MissingImageSignature` (if the `SIGN` chunk was dropped) or
+/// `InvalidImageSignature`.
+fn sign_sp_image(
+    toml: &Config,
+    seed: &[u8; 32],
+    archive_name: &Path,
+) -> Result<()> {
+    let keypair = salty::Keypair::from(seed);
+    check_update_server_key(toml, keypair.public.as_bytes())?;
+
+    let mut archive = hubtools::RawHubrisArchive::load(archive_name)
+        .context("loading archive with hubtools")?;
+    let caboose = archive.read_caboose().context("reading caboose")?;
+    let chunks = tlvc_chunks_without(caboose.as_slice(), *b"SIGN")?;
+
+    let sign_chunk = |signature: &[u8]| {
+        tlvc_text::pack(&[tlvc_text::Piece::Chunk(
+            tlvc_text::Tag::new(*b"SIGN"),
+            vec![tlvc_text::Piece::Bytes(signature.to_vec())],
+        )])
+    };
+
+    // Lay the image out with a placeholder signature, then hash it with the
+    // whole chunk zeroed.
+    let placeholder = sign_chunk(&[0; 64]);
+    let mut data = chunks.clone();
+    data.extend_from_slice(&placeholder);
+    archive.write_caboose(&data).context("writing caboose")?;
+
+    // The binary runs from the start of flash through the end of the
+    // caboose, which is exactly the span of the image header's
+    // `total_image_len`.
+    let mut image = archive.image.to_binary()?;
+    let start = memchr::memmem::find(&image, &data)
+        .ok_or_else(|| anyhow!("could not find caboose in image"))?
+        + chunks.len();
+    image[start..start + placeholder.len()].fill(0);
+    let signature = keypair.sign(&Sha3_256::digest(&image));
+
+    data.truncate(chunks.len());
+    data.extend_from_slice(&sign_chunk(&signature.to_bytes()));
+    archive.write_caboose(&data).context("writing caboose")?;
+    archive.overwrite().context("overwriting archive")?;
+
+    Ok(())
+}
+
+/// Checks that `public_key` is one that the app's `stm32h7-update-server`
+/// will accept, so that a mismatch fails the build rather than an update.
+fn check_update_server_key(toml: &Config, public_key: &[u8; 32]) -> Result<()> {
+    let public_key = hex::encode(public_key);
+    let Some((name, task)) = toml
+        .tasks
+        .iter()
+        .find(|(_, task)| task.name == "stm32h7-update-server")
+    else {
+        bail!("`image-signing` requires an stm32h7-update-server task");
+    };
+    let accepted = match task
+        .config
+        .as_ref()
+        .and_then(|c| c.get("image-signing-keys"))
+    {
+        Some(ordered_toml::Value::Array(keys)) => keys.iter().any(|k| {
+            matches!(k, ordered_toml::Value::String(k)
+                if k.eq_ignore_ascii_case(&public_key))
+        }),
+        _ => false,
+    };
+    if !accepted {
+        bail!(
+            "task {name}: image-signing-keys doesn't include the \
+             image-signing key {public_key}"
+        );
+    }
+    Ok(())
+}
+
+/// Returns the TLV-C chunks at the start of `data`, which are followed by
+/// erased (0xFF) flash, leaving out any with the given `tag`.
+fn tlvc_chunks_without(data: &[u8], tag: [u8; 4]) -> Result<Vec<u8>> {
+    const HEADER_LEN: usize = std::mem::size_of::<tlvc::ChunkHeader>();
+
+    let mut out = vec![];
+    let mut pos = 0;
+    while let Some(header) = data.get(pos..pos + HEADER_LEN) {
+        // A header is the tag, the body length and the header checksum.
+        if header[..4] == [0xFF; 4] {
+            break;
+        }
+        let len = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        // The body is padded to a whole number of words, and followed by its
+        // checksum.
+        let end = pos + HEADER_LEN + ((len as usize + 3) & !3) + 4;
+        if end > data.len() {
+            bail!("caboose chunk runs past the end of the caboose");
+        }
+        if header[..4] != tag {
+            out.extend_from_slice(&data[pos..end]);
+        }
+        pos = end;
+    }
+    Ok(out)
+}
+
 // generate file with hash of expected flash contents
 fn write_fwid(
     cfg: &PackageConfig,
@@ -1210,6 +1404,18 @@ fn build_kernel(
         Some(&cfg.sysroot),
     );
     build(cfg, "kernel", build_config, false)?;
+
+    if cfg.toml.is_sim() {
+        // The simulated kernel is a host program, not part of the image: it
+        // has no image header to update, and its contents don't go in flash.
+        std::fs::copy(
+            &cfg.dist_file("kernel"),
+            cfg.img_file("kernel", image_name),
+        )?;
+        let kentry = get_elf_entry_point(&cfg.img_file("kernel", image_name))?;
+        return Ok((kentry, BTreeMap::default()));
+    }
+
     if update_image_header(
         cfg,
         &cfg.dist_file("kernel"),
@@ -1373,29 +1579,11 @@ fn check_dump_config(toml: &Config) -> Result<()> {
     Ok(())
 }
 
-/// Prints warning messages about priority inversions
+/// Checks task priorities, and checks the task-slot graph for priority
+/// inversions.
 fn check_task_priorities(toml: &Config) -> Result<()> {
     let idle_priority = toml.tasks["idle"].priority;
     for (i, (name, task)) in toml.tasks.iter().enumerate() {
-        for callee in task.task_slots.values() {
-            let p = toml
-                .tasks
-                .get(callee)
-                .ok_or_else(|| anyhow!("Invalid task-slot: {}", callee))?
-                .priority;
-            if p >= task.priority && name != callee {
-                bail!(
-                    concat!(
-                        "Priority inversion: ",
-                        "task {} (priority {}) calls into {} (priority {})",
-                    ),
-                    name,
-                    task.priority,
-                    callee,
-                    p
-                );
-            }
-        }
         if task.priority >= idle_priority && name != "idle" {
             bail!("task {} has priority that's >= idle priority", name);
         } else if i == 0 && task.priority != 0 {
@@ -1405,7 +1593,7 @@ fn check_task_priorities(toml: &Config) -> Result<()> {
         }
     }
 
-    Ok(())
+    crate::graph::check_ipc_graph(toml)
 }
 
 fn generate_task_linker_script(
@@ -1630,17 +1818,32 @@ fn build(
         .iter()
         .map(|r| format!(" --remap-path-prefix={}={}", r.0.display(), r.1))
         .collect();
+    // In the simulator, the kernel is an ordinary host program, linked the
+    // usual way. Tasks are still freestanding images at fixed addresses, but
+    // since their target is a hosted one, we have to ask for that explicitly.
+    let hosted = cfg.toml.is_sim() && name == "kernel";
+    let target_flags = if hosted {
+        ""
+    } else if cfg.toml.is_sim() {
+        "-C link-arg=-z -C link-arg=common-page-size=0x20 \
+         -C link-arg=-z -C link-arg=max-page-size=0x20 \
+         -C panic=abort -C relocation-model=static -C relro-level=off \
+         -C force-unwind-tables=no \
+         -C linker=rust-lld -C linker-flavor=ld.lld"
+    } else {
+        "-C link-arg=-z -C link-arg=common-page-size=0x20 \
+         -C link-arg=-z -C link-arg=max-page-size=0x20"
+    };
     cmd.env(
         "RUSTFLAGS",
         &format!(
-            "-C link-arg=-z -C link-arg=common-page-size=0x20 \
-             -C link-arg=-z -C link-arg=max-page-size=0x20 \
+            "{} \
              -C llvm-args=--enable-machine-outliner=never \
              -C overflow-checks=y \
              -C metadata={} \
              {}
              ",
-            cfg.link_script_hash, remap_path_prefix,
+            target_flags, cfg.link_script_hash, remap_path_prefix,
         ),
     );
     cmd.arg("--");
@@ -1650,10 +1853,10 @@ fn build(
     // which causes the later one to go unused.  Let's detect this explicitly!
     cmd.arg("-Dunused_attributes");
 
-    cmd.arg("-C")
-        .arg("link-arg=-Tlink.x")
-        .arg("-L")
-        .arg(format!("{}", cargo_out.display()));
+    if !hosted {
+        cmd.arg("-C").arg("link-arg=-Tlink.x");
+    }
+    cmd.arg("-L").arg(format!("{}", cargo_out.display()));
     if reloc {
         cmd.arg("-C").arg("link-arg=-r");
     }
@@ -1827,6 +2030,7 @@ fn link(
         "thumbv6m-none-eabi"
         | "thumbv7em-none-eabihf"
         | "thumbv8m.main-none-eabihf" => "armelf",
+        "i686-unknown-linux-gnu" => "elf_i386",
         _ => bail!("No target emulation for '{}'", cfg.toml.target),
     };
     cmd.arg(src_file);
@@ -2352,6 +2556,22 @@ pub fn make_kconfig(
 
     let mut used_shared_regions = BTreeSet::new();
 
+    // Kernel locks are implied by the tasks that use them. The kernel keeps a
+    // per-task mask of permitted locks in a `u32`.
+    let locks = toml_task::all_locks(toml.tasks.values());
+    if locks.len() > 32 {
+        bail!("too many kernel locks ({}); the limit is 32", locks.len());
+    }
+
+    if let Some(ms) = toml.kernel.watchdog_ms {
+        if ms < MIN_WATCHDOG_MS {
+            bail!(
+                "kernel watchdog-ms ({ms}) is too short; \
+                 the minimum is {MIN_WATCHDOG_MS}"
+            );
+        }
+    }
+
     for (i, (name, task)) in toml.tasks.iter().enumerate() {
         let stacksize = task.stacksize.or(toml.stacksize).unwrap();
 
@@ -2439,6 +2659,11 @@ pub fn make_kconfig(
             },
             priority: task.priority,
             start_at_boot: task.start,
+            locks: task
+                .locks
+                .iter()
+                .map(|l| locks.iter().position(|n| n == l).unwrap())
+                .collect(),
         });
 
         // Interrupts.
@@ -2508,6 +2733,8 @@ pub fn make_kconfig(
         irqs,
         tasks,
         shared_regions: flat_shared,
+        locks: locks.into_iter().map(str::to_owned).collect(),
+        watchdog_ms: toml.kernel.watchdog_ms,
     })
 }
 
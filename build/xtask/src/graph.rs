@@ -8,12 +8,12 @@ use std::hash::Hash;
 use std::io::Write;
 use std::path::Path;
 
-use anyhow::{anyhow, Result};
+use anyhow::{bail, Result};
 
 use crate::config::Config;
 
 /// Generate a directed graph of task priorities and task_slot
-/// dependencies.
+/// dependencies, along with the calls hiffy may make.
 pub fn task_graph(app_toml: &Path, path: &Path) -> Result<()> {
     // Generate dot syntax for a graph of process priorities.
     // Collect each task in a priority group
@@ -24,46 +24,27 @@ pub fn task_graph(app_toml: &Path, path: &Path) -> Result<()> {
     let mut ranks = HashSet::new();
     let toml = Config::from_file(app_toml)?;
 
-    #[derive(Debug)]
-    struct Edge<'a> {
-        from: &'a String,
-        to: &'a String,
-        inverted: bool,
-    }
-
     #[derive(Debug, Eq, PartialEq, PartialOrd, Ord, Hash)]
     struct Rank {
         from: u8,
         to: u8,
     }
 
-    for (_i, (name, task)) in toml.tasks.iter().enumerate() {
-        if !priorities.contains_key(&task.priority) {
-            priorities.insert(task.priority, Vec::new());
-        }
-        if let Some(v) = priorities.get_mut(&task.priority) {
-            v.push(name.to_string());
-        }
-        for callee in task.task_slots.values() {
-            let p = toml
-                .tasks
-                .get(callee)
-                .ok_or_else(|| anyhow!("Invalid task-slot: {}", callee))?
-                .priority;
-            let inverted = p >= task.priority && name != callee;
-            edges.push(Edge {
-                from: &name,
-                to: &callee,
-                inverted,
-            });
-            let rank = Rank {
-                from: task.priority,
-                to: p,
-            };
-            if !ranks.contains::<Rank>(&rank) {
-                ranks.insert(rank);
-            }
+    for (name, task) in &toml.tasks {
+        priorities
+            .entry(task.priority)
+            .or_insert_with(Vec::new)
+            .push(name.to_string());
+    }
+    for send in sends(&toml)? {
+        let from = toml.tasks[send.from].priority;
+        let to = toml.tasks[send.to].priority;
+        // Only task slots say anything about how the tasks should be ranked;
+        // hiffy can send anywhere.
+        if let Via::TaskSlot(_) = send.via {
+            ranks.insert(Rank { from, to });
         }
+        edges.push(send);
     }
 
     writeln!(dot, "digraph tasks {{")?;
@@ -86,10 +67,12 @@ pub fn task_graph(app_toml: &Path, path: &Path) -> Result<()> {
         }
     }
     for edge in edges {
-        let attr = if edge.inverted {
-            r#" [color=red, style=dashed, penwidth=3, constraint=false, label="BAD"]"#
-        } else {
-            " [color=green]"
+        let attr = match edge.via {
+            Via::Hiffy => r#" [color=gray, style=dotted, constraint=false]"#,
+            Via::TaskSlot(_) if edge.is_inversion(&toml) => {
+                r#" [color=red, style=dashed, penwidth=3, constraint=false, label="BAD"]"#
+            }
+            Via::TaskSlot(_) => " [color=green]",
         };
         writeln!(dot, "  {} -> {}{};", edge.from, edge.to, attr)?;
     }
@@ -120,3 +103,113 @@ pub fn task_graph(app_toml: &Path, path: &Path) -> Result<()> {
 
     Ok(())
 }
+
+/// A send from one task to another.
+///
+/// Idol clients (like any other senders) find the `TaskId` of a server
+/// through a task slot, so the task slots in the app.toml describe almost
+/// every possible send. The exception is hiffy, which makes idol calls to
+/// whatever task it's asked to by way of HIF, using task indices rather than
+/// task slots.
+#[derive(Debug)]
+struct Send<'a> {
+    from: &'a str,
+    to: &'a str,
+    via: Via<'a>,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Via<'a> {
+    /// The named task slot.
+    TaskSlot(&'a str),
+    /// A HIF call from hiffy.
+    Hiffy,
+}
+
+/// Name of the HIF interpreter task, which may call into any task.
+const HIFFY: &str = "hiffy";
+
+impl Send<'_> {
+    /// Checks whether this is a send to an equal- or lower-priority task,
+    /// ignoring tasks sending to themselves.
+    fn is_inversion(&self, toml: &Config) -> bool {
+        self.from != self.to
+            && toml.tasks[self.to].priority >= toml.tasks[self.from].priority
+    }
+}
+
+fn sends(toml: &Config) -> Result<Vec<Send<'_>>> {
+    let mut sends = vec![];
+    for (name, task) in &toml.tasks {
+        for (slot, callee) in &task.task_slots {
+            if !toml.tasks.contains_key(callee) {
+                bail!("task {name}: invalid task-slot {slot} = {callee:?}");
+            }
+            sends.push(Send {
+                from: name,
+                to: callee,
+                via: Via::TaskSlot(slot),
+            });
+        }
+    }
+
+    // We don't know what hiffy will be asked to call, and whether it can make
+    // idol calls at all depends on its features, so assume the worst.
+    if let Some((hiffy, _)) = toml.tasks.get_key_value(HIFFY) {
+        for name in toml.tasks.keys().filter(|&name| name != HIFFY) {
+            sends.push(Send {
+                from: hiffy,
+                to: name,
+                via: Via::Hiffy,
+            });
+        }
+    }
+    Ok(sends)
+}
+
+/// Checks the task-slot graph for priority inversions, returning a report of
+/// every one found.
+///
+/// Hubris avoids IPC deadlock by requiring that tasks only send to tasks of
+/// strictly higher priority (numerically lower); a send to an equal- or
+/// lower-priority task is a priority inversion. Since priorities strictly
+/// increase along every other send, they can't form a cycle.
+///
+/// This only sees the sends that task slots describe. Sends made any other
+/// way -- by hiffy, which calls whatever it's asked to, or to a `TaskId`
+/// received in a message -- aren't checked.
+pub fn check_ipc_graph(toml: &Config) -> Result<()> {
+    let sends = sends(toml)?;
+    let priority = |name: &str| toml.tasks[name].priority;
+
+    let mut inversions = vec![];
+    for s in &sends {
+        let Via::TaskSlot(slot) = s.via else {
+            continue;
+        };
+        if s.is_inversion(toml) {
+            inversions.push(format!(
+                "{} (priority {}) sends to {} (priority {}) via task-slot {}",
+                s.from,
+                priority(s.from),
+                s.to,
+                priority(s.to),
+                slot,
+            ));
+        }
+    }
+
+    if inversions.is_empty() {
+        return Ok(());
+    }
+
+    let mut report = String::from("priority inversions in task slots:\n");
+    for line in &inversions {
+        report.push_str(&format!("    {line}\n"));
+    }
+    report.push_str(
+        "\ntasks may only send to tasks of higher priority (lower number); \
+         use `cargo xtask graph` to visualize the task-slot graph",
+    );
+    bail!(report)
+}
@@ -20,6 +20,48 @@ pub fn run(
     if interactive {
         ctrlc::set_handler(|| {}).expect("Error setting Ctrl-C handler");
     }
+    let mut humility = command(args, precmd, cmd, image_name)?;
+
+    let status = humility
+        .status()
+        .with_context(|| format!("failed to run humility ({:?})", humility))?;
+
+    if !status.success() {
+        anyhow::bail!("humility failed");
+    }
+
+    Ok(())
+}
+
+/// Runs `humility` non-interactively, returning its standard output.
+pub fn output(
+    args: &HumilityArgs,
+    precmd: &[&str],
+    cmd: Option<&str>,
+    image_name: &String,
+) -> anyhow::Result<String> {
+    let mut humility = command(args, precmd, cmd, image_name)?;
+
+    let output = humility
+        .output()
+        .with_context(|| format!("failed to run humility ({:?})", humility))?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "humility failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    String::from_utf8(output.stdout).context("humility output is not UTF-8")
+}
+
+fn command(
+    args: &HumilityArgs,
+    precmd: &[&str],
+    cmd: Option<&str>,
+    image_name: &String,
+) -> anyhow::Result<Command> {
     let toml = Config::from_file(&args.cfg)?;
 
     let archive = Path::new("target")
@@ -47,13 +89,5 @@ pub fn run(
         humility.arg(opt);
     }
 
-    let status = humility
-        .status()
-        .with_context(|| format!("failed to run humility ({:?})", humility))?;
-
-    if !status.success() {
-        anyhow::bail!("humility failed");
-    }
-
-    Ok(())
+    Ok(humility)
 }
@@ -20,7 +20,9 @@ mod graph;
 mod humility;
 mod lsp;
 mod print;
+mod sim;
 mod sizes;
+mod stacks;
 mod task_slot;
 
 #[derive(Debug, Parser)]
@@ -127,6 +129,43 @@ enum Xtask {
         args: HumilityArgs,
     },
 
+    /// Runs `xtask dist` for a simulator image and then runs it on this
+    /// machine, failing if the simulation exits with an error
+    Sim {
+        /// Request verbosity from tools we shell out to.
+        #[clap(short)]
+        verbose: bool,
+        /// Path to the image configuration file, in TOML.
+        cfg: PathBuf,
+        /// Image name to run
+        #[clap(long)]
+        image_name: Option<String>,
+        /// Allow operation in a dirty checkout, i.e. don't clean before
+        /// rebuilding even if it looks like we need to.
+        #[clap(long)]
+        dirty: bool,
+    },
+
+    /// Signs the SP image in a build archive again, for use after anything
+    /// that rewrites its caboose (which the signature covers)
+    SignSp {
+        /// Path to the image configuration file, in TOML.
+        cfg: PathBuf,
+        /// Image name to sign
+        #[clap(long)]
+        image_name: Option<String>,
+        /// Archive to sign, if not the one `xtask dist` built
+        #[clap(long)]
+        archive: Option<PathBuf>,
+    },
+
+    /// Reports the stack high-water mark of each task on an attached target,
+    /// compared against its configured `stacksize`
+    Stacks {
+        #[clap(flatten)]
+        args: HumilityArgs,
+    },
+
     /// Runs `cargo clippy` on a specified task
     Clippy {
         /// Request verbosity from tools we shell out to.
@@ -154,6 +193,7 @@ enum Xtask {
     ///
     /// Priority inversions are denoted by thick red arrows.
     /// Normal task_slot dependencies are thin green arrows.
+    /// Calls that hiffy may make on a debugger's behalf are dotted gray arrows.
     /// Example:
     ///
     ///   cargo xtask graph -o app.dot $APP_TOML;
@@ -342,6 +382,33 @@ fn run(xtask: Xtask) -> Result<()> {
             }
             humility::run(&args, &[], Some("test"), false, image_name)?;
         }
+        Xtask::Sim {
+            verbose,
+            cfg,
+            image_name,
+            dirty,
+        } => {
+            sim::run(verbose, &cfg, image_name.as_ref(), dirty)?;
+        }
+        Xtask::SignSp {
+            cfg,
+            image_name,
+            archive,
+        } => {
+            dist::resign_sp_image(&cfg, image_name, archive.as_deref())?;
+        }
+        Xtask::Stacks { args } => {
+            let toml = Config::from_file(&args.cfg)?;
+            let image_name = if let Some(ref name) = args.image_name {
+                if !toml.check_image_name(name) {
+                    bail!("Image name {} not declared in TOML", name);
+                }
+                name
+            } else {
+                &toml.image_names[0]
+            };
+            stacks::run(&args, image_name)?;
+        }
         Xtask::Clippy {
             verbose,
             cfg,
@@ -0,0 +1,59 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+
+use crate::{dist, Config};
+
+/// Builds an image for the hosted simulator and runs it, returning once the
+/// simulation exits.
+///
+/// The simulator's kernel is a host program; we hand it the image's
+/// `final.elf` to load. A simulation ends when a task asks it to (as the test
+/// driver does once it's run every test), so a nonzero exit status becomes an
+/// error here.
+pub fn run(
+    verbose: bool,
+    cfg: &Path,
+    image_name: Option<&String>,
+    dirty: bool,
+) -> Result<()> {
+    let toml = Config::from_file(cfg)?;
+    if !toml.is_sim() {
+        bail!(
+            "{} is not a simulator image (target is {})",
+            toml.name,
+            toml.target
+        );
+    }
+    let image_name = if let Some(name) = image_name {
+        if !toml.check_image_name(name) {
+            bail!("Image name {} not declared in TOML", name);
+        }
+        name
+    } else {
+        &toml.image_names[0]
+    };
+
+    dist::package(verbose, false, cfg, None, dirty)?;
+
+    let img_dir = Path::new("target")
+        .join(&toml.name)
+        .join("dist")
+        .join(image_name);
+    let mut kernel = Command::new(img_dir.join("kernel"));
+    kernel.arg(img_dir.join("final.elf"));
+
+    let status = kernel
+        .status()
+        .with_context(|| format!("failed to run simulator ({:?})", kernel))?;
+    if !status.success() {
+        bail!("simulation failed: {status}");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,91 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use anyhow::{anyhow, Context, Result};
+use colored::*;
+
+use crate::{humility, Config, HumilityArgs};
+
+/// Tasks using at least this percentage of their stack are flagged as being
+/// in danger of overflowing.
+const DANGER_PERCENT: u64 = 90;
+
+/// Tasks using less than this percentage of their stack are flagged as
+/// candidates for shrinking.
+const SLACK_PERCENT: u64 = 50;
+
+/// Asks the supervisor on an attached target for each task's stack high-water
+/// mark (via `humility hiffy`), and prints a table comparing it against the
+/// `stacksize` configured in the app.toml.
+pub fn run(args: &HumilityArgs, image_name: &String) -> Result<()> {
+    let toml = Config::from_file(&args.cfg)?;
+
+    println!(
+        "{:<24} {:>10} {:>10} {:>10} {:>6}",
+        "TASK", "STACKSIZE", "HIGHWATER", "MARGIN", "USED"
+    );
+
+    for (index, (name, task)) in toml.tasks.iter().enumerate() {
+        let stacksize = task
+            .stacksize
+            .or(toml.stacksize)
+            .ok_or_else(|| anyhow!("{name}: no stacksize configured"))?;
+
+        let task_index = format!("task_index={index}");
+        let out = humility::output(
+            args,
+            &["hiffy", "-c", "Jefe.get_stack_usage", "-a", &task_index],
+            None,
+            image_name,
+        )
+        .with_context(|| format!("reading stack usage of {name}"))?;
+        let high_water = parse_field(&out, "high_water")
+            .with_context(|| format!("parsing stack usage of {name}"))?;
+
+        let used = u64::from(high_water) * 100 / u64::from(stacksize.max(1));
+        let line = format!(
+            "{:<24} {:>10} {:>10} {:>10} {:>5}%",
+            name,
+            stacksize,
+            high_water,
+            i64::from(stacksize) - i64::from(high_water),
+            used,
+        );
+        if used >= DANGER_PERCENT {
+            println!("{}", line.red());
+        } else if used < SLACK_PERCENT {
+            println!("{}", line.yellow());
+        } else {
+            println!("{line}");
+        }
+    }
+
+    println!();
+    println!(
+        "High-water marks cover the time since each task last started; run \
+         your workload before relying on them."
+    );
+
+    Ok(())
+}
+
+/// Finds `field: <number>` in humility's rendering of an Idol reply, where
+/// the number may be in decimal or hex.
+fn parse_field(out: &str, field: &str) -> Result<u32> {
+    let rest = out
+        .split_once(field)
+        .and_then(|(_, rest)| rest.trim_start().strip_prefix(':'))
+        .ok_or_else(|| anyhow!("no `{field}` in humility output: {out:?}"))?;
+    let value = rest
+        .trim_start()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .next()
+        .unwrap_or("");
+
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.with_context(|| format!("bad `{field}` value {value:?}"))
+}
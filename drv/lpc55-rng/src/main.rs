@@ -56,11 +56,16 @@ impl Lpc55Rng {
     }
 
     fn init(&self) {
-        self.0
-            .core
-            .pmc
-            .pdruncfg0
-            .modify(|_, w| w.pden_rng().poweredon());
+        {
+            // The PMC is also mapped into the syscon driver, so we take
+            // turns with it using the `pmc` kernel lock.
+            let _pmc = hl::lock(locks::PMC);
+            self.0
+                .core
+                .pmc
+                .pdruncfg0
+                .modify(|_, w| w.pden_rng().poweredon());
+        }
 
         self.0.core.syscon.enable_clock(Peripheral::Rng);
 
@@ -206,3 +211,5 @@ mod idl {
 
     include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
 }
+
+include!(concat!(env!("OUT_DIR"), "/locks.rs"));
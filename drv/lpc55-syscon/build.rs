@@ -8,6 +8,7 @@ fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         "server_stub.rs",
         idol::server::ServerStyle::InOrder,
     )?;
+    build_util::build_locks()?;
 
     Ok(())
 }
@@ -232,11 +232,17 @@ fn main() -> ! {
 
     let pmc = unsafe { &*device::PMC::ptr() };
 
-    // Need this to be able to use the syscon reset that works for CFPA
-    // update
-    pmc.resetctrl.write(|w| w.swrresetenable().enable());
+    {
+        // The PMC is also mapped into the RNG driver, so we take turns with
+        // it using the `pmc` kernel lock.
+        let _pmc = hl::lock(locks::PMC);
 
-    set_reset_reason(pmc);
+        // Need this to be able to use the syscon reset that works for CFPA
+        // update
+        pmc.resetctrl.write(|w| w.swrresetenable().enable());
+
+        set_reset_reason(pmc);
+    }
 
     let mut server = ServerImpl { syscon };
 
@@ -261,12 +267,15 @@ fn set_reset_reason(pmc: &device::pmc::RegisterBlock) {
 
     let aoreg1 = pmc.aoreg1.read().bits();
 
+    // If the WWDT reset us, say so even if some other cause is recorded
+    // alongside it: a watchdog reset means something wedged, which is what
+    // we most need to hear about.
     let reason = match aoreg1 {
+        _ if aoreg1 & WDTRESET != 0 => ResetReason::SystemWatchdog,
         POR => ResetReason::PowerOn,
         PADRESET => ResetReason::Pin,
         BODRESET => ResetReason::Brownout,
         SYSTEMRESET => ResetReason::SystemCall,
-        WDTRESET => ResetReason::SystemWatchdog,
         _ => ResetReason::Other(aoreg1),
     };
 
@@ -278,3 +287,5 @@ mod idl {
 
     include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
 }
+
+include!(concat!(env!("OUT_DIR"), "/locks.rs"));
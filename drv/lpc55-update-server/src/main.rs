@@ -6,6 +6,26 @@
 //
 // This driver is intended to carry as little state as possible. Most of the
 // heavy work and decision making should be handled in other tasks.
+//
+// The one decision we do make is to refuse Hubris images whose epoch (from
+// the image header) is older than our minimum epoch: the greater of our own
+// epoch and one kept in the CFPA's customer data.  We check this both when an
+// image is written and when a slot becomes the persistent boot preference,
+// which is also when we raise the minimum to that slot's epoch: both changes
+// go into the same CFPA scratch page, which the ROM applies at the next
+// reset.  Until then, nothing has been committed, and the image we're
+// running remains bootable.
+//
+// We don't use the CFPA's `NS_FW_VERSION` for this, because the ROM checks it
+// against the images it boots.
+//
+// As blocks are written, we also feed them into the HASHCRYPT unit, so that
+// `finish_image_update_with_digest` can check the caller's SHA-256 digest
+// against both what we were sent and what ended up in flash.  Both checks
+// are made before we write the header block, which is what makes the image
+// bootable; we then read back the header block itself, and erase it again if
+// it didn't take.  A block may be sent again, but the streamed digest keeps
+// what was first sent, so correcting a block means starting the update over.
 #![no_std]
 #![no_main]
 
@@ -20,6 +40,7 @@ use idol_runtime::{ClientError, Leased, LenLimit, RequestError, R, W};
 use stage0_handoff::{
     HandoffData, HandoffDataLoadError, ImageVersion, RotBootState,
 };
+use stream_digest::{Hasher as _, StreamedDigest};
 use userlib::*;
 use zerocopy::{AsBytes, FromBytes};
 
@@ -57,6 +78,64 @@ struct ServerImpl<'a> {
     flash: drv_lpc55_flash::Flash<'a>,
     hashcrypt: &'a lpc55_pac::hashcrypt::RegisterBlock,
     syscon: drv_lpc55_syscon_api::Syscon,
+
+    /// Digest of the image being written
+    streamed: StreamedDigest<Hashcrypt<'a>>,
+}
+
+/// SHA-256 in the HASHCRYPT unit, which stays out of reset for as long as
+/// this exists.
+struct Hashcrypt<'a> {
+    hasher: Option<drv_lpc55_sha256::Hasher<'a>>,
+    syscon: drv_lpc55_syscon_api::Syscon,
+}
+
+impl<'a> Hashcrypt<'a> {
+    fn begin(engine: &'a lpc55_pac::hashcrypt::RegisterBlock) -> Self {
+        let syscon = drv_lpc55_syscon_api::Syscon::from(SYSCON.get_task_id());
+        syscon.leave_reset(drv_lpc55_syscon_api::Peripheral::HashAes);
+        let hasher = drv_lpc55_sha256::Hasher::begin(
+            engine,
+            notifications::HASHCRYPT_IRQ_MASK,
+        );
+        Self {
+            hasher: Some(hasher),
+            syscon,
+        }
+    }
+
+    fn update_words(&mut self, words: &[u32]) {
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(words, 0);
+        }
+    }
+}
+
+impl stream_digest::Hasher for Hashcrypt<'_> {
+    // The hardware only takes whole words.
+    const WORD_BYTES: usize = 4;
+
+    fn update(&mut self, data: &[u8]) {
+        for word in data.chunks_exact(4) {
+            let word = u32::from_le_bytes(word.try_into().unwrap_lite());
+            self.update_words(&[word]);
+        }
+    }
+
+    fn finish(mut self) -> [u8; 32] {
+        let mut digest = [0; 32];
+        if let Some(hasher) = self.hasher.take() {
+            digest.copy_from_slice(hasher.finish().as_bytes());
+        }
+        digest
+    }
+}
+
+impl Drop for Hashcrypt<'_> {
+    fn drop(&mut self) {
+        self.syscon
+            .enter_reset(drv_lpc55_syscon_api::Peripheral::HashAes);
+    }
 }
 
 // TODO: This is the size of the vector table on the LPC55. We should
@@ -75,6 +154,9 @@ const CFPA_PONG_FLASH_WORD: u32 = 0x9E20;
 const CFPA_SCRATCH_FLASH_WORD: u32 = 0x9DE0;
 const CFPA_SCRATCH_FLASH_ADDR: u32 = CFPA_SCRATCH_FLASH_WORD << 4;
 const BOOT_PREFERENCE_FLASH_WORD_OFFSET: u32 = 0x10;
+// Our minimum image epoch is the first 32-bit word of the customer data
+// flash word after the boot preference; it reads as 0 until we first set it.
+const MIN_EPOCH_FLASH_WORD_OFFSET: u32 = 0x11;
 
 #[derive(PartialEq)]
 enum CfpaPage {
@@ -101,6 +183,9 @@ impl idl::InOrderUpdateImpl for ServerImpl<'_> {
         }
 
         self.image = Some(image_type);
+        // Put the unit back into reset before starting over with it.
+        self.streamed.abandon();
+        self.streamed = StreamedDigest::new(Hashcrypt::begin(self.hashcrypt));
         self.state = UpdateState::InProgress;
         Ok(())
     }
@@ -116,6 +201,7 @@ impl idl::InOrderUpdateImpl for ServerImpl<'_> {
             UpdateState::InProgress | UpdateState::NoUpdate => (),
         }
 
+        self.streamed.abandon();
         self.state = UpdateState::NoUpdate;
         Ok(())
     }
@@ -179,6 +265,14 @@ impl idl::InOrderUpdateImpl for ServerImpl<'_> {
 
         do_block_write(&mut self.flash, target, block_num, &flash_page)?;
 
+        // The header block isn't written until we finish, so what we were
+        // sent is in our cached copy.
+        let data = match &self.header_block {
+            Some(header) if block_num == HEADER_BLOCK => &header[..len],
+            _ => &flash_page[..len],
+        };
+        self.streamed.block(block_num * BLOCK_SIZE_BYTES, data);
+
         Ok(())
     }
 
@@ -186,30 +280,16 @@ impl idl::InOrderUpdateImpl for ServerImpl<'_> {
         &mut self,
         _: &RecvMessage,
     ) -> Result<(), RequestError<UpdateError>> {
-        match self.state {
-            UpdateState::NoUpdate => {
-                return Err(UpdateError::UpdateNotStarted.into())
-            }
-            UpdateState::Finished => {
-                return Err(UpdateError::UpdateAlreadyFinished.into())
-            }
-            UpdateState::InProgress => (),
-        }
-
-        if self.header_block.is_none() {
-            return Err(UpdateError::MissingHeaderBlock.into());
-        }
-
-        do_block_write(
-            &mut self.flash,
-            self.image.unwrap_lite(),
-            HEADER_BLOCK,
-            self.header_block.as_ref().unwrap_lite(),
-        )?;
+        self.finish_update(None).map_err(RequestError::from)
+    }
 
-        self.state = UpdateState::Finished;
-        self.image = None;
-        Ok(())
+    fn finish_image_update_with_digest(
+        &mut self,
+        _: &RecvMessage,
+        digest: [u8; 32],
+    ) -> Result<(), RequestError<UpdateError>> {
+        self.finish_update(Some(&digest))
+            .map_err(RequestError::from)
     }
 
     fn block_size(
@@ -305,116 +385,34 @@ impl idl::InOrderUpdateImpl for ServerImpl<'_> {
                 return Err(UpdateError::NotImplemented.into());
             }
             SwitchDuration::Forever => {
-                // Locate and return the authoritative CFPA flash word number
-                // and the CFPA version for that flash number.
-                //
-                // There are two "official" copies of the CFPA, referred to as
-                // ping and pong. One of them will supercede the other, based on
-                // a monotonic version field at offset 4. We'll take the
-                // contents of whichever one is most recent, alter them, and
-                // then write them into the _third_ copy, called the scratch
-                // page.
-                //
-                // At reset, the boot ROM will inspect the scratch page, check
-                // invariants, and copy it to overwrite the older of the ping
-                // and pong pages if it approves.
-                //
-                // That means you can apply this operation several times before
-                // resetting without burning many monotonic versions, if you
-                // want to do that for some reason.
-                //
-                // The addresses of these pages are as follows (see Figure 13,
-                // "Protected Flash Region," in UM11126 rev 2.4, or the NXP
-                // flash layout spreadsheet):
-                //
-                // Page     Addr        16-byte word number
-                // Scratch  0x9_DE00    0x9DE0
-                // Ping     0x9_E000    0x9E00
-                // Pong     0x9_E200    0x9E20
-                let (cfpa_word_number, _) =
-                    self.cfpa_word_number_and_version(CfpaPage::Active)?;
-
-                // Read current CFPA contents.
-                let mut cfpa = [[0u32; 4]; 512 / 16];
-                indirect_flash_read_words(
-                    &self.flash,
-                    cfpa_word_number,
-                    &mut cfpa,
-                )?;
-
-                // Alter the boot setting, if it needs changing. The boot
-                // setting (per RFD 374) is in the lowest bit of the 32-bit word
-                // starting at (byte) offset 0x100. This is flash word offset
-                // 0x10.
+                // The boot setting (per RFD 374) is in the lowest bit of the
+                // 32-bit word starting at (byte) offset 0x100. This is flash
+                // word offset 0x10.
                 //
                 // Leave remaining bits undisturbed; they are currently
                 // reserved.
-                let offset = BOOT_PREFERENCE_FLASH_WORD_OFFSET as usize;
-                let bit = cfpa[offset][0] & 1;
-                let new_bit = u32::from(slot != SlotId::A);
-                if bit == new_bit {
-                    // No need to write the CFPA if it's unchanged
-                    return Ok(());
-                }
-                cfpa[offset][0] &= !1;
-                cfpa[offset][0] |= new_bit;
-                // Increment the monotonic version. The manual doesn't specify
-                // how the version numbers are compared or what happens if they
-                // wrap, so, we'll treat wrapping as an error and report it for
-                // now. (Note that getting this version to wrap _should_ require
-                // more write cycles than the flash can take.)
-                let new_version =
-                    cfpa[0][1].checked_add(1).ok_or(UpdateError::SecureErr)?;
-                cfpa[0][1] = new_version;
-                // The last two flash words are a SHA256 hash of the preceding
-                // data. This means we need to compute a SHA256 hash of the
-                // preceding data -- meaning flash words 0 thru 29 inclusive.
-                let cfpa_hash = {
-                    // We leave the hashcrypt unit in reset when unused,
-                    // starting in the `main` function, so we only need to bring
-                    // it _out of_ reset here.
-                    self.syscon
-                        .leave_reset(drv_lpc55_syscon_api::Peripheral::HashAes);
-                    let mut h = drv_lpc55_sha256::Hasher::begin(
-                        self.hashcrypt,
-                        notifications::HASHCRYPT_IRQ_MASK,
-                    );
-                    for chunk in &cfpa[..30] {
-                        h.update(chunk, 0);
-                    }
-                    let hash = h.finish();
-
-                    // Put it back.
-                    self.syscon
-                        .enter_reset(drv_lpc55_syscon_api::Peripheral::HashAes);
-
-                    hash
-                };
-                cfpa[30] = cfpa_hash[..4].try_into().unwrap_lite();
-                cfpa[31] = cfpa_hash[4..].try_into().unwrap_lite();
-
-                // Recast that as a page-sized byte array because that's what
-                // the update side of the machinery wants. The try_into on the
-                // second line can't fail at runtime, but there's no good
-                // support for casting between fixed-size arrays in zerocopy
-                // yet.
-                let cfpa_bytes: &[u8] = cfpa.as_bytes();
-                let cfpa_bytes: &[u8; BLOCK_SIZE_BYTES] =
-                    cfpa_bytes.try_into().unwrap_lite();
-
-                // Erase and program the scratch page. Note that because the
-                // scratch page is _not_ the authoritative copy, and because the
-                // ROM will check its contents before making it authoritative,
-                // we can fail during this operation without corrupting anything
-                // permanent. Yay!
                 //
-                self.flash
-                    .write_page(
-                        CFPA_SCRATCH_FLASH_ADDR,
-                        cfpa_bytes,
-                        wait_for_flash_interrupt,
-                    )
-                    .map_err(|_| UpdateError::FlashError)?;
+                // This commits us to the slot's image, so we refuse to roll
+                // back to an older epoch, and otherwise advance our minimum
+                // to its epoch.
+                let epoch = slot_epoch(&self.flash, slot)?;
+                if epoch < self.min_epoch()? {
+                    return Err(UpdateError::ImageEpochTooOld.into());
+                }
+                self.update_cfpa(|cfpa| {
+                    let offset = BOOT_PREFERENCE_FLASH_WORD_OFFSET as usize;
+                    let bit = cfpa[offset][0] & 1;
+                    let new_bit = u32::from(slot != SlotId::A);
+                    cfpa[offset][0] &= !1;
+                    cfpa[offset][0] |= new_bit;
+
+                    let min =
+                        &mut cfpa[MIN_EPOCH_FLASH_WORD_OFFSET as usize][0];
+                    let advanced = *min < epoch;
+                    *min = (*min).max(epoch);
+
+                    bit != new_bit || advanced
+                })?;
             }
         }
 
@@ -467,6 +465,285 @@ impl idl::InOrderUpdateImpl for ServerImpl<'_> {
 }
 
 impl ServerImpl<'_> {
+    /// Completes the update, first checking the image against `digest` if
+    /// one is given.
+    fn finish_update(
+        &mut self,
+        digest: Option<&[u8; 32]>,
+    ) -> Result<(), UpdateError> {
+        match self.state {
+            UpdateState::NoUpdate => return Err(UpdateError::UpdateNotStarted),
+            UpdateState::Finished => {
+                return Err(UpdateError::UpdateAlreadyFinished)
+            }
+            UpdateState::InProgress => (),
+        }
+
+        let Some(header_block) = self.header_block.as_ref() else {
+            return Err(UpdateError::MissingHeaderBlock);
+        };
+        let target = self.image.unwrap_lite();
+
+        // Refuse to roll back to an older epoch. Stage0 images don't carry
+        // a Hubris image header, so have no epoch to check.
+        let epoch = if target == UpdateTarget::Bootloader {
+            None
+        } else {
+            Some(image_epoch(header_block)?)
+        };
+        if let Some(epoch) = epoch {
+            if epoch < self.min_epoch()? {
+                return Err(UpdateError::ImageEpochTooOld);
+            }
+        }
+
+        // Check the image before we write the header block, which makes it
+        // bootable.
+        if let Some(digest) = digest {
+            let len = self.check_streamed_digest(digest)?;
+            self.check_flash_digest(target, len, digest)?;
+        }
+
+        do_block_write(
+            &mut self.flash,
+            target,
+            HEADER_BLOCK,
+            self.header_block.as_ref().unwrap_lite(),
+        )?;
+
+        if digest.is_some() {
+            self.check_header_block(target)?;
+        }
+
+        self.streamed.abandon();
+        self.state = UpdateState::Finished;
+        self.image = None;
+        Ok(())
+    }
+
+    /// Checks `digest` against the digest streamed while the image was
+    /// written, returning the number of bytes it covers.
+    fn check_streamed_digest(
+        &mut self,
+        digest: &[u8; 32],
+    ) -> Result<usize, UpdateError> {
+        let (streamed, len) = self
+            .streamed
+            .finish()
+            .ok_or(UpdateError::ImageDigestUnavailable)?;
+        if streamed != *digest {
+            return Err(UpdateError::ImageDigestMismatch);
+        }
+        Ok(len)
+    }
+
+    /// Checks `digest` against a digest of the first `len` bytes of the
+    /// `target` slot, taking the header block -- which we haven't written
+    /// yet -- from our cached copy, and the rest as read back from flash.
+    fn check_flash_digest(
+        &self,
+        target: UpdateTarget,
+        len: usize,
+        digest: &[u8; 32],
+    ) -> Result<(), UpdateError> {
+        let header = self.header_block.as_ref().unwrap_lite();
+        // Convert from memory (byte) address to word address, per comments in
+        // `lpc55_flash` driver.
+        let first_word = (get_base(target) / 16) & ((1 << 18) - 1);
+        let body_word = first_word + (BLOCK_SIZE_BYTES / 16) as u32;
+        let body_len = len.saturating_sub(BLOCK_SIZE_BYTES);
+
+        let mut h = Hashcrypt::begin(self.hashcrypt);
+        h.update(&header[..len.min(BLOCK_SIZE_BYTES)]);
+        let mut remaining = body_len / 4;
+        for wn in (body_word..).take((body_len + 15) / 16) {
+            let mut flash_word = [0u32; 4];
+            indirect_flash_read_words(
+                &self.flash,
+                wn,
+                core::slice::from_mut(&mut flash_word),
+            )?;
+            let n = remaining.min(flash_word.len());
+            h.update_words(&flash_word[..n]);
+            remaining -= n;
+        }
+
+        if h.finish() != *digest {
+            return Err(UpdateError::FlashDigestMismatch);
+        }
+        Ok(())
+    }
+
+    /// Checks that the header block of the `target` slot, as read back from
+    /// flash, is our cached copy; if not, erases it again, so that the image
+    /// isn't bootable.
+    fn check_header_block(
+        &mut self,
+        target: UpdateTarget,
+    ) -> Result<(), UpdateError> {
+        let first_word = (get_base(target) / 16) & ((1 << 18) - 1);
+        let mut flash_words = [[0u32; 4]; BLOCK_SIZE_BYTES / 16];
+        let read = indirect_flash_read_words(
+            &self.flash,
+            first_word,
+            &mut flash_words,
+        );
+        if read.is_ok()
+            && flash_words.as_bytes()
+                == &self.header_block.as_ref().unwrap_lite()[..]
+        {
+            return Ok(());
+        }
+
+        do_block_write(
+            &mut self.flash,
+            target,
+            HEADER_BLOCK,
+            &[0xff; BLOCK_SIZE_BYTES],
+        )?;
+        read?;
+        Err(UpdateError::FlashDigestMismatch)
+    }
+
+    /// Alters the CFPA, by applying `f` to its contents (which returns whether
+    /// it changed anything) and writing the result to the scratch page.
+    ///
+    /// There are two "official" copies of the CFPA, referred to as ping and
+    /// pong. One of them will supercede the other, based on a monotonic
+    /// version field at offset 4. We'll take the contents of whichever one is
+    /// most recent -- or of the scratch page, if it holds changes that have
+    /// yet to be applied -- alter them, and then write them into the scratch
+    /// page.
+    ///
+    /// At reset, the boot ROM will inspect the scratch page, check invariants,
+    /// and copy it to overwrite the older of the ping and pong pages if it
+    /// approves.
+    ///
+    /// That means you can apply this operation several times before resetting
+    /// without burning many monotonic versions, if you want to do that for
+    /// some reason.
+    ///
+    /// The addresses of these pages are as follows (see Figure 13, "Protected
+    /// Flash Region," in UM11126 rev 2.4, or the NXP flash layout
+    /// spreadsheet):
+    ///
+    /// Page     Addr        16-byte word number
+    /// Scratch  0x9_DE00    0x9DE0
+    /// Ping     0x9_E000    0x9E00
+    /// Pong     0x9_E200    0x9E20
+    fn update_cfpa(
+        &mut self,
+        f: impl FnOnce(&mut [[u32; 4]; 512 / 16]) -> bool,
+    ) -> Result<(), UpdateError> {
+        let (cfpa_word_number, cfpa_version) = self.newest_cfpa()?;
+
+        // Read current CFPA contents.
+        let mut cfpa = [[0u32; 4]; 512 / 16];
+        indirect_flash_read_words(&self.flash, cfpa_word_number, &mut cfpa)?;
+
+        if !f(&mut cfpa) {
+            // No need to write the CFPA if it's unchanged
+            return Ok(());
+        }
+
+        // Increment the monotonic version. The manual doesn't specify how the
+        // version numbers are compared or what happens if they wrap, so,
+        // we'll treat wrapping as an error and report it for now. (Note that
+        // getting this version to wrap _should_ require more write cycles
+        // than the flash can take.)
+        let new_version =
+            cfpa_version.checked_add(1).ok_or(UpdateError::SecureErr)?;
+        cfpa[0][1] = new_version;
+        // The last two flash words are a SHA256 hash of the preceding data.
+        // This means we need to compute a SHA256 hash of the preceding data
+        // -- meaning flash words 0 thru 29 inclusive.
+        let cfpa_hash = {
+            // The HASHCRYPT unit only does one hash at a time, so an image
+            // digest in progress can't survive this.
+            self.streamed.abandon();
+
+            // We leave the hashcrypt unit in reset when unused, starting in
+            // the `main` function, so we only need to bring it _out of_ reset
+            // here.
+            self.syscon
+                .leave_reset(drv_lpc55_syscon_api::Peripheral::HashAes);
+            let mut h = drv_lpc55_sha256::Hasher::begin(
+                self.hashcrypt,
+                notifications::HASHCRYPT_IRQ_MASK,
+            );
+            for chunk in &cfpa[..30] {
+                h.update(chunk, 0);
+            }
+            let hash = h.finish();
+
+            // Put it back.
+            self.syscon
+                .enter_reset(drv_lpc55_syscon_api::Peripheral::HashAes);
+
+            hash
+        };
+        cfpa[30] = cfpa_hash[..4].try_into().unwrap_lite();
+        cfpa[31] = cfpa_hash[4..].try_into().unwrap_lite();
+
+        // Recast that as a page-sized byte array because that's what the
+        // update side of the machinery wants. The try_into on the second line
+        // can't fail at runtime, but there's no good support for casting
+        // between fixed-size arrays in zerocopy yet.
+        let cfpa_bytes: &[u8] = cfpa.as_bytes();
+        let cfpa_bytes: &[u8; BLOCK_SIZE_BYTES] =
+            cfpa_bytes.try_into().unwrap_lite();
+
+        // Erase and program the scratch page. Note that because the scratch
+        // page is _not_ the authoritative copy, and because the ROM will check
+        // its contents before making it authoritative, we can fail during this
+        // operation without corrupting anything permanent. Yay!
+        self.flash
+            .write_page(
+                CFPA_SCRATCH_FLASH_ADDR,
+                cfpa_bytes,
+                wait_for_flash_interrupt,
+            )
+            .map_err(|_| UpdateError::FlashError)
+    }
+
+    /// Returns the flash word number of the newest CFPA contents -- the
+    /// scratch page if it holds changes that have yet to be applied,
+    /// otherwise the active page -- and the version of the active page.
+    fn newest_cfpa(&mut self) -> Result<(u32, u32), UpdateError> {
+        let (cfpa_word_number, cfpa_version) =
+            self.cfpa_word_number_and_version(CfpaPage::Active)?;
+
+        // Read the scratch version, which may be erased
+        let mut scratch_header = [0u32; 4];
+        match indirect_flash_read_words(
+            &self.flash,
+            CFPA_SCRATCH_FLASH_WORD,
+            core::slice::from_mut(&mut scratch_header),
+        ) {
+            Ok(()) if scratch_header[1] > cfpa_version => {
+                Ok((CFPA_SCRATCH_FLASH_WORD, cfpa_version))
+            }
+            Ok(()) | Err(UpdateError::EccDoubleErr) => {
+                Ok((cfpa_word_number, cfpa_version))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the oldest epoch we'll accept in a new image: the greater of
+    /// our own epoch and the minimum in the CFPA, including any advance that
+    /// has yet to be applied.
+    fn min_epoch(&mut self) -> Result<u32, UpdateError> {
+        let (cfpa_word_number, _) = self.newest_cfpa()?;
+        let mut min_epoch_word = [0u32; 4];
+        indirect_flash_read_words(
+            &self.flash,
+            cfpa_word_number + MIN_EPOCH_FLASH_WORD_OFFSET,
+            core::slice::from_mut(&mut min_epoch_word),
+        )?;
+        Ok(min_epoch_word[0].max(HUBRIS_BUILD_EPOCH))
+    }
+
     fn cfpa_word_number_and_version(
         &mut self,
         page: CfpaPage,
@@ -731,6 +1008,37 @@ fn validate_header_block(
     Ok(())
 }
 
+/// Returns the epoch from the image header in a (validated) header block
+fn image_epoch(block: &[u8; BLOCK_SIZE_BYTES]) -> Result<u32, UpdateError> {
+    abi::ImageHeader::read_from_prefix(&block[MAGIC_OFFSET..])
+        .map(|header| header.epoch)
+        .ok_or(UpdateError::InvalidHeaderBlock)
+}
+
+/// Returns the epoch from the image header of the Hubris image in `slot`
+fn slot_epoch(
+    flash: &drv_lpc55_flash::Flash<'_>,
+    slot: SlotId,
+) -> Result<u32, UpdateError> {
+    // SAFETY: these symbols are populated by the linker
+    let image_start = unsafe {
+        match slot {
+            SlotId::A => __IMAGE_A_BASE.as_ptr() as u32,
+            SlotId::B => __IMAGE_B_BASE.as_ptr() as u32,
+        }
+    };
+    let mut header = ImageHeader::new_zeroed();
+    indirect_flash_read(
+        flash,
+        image_start + MAGIC_OFFSET as u32,
+        header.as_bytes_mut(),
+    )?;
+    if header.magic != HEADER_MAGIC {
+        return Err(UpdateError::MissingHeaderBlock);
+    }
+    Ok(header.epoch)
+}
+
 /// Performs an erase-write sequence to a single page within a given target
 /// image.
 fn do_block_write(
@@ -969,6 +1277,7 @@ fn main() -> ! {
         }),
         hashcrypt: unsafe { &*lpc55_pac::HASHCRYPT::ptr() },
         syscon,
+        streamed: StreamedDigest::unavailable(),
     };
     let mut incoming = [0u8; idl::INCOMING_SIZE];
 
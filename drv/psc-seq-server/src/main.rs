@@ -3,6 +3,10 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 //! Server for managing the PSC sequencing process.
+//!
+//! Once sequencing is done, the status LED is lit steadily. While any sensor
+//! is past one of its critical thresholds (e.g. a PSU's 12V output), it
+//! blinks instead.
 
 #![no_std]
 #![no_main]
@@ -11,15 +15,28 @@ use drv_packrat_vpd_loader::{read_vpd_and_load_packrat, Packrat};
 use drv_psc_seq_api::PowerState;
 use drv_stm32xx_sys_api as sys_api;
 use task_jefe_api::Jefe;
+use task_sensor_api::{AlarmEntry, Sensor};
 use userlib::*;
 
 task_slot!(SYS, sys);
 task_slot!(I2C, i2c_driver);
 task_slot!(JEFE, jefe);
 task_slot!(PACKRAT, packrat);
+task_slot!(SENSOR, sensor);
 
 const STATUS_LED: sys_api::PinSet = sys_api::Port::A.pin(3);
 
+/// How often to toggle the status LED while an alarm is critical.
+const BLINK_INTERVAL: u64 = 250;
+
+/// How often to renew our alarm subscription, which is lost if `sensor`
+/// restarts.
+const SUBSCRIBE_INTERVAL: u64 = 10_000;
+
+/// Most alarms that we look through. PSC has thresholds on one rail per PSU,
+/// so this leaves some room.
+const MAX_ALARMS: usize = 8;
+
 #[export_name = "main"]
 fn main() -> ! {
     let sys = sys_api::Sys::from(SYS.get_task_id());
@@ -42,9 +59,41 @@ fn main() -> ! {
     jefe.set_state(PowerState::A2 as u32);
     sys.gpio_set(STATUS_LED);
 
-    // We have nothing else to do, so sleep forever via waiting for a message
-    // from the kernel that won't arrive.
+    let sensor = Sensor::from(SENSOR.get_task_id());
+    let mut alarms = [AlarmEntry::default(); MAX_ALARMS];
+    let mut led_on = true;
+
     loop {
-        _ = sys_recv_closed(&mut [], 0, TaskId::KERNEL);
+        // Subscribing again is harmless, and picks up where we left off if
+        // `sensor` has restarted (and so forgotten us) since last time. If
+        // it has too many subscribers, we'll just try again later.
+        let _ = sensor.subscribe_alarms(notifications::SENSOR_ALARM_MASK);
+
+        let (active, total) = sensor.get_alarm_entries(&mut alarms);
+        // If there are more alarms than we can see, assume the worst.
+        let critical = total > active.len()
+            || active
+                .iter()
+                .any(|a| a.level().map(|l| l.is_critical()).unwrap_or(false));
+        led_on = !critical || !led_on;
+        sys.gpio_set_to(STATUS_LED, led_on);
+
+        let interval = if critical {
+            BLINK_INTERVAL
+        } else {
+            SUBSCRIBE_INTERVAL
+        };
+        sys_set_timer(
+            Some(sys_get_timer().now + interval),
+            notifications::TIMER_MASK,
+        );
+        sys_recv_closed(
+            &mut [],
+            notifications::SENSOR_ALARM_MASK | notifications::TIMER_MASK,
+            TaskId::KERNEL,
+        )
+        .unwrap_lite();
     }
 }
+
+include!(concat!(env!("OUT_DIR"), "/notifications.rs"));
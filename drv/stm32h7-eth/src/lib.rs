@@ -486,6 +486,12 @@ impl Ethernet {
         can_recv
     }
 
+    /// Returns counts of received packets that were dropped before reaching
+    /// the caller.
+    pub fn rx_drop_counts(&self) -> ring::RxDropCounts {
+        self.rx_ring.drop_counts()
+    }
+
     /// Receives a packet from the Rx ring, calling `readout` on it and
     /// returning its value.
     ///
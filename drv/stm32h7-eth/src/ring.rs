@@ -402,6 +402,19 @@ pub struct RxRing {
     /// received packet. This must be in the range `0..storage.len()` at all
     /// times.
     next: Cell<usize>,
+    /// Packets we've thrown away without showing anyone.
+    drops: Cell<RxDropCounts>,
+}
+
+/// Counts of received packets that the ring discarded before they reached the
+/// network stack. These wrap on overflow.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RxDropCounts {
+    /// Packets with a receive error (e.g. a bad frame checksum) or that
+    /// didn't fit in a single buffer
+    pub errors: u32,
+    /// Packets without a VLAN tag, or with a VID outside the configured range
+    pub bad_vid: u32,
 }
 
 impl RxRing {
@@ -435,9 +448,15 @@ impl RxRing {
             storage,
             buffers,
             next: Cell::new(0),
+            drops: Cell::new(RxDropCounts::default()),
         }
     }
 
+    /// Returns the number of packets the ring has discarded so far.
+    pub fn drop_counts(&self) -> RxDropCounts {
+        self.drops.get()
+    }
+
     /// Returns the base pointer of the `RxDesc` ring. This needs to be loaded
     /// into the DMA controller so it knows where to look for descriptors.
     pub fn base_ptr(&self) -> *const RxDesc {
@@ -519,6 +538,10 @@ impl RxRing {
             // Otherwise, drop the packet by bumping our index
             self.incr_next();
 
+            let mut drops = self.drops.get();
+            drops.errors = drops.errors.wrapping_add(1);
+            self.drops.set(drops);
+
             any_dropped = true;
         }
     }
@@ -655,6 +678,14 @@ impl RxRing {
             // Bump index forward.
             self.incr_next();
 
+            let mut drops = self.drops.get();
+            if packet_okay {
+                drops.bad_vid = drops.bad_vid.wrapping_add(1);
+            } else {
+                drops.errors = drops.errors.wrapping_add(1);
+            }
+            self.drops.set(drops);
+
             any_dropped = true;
         }
     }
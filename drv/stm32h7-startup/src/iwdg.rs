@@ -0,0 +1,77 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Independent watchdog (IWDG) hooks for the kernel.
+//!
+//! `system_init_custom` hands these to the kernel, which only uses them if the
+//! app.toml sets a kernel `watchdog-ms`.
+//!
+//! Once started, the IWDG can't be stopped by anything short of a reset.
+
+use super::device;
+
+/// The IWDG hooks, in the form the kernel wants them.
+pub static WATCHDOG: kern::watchdog::WatchdogTable =
+    kern::watchdog::WatchdogTable { arm, pet };
+
+/// Frequency of the LSI oscillator clocking the IWDG, in kHz. (RM0433 says
+/// 32 kHz nominal; it's not very accurate, so we don't try to be either.)
+const LSI_KHZ: u32 = 32;
+
+/// Largest value the reload register can hold.
+const MAX_RELOAD: u32 = 0xFFF;
+
+/// Largest prescaler setting, which divides by 256.
+const MAX_PRESCALER: u32 = 6;
+
+// Values written to IWDG_KR. See RM0433 section 46.4.
+const KEY_START: u32 = 0xCCCC;
+const KEY_UNLOCK: u32 = 0x5555;
+const KEY_RELOAD: u32 = 0xAAAA;
+
+/// DBGMCU_APB4FZ1 bit that stops IWDG1 while the core is halted.
+const DBG_IWDG1: u32 = 1 << 18;
+
+/// Starts the IWDG with (approximately) the given timeout, in milliseconds.
+/// Timeouts beyond what the hardware can count (about 32 seconds) are
+/// clamped.
+pub fn arm(timeout_ms: u32) {
+    // Safety: we only touch the IWDG from the kernel, and only these
+    // functions touch it.
+    let iwdg = unsafe { &*device::IWDG::ptr() };
+    let dbgmcu = unsafe { &*device::DBGMCU::ptr() };
+
+    // Don't reset the system out from under someone at a breakpoint.
+    dbgmcu
+        .apb4fz1
+        .modify(|r, w| unsafe { w.bits(r.bits() | DBG_IWDG1) });
+
+    // Find the smallest prescaler (dividing by 4 << prescaler) that lets us
+    // count out the whole timeout.
+    let ticks = timeout_ms.saturating_mul(LSI_KHZ);
+    let mut prescaler = 0;
+    while prescaler < MAX_PRESCALER && ticks >> (prescaler + 2) > MAX_RELOAD {
+        prescaler += 1;
+    }
+    let reload = (ticks >> (prescaler + 2)).clamp(1, MAX_RELOAD);
+
+    // Starting the IWDG also starts the LSI, so do that first.
+    iwdg.kr.write(|w| unsafe { w.bits(KEY_START) });
+    iwdg.kr.write(|w| unsafe { w.bits(KEY_UNLOCK) });
+    iwdg.pr.write(|w| unsafe { w.bits(prescaler) });
+    iwdg.rlr.write(|w| unsafe { w.bits(reload) });
+
+    // The new settings cross into the LSI domain slowly; they must land
+    // before we reload the counter.
+    while iwdg.sr.read().bits() != 0 {
+        // spin
+    }
+    pet();
+}
+
+/// Reloads the IWDG counter.
+pub fn pet() {
+    let iwdg = unsafe { &*device::IWDG::ptr() };
+    iwdg.kr.write(|w| unsafe { w.bits(KEY_RELOAD) });
+}
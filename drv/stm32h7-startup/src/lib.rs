@@ -12,6 +12,8 @@ use stm32h7::stm32h743 as device;
 #[cfg(feature = "h753")]
 use stm32h7::stm32h753 as device;
 
+pub mod iwdg;
+
 #[cfg(any(feature = "h743", feature = "h753"))]
 #[pre_init]
 unsafe fn system_pre_init() {
@@ -330,6 +332,9 @@ pub fn system_init_custom(
 
     // Hello from target speed!
 
+    // Only used if the app.toml sets a kernel `watchdog-ms`.
+    kern::watchdog::configure_watchdog(&iwdg::WATCHDOG);
+
     // Hand the peripherals back in case the board-specific setup code needs to
     // do anything.
     p
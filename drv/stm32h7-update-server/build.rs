@@ -2,9 +2,31 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use serde::Deserialize;
 use std::fs::File;
 use std::io::Write;
 
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Config {
+    /// Ed25519 public keys (as hex strings), any of which may sign an image
+    /// that we'll swap banks to; there must be at least one.
+    image_signing_keys: Vec<String>,
+}
+
+fn parse_key(key: &str) -> Result<[u8; 32], String> {
+    let bad = || format!("bad image signing key {key:?}");
+    if key.len() != 64 {
+        return Err(bad());
+    }
+    let mut out = [0u8; 32];
+    for (b, pair) in out.iter_mut().zip(key.as_bytes().chunks(2)) {
+        let pair = std::str::from_utf8(pair).map_err(|_| bad())?;
+        *b = u8::from_str_radix(pair, 16).map_err(|_| bad())?;
+    }
+    Ok(out)
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     build_util::build_notifications()?;
     idol::server::build_server_support(
@@ -22,5 +44,21 @@ fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     writeln!(ver_file, "const HUBRIS_BUILD_VERSION: u32 = {};", version)?;
     writeln!(ver_file, "const HUBRIS_BUILD_EPOCH: u32 = {};", epoch)?;
 
+    let cfg = build_util::task_config::<Config>()?;
+    if cfg.image_signing_keys.is_empty() {
+        return Err("at least one image signing key is required".into());
+    }
+    let keys = cfg
+        .image_signing_keys
+        .iter()
+        .map(|k| parse_key(k))
+        .collect::<Result<Vec<_>, _>>()?;
+    writeln!(
+        ver_file,
+        "const IMAGE_SIGNING_KEYS: [[u8; 32]; {}] = {:?};",
+        keys.len(),
+        keys
+    )?;
+
     Ok(())
 }
@@ -6,6 +6,42 @@
 //
 // This driver is intended to carry as little state as possible. Most of the
 // heavy work and decision making should be handled in other tasks.
+//
+// The one decision we do make is whether the image in bank 2 may become the
+// boot bank: `finish_image_update` refuses to swap banks unless the image's
+// caboose names our board and a version, and carries a valid signature under
+// the `SIGN` key from one of the `image-signing-keys` this task was built
+// with.  That signature is an Ed25519 signature over the SHA3-256 digest of
+// the whole image (as delimited by its header), computed with the `SIGN`
+// chunk itself -- header, body and body checksum -- replaced by zeros; see
+// `sign_sp_image` in xtask, which adds it when the app has an `image-signing`
+// key.
+//
+// We also refuse images whose epoch (from the image header) is older than
+// our minimum epoch, which we persist as epoch records in the last sector of
+// each bank.  Images may not occupy that sector (apps running this server use
+// the chip's `memory*-update.toml` memory map, which sets it aside in bank 1
+// as the `bank1_epoch` region), and we erase the rest of bank 2 sector by
+// sector rather than as a whole, so the records survive updates.  Before
+// swapping banks, we append the new image's epoch to the records in bank 2
+// and then in bank 1, reading each back.
+//
+// Swapping banks only exchanges their addresses, so with the records in both
+// banks, both copies hold the newest minimum whichever bank we then boot
+// from.  If we lose power between the two appends, the banks haven't been
+// swapped and only bank 2 holds the new minimum; at boot, we take the
+// greatest epoch from both banks' records (and our own), and append it to
+// either bank whose records lack it, so the copies agree again before we
+// accept another update.  (That holds us to the epoch of an update which we
+// never swapped to, which errs on the side of refusing a rollback.)
+//
+// We also take a SHA-256 digest of each block as it's written, so that
+// `finish_image_update_with_digest` can check the caller's digest against
+// both what we were sent and what ended up in flash.  The streamed digest is
+// done in software: the hash server has a single context, which other clients
+// could disturb over the course of an update.  When built with the `hash`
+// feature, the digest of what's in flash is done by the HASH engine, in one go
+// once the image is complete.
 #![no_std]
 #![no_main]
 
@@ -17,7 +53,10 @@ use drv_stm32h7_update_api::{
 use drv_update_api::UpdateError;
 use idol_runtime::{ClientError, Leased, LenLimit, RequestError, R};
 use ringbuf::*;
+use sha2::Sha256;
+use sha3::{Digest, Sha3_256};
 use stm32h7::stm32h753 as device;
+use stream_digest::{Block, StreamedDigest};
 use userlib::*;
 use zerocopy::AsBytes;
 
@@ -34,12 +73,50 @@ const FLASH_KEY2: u32 = 0xCDEF_89AB;
 const FLASH_OPT_KEY1: u32 = 0x0819_2A3B;
 const FLASH_OPT_KEY2: u32 = 0x4C5D_6E7F;
 
+// Caboose keys checked before we'll swap banks
+const BOARD_KEY: [u8; 4] = *b"BORD";
+const VERSION_KEY: [u8; 4] = *b"VERS";
+const SIGNATURE_KEY: [u8; 4] = *b"SIGN";
+
+const SIGNATURE_LEN: usize = 64;
+
+// Each bank has eight 128 KiB sectors; see RM0433 Rev 7 section 4.3.4
+const SECTOR_BYTES: usize = 128 * 1024;
+const SECTORS_PER_BANK: u8 = 8;
+
+// Epoch records are flash words appended to the last sector of each bank,
+// each holding `[EPOCH_RECORD_MAGIC, epoch, !epoch]` followed by zeros.  The
+// greatest epoch in a valid record is our minimum.
+const EPOCH_SECTOR: u8 = SECTORS_PER_BANK - 1;
+const EPOCH_RECORD_MAGIC: u32 = 0x45_50_4f_43;
+const EPOCH_RECORDS_PER_SECTOR: usize = SECTOR_BYTES / FLASH_WORD_BYTES;
+
+// Length of the whole `SIGN` TLV-C chunk: header, signature and body checksum
+const SIGNATURE_CHUNK_LEN: usize =
+    core::mem::size_of::<tlvc::ChunkHeader>() + SIGNATURE_LEN + 4;
+
+// Largest lease the hash server will accept in a single `update`
+#[cfg(feature = "hash")]
+const HASH_CHUNK_BYTES: usize = 512;
+
+#[cfg(feature = "hash")]
+task_slot!(HASH, hash_driver);
+
 extern "C" {
     // Symbols injected by the linker.
     //
-    // This requires adding `extern-regions = ["bank2"]` to the task config
+    // This requires adding `extern-regions = ["bank2", "bank1_epoch"]` to
+    // the task config
     pub static mut __REGION_BANK2_BASE: [u32; 0];
     pub static mut __REGION_BANK2_END: [u32; 0];
+    // The last sector of bank 1, which holds its epoch records
+    pub static mut __REGION_BANK1_EPOCH_BASE: [u32; 0];
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Bank {
+    One,
+    Two,
 }
 
 #[derive(Copy, Clone, PartialEq)]
@@ -50,6 +127,22 @@ enum Trace {
     WriteEnd,
     FinishStart,
     FinishEnd,
+    VerifyStart,
+    VerifyEnd,
+    VerifyFailed(UpdateError),
+    EpochRecord {
+        bank: Bank,
+        epoch: u32,
+    },
+    EpochSyncFailed,
+    EpochTooOld {
+        image: u32,
+        min: u32,
+    },
+    DigestAbandoned(usize),
+    BlockAlreadyWritten(usize),
+    #[cfg(feature = "hash")]
+    HashError(drv_hash_api::HashError),
     WriteBlock(usize),
     None,
 }
@@ -65,9 +158,184 @@ ringbuf!(Trace, 64, Trace::None);
 struct ServerImpl<'a> {
     flash: &'a device::flash::RegisterBlock,
     state: UpdateState,
+    /// Oldest epoch we'll accept in a new image
+    min_epoch: u32,
+    #[cfg(feature = "hash")]
+    hash: drv_hash_api::Hash,
+    /// Digest of the image being written
+    streamed: StreamedDigest<SoftHasher>,
+}
+
+/// Software SHA-256, for the streamed digest
+struct SoftHasher(Sha256);
+
+impl stream_digest::Hasher for SoftHasher {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finish(self) -> [u8; 32] {
+        self.0.finalize().into()
+    }
 }
 
 impl<'a> ServerImpl<'a> {
+    /// Checks that the image in bank 2 is one we're willing to boot; see the
+    /// comment at the top of this file.
+    fn verify_image(&self) -> Result<(), UpdateError> {
+        ringbuf_entry!(Trace::VerifyStart);
+        let r = verify_bank2_image();
+        match r {
+            Ok(()) => ringbuf_entry!(Trace::VerifyEnd),
+            Err(e) => ringbuf_entry!(Trace::VerifyFailed(e)),
+        }
+        r
+    }
+
+    /// Checks the epoch of the image in bank 2 against our minimum, returning
+    /// the image's epoch (which becomes our new minimum).
+    fn check_epoch(&self) -> Result<u32, UpdateError> {
+        // `verify_image` has already checked that the header is present.
+        let epoch =
+            bank2_header().ok_or(UpdateError::MissingHeaderBlock)?.epoch;
+        if epoch < self.min_epoch {
+            ringbuf_entry!(Trace::EpochTooOld {
+                image: epoch,
+                min: self.min_epoch,
+            });
+            return Err(UpdateError::ImageEpochTooOld);
+        }
+        Ok(epoch)
+    }
+
+    /// Checks `digest` against the digest streamed while the image was
+    /// written, and then against a digest of what's in bank 2.
+    fn check_digest(&mut self, digest: &[u8; 32]) -> Result<(), UpdateError> {
+        let (streamed, len) = self
+            .streamed
+            .finish()
+            .ok_or(UpdateError::ImageDigestUnavailable)?;
+        if streamed != *digest {
+            return Err(UpdateError::ImageDigestMismatch);
+        }
+
+        // SAFETY: `len` bytes have been written to bank 2 without going past
+        // its end, so this is a slice within the bank2 flash.
+        let image = unsafe {
+            core::slice::from_raw_parts(
+                __REGION_BANK2_BASE.as_ptr() as *const u8,
+                len,
+            )
+        };
+        if self.flash_digest(image)? != streamed {
+            return Err(UpdateError::FlashDigestMismatch);
+        }
+        Ok(())
+    }
+
+    /// Computes the digest of `image`, in bank 2, with the HASH engine
+    #[cfg(feature = "hash")]
+    fn flash_digest(&self, image: &[u8]) -> Result<[u8; 32], UpdateError> {
+        let hash_err = |e| {
+            ringbuf_entry!(Trace::HashError(e));
+            UpdateError::ImageDigestUnavailable
+        };
+
+        // We can't lend the bank2 region to the hash server, because the
+        // kernel disallows using regions marked with the DMA attribute as a
+        // lease source, so go through a buffer.
+        self.hash.init_sha256().map_err(hash_err)?;
+        let mut buf = [0u8; HASH_CHUNK_BYTES];
+        for chunk in image.chunks(HASH_CHUNK_BYTES) {
+            let buf = &mut buf[..chunk.len()];
+            buf.copy_from_slice(chunk);
+            self.hash.update(buf.len() as u32, buf).map_err(hash_err)?;
+        }
+        self.hash.finalize_sha256().map_err(hash_err)
+    }
+
+    /// Computes the digest of `image`, in bank 2, in software
+    #[cfg(not(feature = "hash"))]
+    fn flash_digest(&self, image: &[u8]) -> Result<[u8; 32], UpdateError> {
+        Ok(Sha256::digest(image).into())
+    }
+
+    /// Brings the epoch records in both banks up to `epoch`, bank 2 first;
+    /// see the comment at the top of this file.
+    fn sync_epoch_records(
+        &mut self,
+        epoch: u32,
+    ) -> Result<(), RequestError<UpdateError>> {
+        self.record_epoch(Bank::Two, epoch)?;
+        self.record_epoch(Bank::One, epoch)
+    }
+
+    /// Appends a record of `epoch` to the epoch records in `bank`, unless
+    /// they already hold it (or a later epoch), and reads it back.
+    fn record_epoch(
+        &mut self,
+        bank: Bank,
+        epoch: u32,
+    ) -> Result<(), RequestError<UpdateError>> {
+        let (newest, free) = read_epoch_records(bank);
+        if newest >= Some(epoch) {
+            return Ok(());
+        }
+        ringbuf_entry!(Trace::EpochRecord { bank, epoch });
+
+        match bank {
+            Bank::One => self.unlock_bank1(),
+            Bank::Two => self.unlock(),
+        }
+        let r = self.append_epoch_record(bank, free, epoch);
+        if bank == Bank::One {
+            self.lock_bank1();
+        }
+        r
+    }
+
+    // Writes a record of `epoch` at index `free` of the epoch records in
+    // `bank`, or starts them over if there's no room.
+    fn append_epoch_record(
+        &mut self,
+        bank: Bank,
+        free: Option<usize>,
+        epoch: u32,
+    ) -> Result<(), RequestError<UpdateError>> {
+        let index = match free {
+            Some(index) => index,
+            None => {
+                // Every record in this sector holds an older epoch than the
+                // one we're about to write, and the other bank holds a copy
+                // of our current minimum while we start over.
+                self.erase_sector(bank, EPOCH_SECTOR)?;
+                0
+            }
+        };
+
+        let mut words = [0; FLASH_WORD_WORDS];
+        words[..3].copy_from_slice(&[EPOCH_RECORD_MAGIC, epoch, !epoch]);
+        let addr = epoch_records_start(bank) + index * FLASH_WORD_BYTES;
+        self.program_flash_word(bank, addr, &words)?;
+
+        // SAFETY: this is the flash word we just programmed, within the
+        // epoch records of `bank`.
+        let written = unsafe {
+            core::ptr::read_volatile(addr as *const [u32; FLASH_WORD_WORDS])
+        };
+        if written != words {
+            return Err(UpdateError::FlashError.into());
+        }
+        Ok(())
+    }
+
+    fn bank(&self, bank: Bank) -> &'a device::flash::BANK {
+        match bank {
+            Bank::One => self.flash.bank1(),
+            Bank::Two => self.flash.bank2(),
+        }
+    }
+
     // See RM0433 Rev 7 section 4.3.13
     fn swap_banks(&mut self) -> Result<(), RequestError<UpdateError>> {
         ringbuf_entry!(Trace::FinishStart);
@@ -93,7 +361,10 @@ impl<'a> ServerImpl<'a> {
         Ok(())
     }
 
-    fn poll_flash_done(&mut self) -> Result<(), RequestError<UpdateError>> {
+    fn poll_flash_done(
+        &mut self,
+        bank: Bank,
+    ) -> Result<(), RequestError<UpdateError>> {
         // This method should implement step 5 of the Single Write Sequence from
         // RM0433 Rev 7 section 4.3.9, which states
         //
@@ -105,16 +376,16 @@ impl<'a> ServerImpl<'a> {
         // have observed this race in practice, so we omit the check that QW2
         // has been raised and only wait until QW2 is reset to 0.
         loop {
-            if !self.flash.bank2().sr.read().qw().bit() {
+            if !self.bank(bank).sr.read().qw().bit() {
                 break;
             }
         }
 
-        self.bank2_status()
+        self.bank_status(bank)
     }
 
-    fn bank2_status(&self) -> Result<(), RequestError<UpdateError>> {
-        let err = self.flash.bank2().sr.read();
+    fn bank_status(&self, bank: Bank) -> Result<(), RequestError<UpdateError>> {
+        let err = self.bank(bank).sr.read();
 
         if err.dbeccerr().bit() {
             return Err(UpdateError::EccDoubleErr.into());
@@ -164,28 +435,22 @@ impl<'a> ServerImpl<'a> {
     ) -> Result<(), RequestError<UpdateError>> {
         ringbuf_entry!(Trace::WriteStart);
 
-        // These variables are _philosophically_ constants, but since they're
-        // generated by taking the address of a linker-generated symbol, we
-        // can't define them as `const` values.
-        //
-        // SAFETY: these are symbols populated by the linker.
-        let bank_addr = unsafe { __REGION_BANK2_BASE.as_ptr() } as usize;
-        let bank_end = unsafe { __REGION_BANK2_END.as_ptr() } as usize;
-        let bank_word_limit = (bank_end - bank_addr) / FLASH_WORD_BYTES;
-
-        if word_number > bank_word_limit {
-            panic!();
-        }
-
-        let start = bank_addr + (word_number * FLASH_WORD_BYTES);
-
-        if start + FLASH_WORD_BYTES > bank_end {
-            return Err(UpdateError::BadLength.into());
-        }
+        let start = bank2_word_addr(word_number)?;
+        let b = self.program_flash_word(Bank::Two, start, words);
+        ringbuf_entry!(Trace::WriteEnd);
+        b
+    }
 
+    // Programs a single flash word in `bank`, starting at address `start`
+    fn program_flash_word(
+        &mut self,
+        bank: Bank,
+        start: usize,
+        words: &[u32; FLASH_WORD_WORDS],
+    ) -> Result<(), RequestError<UpdateError>> {
         let addresses = (start..start + FLASH_WORD_BYTES).step_by(4);
 
-        self.flash.bank2().cr.write(|w| {
+        self.bank(bank).cr.write(|w| {
             // SAFETY
             // The `psize().bits(_)` function is marked unsafe in the stm32
             // crate because it allows arbitrary bit patterns. `0b11`
@@ -198,17 +463,17 @@ impl<'a> ServerImpl<'a> {
         for (addr, &word) in addresses.zip(words) {
             // SAFETY
             // This code is running out of bank #1. The programming for bank #2
-            // is completely separate so it will not affect running code.
+            // is completely separate so it will not affect running code, and
+            // in bank #1 we only program the epoch records sector, which
+            // holds no code (reads from bank #1 stall until we're done).
             // The address is bounds checked against the start and end of
-            // the bank limits.
+            // the bank limits, or is within the epoch records.
             unsafe {
                 core::ptr::write_volatile(addr as *mut u32, word);
             }
         }
 
-        let b = self.poll_flash_done();
-        ringbuf_entry!(Trace::WriteEnd);
-        b
+        self.poll_flash_done(bank)
     }
 
     // All sequences can be found in RM0433 Rev 7
@@ -234,13 +499,51 @@ impl<'a> ServerImpl<'a> {
             .write(|w| unsafe { w.optkeyr().bits(FLASH_OPT_KEY2) });
     }
 
-    fn bank_erase(&mut self) -> Result<(), RequestError<UpdateError>> {
+    // Unlocks bank 1, which we only program to append epoch records
+    fn unlock_bank1(&mut self) {
+        if !self.flash.bank1().cr.read().lock().bit() {
+            return;
+        }
+
+        self.flash
+            .bank1()
+            .keyr
+            .write(|w| unsafe { w.keyr().bits(FLASH_KEY1) });
+        self.flash
+            .bank1()
+            .keyr
+            .write(|w| unsafe { w.keyr().bits(FLASH_KEY2) });
+    }
+
+    fn lock_bank1(&mut self) {
+        self.flash.bank1().cr.modify(|_, w| w.lock().set_bit());
+    }
+
+    // Erases all of bank 2 except its epoch records
+    fn erase_image_sectors(&mut self) -> Result<(), RequestError<UpdateError>> {
+        for sector in 0..EPOCH_SECTOR {
+            self.erase_sector(Bank::Two, sector)?;
+        }
+        Ok(())
+    }
+
+    // RM0433 Rev 7 section 4.3.10
+    fn erase_sector(
+        &mut self,
+        bank: Bank,
+        sector: u8,
+    ) -> Result<(), RequestError<UpdateError>> {
         ringbuf_entry!(Trace::EraseStart);
+        let regs = self.bank(bank);
+
+        // Clear the end-of-operation flag from any earlier erase, so that we
+        // wait for this one.
+        regs.ccr.write(|w| w.clr_eop().set_bit());
 
-        // Enable relevant interrupts for completion (or failure) of erasing
-        // bank2.
+        // Enable relevant interrupts for completion (or failure) of the
+        // erase.
         sys_irq_control(notifications::FLASH_IRQ_MASK, true);
-        self.flash.bank2().cr.modify(|_, w| {
+        regs.cr.modify(|_, w| {
             w.eopie()
                 .set_bit()
                 .wrperrie()
@@ -255,10 +558,11 @@ impl<'a> ServerImpl<'a> {
                 .set_bit()
         });
 
-        self.flash
-            .bank2()
-            .cr
-            .modify(|_, w| w.start().set_bit().ber().set_bit());
+        // SAFETY: `snb().bits(_)` is unsafe because it allows arbitrary bit
+        // patterns; every sector number below `SECTORS_PER_BANK` is valid.
+        regs.cr
+            .modify(|_, w| unsafe { w.snb().bits(sector) }.ser().set_bit());
+        regs.cr.modify(|_, w| w.start().set_bit());
 
         // Wait for EOP notification via interrupt.
         loop {
@@ -268,14 +572,15 @@ impl<'a> ServerImpl<'a> {
                 TaskId::KERNEL,
             )
             .unwrap_lite();
-            if self.flash.bank2().sr.read().eop().bit() {
+            if regs.sr.read().eop().bit() {
                 break;
             } else {
                 sys_irq_control(notifications::FLASH_IRQ_MASK, true);
             }
         }
+        regs.cr.modify(|_, w| w.ser().clear_bit());
 
-        let b = self.bank2_status();
+        let b = self.bank_status(bank);
         ringbuf_entry!(Trace::EraseEnd);
         b
     }
@@ -297,7 +602,8 @@ impl idl::InOrderUpdateImpl for ServerImpl<'_> {
         }
 
         self.unlock();
-        self.bank_erase()?;
+        self.erase_image_sectors()?;
+        self.streamed = StreamedDigest::new(SoftHasher(Sha256::new()));
         self.state = UpdateState::InProgress;
         Ok(())
     }
@@ -316,6 +622,7 @@ impl idl::InOrderUpdateImpl for ServerImpl<'_> {
             UpdateState::InProgress => (),
         }
 
+        self.streamed.abandon();
         self.state = UpdateState::NoUpdate;
         Ok(())
     }
@@ -361,9 +668,42 @@ impl idl::InOrderUpdateImpl for ServerImpl<'_> {
             }
         }
 
+        // Flash words carry ECC, so each may be programmed only once between
+        // erases.  A block sent again (say, because the reply to the first
+        // attempt was lost) lands on words that are already programmed: we
+        // skip words that already hold the same data, and refuse the block,
+        // programming none of it, if any hold something else.  Words of all
+        // ones are skipped rather than programmed, so a word that reads as
+        // all ones is always still erased.
+        let first_word = block_num * FLASH_WORDS_PER_BLOCK;
+        let mut unwritten = [false; FLASH_WORDS_PER_BLOCK];
+        for (i, fw) in flash_page.iter().enumerate() {
+            let addr = bank2_word_addr(first_word + i)?;
+            // SAFETY: `bank2_word_addr` checked that this is within bank 2
+            let current = unsafe {
+                core::ptr::read_volatile(addr as *const [u32; FLASH_WORD_WORDS])
+            };
+            if current == *fw {
+                continue;
+            }
+            if current != [u32::MAX; FLASH_WORD_WORDS] {
+                ringbuf_entry!(Trace::BlockAlreadyWritten(block_num));
+                return Err(UpdateError::BlockAlreadyWritten.into());
+            }
+            unwritten[i] = true;
+        }
+
         ringbuf_entry!(Trace::WriteBlock(block_num));
         for (i, fw) in flash_page.iter().enumerate() {
-            self.write_word(block_num * FLASH_WORDS_PER_BLOCK + i, fw)?;
+            if unwritten[i] {
+                self.write_word(first_word + i, fw)?;
+            }
+        }
+        let data = &flash_page.as_bytes()[..len];
+        if self.streamed.block(block_num * BLOCK_SIZE_BYTES, data)
+            == Block::Abandoned
+        {
+            ringbuf_entry!(Trace::DigestAbandoned(block_num));
         }
 
         Ok(())
@@ -383,11 +723,37 @@ impl idl::InOrderUpdateImpl for ServerImpl<'_> {
             UpdateState::InProgress => (),
         }
 
+        self.verify_image()?;
+        let epoch = self.check_epoch()?;
+        self.sync_epoch_records(epoch)?;
+        self.min_epoch = epoch;
         self.swap_banks()?;
         self.state = UpdateState::Finished;
         Ok(())
     }
 
+    fn finish_image_update_with_digest(
+        &mut self,
+        msg: &RecvMessage,
+        digest: [u8; 32],
+    ) -> Result<(), RequestError<UpdateError>> {
+        match self.state {
+            UpdateState::NoUpdate => {
+                return Err(UpdateError::UpdateNotStarted.into())
+            }
+            UpdateState::Finished => {
+                return Err(UpdateError::UpdateAlreadyFinished.into())
+            }
+            UpdateState::InProgress => (),
+        }
+
+        if let Err(e) = self.check_digest(&digest) {
+            ringbuf_entry!(Trace::VerifyFailed(e));
+            return Err(e.into());
+        }
+        idl::InOrderUpdateImpl::finish_image_update(self, msg)
+    }
+
     fn block_size(
         &mut self,
         _: &RecvMessage,
@@ -411,69 +777,7 @@ impl idl::InOrderUpdateImpl for ServerImpl<'_> {
         name: [u8; 4],
         data: Leased<idol_runtime::W, [u8]>,
     ) -> Result<u32, RequestError<CabooseError>> {
-        // This code is very similar to `kipc::read_caboose_pos`, but it
-        // operates on the alternate flash bank rather than on the loaded image.
-        let image_start = unsafe { __REGION_BANK2_BASE.as_ptr() } as u32;
-
-        // If all is going according to plan, there will be a valid Hubris image
-        // flashed into the other slot, delimited by `__REGION_BANK2_BASE` and
-        // `__REGION_BASE2_END` (which are symbols injected by the linker).
-        //
-        // We'll first want to read the image header, which is at a fixed
-        // location at the end of the vector table.  The length of the vector
-        // table is fixed in hardware, so this should never change.
-        const HEADER_OFFSET: u32 = 0x298;
-        let header: ImageHeader = unsafe {
-            core::ptr::read_volatile(
-                (image_start + HEADER_OFFSET) as *const ImageHeader,
-            )
-        };
-        if header.magic != HEADER_MAGIC {
-            return Err(CabooseError::NoImageHeader.into());
-        }
-
-        // Calculate where the image header implies that the image should end
-        //
-        // This is a one-past-the-end value.
-        let image_end = image_start + header.total_image_len;
-
-        // Then, check that value against the BANK2 bounds.
-        //
-        // SAFETY: populated by the linker, so this should be valid
-        if image_end > unsafe { __REGION_BANK2_END.as_ptr() } as u32 {
-            return Err(CabooseError::MissingCaboose.into());
-        }
-
-        // By construction, the last word of the caboose is its size as a `u32`
-        let caboose_size: u32 =
-            unsafe { core::ptr::read_volatile((image_end - 4) as *const u32) };
-
-        let caboose_start = image_end.saturating_sub(caboose_size);
-        let caboose_range = if caboose_start < image_start {
-            // This branch will be encountered if there's no caboose, because
-            // then the nominal caboose size will be 0xFFFFFFFF, which will send
-            // us out of the bank2 region.
-            return Err(CabooseError::MissingCaboose.into());
-        } else {
-            // SAFETY: we know this pointer is within the bank2 flash region,
-            // since it's checked above.
-            let v = unsafe {
-                core::ptr::read_volatile(caboose_start as *const u32)
-            };
-            if v == CABOOSE_MAGIC {
-                caboose_start + 4..image_end - 4
-            } else {
-                return Err(CabooseError::MissingCaboose.into());
-            }
-        };
-
-        // SAFETY: this is a slice within the bank2 flash
-        let caboose = unsafe {
-            core::slice::from_raw_parts(
-                caboose_range.start as *const u8,
-                caboose_range.len(),
-            )
-        };
+        let (_image, caboose) = bank2_image()?;
 
         let reader = CabooseReader::new(caboose);
 
@@ -507,6 +811,228 @@ impl idl::InOrderUpdateImpl for ServerImpl<'_> {
     }
 }
 
+/// Finds the image in bank 2, returning it (as delimited by its header) and
+/// the TLV-C contents of its caboose.
+//
+// This code is very similar to `kipc::read_caboose_pos`, but it operates on
+// the alternate flash bank rather than on the loaded image.
+fn bank2_image() -> Result<(&'static [u8], &'static [u8]), CabooseError> {
+    let image_start = unsafe { __REGION_BANK2_BASE.as_ptr() } as u32;
+
+    // If all is going according to plan, there will be a valid Hubris image
+    // flashed into the other slot, delimited by `__REGION_BANK2_BASE` and
+    // `__REGION_BASE2_END` (which are symbols injected by the linker).
+    let header = bank2_header().ok_or(CabooseError::NoImageHeader)?;
+
+    // Calculate where the image header implies that the image should end
+    //
+    // This is a one-past-the-end value.
+    let image_end = image_start + header.total_image_len;
+
+    // Then, check that value against the BANK2 bounds.
+    //
+    // SAFETY: populated by the linker, so this should be valid
+    if image_end > unsafe { __REGION_BANK2_END.as_ptr() } as u32 {
+        return Err(CabooseError::MissingCaboose);
+    }
+
+    // By construction, the last word of the caboose is its size as a `u32`
+    let caboose_size: u32 =
+        unsafe { core::ptr::read_volatile((image_end - 4) as *const u32) };
+
+    let caboose_start = image_end.saturating_sub(caboose_size);
+    let caboose_range = if caboose_start < image_start {
+        // This branch will be encountered if there's no caboose, because
+        // then the nominal caboose size will be 0xFFFFFFFF, which will send
+        // us out of the bank2 region.
+        return Err(CabooseError::MissingCaboose);
+    } else {
+        // SAFETY: we know this pointer is within the bank2 flash region,
+        // since it's checked above.
+        let v =
+            unsafe { core::ptr::read_volatile(caboose_start as *const u32) };
+        if v == CABOOSE_MAGIC {
+            caboose_start + 4..image_end - 4
+        } else {
+            return Err(CabooseError::MissingCaboose);
+        }
+    };
+
+    // SAFETY: this is a slice within the bank2 flash
+    let caboose = unsafe {
+        core::slice::from_raw_parts(
+            caboose_range.start as *const u8,
+            caboose_range.len(),
+        )
+    };
+
+    // SAFETY: this is a slice within the bank2 flash, as checked above
+    let image = unsafe {
+        core::slice::from_raw_parts(
+            image_start as *const u8,
+            header.total_image_len as usize,
+        )
+    };
+
+    Ok((image, caboose))
+}
+
+/// Reads the header of the image in bank 2, if it has one
+fn bank2_header() -> Option<ImageHeader> {
+    let image_start = unsafe { __REGION_BANK2_BASE.as_ptr() } as u32;
+
+    // The image header is at a fixed location at the end of the vector table.
+    // The length of the vector table is fixed in hardware, so this should
+    // never change.
+    const HEADER_OFFSET: u32 = 0x298;
+    let header: ImageHeader = unsafe {
+        core::ptr::read_volatile(
+            (image_start + HEADER_OFFSET) as *const ImageHeader,
+        )
+    };
+    if header.magic == HEADER_MAGIC {
+        Some(header)
+    } else {
+        None
+    }
+}
+
+/// Returns the address of flash word `word_number` of the image in bank 2,
+/// which must lie before the epoch records.
+fn bank2_word_addr(word_number: usize) -> Result<usize, UpdateError> {
+    // These variables are _philosophically_ constants, but since they're
+    // generated by taking the address of a linker-generated symbol, we
+    // can't define them as `const` values.
+    //
+    // SAFETY: these are symbols populated by the linker.
+    let bank_addr = unsafe { __REGION_BANK2_BASE.as_ptr() } as usize;
+    let bank_end = unsafe { __REGION_BANK2_END.as_ptr() } as usize;
+    let bank_word_limit = (bank_end - bank_addr) / FLASH_WORD_BYTES;
+
+    if word_number > bank_word_limit {
+        panic!();
+    }
+
+    let start = bank_addr + (word_number * FLASH_WORD_BYTES);
+
+    // The image may not overwrite our epoch records.
+    if start + FLASH_WORD_BYTES > bank_end - SECTOR_BYTES {
+        return Err(UpdateError::BadLength);
+    }
+
+    Ok(start)
+}
+
+/// Returns the address of the epoch records in `bank`, its last sector
+fn epoch_records_start(bank: Bank) -> usize {
+    // SAFETY: populated by the linker, so these should be valid
+    unsafe {
+        match bank {
+            Bank::One => __REGION_BANK1_EPOCH_BASE.as_ptr() as usize,
+            Bank::Two => __REGION_BANK2_END.as_ptr() as usize - SECTOR_BYTES,
+        }
+    }
+}
+
+/// Scans the epoch records in `bank`, returning the greatest epoch held in a
+/// valid record (if any) and the index of the first free record (if any).
+fn read_epoch_records(bank: Bank) -> (Option<u32>, Option<usize>) {
+    let start = epoch_records_start(bank);
+    let mut newest = None;
+    for index in 0..EPOCH_RECORDS_PER_SECTOR {
+        // SAFETY: the records are within flash regions we have mapped
+        let words = unsafe {
+            core::ptr::read_volatile(
+                (start + index * FLASH_WORD_BYTES)
+                    as *const [u32; FLASH_WORD_WORDS],
+            )
+        };
+        if words == [u32::MAX; FLASH_WORD_WORDS] {
+            // Records are appended in order, so the rest are free too.
+            return (newest, Some(index));
+        }
+        let [magic, epoch, check, ..] = words;
+        if magic == EPOCH_RECORD_MAGIC && check == !epoch {
+            newest = newest.max(Some(epoch));
+        }
+    }
+    (newest, None)
+}
+
+/// Converts an error finding or reading the caboose of the image in bank 2,
+/// using `missing` if the caboose doesn't have the key we wanted.
+fn caboose_error(e: CabooseError, missing: UpdateError) -> UpdateError {
+    match e {
+        CabooseError::NoSuchTag => missing,
+        CabooseError::NoImageHeader => UpdateError::MissingHeaderBlock,
+        CabooseError::MissingCaboose => UpdateError::MissingImageCaboose,
+        CabooseError::TlvcReaderBeginFailed
+        | CabooseError::TlvcReadExactFailed
+        | CabooseError::BadChecksum
+        | CabooseError::RawReadFailed
+        | CabooseError::InvalidRead => UpdateError::FlashError,
+    }
+}
+
+/// Checks the caboose and signature of the image in bank 2
+fn verify_bank2_image() -> Result<(), UpdateError> {
+    let (image, caboose) = bank2_image()
+        .map_err(|e| caboose_error(e, UpdateError::MissingImageCaboose))?;
+    let reader = CabooseReader::new(caboose);
+
+    // The image must name a board, which must be ours, unless our own image
+    // doesn't say which board we are.
+    let board = reader
+        .get(BOARD_KEY)
+        .map_err(|e| caboose_error(e, UpdateError::ImageBoardUnknown))?;
+    let ours = drv_caboose_pos::CABOOSE_POS
+        .as_slice()
+        .map(CabooseReader::new)
+        .and_then(|reader| reader.get(BOARD_KEY).ok());
+    if !ours.map(|ours| ours == board).unwrap_or(true) {
+        return Err(UpdateError::ImageBoardMismatch);
+    }
+
+    let version = reader
+        .get(VERSION_KEY)
+        .map_err(|e| caboose_error(e, UpdateError::MissingImageVersion))?;
+    if version.is_empty() {
+        return Err(UpdateError::MissingImageVersion);
+    }
+
+    let signature = reader
+        .get(SIGNATURE_KEY)
+        .map_err(|e| caboose_error(e, UpdateError::MissingImageSignature))?;
+    let signature: &[u8; SIGNATURE_LEN] = signature
+        .try_into()
+        .map_err(|_| UpdateError::InvalidImageSignature)?;
+
+    // The `SIGN` chunk is hashed as zeros: its header precedes the signature,
+    // and its body checksum follows it (there's no padding, as the signature
+    // is a whole number of words).
+    let offset = signature.as_ptr() as usize - image.as_ptr() as usize;
+    let chunk_start = offset - core::mem::size_of::<tlvc::ChunkHeader>();
+    let chunk_end = chunk_start + SIGNATURE_CHUNK_LEN;
+
+    let mut hasher = Sha3_256::new();
+    hasher.update(&image[..chunk_start]);
+    hasher.update([0u8; SIGNATURE_CHUNK_LEN]);
+    hasher.update(&image[chunk_end..]);
+    let digest = hasher.finalize();
+
+    let signature = salty::Signature::from(signature);
+    let valid = IMAGE_SIGNING_KEYS.iter().any(|key| {
+        salty::PublicKey::try_from(key)
+            .map(|key| key.verify(&digest, &signature).is_ok())
+            .unwrap_or(false)
+    });
+    if valid {
+        Ok(())
+    } else {
+        Err(UpdateError::InvalidImageSignature)
+    }
+}
+
 #[export_name = "main"]
 fn main() -> ! {
     let flash = unsafe { &*device::FLASH::ptr() };
@@ -514,7 +1040,23 @@ fn main() -> ! {
     let mut server = ServerImpl {
         flash,
         state: UpdateState::NoUpdate,
+        min_epoch: read_epoch_records(Bank::One)
+            .0
+            .max(read_epoch_records(Bank::Two).0)
+            .unwrap_or(0)
+            .max(HUBRIS_BUILD_EPOCH),
+        #[cfg(feature = "hash")]
+        hash: drv_hash_api::Hash::from(HASH.get_task_id()),
+        streamed: StreamedDigest::unavailable(),
     };
+
+    // Make sure both banks' epoch records hold our minimum, in case we lost
+    // power partway through finishing an update (or this is our first boot).
+    // If we can't, we still enforce the minimum we found.
+    if server.sync_epoch_records(server.min_epoch).is_err() {
+        ringbuf_entry!(Trace::EpochSyncFailed);
+    }
+
     let mut incoming = [0u8; idl::INCOMING_SIZE];
 
     loop {
@@ -307,9 +307,53 @@ cfg_if::cfg_if! {
         fn try_read_reset_reason(
             rcc: &device::rcc::RegisterBlock,
         ) -> Option<ResetReason> {
-            // TODO map to ResetReason cases
+            bitflags::bitflags! {
+                // See RM0444 section 5.4.25 (RCC_CSR).
+                #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+                #[repr(transparent)]
+                pub struct ResetFlags: u32 {
+                    const LPWR = 1 << 31;
+                    const WWDG = 1 << 30;
+                    const IWDG = 1 << 29;
+                    const SFT = 1 << 28;
+                    const PWR = 1 << 27;
+                    const PIN = 1 << 26;
+                    const OBL = 1 << 25;
+                }
+            }
+
             let bits = rcc.csr.read().bits();
-            Some(ResetReason::Other(bits))
+            let flags = ResetFlags::from_bits_truncate(bits);
+            if flags.is_empty() {
+                // CSR has been cleared, so we don't know why we most recently
+                // reset.
+                return None;
+            }
+
+            // Unlike the H7, the G0 doesn't document which combinations of
+            // flags each kind of reset produces -- and every internal reset
+            // also drives NRST, setting PIN. So we go by the most specific
+            // flag present.
+            let reason = if flags.contains(ResetFlags::IWDG) {
+                ResetReason::IndependentWatchdog
+            } else if flags.contains(ResetFlags::WWDG) {
+                ResetReason::SystemWatchdog
+            } else if flags.contains(ResetFlags::LPWR) {
+                ResetReason::LowPowerSecurity
+            } else if flags.contains(ResetFlags::SFT) {
+                ResetReason::SystemCall
+            } else if flags.contains(ResetFlags::PWR) {
+                ResetReason::PowerOn
+            } else if flags == ResetFlags::PIN {
+                ResetReason::Pin
+            } else {
+                ResetReason::Other(bits)
+            };
+
+            // Clear CSR.
+            rcc.csr.modify(|_, w| w.rmvf().set_bit());
+
+            Some(reason)
         }
     } else if #[cfg(feature = "family-stm32h7")] {
         fn enable_clock(
@@ -428,16 +472,6 @@ cfg_if::cfg_if! {
                 | ResetFlags::PIN.bits()
                 | ResetFlags::SFT.bits()
             );
-            const WWDG1_RESET: ResetFlags = ResetFlags::from_bits_truncate(
-                ResetFlags::CPU.bits()
-                | ResetFlags::PIN.bits()
-                | ResetFlags::WWDG1.bits()
-            );
-            const IWDG1_RESET: ResetFlags = ResetFlags::from_bits_truncate(
-                ResetFlags::CPU.bits()
-                | ResetFlags::PIN.bits()
-                | ResetFlags::IWDG1.bits()
-            );
             const LOW_POWER_SECURITY_RESET: ResetFlags =
                 ResetFlags::from_bits_truncate(
                     ResetFlags::CPU.bits()
@@ -454,14 +488,22 @@ cfg_if::cfg_if! {
                 return None;
             }
 
+            // The flags pile up until we clear them, so a watchdog that
+            // fires before we've run -- say, because boot wedged -- leaves
+            // its flag on top of whatever reset came before. Don't let that
+            // hide it.
             let flags = ResetFlags::from_bits_truncate(bits);
             let reason = match flags {
+                _ if flags.contains(ResetFlags::IWDG1) => {
+                    ResetReason::IndependentWatchdog
+                }
+                _ if flags.contains(ResetFlags::WWDG1) => {
+                    ResetReason::SystemWatchdog
+                }
                 POWER_ON_RESET => ResetReason::PowerOn,
                 PIN_RESET => ResetReason::Pin,
                 SYSTEM_RESET => ResetReason::SystemCall,
                 BROWNOUT_RESET => ResetReason::Brownout,
-                WWDG1_RESET => ResetReason::SystemWatchdog,
-                IWDG1_RESET => ResetReason::IndependentWatchdog,
                 LOW_POWER_SECURITY_RESET => ResetReason::LowPowerSecurity,
                 ResetFlags::D1 | ResetFlags::D2 => ResetReason::ExitStandby,
                 _ => ResetReason::Other(bits),
@@ -59,6 +59,25 @@ pub enum UpdateError {
     NotImplemented,
 
     MissingHandoffData,
+
+    // Checks made before an image may become the boot image.  An SP image
+    // whose caboose was rewritten after it was signed fails with one of the
+    // signature errors until it's signed again (`cargo xtask sign-sp`).
+    MissingImageVersion,
+    MissingImageSignature,
+    InvalidImageSignature,
+    ImageEpochTooOld,
+
+    // Digest checks made by `finish_image_update_with_digest`
+    ImageDigestUnavailable,
+    ImageDigestMismatch,
+    FlashDigestMismatch,
+
+    MissingImageCaboose,
+
+    // A block was sent again with different contents, over flash that can't
+    // be programmed twice without an erase
+    BlockAlreadyWritten,
 }
 
 impl From<UpdateError> for GwUpdateError {
@@ -90,6 +109,15 @@ impl From<UpdateError> for GwUpdateError {
             UpdateError::TaskRestarted => Self::TaskRestarted,
             UpdateError::NotImplemented => Self::NotImplemented,
             UpdateError::MissingHandoffData => Self::MissingHandoffData,
+            UpdateError::MissingImageVersion => Self::MissingImageVersion,
+            UpdateError::MissingImageSignature => Self::MissingImageSignature,
+            UpdateError::InvalidImageSignature => Self::InvalidImageSignature,
+            UpdateError::ImageEpochTooOld => Self::ImageEpochTooOld,
+            UpdateError::ImageDigestUnavailable => Self::ImageDigestUnavailable,
+            UpdateError::ImageDigestMismatch => Self::ImageDigestMismatch,
+            UpdateError::FlashDigestMismatch => Self::FlashDigestMismatch,
+            UpdateError::MissingImageCaboose => Self::MissingImageCaboose,
+            UpdateError::BlockAlreadyWritten => Self::BlockAlreadyWritten,
         }
     }
 }
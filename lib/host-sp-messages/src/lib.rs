@@ -45,6 +45,19 @@ const CHECKSUM_SIZE: usize = core::mem::size_of::<u16>();
 
 pub mod version {
     pub const V1: u32 = 1;
+    /// `HostToSp::RotRequest` and `HostToSp::RotAddHostMeasurements` are
+    /// forwarded to the RoT, and both are answered with an
+    /// `SpToHost::RotResponse` whose data starts with a `RotResult`.  The
+    /// messages themselves are encoded as in `V1`.
+    pub const V2: u32 = 2;
+
+    /// `HostToSp::GetAlert` is answered with `SpToHost::QueuedAlert` (rather
+    /// than `SpToHost::Alert`).
+    pub const V3: u32 = 3;
+
+    /// The newest version we speak.  The SP accepts requests of any version
+    /// from `V1` up to this one, and replies with the request's version.
+    pub const CURRENT: u32 = V3;
 }
 
 #[derive(
@@ -90,8 +103,14 @@ pub enum HostToSp {
     // Host ack'ing SP task startup.
     AckSpStart,
     GetAlert,
-    RotRequest, // Followed by a binary data blob (the request)
-    RotAddHostMeasurements, // Followed by a binary data blob?
+    // Followed by a binary data blob (the request). As of `version::V2`, that's
+    // a hubpack-serialized `RotRequest`, followed by any data that request
+    // calls for.
+    RotRequest,
+    // Followed by a binary data blob. As of `version::V2`, that's one or more
+    // SHA3-256 digests, each of which is recorded in the RoT's measurement
+    // log, and the SP responds with `RotResponse` (rather than `Ack`).
+    RotAddHostMeasurements,
     /// Get as much phase 2 data as we can from the image identified by `hash`
     /// starting at `offset`.
     GetPhase2Data {
@@ -147,13 +166,15 @@ pub enum SpToHost {
         status: Status,
         startup: HostStartupOptions,
     },
-    // Followed by a binary data blob (the alert), or maybe action is another
-    // hubpack-encoded enum?
+    // Response to `GetAlert` before `version::V3`, which carries no
+    // information; see `QueuedAlert`.
     Alert {
-        // details TBD
         action: u8,
     },
-    // Followed by a binary data blob (the response)
+    // Followed by a binary data blob (the response). As of `version::V2`,
+    // that's a hubpack-serialized `RotResult`, then, if that's `RotResult::Ok`,
+    // whatever the `RotRequest` being answered calls for (if anything); this
+    // also answers `RotAddHostMeasurements`.
     RotResponse,
     // Followed by a binary data blob (the data)
     Phase2Data,
@@ -168,6 +189,16 @@ pub enum SpToHost {
         name: [u8; 32],
     },
     KeySetResult(KeySetResult),
+    // Response to `GetAlert` as of `version::V3`: the oldest alert queued on
+    // the SP (which is removed from the queue), or `None` if the queue is
+    // empty. `remaining` is the number of alerts still queued after this one,
+    // and `dropped` is the number of alerts discarded (oldest first) because
+    // the queue was full since the last `GetAlert`.
+    QueuedAlert {
+        alert: Option<Alert>,
+        remaining: u8,
+        dropped: u8,
+    },
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, num_derive::FromPrimitive)]
@@ -211,6 +242,72 @@ pub enum KeySetResult {
     DataTooLong,
 }
 
+/// Requests that the host can forward to the RoT's attestation task
+///
+/// These **cannot be reordered**; the host and SP must agree on them.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, SerializedSize,
+)]
+pub enum RotRequest {
+    /// Responds with a hubpack-serialized `u32`: the number of certs in the
+    /// alias cert chain
+    CertChainLen,
+    /// Responds with a hubpack-serialized `u32`: the length of the given cert
+    CertLen { index: u32 },
+    /// Responds with the next chunk of the given cert, starting at `offset`;
+    /// the chunk may be shorter than the rest of the cert
+    Cert { index: u32, offset: u32 },
+    /// Responds with a hubpack-serialized `u32`: the length of the
+    /// measurement log
+    LogLen,
+    /// Responds with the next chunk of the measurement log, starting at
+    /// `offset`; the chunk may be shorter than the rest of the log
+    Log { offset: u32 },
+    /// Responds with a hubpack-serialized `u32`: the length of an attestation
+    AttestLen,
+    /// Followed by a nonce; responds with an attestation over the nonce
+    Attest,
+}
+
+/// Results for a request forwarded to the RoT
+///
+/// These **cannot be reordered**; the host and SP must agree on them.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, SerializedSize,
+)]
+pub enum RotResult {
+    Ok,
+    /// The request (or its data) was malformed
+    BadRequest,
+    /// Communication with the RoT failed
+    CommsFailed,
+    /// The RoT's attestation task rejected the request
+    AttestFailed,
+    /// The response wouldn't fit in a message
+    ResponseTooLong,
+}
+
+/// Events originating on the SP that the host may want to react to
+///
+/// The SP queues these and sets [`Status::ALERTS_AVAILABLE`] (interrupting the
+/// host) until the host has drained the queue with `GetAlert`.
+///
+/// These **cannot be reordered**; the host and SP must agree on them.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, SerializedSize,
+)]
+pub enum Alert {
+    /// The sequencer saw THERMTRIP and powered the host off
+    ThermalTrip,
+    /// A power device reported a new fault; `rail` is the voltage sensor of
+    /// the first device found faulted
+    PowerFault { rail: SensorId },
+    /// The RoT will boot from a different image the next time it resets
+    RotUpdatePending,
+    /// The control plane has asked the SP to prepare to reset itself
+    SpResetImminent,
+}
+
 /// Results for an inventory data request
 ///
 /// These **cannot be reordered**; the host and SP must agree on them.
@@ -824,6 +921,14 @@ mod tests {
                 },
             ),
             (0x0c, SpToHost::KeySetResult(KeySetResult::Ok)),
+            (
+                0x0d,
+                SpToHost::QueuedAlert {
+                    alert: None,
+                    remaining: 0,
+                    dropped: 0,
+                },
+            ),
         ] {
             let n = hubpack::serialize(&mut buf[..], &variant).unwrap();
             assert!(n >= 1);
@@ -863,6 +968,74 @@ mod tests {
         }
     }
 
+    #[test]
+    fn rot_response_keeps_v1_encoding() {
+        // V1 hosts expect `RotResponse` to be a bare variant; its result is
+        // carried in the data that follows.
+        let mut buf = [0; SpToHost::MAX_SIZE];
+        let n =
+            hubpack::serialize(&mut buf[..], &SpToHost::RotResponse).unwrap();
+        assert_eq!(&buf[..n], &[0x08]);
+    }
+
+    #[test]
+    fn rot_request_values() {
+        let mut buf = [0; RotRequest::MAX_SIZE];
+
+        for (expected_cmd, variant) in [
+            (0x0, RotRequest::CertChainLen),
+            (0x1, RotRequest::CertLen { index: 0 }),
+            (
+                0x2,
+                RotRequest::Cert {
+                    index: 0,
+                    offset: 0,
+                },
+            ),
+            (0x3, RotRequest::LogLen),
+            (0x4, RotRequest::Log { offset: 0 }),
+            (0x5, RotRequest::AttestLen),
+            (0x6, RotRequest::Attest),
+        ] {
+            let n = hubpack::serialize(&mut buf[..], &variant).unwrap();
+            assert!(n >= 1);
+            assert_eq!(expected_cmd, buf[0]);
+        }
+    }
+
+    #[test]
+    fn rot_result_values() {
+        let mut buf = [0; RotResult::MAX_SIZE];
+
+        for (expected_cmd, variant) in [
+            (0x0, RotResult::Ok),
+            (0x1, RotResult::BadRequest),
+            (0x2, RotResult::CommsFailed),
+            (0x3, RotResult::AttestFailed),
+            (0x4, RotResult::ResponseTooLong),
+        ] {
+            let n = hubpack::serialize(&mut buf[..], &variant).unwrap();
+            assert!(n <= 1);
+            assert_eq!(expected_cmd, buf[0]);
+        }
+    }
+
+    #[test]
+    fn alert_values() {
+        let mut buf = [0; Alert::MAX_SIZE];
+
+        for (expected_cmd, variant) in [
+            (0x0, Alert::ThermalTrip),
+            (0x1, Alert::PowerFault { rail: SensorId(0) }),
+            (0x2, Alert::RotUpdatePending),
+            (0x3, Alert::SpResetImminent),
+        ] {
+            let n = hubpack::serialize(&mut buf[..], &variant).unwrap();
+            assert!(n >= 1);
+            assert_eq!(expected_cmd, buf[0]);
+        }
+    }
+
     #[test]
     fn inventory_data_result_values() {
         let mut buf = [0; InventoryDataResult::MAX_SIZE];
@@ -14,6 +14,7 @@ mod images;
 use lpc55_puf::Puf;
 
 pub mod handoff;
+pub mod wwdt;
 use handoff::Handoff;
 
 use armv8_m_mpu::{disable_mpu, enable_mpu};
@@ -190,6 +191,9 @@ pub fn startup(
 
     handoff.store(&details);
 
+    // Only used if the app.toml sets a kernel `watchdog-ms`.
+    kern::watchdog::configure_watchdog(&wwdt::WATCHDOG);
+
     // This is purposely done as the very last step after all validation
     // and secret clearing has happened
     enable_debug(peripherals);
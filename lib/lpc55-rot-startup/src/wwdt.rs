@@ -0,0 +1,81 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Windowed watchdog (WWDT) hooks for the kernel.
+//!
+//! `startup` hands these to the kernel, which only uses them if the app.toml
+//! sets a kernel `watchdog-ms`.
+//!
+//! We don't use the window: any feed restarts the countdown. Once started,
+//! the WWDT can't be stopped by anything short of a reset, and unlike the
+//! STM32's IWDG it keeps counting while the core is halted by a debugger, so
+//! leave `watchdog-ms` unset in images meant for debugging.
+
+use lpc55_pac as device;
+
+/// The WWDT hooks, in the form the kernel wants them.
+pub static WATCHDOG: kern::watchdog::WatchdogTable =
+    kern::watchdog::WatchdogTable { arm, pet };
+
+/// Frequency of the WWDT's clock after its fixed divide-by-4 prescaler, in
+/// kHz. The clock is fro_1m, which we don't divide any further.
+const CLOCK_KHZ: u32 = 1_000 / 4;
+
+/// Smallest and largest values the timer constant register accepts
+/// (UM11126 section 18.6.2).
+const MIN_TC: u32 = 0xFF;
+const MAX_TC: u32 = 0xFF_FFFF;
+
+/// SYSCON_CLOCK_CTRL bit that enables fro_1m for the WWDT.
+const FRO1MHZ_CLK_ENA: u32 = 1 << 1;
+
+/// SYSCON_AHBCLKCTRL0 bit that clocks the WWDT's registers.
+const AHBCLK_WWDT: u32 = 1 << 22;
+
+// WWDT_MOD bits.
+const MOD_WDEN: u32 = 1 << 0;
+const MOD_WDRESET: u32 = 1 << 1;
+
+// Values written to WWDT_FEED, in this order. See UM11126 section 18.6.3.
+const FEED_1: u32 = 0xAA;
+const FEED_2: u32 = 0x55;
+
+/// Starts the WWDT with (approximately) the given timeout, in milliseconds.
+/// Timeouts beyond what the hardware can count (about 67 seconds) are
+/// clamped.
+pub fn arm(timeout_ms: u32) {
+    // Safety: we only touch the WWDT from the kernel, and only these
+    // functions touch it. The SYSCON registers we modify are only otherwise
+    // touched during startup, which is over by the time the kernel arms us.
+    let syscon = unsafe { &*device::SYSCON::ptr() };
+    let wwdt = unsafe { &*device::WWDT::ptr() };
+
+    syscon
+        .clock_ctrl
+        .modify(|r, w| unsafe { w.bits(r.bits() | FRO1MHZ_CLK_ENA) });
+    // Divide by 1, and release the divider from its reset-time halt.
+    syscon.wdtclkdiv.write(|w| unsafe { w.bits(0) });
+    syscon
+        .ahbclkctrl0
+        .modify(|r, w| unsafe { w.bits(r.bits() | AHBCLK_WWDT) });
+
+    let tc = timeout_ms.saturating_mul(CLOCK_KHZ).clamp(MIN_TC, MAX_TC);
+    wwdt.tc.write(|w| unsafe { w.bits(tc) });
+
+    // Enabling the watchdog doesn't start it; the first feed does.
+    wwdt.mod_
+        .write(|w| unsafe { w.bits(MOD_WDEN | MOD_WDRESET) });
+    pet();
+}
+
+/// Reloads the WWDT counter.
+pub fn pet() {
+    let wwdt = unsafe { &*device::WWDT::ptr() };
+
+    // The two writes must be back to back, without an intervening access to
+    // any WWDT register, or the WWDT resets us; the kernel doesn't let
+    // anything interrupt it here.
+    wwdt.feed.write(|w| unsafe { w.bits(FEED_1) });
+    wwdt.feed.write(|w| unsafe { w.bits(FEED_2) });
+}
@@ -0,0 +1,302 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Authentication of requests from the management network.
+//!
+//! `udprpc` and hiffy's network transport both take requests that can do
+//! nearly anything, so they authenticate them the same way:
+//!
+//! - each request names one of the task's keys (from its `app.toml` config),
+//!   and ends with the HMAC-SHA256 of everything before it under that key;
+//! - each request carries our image ID, and our session ID, which the task
+//!   picks at random when it starts, so requests captured before a restart
+//!   can't be replayed afterwards; and
+//! - each request carries a sequence number, which must be greater than that
+//!   of any request we've previously accepted with the same key in this
+//!   session.
+//!
+//! Replies are signed with the request's key, over the request's HMAC
+//! followed by the reply, so the client can tell that a reply is genuine and
+//! answers its request.
+//!
+//! The wire formats (and so where these fields live in a request) are up to
+//! each task.
+
+#![no_std]
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use unwrap_lite::UnwrapLite;
+
+/// Length of a key, in bytes. This must match `build_util::HMAC_KEY_LEN`,
+/// with which the keys in our config are parsed.
+pub const KEY_LEN: usize = 32;
+
+/// Length of the HMAC-SHA256 tags on requests and replies.
+pub const TAG_SIZE: usize = 32;
+
+pub type Key = [u8; KEY_LEN];
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The authentication fields from a request's header.
+#[derive(Copy, Clone, Debug)]
+pub struct Credentials {
+    /// Index of the key that the request is signed with
+    pub key: u8,
+    pub image_id: u64,
+    pub session: u64,
+    pub sequence: u64,
+}
+
+/// Why a request failed authentication, in the order that we check.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AuthError {
+    /// The request names a key that we don't have
+    BadKey,
+    /// The request's HMAC is wrong
+    BadMac,
+    /// The request's image ID doesn't match ours
+    BadImageId,
+    /// The request's session ID doesn't match ours
+    BadSession,
+    /// The request's sequence number has already been used
+    Replayed,
+}
+
+/// Checks requests against a fixed set of keys, and tracks which sequence
+/// numbers have been used with each.
+pub struct Authenticator<const N: usize> {
+    keys: &'static [Key; N],
+    image_id: u64,
+    session: u64,
+    /// Highest sequence number accepted for each key this session.
+    last_sequence: [Option<u64>; N],
+}
+
+impl<const N: usize> Authenticator<N> {
+    pub fn new(keys: &'static [Key; N], image_id: u64, session: u64) -> Self {
+        Self {
+            keys,
+            image_id,
+            session,
+            last_sequence: [None; N],
+        }
+    }
+
+    pub fn image_id(&self) -> u64 {
+        self.image_id
+    }
+
+    pub fn session(&self) -> u64 {
+        self.session
+    }
+
+    /// Checks a request with the given `credentials`, where `tag` is the
+    /// request's HMAC and `signed` is everything it covers.
+    ///
+    /// Nothing but `BadKey` and `BadMac` is revealed to anyone without the
+    /// key. A request that gets past those uses up its sequence number,
+    /// whether or not it's then executed.
+    pub fn check(
+        &mut self,
+        credentials: &Credentials,
+        signed: &[u8],
+        tag: &[u8],
+    ) -> Result<Verified, AuthError> {
+        let index = usize::from(credentials.key);
+        let key = self.keys.get(index).ok_or(AuthError::BadKey)?;
+
+        let mut mac = new_mac(key);
+        mac.update(signed);
+        if mac.verify_slice(tag).is_err() {
+            return Err(AuthError::BadMac);
+        }
+
+        if credentials.image_id != self.image_id {
+            return Err(AuthError::BadImageId);
+        }
+        if credentials.session != self.session {
+            return Err(AuthError::BadSession);
+        }
+        let last = &mut self.last_sequence[index];
+        if last.map(|l| credentials.sequence <= l).unwrap_or(false) {
+            return Err(AuthError::Replayed);
+        }
+        *last = Some(credentials.sequence);
+
+        Ok(Verified {
+            key,
+            // `verify_slice` only accepts tags of the right length.
+            tag: tag.try_into().unwrap_lite(),
+        })
+    }
+}
+
+/// A request that has passed [`Authenticator::check`].
+pub struct Verified {
+    key: &'static Key,
+    tag: [u8; TAG_SIZE],
+}
+
+impl Verified {
+    /// Returns the tag to append to `reply`, binding it to this request.
+    pub fn sign(&self, reply: &[u8]) -> [u8; TAG_SIZE] {
+        let mut mac = new_mac(self.key);
+        mac.update(&self.tag);
+        mac.update(reply);
+        mac.finalize().into_bytes().into()
+    }
+}
+
+fn new_mac(key: &Key) -> HmacSha256 {
+    // HMAC accepts keys of any length, so this can't fail.
+    HmacSha256::new_from_slice(key).unwrap_lite()
+}
+
+/// An allow-list entry: a task index, and the operations that may be sent to
+/// it (or `None` for all of them).
+pub type Allow = (usize, Option<&'static [u16]>);
+
+/// Checks whether `allowed` lets a request send `op` to the task with index
+/// `task`.
+pub fn is_allowed(allowed: &[Allow], task: usize, op: u16) -> bool {
+    allowed.iter().any(|&(t, ops)| {
+        t == task && ops.map(|ops| ops.contains(&op)).unwrap_or(true)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static KEYS: [Key; 2] = [[1; KEY_LEN], [2; KEY_LEN]];
+    const IMAGE_ID: u64 = 0x1234;
+    const SESSION: u64 = 0x5678;
+
+    fn auth() -> Authenticator<2> {
+        Authenticator::new(&KEYS, IMAGE_ID, SESSION)
+    }
+
+    fn creds(key: u8, sequence: u64) -> Credentials {
+        Credentials {
+            key,
+            image_id: IMAGE_ID,
+            session: SESSION,
+            sequence,
+        }
+    }
+
+    fn tag(key: &Key, signed: &[u8]) -> [u8; TAG_SIZE] {
+        let mut mac = new_mac(key);
+        mac.update(signed);
+        mac.finalize().into_bytes().into()
+    }
+
+    #[test]
+    fn accepts_signed_request() {
+        let mut a = auth();
+        let t = tag(&KEYS[1], b"request");
+        assert!(a.check(&creds(1, 0), b"request", &t).is_ok());
+    }
+
+    #[test]
+    fn rejects_unknown_key() {
+        let mut a = auth();
+        let t = tag(&KEYS[0], b"request");
+        assert_eq!(
+            a.check(&creds(2, 0), b"request", &t).err(),
+            Some(AuthError::BadKey)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_mac() {
+        let mut a = auth();
+        let t = tag(&KEYS[0], b"request");
+        // Right tag, wrong key
+        assert_eq!(
+            a.check(&creds(1, 0), b"request", &t).err(),
+            Some(AuthError::BadMac)
+        );
+        // Right key, altered request
+        assert_eq!(
+            a.check(&creds(0, 0), b"requesT", &t).err(),
+            Some(AuthError::BadMac)
+        );
+        // Truncated tag
+        assert_eq!(
+            a.check(&creds(0, 0), b"request", &t[..16]).err(),
+            Some(AuthError::BadMac)
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_image_and_session() {
+        let mut a = auth();
+        let t = tag(&KEYS[0], b"request");
+        let c = Credentials {
+            image_id: IMAGE_ID + 1,
+            ..creds(0, 0)
+        };
+        assert_eq!(
+            a.check(&c, b"request", &t).err(),
+            Some(AuthError::BadImageId)
+        );
+        let c = Credentials {
+            session: SESSION + 1,
+            ..creds(0, 0)
+        };
+        assert_eq!(
+            a.check(&c, b"request", &t).err(),
+            Some(AuthError::BadSession)
+        );
+        // Neither of those used up the sequence number.
+        assert!(a.check(&creds(0, 0), b"request", &t).is_ok());
+    }
+
+    #[test]
+    fn rejects_replay_per_key() {
+        let mut a = auth();
+        let t0 = tag(&KEYS[0], b"request");
+        let t1 = tag(&KEYS[1], b"request");
+        assert!(a.check(&creds(0, 5), b"request", &t0).is_ok());
+        assert_eq!(
+            a.check(&creds(0, 5), b"request", &t0).err(),
+            Some(AuthError::Replayed)
+        );
+        assert_eq!(
+            a.check(&creds(0, 4), b"request", &t0).err(),
+            Some(AuthError::Replayed)
+        );
+        // The other key has its own sequence.
+        assert!(a.check(&creds(1, 1), b"request", &t1).is_ok());
+        assert!(a.check(&creds(0, 6), b"request", &t0).is_ok());
+    }
+
+    #[test]
+    fn reply_is_bound_to_request() {
+        let mut a = auth();
+        let t = tag(&KEYS[0], b"request");
+        let v = a.check(&creds(0, 0), b"request", &t).unwrap();
+
+        let mut expected = new_mac(&KEYS[0]);
+        expected.update(&t);
+        expected.update(b"reply");
+        expected.verify_slice(&v.sign(b"reply")).unwrap();
+
+        assert_ne!(v.sign(b"reply"), tag(&KEYS[0], b"reply"));
+    }
+
+    #[test]
+    fn allow_list() {
+        static ALLOWED: [Allow; 2] = [(1, None), (3, Some(&[2, 4]))];
+        assert!(is_allowed(&ALLOWED, 1, 0));
+        assert!(is_allowed(&ALLOWED, 1, 99));
+        assert!(is_allowed(&ALLOWED, 3, 4));
+        assert!(!is_allowed(&ALLOWED, 3, 3));
+        assert!(!is_allowed(&ALLOWED, 2, 0));
+        assert!(!is_allowed(&[], 1, 0));
+    }
+}
@@ -0,0 +1,276 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Restart policies, with backoff and crash-loop detection.
+//!
+//! By default, a faulted task with `Disposition::Restart` is restarted
+//! immediately, every time. A task may instead be given a restart policy in
+//! the app.toml (under `[tasks.jefe.config.restart-policies]`), which limits
+//! the number of restarts within a window, delays each successive restart
+//! within that window by an exponentially increasing backoff, and escalates
+//! once the limit has been exceeded.
+//!
+//! Windows are fixed rather than sliding: a window opens at the first fault
+//! after the previous one has elapsed, and lasts `window` ms from there. This
+//! means a task that faults steadily can, at worst, see up to twice
+//! `max_restarts` restarts in `window` ms if its faults straddle the boundary
+//! between two windows, but it keeps the state per task down to a few words.
+//!
+//! This lives outside of jefe so that it can be tested on the host; jefe
+//! services backoff delays from its periodic timer, so in practice they have
+//! a granularity of jefe's `TIMER_INTERVAL`.
+
+#![no_std]
+
+/// What to do when a task exceeds the restarts allowed by its policy.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Escalation {
+    /// Stop restarting the task and hold it at its fault, as if it had been
+    /// configured with `Disposition::Hold`.
+    Hold,
+    /// Reset the system.
+    Reset,
+}
+
+/// Per-task restart policy, generated from the app.toml.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Policy {
+    /// Number of restarts permitted within `window` before escalating.
+    pub max_restarts: u32,
+    /// Length of the window over which restarts are counted, in ms.
+    pub window: u64,
+    /// Delay before the first restart within a window, in ms; this doubles
+    /// with each subsequent restart in the same window. Zero restarts
+    /// immediately.
+    pub backoff: u64,
+    /// Upper bound on the backoff delay, in ms.
+    pub max_backoff: u64,
+    /// Action to take once `max_restarts` is exceeded.
+    pub escalation: Escalation,
+}
+
+/// What the caller should do about a fault.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Action {
+    /// Restart the task right now.
+    Restart,
+    /// Restart the task once the timer reaches the given deadline.
+    RestartAt(u64),
+    /// Leave the task at its fault.
+    Hold,
+    /// Reset the system.
+    Reset,
+}
+
+/// Restart bookkeeping for a single task.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RestartState {
+    /// Total restarts performed by jefe due to faults.
+    restarts: u32,
+    /// Start of the current window.
+    window_start: u64,
+    /// Faults observed since `window_start`.
+    faults_in_window: u32,
+    /// Deadline of a pending (backed off) restart, if any.
+    restart_at: Option<u64>,
+    /// Set when the policy has been exceeded.
+    crash_looping: bool,
+}
+
+impl RestartState {
+    /// Records a fault at time `now`, and decides what to do about it.
+    pub fn fault(&mut self, policy: Option<&Policy>, now: u64) -> Action {
+        let Some(policy) = policy else {
+            // No policy: the historical behavior of restarting immediately.
+            self.restarts = self.restarts.wrapping_add(1);
+            return Action::Restart;
+        };
+
+        if self.faults_in_window == 0
+            || now.saturating_sub(self.window_start) >= policy.window
+        {
+            self.window_start = now;
+            self.faults_in_window = 0;
+        }
+        self.faults_in_window = self.faults_in_window.saturating_add(1);
+
+        if self.faults_in_window > policy.max_restarts {
+            self.crash_looping = true;
+            return match policy.escalation {
+                Escalation::Hold => Action::Hold,
+                Escalation::Reset => Action::Reset,
+            };
+        }
+
+        self.restarts = self.restarts.wrapping_add(1);
+
+        // Double the backoff for every restart beyond the first in this
+        // window, taking care not to shift ourselves into oblivion.
+        let shift = (self.faults_in_window - 1).min(63);
+        let delay = policy
+            .backoff
+            .saturating_mul(1 << shift)
+            .min(policy.max_backoff);
+
+        if delay == 0 {
+            Action::Restart
+        } else {
+            let deadline = now.saturating_add(delay);
+            self.restart_at = Some(deadline);
+            Action::RestartAt(deadline)
+        }
+    }
+
+    /// Returns `true` if a backed-off restart is waiting for the timer.
+    pub fn is_pending(&self) -> bool {
+        self.restart_at.is_some()
+    }
+
+    /// Checks whether a pending restart is due at time `now`. If so, clears it
+    /// and returns `true`; the caller is expected to restart the task.
+    pub fn take_due(&mut self, now: u64) -> bool {
+        match self.restart_at {
+            Some(deadline) if now >= deadline => {
+                self.restart_at = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Forgets any crash loop and pending restart, e.g. because the task has
+    /// been explicitly restarted from outside.
+    pub fn clear(&mut self) {
+        self.faults_in_window = 0;
+        self.restart_at = None;
+        self.crash_looping = false;
+    }
+
+    /// Returns the number of restarts performed due to faults.
+    pub fn restarts(&self) -> u32 {
+        self.restarts
+    }
+
+    /// Returns the number of faults observed in the current window.
+    pub fn faults_in_window(&self) -> u32 {
+        self.faults_in_window
+    }
+
+    /// Returns `true` if the task has exceeded its policy and been escalated.
+    pub fn is_crash_looping(&self) -> bool {
+        self.crash_looping
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: Policy = Policy {
+        max_restarts: 3,
+        window: 1000,
+        backoff: 10,
+        max_backoff: 25,
+        escalation: Escalation::Hold,
+    };
+
+    #[test]
+    fn no_policy_always_restarts() {
+        let mut state = RestartState::default();
+        for i in 0..100 {
+            assert_eq!(state.fault(None, i), Action::Restart);
+        }
+        assert_eq!(state.restarts(), 100);
+        assert!(!state.is_crash_looping());
+        assert!(!state.is_pending());
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_limit() {
+        let mut state = RestartState::default();
+        assert_eq!(state.fault(Some(&POLICY), 100), Action::RestartAt(110));
+        assert!(state.is_pending());
+        assert!(state.take_due(110));
+        assert_eq!(state.fault(Some(&POLICY), 200), Action::RestartAt(220));
+        assert!(state.take_due(220));
+        // 40 ms would exceed max_backoff.
+        assert_eq!(state.fault(Some(&POLICY), 300), Action::RestartAt(325));
+        assert_eq!(state.faults_in_window(), 3);
+        assert_eq!(state.restarts(), 3);
+    }
+
+    #[test]
+    fn zero_backoff_restarts_immediately() {
+        let policy = Policy {
+            backoff: 0,
+            ..POLICY
+        };
+        let mut state = RestartState::default();
+        assert_eq!(state.fault(Some(&policy), 5), Action::Restart);
+        assert!(!state.is_pending());
+    }
+
+    #[test]
+    fn pending_restart_not_due_early() {
+        let mut state = RestartState::default();
+        assert_eq!(state.fault(Some(&POLICY), 0), Action::RestartAt(10));
+        assert!(!state.take_due(9));
+        assert!(state.is_pending());
+        assert!(state.take_due(10));
+        assert!(!state.is_pending());
+        assert!(!state.take_due(11));
+    }
+
+    #[test]
+    fn escalates_past_max_restarts() {
+        let mut state = RestartState::default();
+        for t in 0..3 {
+            assert!(matches!(
+                state.fault(Some(&POLICY), t),
+                Action::RestartAt(_)
+            ));
+        }
+        assert_eq!(state.fault(Some(&POLICY), 3), Action::Hold);
+        assert!(state.is_crash_looping());
+        assert_eq!(state.restarts(), 3);
+
+        let reset = Policy {
+            escalation: Escalation::Reset,
+            ..POLICY
+        };
+        let mut state = RestartState::default();
+        for t in 0..3 {
+            state.fault(Some(&reset), t);
+        }
+        assert_eq!(state.fault(Some(&reset), 3), Action::Reset);
+    }
+
+    #[test]
+    fn window_is_fixed_from_first_fault() {
+        let mut state = RestartState::default();
+        // The first window opens at t = 500...
+        state.fault(Some(&POLICY), 500);
+        state.fault(Some(&POLICY), 1400);
+        assert_eq!(state.faults_in_window(), 2);
+        // ...and ends 1000 ms later, regardless of the fault at 1400.
+        assert_eq!(state.fault(Some(&POLICY), 1500), Action::RestartAt(1510));
+        assert_eq!(state.faults_in_window(), 1);
+        assert!(!state.is_crash_looping());
+    }
+
+    #[test]
+    fn clear_forgets_crash_loop() {
+        let mut state = RestartState::default();
+        for t in 0..4 {
+            state.fault(Some(&POLICY), t);
+        }
+        assert!(state.is_crash_looping());
+        state.clear();
+        assert!(!state.is_crash_looping());
+        assert_eq!(state.faults_in_window(), 0);
+        assert_eq!(state.fault(Some(&POLICY), 10), Action::RestartAt(20));
+        // Total restarts survive clearing.
+        assert_eq!(state.restarts(), 4);
+    }
+}
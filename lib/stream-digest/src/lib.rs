@@ -0,0 +1,291 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Digests of update images, computed as their blocks are written.
+//!
+//! The update servers feed each block they write into a `StreamedDigest`, so
+//! that when an update finishes they can check a caller's digest against what
+//! they were actually sent.  Blocks must arrive in order, with one exception:
+//! a block that has already been hashed may be sent again (say, because the
+//! reply to the first attempt was lost), and is skipped.  The digest can't
+//! account for a re-sent block whose contents differ from the first, so the
+//! update servers also check the digest of what's in flash; correcting a block
+//! means starting the update over.
+//!
+//! Any other block out of order -- one past the end of what's been hashed, or
+//! straddling it -- makes the digest unavailable for the rest of the update.
+//!
+//! Finishing the digest doesn't consume it, so that a caller whose attempt to
+//! finish an update failed for some other reason can try again.
+//!
+//! This lives outside of the update servers so that it can be tested on the
+//! host.
+
+#![no_std]
+
+/// Length of a SHA-256 digest, in bytes
+pub const SHA256_SZ: usize = 32;
+
+/// A SHA-256 hash in progress, in hardware or software.
+pub trait Hasher {
+    /// Granularity of the input, in bytes. Blocks whose length isn't a
+    /// multiple of this can't be hashed.
+    const WORD_BYTES: usize = 1;
+
+    fn update(&mut self, data: &[u8]);
+    fn finish(self) -> [u8; SHA256_SZ];
+}
+
+/// What became of a block passed to `StreamedDigest::block`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Block {
+    /// The block was the next one in the image, and has been hashed.
+    Hashed,
+    /// The block had already been hashed, so was skipped.
+    Repeated,
+    /// The block couldn't be hashed, so the digest is now unavailable.
+    Abandoned,
+    /// The digest was already unavailable.
+    Unavailable,
+}
+
+enum State<H> {
+    Hashing(H),
+    Finished([u8; SHA256_SZ]),
+    Unavailable,
+}
+
+/// Digest of an image, fed with blocks as they're written.
+pub struct StreamedDigest<H> {
+    state: State<H>,
+    /// Number of bytes of the image hashed so far
+    len: usize,
+}
+
+impl<H: Hasher> StreamedDigest<H> {
+    /// Starts a digest of a new image with `hasher`.
+    pub fn new(hasher: H) -> Self {
+        Self {
+            state: State::Hashing(hasher),
+            len: 0,
+        }
+    }
+
+    /// Returns a digest which is unavailable, for when there's no image.
+    pub const fn unavailable() -> Self {
+        Self {
+            state: State::Unavailable,
+            len: 0,
+        }
+    }
+
+    /// Feeds in `data`, which was written at byte `offset` of the image.
+    pub fn block(&mut self, offset: usize, data: &[u8]) -> Block {
+        if let State::Unavailable = self.state {
+            return Block::Unavailable;
+        }
+        let end = offset.saturating_add(data.len());
+        if end <= self.len {
+            return Block::Repeated;
+        }
+        match &mut self.state {
+            State::Hashing(hasher)
+                if offset == self.len && data.len() % H::WORD_BYTES == 0 =>
+            {
+                hasher.update(data);
+                self.len = end;
+                Block::Hashed
+            }
+            _ => {
+                self.abandon();
+                Block::Abandoned
+            }
+        }
+    }
+
+    /// Finishes the digest, if it isn't already, returning it along with the
+    /// number of bytes it covers; or `None` if it's unavailable.
+    pub fn finish(&mut self) -> Option<([u8; SHA256_SZ], usize)> {
+        self.state =
+            match core::mem::replace(&mut self.state, State::Unavailable) {
+                State::Hashing(hasher) => State::Finished(hasher.finish()),
+                state => state,
+            };
+        match self.state {
+            State::Finished(digest) => Some((digest, self.len)),
+            _ => None,
+        }
+    }
+
+    /// Gives up on the digest, dropping the hasher if it's still in use.
+    pub fn abandon(&mut self) {
+        self.state = State::Unavailable;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest, Sha256};
+
+    /// Software hasher, which may be made to take only whole words
+    struct Soft<const WORD_BYTES: usize>(Sha256);
+
+    impl<const WORD_BYTES: usize> Hasher for Soft<WORD_BYTES> {
+        const WORD_BYTES: usize = WORD_BYTES;
+
+        fn update(&mut self, data: &[u8]) {
+            self.0.update(data);
+        }
+
+        fn finish(self) -> [u8; SHA256_SZ] {
+            self.0.finalize().into()
+        }
+    }
+
+    const BLOCK: usize = 512;
+
+    fn image() -> [u8; 3 * BLOCK + 100] {
+        let mut image = [0; 3 * BLOCK + 100];
+        for (i, b) in image.iter_mut().enumerate() {
+            *b = (i * 7 + i / 251) as u8;
+        }
+        image
+    }
+
+    fn start() -> StreamedDigest<Soft<1>> {
+        StreamedDigest::new(Soft(Sha256::new()))
+    }
+
+    fn expected(data: &[u8]) -> [u8; SHA256_SZ] {
+        Sha256::digest(data).into()
+    }
+
+    #[test]
+    fn in_order_blocks_match() {
+        let image = image();
+        let mut d = start();
+        for (i, chunk) in image.chunks(BLOCK).enumerate() {
+            assert_eq!(d.block(i * BLOCK, chunk), Block::Hashed);
+        }
+        assert_eq!(d.finish(), Some((expected(&image), image.len())));
+    }
+
+    #[test]
+    fn resent_block_is_skipped() {
+        let image = image();
+        let mut d = start();
+        let blocks: [usize; 6] = [0, 1, 1, 0, 2, 3];
+        for i in blocks {
+            let chunk = image.chunks(BLOCK).nth(i).unwrap();
+            let want = if i * BLOCK + chunk.len() <= d.len {
+                Block::Repeated
+            } else {
+                Block::Hashed
+            };
+            assert_eq!(d.block(i * BLOCK, chunk), want);
+        }
+        assert_eq!(d.finish(), Some((expected(&image), image.len())));
+    }
+
+    #[test]
+    fn resent_block_keeps_first_contents() {
+        // The digest is of what was first sent; the update servers catch the
+        // difference by reading back flash.
+        let image = image();
+        let mut d = start();
+        assert_eq!(d.block(0, &image[..BLOCK]), Block::Hashed);
+        assert_eq!(d.block(0, &[0xff; BLOCK]), Block::Repeated);
+        assert_eq!(d.block(BLOCK, &image[BLOCK..]), Block::Hashed);
+        assert_eq!(d.finish(), Some((expected(&image), image.len())));
+    }
+
+    #[test]
+    fn block_past_the_end_abandons() {
+        let image = image();
+        let mut d = start();
+        assert_eq!(d.block(0, &image[..BLOCK]), Block::Hashed);
+        assert_eq!(
+            d.block(2 * BLOCK, &image[2 * BLOCK..3 * BLOCK]),
+            Block::Abandoned
+        );
+        // Filling in the gap doesn't bring it back.
+        assert_eq!(
+            d.block(BLOCK, &image[BLOCK..2 * BLOCK]),
+            Block::Unavailable
+        );
+        assert_eq!(d.finish(), None);
+    }
+
+    #[test]
+    fn first_block_out_of_order_abandons() {
+        let image = image();
+        let mut d = start();
+        assert_eq!(d.block(BLOCK, &image[BLOCK..2 * BLOCK]), Block::Abandoned);
+        assert_eq!(d.block(0, &image[..BLOCK]), Block::Unavailable);
+        assert_eq!(d.finish(), None);
+    }
+
+    #[test]
+    fn block_after_short_block_abandons() {
+        let image = image();
+        let mut d = start();
+        assert_eq!(d.block(0, &image[..100]), Block::Hashed);
+        assert_eq!(d.block(BLOCK, &image[BLOCK..2 * BLOCK]), Block::Abandoned);
+        assert_eq!(d.finish(), None);
+    }
+
+    #[test]
+    fn longer_resent_block_abandons() {
+        let image = image();
+        let mut d = start();
+        assert_eq!(d.block(0, &image[..100]), Block::Hashed);
+        assert_eq!(d.block(0, &image[..BLOCK]), Block::Abandoned);
+        assert_eq!(d.finish(), None);
+    }
+
+    #[test]
+    fn partial_words_abandon() {
+        let image = image();
+        let mut d = StreamedDigest::new(Soft::<4>(Sha256::new()));
+        assert_eq!(d.block(0, &image[..BLOCK]), Block::Hashed);
+        assert_eq!(d.block(BLOCK, &image[BLOCK..BLOCK + 6]), Block::Abandoned);
+        assert_eq!(d.finish(), None);
+    }
+
+    #[test]
+    fn finish_can_be_repeated() {
+        let image = image();
+        let mut d = start();
+        assert_eq!(d.block(0, &image), Block::Hashed);
+        let digest = Some((expected(&image), image.len()));
+        assert_eq!(d.finish(), digest);
+        assert_eq!(d.block(0, &image[..BLOCK]), Block::Repeated);
+        assert_eq!(d.finish(), digest);
+    }
+
+    #[test]
+    fn block_after_finish_abandons() {
+        let image = image();
+        let mut d = start();
+        assert_eq!(d.block(0, &image[..BLOCK]), Block::Hashed);
+        assert!(d.finish().is_some());
+        assert_eq!(d.block(BLOCK, &image[BLOCK..]), Block::Abandoned);
+        assert_eq!(d.finish(), None);
+    }
+
+    #[test]
+    fn abandoned_and_unavailable() {
+        let image = image();
+        let mut d = start();
+        assert_eq!(d.block(0, &image[..BLOCK]), Block::Hashed);
+        d.abandon();
+        assert_eq!(d.block(BLOCK, &image[BLOCK..]), Block::Unavailable);
+        assert_eq!(d.finish(), None);
+
+        let mut d = StreamedDigest::<Soft<1>>::unavailable();
+        assert_eq!(d.block(0, &image[..BLOCK]), Block::Unavailable);
+        assert_eq!(d.finish(), None);
+    }
+}
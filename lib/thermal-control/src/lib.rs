@@ -0,0 +1,236 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Fan control algorithms for the `thermal` task
+//!
+//! Each fan zone in a thermal BSP picks one of the algorithms here, through a
+//! `ControllerConfig`.  Every algorithm implements [`Controller`], which
+//! turns a zone's temperature error (and, optionally, its power draw, which
+//! is also recorded in telemetry) into a PWM duty cycle for that zone's fans.  For now, that's only [`Pid`]; a new
+//! algorithm is added as another variant of `ControllerConfig` and
+//! `ZoneController`.
+//!
+//! This lives outside the task so that the same code can be run on the host,
+//! replaying a [`telemetry`] capture to evaluate new tuning offline.
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod telemetry;
+
+/// The inputs to a zone's controller on a single iteration of the loop
+#[derive(Copy, Clone, Debug)]
+pub struct ZoneInput {
+    /// Target margin minus the worst margin across the zone's inputs.  This
+    /// is positive when parts are hotter than we'd like, and very negative if
+    /// there are no active inputs in the zone.
+    pub error: f32,
+
+    /// Total power draw of the zone's power sensors in watts, or `None` if
+    /// the zone has none or they couldn't be read.
+    pub power: Option<f32>,
+}
+
+/// A fan control algorithm
+pub trait Controller {
+    /// Returns the zone's new PWM duty cycle, in the range `0..=100`
+    fn run(&mut self, input: &ZoneInput) -> f32;
+
+    /// Discards accumulated state, e.g. when (re)entering the `Running` state
+    fn reset(&mut self);
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Configuration for a PID controller
+#[derive(Copy, Clone)]
+pub struct PidConfig {
+    pub zero: f32,
+    pub gain_p: f32,
+    pub gain_i: f32,
+    pub gain_d: f32,
+}
+
+/// Contributions of each term to a PID controller's most recent output
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PidTerms {
+    pub p: f32,
+    pub i: f32,
+    pub d: f32,
+}
+
+/// Represents a PID controller that can only push in one direction (i.e. the
+/// output must always be positive).
+#[derive(Default)]
+struct OneSidedPidState {
+    /// Previous (time, input) tuple, for derivative term
+    prev_error: Option<f32>,
+
+    /// Accumulated integral term, pre-multiplied by gain
+    integral: f32,
+
+    /// Terms of the most recent output, for telemetry
+    terms: PidTerms,
+}
+
+impl OneSidedPidState {
+    /// Attempts to drive the error to zero.
+    ///
+    /// The error and output are expected to have the same signs, i.e. a large
+    /// positive error will produce a large positive output.
+    fn run(&mut self, cfg: &PidConfig, error: f32, output_limit: f32) -> f32 {
+        let p_contribution = cfg.gain_p * error;
+
+        // Pre-multiply accumulated integral by gain, to make clamping easier
+        // (this also means we can change the gain_i without glitches)
+        self.integral += error * cfg.gain_i;
+
+        // Calculate the derivative term if there was a previous error
+        let d_contribution = if let Some(prev_error) = self.prev_error {
+            (error - prev_error) * cfg.gain_d
+        } else {
+            0.0
+        };
+        self.prev_error = Some(error);
+
+        // To prevent integral windup, integral term needs to be clamped to values
+        // can effect the output.
+        let out_pd = cfg.zero + p_contribution + d_contribution;
+        let (integral_min, integral_max) = if out_pd > output_limit {
+            (-out_pd, 0.0)
+        } else if out_pd < 0.0 {
+            (0.0, -out_pd + output_limit)
+        } else {
+            (-out_pd, output_limit - out_pd)
+        };
+        self.integral = self.integral.clamp(integral_min, integral_max);
+        self.terms = PidTerms {
+            p: p_contribution,
+            i: self.integral,
+            d: d_contribution,
+        };
+
+        // Clamp output values to valid range.
+        let out = out_pd + self.integral;
+        out.clamp(0.0, output_limit)
+    }
+}
+
+/// Closed-loop control on the zone's worst-case margin
+pub struct Pid {
+    config: PidConfig,
+    state: OneSidedPidState,
+}
+
+impl Pid {
+    fn new(config: PidConfig) -> Self {
+        Self {
+            config,
+            state: OneSidedPidState::default(),
+        }
+    }
+
+    fn set_config(&mut self, config: PidConfig) {
+        // If the incoming integral gain is zero, then it will never be able
+        // to wind down the integral accumulator (which is pre-multiplied),
+        // so clear it here.
+        if config.gain_i == 0.0 {
+            self.state.integral = 0.0;
+        }
+        self.config = config;
+    }
+}
+
+impl Controller for Pid {
+    fn run(&mut self, input: &ZoneInput) -> f32 {
+        self.state.run(&self.config, input.error, 100.0)
+    }
+
+    fn reset(&mut self) {
+        self.state = OneSidedPidState::default();
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Selects the control algorithm for a zone, along with its tuning
+#[derive(Copy, Clone)]
+pub enum ControllerConfig {
+    Pid(PidConfig),
+}
+
+/// Any of our controllers, so that zones can be stored in an array
+pub enum ZoneController {
+    Pid(Pid),
+}
+
+impl ZoneController {
+    pub fn new(config: &ControllerConfig) -> Self {
+        match *config {
+            ControllerConfig::Pid(c) => Self::Pid(Pid::new(c)),
+        }
+    }
+
+    /// Updates the PID tuning of this controller, if it has a PID loop.
+    ///
+    /// Returns `false` if it doesn't.
+    pub fn set_pid(&mut self, config: PidConfig) -> bool {
+        match self {
+            Self::Pid(p) => p.set_config(config),
+        }
+        true
+    }
+
+    /// Returns the PID terms behind the most recent output, which are all
+    /// zero for controllers without a PID loop
+    pub fn pid_terms(&self) -> PidTerms {
+        match self {
+            Self::Pid(p) => p.state.terms,
+        }
+    }
+}
+
+impl Controller for ZoneController {
+    fn run(&mut self, input: &ZoneInput) -> f32 {
+        match self {
+            Self::Pid(c) => c.run(input),
+        }
+    }
+
+    fn reset(&mut self) {
+        match self {
+            Self::Pid(c) => c.reset(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PID: PidConfig = PidConfig {
+        zero: 35.0,
+        gain_p: 1.75,
+        gain_i: 0.0135,
+        gain_d: 0.4,
+    };
+
+    #[test]
+    fn pid_output_is_clamped() {
+        let mut c = ZoneController::new(&ControllerConfig::Pid(PID));
+        for _ in 0..1000 {
+            let out = c.run(&ZoneInput {
+                error: 100.0,
+                power: None,
+            });
+            assert_eq!(out, 100.0);
+        }
+        // The integral mustn't have wound up past what it takes to saturate
+        // the output, so it should drop off as soon as we're cool.
+        let out = c.run(&ZoneInput {
+            error: -100.0,
+            power: None,
+        });
+        assert_eq!(out, 0.0);
+    }
+}
@@ -0,0 +1,107 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Control loop telemetry
+//!
+//! When configured to, the `thermal` task records every iteration of its
+//! control loop, and `Thermal::get_telemetry` copies these records out.  Each
+//! record is a [`RecordHeader`], followed by one margin per temperature input
+//! and then one [`ZoneRecord`] per fan zone; the header gives both counts, so
+//! a stream of records can be parsed without knowing which board it came from.
+//!
+//! All values are little-endian.
+
+use zerocopy::{
+    AsBytes, FromBytes, LayoutVerified, LittleEndian, F32, U32, U64,
+};
+
+/// Values of [`RecordHeader::state`], matching `ThermalAutoState`
+pub mod state {
+    pub const BOOT: u8 = 0;
+    pub const RUNNING: u8 = 1;
+    pub const OVERHEATED: u8 = 2;
+    pub const UNCONTROLLABLE: u8 = 3;
+}
+
+#[derive(AsBytes, FromBytes, Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct RecordHeader {
+    /// Sequence number of this iteration, counting from zero when the task
+    /// started
+    pub seq: U32<LittleEndian>,
+    /// Time at which the iteration ran, in milliseconds since boot
+    pub time: U64<LittleEndian>,
+    /// State of the control loop *after* this iteration
+    pub state: u8,
+    /// Number of margins that follow this header
+    pub ninputs: u8,
+    /// Number of `ZoneRecord`s that follow the margins
+    pub nzones: u8,
+    pub _reserved: u8,
+    /// Target margin at the time
+    pub target_margin: F32<LittleEndian>,
+}
+
+/// What a zone's controller saw and did on one iteration
+#[derive(AsBytes, FromBytes, Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct ZoneRecord {
+    /// `ZoneInput::error`, which is NaN if the controller didn't run
+    pub error: F32<LittleEndian>,
+    /// `ZoneInput::power`, or NaN if it was `None`
+    pub power: F32<LittleEndian>,
+    /// PID terms behind `pwm` (see `PidTerms`)
+    pub p: F32<LittleEndian>,
+    pub i: F32<LittleEndian>,
+    pub d: F32<LittleEndian>,
+    /// PWM duty cycle the zone's fans were set to
+    pub pwm: u8,
+    pub _reserved: [u8; 3],
+}
+
+/// Returns the size in bytes of a record with the given number of inputs and
+/// zones
+pub const fn record_size(ninputs: usize, nzones: usize) -> usize {
+    core::mem::size_of::<RecordHeader>()
+        + ninputs * core::mem::size_of::<F32<LittleEndian>>()
+        + nzones * core::mem::size_of::<ZoneRecord>()
+}
+
+/// A record parsed out of a stream
+pub struct Record<'a> {
+    pub header: &'a RecordHeader,
+    /// Worst-case margin of each temperature input, in the order they appear
+    /// in the BSP (static inputs, then dynamic inputs).  This is NaN for
+    /// inputs that were inactive or hadn't reported in yet.
+    pub margins: &'a [F32<LittleEndian>],
+    pub zones: &'a [ZoneRecord],
+}
+
+impl<'a> Record<'a> {
+    /// Parses the record at the start of `bytes`, returning it and the rest
+    /// of the bytes; returns `None` if `bytes` is too short.
+    pub fn parse(bytes: &'a [u8]) -> Option<(Self, &'a [u8])> {
+        let (header, rest) =
+            LayoutVerified::<_, RecordHeader>::new_from_prefix(bytes)?;
+        let header = header.into_ref();
+        let (margins, rest) =
+            LayoutVerified::<_, [F32<LittleEndian>]>::new_slice_from_prefix(
+                rest,
+                header.ninputs.into(),
+            )?;
+        let (zones, rest) =
+            LayoutVerified::<_, [ZoneRecord]>::new_slice_from_prefix(
+                rest,
+                header.nzones.into(),
+            )?;
+        Some((
+            Self {
+                header,
+                margins: margins.into_slice(),
+                zones: zones.into_slice(),
+            },
+            rest,
+        ))
+    }
+}
@@ -31,6 +31,10 @@ pub struct Task<T = ordered_toml::Value> {
     #[serde(default)]
     pub copy_to_archive: Vec<String>,
 
+    /// Kernel locks which this task may acquire
+    #[serde(default)]
+    pub locks: Vec<String>,
+
     /// Memory regions which should be mapped as accessible to this task
     #[serde(default)]
     pub extern_regions: Vec<String>,
@@ -82,6 +86,21 @@ impl<T> Task<T> {
     }
 }
 
+/// Returns the names of all kernel locks used by `tasks`, in lock index order.
+///
+/// Locks exist by virtue of being named in at least one task's `locks` list,
+/// and are numbered in sorted order of name. Both the kernel configuration and
+/// the constants generated for tasks are derived from this, so they agree.
+pub fn all_locks<'a, T: 'a>(
+    tasks: impl IntoIterator<Item = &'a Task<T>>,
+) -> Vec<&'a str> {
+    let names: std::collections::BTreeSet<&str> = tasks
+        .into_iter()
+        .flat_map(|t| t.locks.iter().map(String::as_str))
+        .collect();
+    names.into_iter().collect()
+}
+
 /// In the common case, task slots map back to a task of the same name (e.g.
 /// `gpio_driver`, `rcc_driver`).  However, certain tasks need generic task
 /// slot names, e.g. they'll have a task slot named `spi_driver` which will
@@ -284,6 +284,9 @@ pub enum SchedState {
     /// This task is blocked waiting for messages, either from any source
     /// (`None`) or from a particular sender only.
     InRecv(Option<TaskId>),
+    /// This task is blocked waiting to acquire the kernel lock with the given
+    /// index.
+    InLock(u16),
 }
 
 impl From<SchedState> for TaskState {
@@ -389,6 +392,13 @@ pub enum UsageError {
     BadKernelMessage,
     BadReplyFaultReason,
     NotSupervisor,
+    /// A program named a kernel lock that doesn't exist, or that it isn't
+    /// permitted to use.
+    NoLock,
+    /// A program misused a kernel lock: by releasing a lock it doesn't hold,
+    /// acquiring one it already holds, acquiring one in a way that would
+    /// deadlock, or specifying an undefined operation.
+    BadLockOperation,
 }
 
 /// Origin of a fault.
@@ -446,6 +456,7 @@ pub enum Sysnum {
     RefreshTaskId = 10,
     Post = 11,
     ReplyFault = 12,
+    Lock = 13,
 }
 
 /// We're using an explicit `TryFrom` impl for `Sysnum` instead of
@@ -469,6 +480,34 @@ impl core::convert::TryFrom<u32> for Sysnum {
             10 => Ok(Self::RefreshTaskId),
             11 => Ok(Self::Post),
             12 => Ok(Self::ReplyFault),
+            13 => Ok(Self::Lock),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Requests that a task in a hosted (simulated) image can make of the
+/// simulator itself. These share the syscall number space, well above any
+/// real `Sysnum`, and have no equivalent on hardware; a hardware kernel treats
+/// them as bogus syscall numbers.
+#[repr(u32)]
+pub enum Hostcall {
+    /// Writes the bytes at (`arg0`, `arg1`) to the simulator's console.
+    Write = 0x8000_0000,
+    /// Ends the simulation, with `arg0` as the process exit status.
+    Exit = 0x8000_0001,
+    /// Blocks until the scheduler next runs; the simulator's `WFI`.
+    WaitForInterrupt = 0x8000_0002,
+}
+
+impl core::convert::TryFrom<u32> for Hostcall {
+    type Error = ();
+
+    fn try_from(x: u32) -> Result<Self, Self::Error> {
+        match x {
+            0x8000_0000 => Ok(Self::Write),
+            0x8000_0001 => Ok(Self::Exit),
+            0x8000_0002 => Ok(Self::WaitForInterrupt),
             _ => Err(()),
         }
     }
@@ -481,6 +520,27 @@ pub struct TaskDumpRegion {
     pub size: u32,
 }
 
+/// CPU time accounting for a task, as returned by the `ReadTaskAccounting`
+/// kipc. This is only available if the kernel is built with the `accounting`
+/// feature.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct TaskAccounting {
+    /// Total time the task has spent running, in units of the kernel's cycle
+    /// counter (CPU cycles, on ARM).
+    pub run_time: u64,
+    /// Number of times the task has been switched in.
+    pub switches: u32,
+}
+
+/// Stack usage of a task, as returned by the `ReadTaskStackUsage` kipc.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct StackUsage {
+    /// Size of the task's stack, in bytes.
+    pub size: u32,
+    /// Deepest stack use observed since the task was last started, in bytes.
+    pub high_water: u32,
+}
+
 /// Representation of kipc numbers
 pub enum Kipcnum {
     ReadTaskStatus = 1,
@@ -490,6 +550,10 @@ pub enum Kipcnum {
     Reset = 5,
     GetTaskDumpRegion = 6,
     ReadTaskDumpRegion = 7,
+    FindFaultedTask = 8,
+    ReadTaskAccounting = 9,
+    ReadTaskStackUsage = 10,
+    PetWatchdog = 11,
 }
 
 impl core::convert::TryFrom<u16> for Kipcnum {
@@ -504,6 +568,10 @@ impl core::convert::TryFrom<u16> for Kipcnum {
             5 => Ok(Self::Reset),
             6 => Ok(Self::GetTaskDumpRegion),
             7 => Ok(Self::ReadTaskDumpRegion),
+            8 => Ok(Self::FindFaultedTask),
+            9 => Ok(Self::ReadTaskAccounting),
+            10 => Ok(Self::ReadTaskStackUsage),
+            11 => Ok(Self::PetWatchdog),
             _ => Err(()),
         }
     }
@@ -15,7 +15,10 @@ use indexmap::IndexMap;
 use proc_macro2::TokenStream;
 
 fn main() -> Result<()> {
-    build_util::expose_m_profile();
+    // The hosted simulator has no M-profile to expose.
+    if build_util::target_os() == "none" {
+        build_util::expose_m_profile();
+    }
 
     let g = process_config()?;
     generate_statics(&g)?;
@@ -27,6 +30,8 @@ struct Generated {
     tasks: Vec<TokenStream>,
     regions: Vec<TokenStream>,
     irq_code: TokenStream,
+    lock_count: usize,
+    watchdog_ms: Option<u32>,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
@@ -167,6 +172,13 @@ fn process_config() -> Result<Generated> {
         } else {
             quote::quote! { TaskFlags::empty() }
         };
+        let mut locks = 0u32;
+        for &lock in &task.locks {
+            if lock >= kconfig.locks.len() || lock >= 32 {
+                bail!("task {i} uses unknown lock {lock}");
+            }
+            locks |= 1 << lock;
+        }
         task_descs.push(quote::quote! {
             TaskDesc {
                 regions: [#(&HUBRIS_REGION_DESCS[#regions]),*],
@@ -175,6 +187,7 @@ fn process_config() -> Result<Generated> {
                 priority: #priority,
                 index: #index,
                 flags: #flags,
+                locks: #locks,
             }
         });
     }
@@ -246,7 +259,11 @@ fn process_config() -> Result<Generated> {
     } else if target.starts_with("thumbv7m")
         || target.starts_with("thumbv7em")
         || target.starts_with("thumbv8m")
+        || target.starts_with("i686")
     {
+        // The hosted simulator has hardware division too, so it shares this
+        // path.
+        //
         // First, try to build it as a single-level perfect hash map, which is
         // cheaper but won't always succeed.
         let map1 = if let Ok(task_irq_map) =
@@ -319,6 +336,8 @@ fn process_config() -> Result<Generated> {
         tasks: task_descs,
         regions: region_descs,
         irq_code,
+        lock_count: kconfig.locks.len(),
+        watchdog_ms: kconfig.watchdog_ms,
     })
 }
 
@@ -402,11 +421,18 @@ fn generate_statics(gen: &Generated) -> Result<()> {
     // Basic constants and empty space
 
     let task_count = gen.tasks.len();
+    let lock_count = gen.lock_count;
+    let watchdog_ms = match gen.watchdog_ms {
+        Some(ms) => quote::quote! { Some(#ms) },
+        None => quote::quote! { None },
+    };
     writeln!(
         file,
         "{}",
         quote::quote! {
-            const HUBRIS_TASK_COUNT: usize = #task_count;
+            pub(crate) const HUBRIS_TASK_COUNT: usize = #task_count;
+            pub(crate) const HUBRIS_LOCK_COUNT: usize = #lock_count;
+            pub(crate) const HUBRIS_WATCHDOG_MS: Option<u32> = #watchdog_ms;
             #[no_mangle]
             pub static HUBRIS_IMAGE_ID: u64 = #image_id;
 
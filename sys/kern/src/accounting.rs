@@ -0,0 +1,116 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Per-task CPU time accounting.
+//!
+//! When the kernel is built with the `accounting` feature, it keeps a running
+//! total of the time each task has spent on the CPU, and the number of times
+//! each task has been switched in. Any task can read these numbers using the
+//! `ReadTaskAccounting` kipc; the intent is to support a `top`-like view of
+//! the system, and in particular to find tasks that are starving the idle
+//! task.
+//!
+//! Time is measured using `arch::cycle_count`, a free-running 32-bit counter
+//! whose units depend on the architecture (CPU cycles on ARMv7-M and ARMv8-M;
+//! ARMv6-M has no such counter and can't use this feature). Time is charged to
+//! the current task whenever we switch tasks, and also on every kernel tick,
+//! so that the counter can't wrap unnoticed while a task runs for a long time.
+//! Time spent in the kernel or in interrupt handlers is charged to whichever
+//! task was interrupted.
+//!
+//! Totals are indexed by task index and are _not_ cleared when a task is
+//! restarted.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::arch;
+use crate::startup::HUBRIS_TASK_COUNT;
+
+/// Accumulated accounting for a single task.
+///
+/// As with the kernel's `TICKS`, these are atomics only for their interior
+/// mutability: we access them only from contexts where we can't be preempted,
+/// so splitting `time` across two words is ok.
+struct Account {
+    /// Total time spent running, least significant word first.
+    time: [AtomicU32; 2],
+    /// Number of times the task has been switched in.
+    switches: AtomicU32,
+}
+
+impl Account {
+    fn add_time(&self, elapsed: u32) {
+        let lo = self.time[0].load(Ordering::Relaxed);
+        let (lo, carry) = lo.overflowing_add(elapsed);
+        self.time[0].store(lo, Ordering::Relaxed);
+        if carry {
+            let hi = self.time[1].load(Ordering::Relaxed);
+            self.time[1].store(hi.wrapping_add(1), Ordering::Relaxed);
+        }
+    }
+}
+
+static ACCOUNTS: [Account; HUBRIS_TASK_COUNT] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: Account = Account {
+        time: [AtomicU32::new(0), AtomicU32::new(0)],
+        switches: AtomicU32::new(0),
+    };
+    [ZERO; HUBRIS_TASK_COUNT]
+};
+
+/// Index of the task currently being charged. This starts out out of range,
+/// so that time before the first task starts isn't charged to anyone.
+static CHARGING: AtomicU32 = AtomicU32::new(u32::MAX);
+
+/// Value of `arch::cycle_count` when we last charged a task.
+static LAST_CHARGE: AtomicU32 = AtomicU32::new(0);
+
+/// Charges the time elapsed since the last call to the current task.
+fn charge() {
+    let now = arch::cycle_count();
+    let elapsed = now.wrapping_sub(LAST_CHARGE.load(Ordering::Relaxed));
+    LAST_CHARGE.store(now, Ordering::Relaxed);
+
+    let current = CHARGING.load(Ordering::Relaxed) as usize;
+    if let Some(account) = ACCOUNTS.get(current) {
+        account.add_time(elapsed);
+    }
+}
+
+/// Notes that the task with the given index is about to become current. This
+/// should be called by the architecture's `set_current_task`.
+pub fn switch_to(index: usize) {
+    charge();
+    if CHARGING.load(Ordering::Relaxed) as usize != index {
+        CHARGING.store(index as u32, Ordering::Relaxed);
+        let switches = &ACCOUNTS[index].switches;
+        switches.store(
+            switches.load(Ordering::Relaxed).wrapping_add(1),
+            Ordering::Relaxed,
+        );
+    }
+}
+
+/// Keeps the current task's total up to date. This should be called on every
+/// kernel tick.
+pub fn tick() {
+    charge();
+}
+
+/// Returns the accounting totals for the task with the given index, which
+/// must be in range.
+pub fn read(index: usize) -> abi::TaskAccounting {
+    // Bring the current task's total up to date, so the caller sees its own
+    // time accurately.
+    charge();
+
+    let account = &ACCOUNTS[index];
+    let lo = account.time[0].load(Ordering::Relaxed);
+    let hi = account.time[1].load(Ordering::Relaxed);
+    abi::TaskAccounting {
+        run_time: u64::from(hi) << 32 | u64::from(lo),
+        switches: account.switches.load(Ordering::Relaxed),
+    }
+}
@@ -268,6 +268,10 @@ const INITIAL_FPSCR: u32 = 0;
 /// that support it) (and that bit 6 and bit 0 can always be set).
 const EXC_RETURN_CONST: u32 = 0xFFFFFFED;
 
+/// Pattern painted over unused task stack by `reinitialize`, so that we can
+/// later find out how deep the stack has gone.
+const STACK_CANARY: u32 = 0xbaddcafe;
+
 // Because debuggers need to know the clock frequency to set the SWO clock
 // scaler that enables ITM, and because ITM is particularly useful when
 // debugging boot failures, this should be set as early in boot as it can
@@ -310,7 +314,7 @@ pub fn reinitialize(task: &mut task::Task) {
 
         let zap = task.try_write(&mut uslice).unwrap_lite();
         for word in zap.iter_mut() {
-            *word = 0xbaddcafe;
+            *word = STACK_CANARY;
         }
     }
 
@@ -336,6 +340,38 @@ pub fn reinitialize(task: &mut task::Task) {
     task.save_mut().exc_return = EXC_RETURN_CONST;
 }
 
+/// Measures the stack usage of `task`, by finding the deepest word that no
+/// longer holds the `STACK_CANARY` painted by `reinitialize`.
+pub fn stack_usage(task: &task::Task) -> abi::StackUsage {
+    let initial_stack = task.descriptor().initial_stack as usize;
+    let Some(region) = task
+        .region_table()
+        .iter()
+        .find(|region| region.contains(initial_stack))
+    else {
+        // No stack region means no stack to measure.
+        return abi::StackUsage::default();
+    };
+
+    let size = initial_stack - region.base as usize;
+    let uslice: USlice<u32> =
+        USlice::from_raw(region.base as usize, size >> 2).unwrap_lite();
+    let stack = task.try_read(&uslice).unwrap_lite();
+
+    // The stack grows down, so the first word (from the bottom) that's been
+    // disturbed marks the high-water mark. The initial exception frame is
+    // never painted, so we'll always find something.
+    let untouched = stack
+        .iter()
+        .take_while(|&&word| word == STACK_CANARY)
+        .count();
+
+    abi::StackUsage {
+        size: size as u32,
+        high_water: (size - untouched * 4) as u32,
+    }
+}
+
 #[cfg(any(armv6m, armv7m))]
 pub fn apply_memory_protection(task: &task::Task) {
     // We are manufacturing authority to interact with the MPU here, because we
@@ -624,6 +660,19 @@ pub fn start_first_task(tick_divisor: u32, task: &mut task::Task) -> ! {
         // Enable counter and interrupt.
         syst.csr.modify(|v| v | 0b111);
     }
+
+    // Start the cycle counter used for task accounting. (This can't be done on
+    // ARMv6-M, but `cycle_count` will have already complained about that.)
+    #[cfg(all(feature = "accounting", any(armv7m, armv8m)))]
+    // Safety: this only turns on a counter; it's unsafe in API only.
+    unsafe {
+        const DEMCR_TRCENA: u32 = 1 << 24;
+        const DWT_CYCCNTENA: u32 = 1 << 0;
+        let dcb = &*cortex_m::peripheral::DCB::PTR;
+        dcb.demcr.modify(|v| v | DEMCR_TRCENA);
+        let dwt = &*cortex_m::peripheral::DWT::PTR;
+        dwt.ctrl.modify(|v| v | DWT_CYCCNTENA);
+    }
     // We are manufacturing authority to interact with the MPU here, because we
     // can't thread a cortex-specific peripheral through an
     // architecture-independent API. This approach might bear revisiting later.
@@ -642,6 +691,8 @@ pub fn start_first_task(tick_divisor: u32, task: &mut task::Task) -> ! {
     }
 
     CURRENT_TASK_PTR.store(task, Ordering::Relaxed);
+    #[cfg(feature = "accounting")]
+    crate::accounting::switch_to(usize::from(task.descriptor().index));
 
     extern "C" {
         // Exposed by the linker script.
@@ -892,6 +943,8 @@ pub unsafe extern "C" fn SVCall() {
 pub unsafe fn set_current_task(task: &mut task::Task) {
     CURRENT_TASK_PTR.store(task, Ordering::Relaxed);
     crate::profiling::event_context_switch(task as *mut _ as usize);
+    #[cfg(feature = "accounting")]
+    crate::accounting::switch_to(usize::from(task.descriptor().index));
 }
 
 /// Reads the tick counter.
@@ -918,12 +971,28 @@ static TICKS: [AtomicU32; 2] = {
     [ZERO; 2]
 };
 
+/// Reads the free-running cycle counter used for task accounting.
+#[cfg(feature = "accounting")]
+pub fn cycle_count() -> u32 {
+    cfg_if::cfg_if! {
+        if #[cfg(armv6m)] {
+            compile_error!("ARMv6-M has no cycle counter for accounting");
+        } else {
+            // Safety: this is a read of a read-only-to-us register, which
+            // `start_first_task` has enabled.
+            unsafe { (*cortex_m::peripheral::DWT::PTR).cyccnt.read() }
+        }
+    }
+}
+
 /// Handler that gets linked into the vector table for the System Tick Timer
 /// overflow interrupt. (Name is dictated by the `cortex_m` crate.)
 #[allow(non_snake_case)]
 #[no_mangle]
 pub unsafe extern "C" fn SysTick() {
     crate::profiling::event_timer_isr_enter();
+    #[cfg(feature = "accounting")]
+    crate::accounting::tick();
     with_task_table(|tasks| {
         // Load the time before this tick event.
         let t0 = TICKS[0].load(Ordering::Relaxed);
@@ -0,0 +1,806 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Architecture support for running the kernel as a hosted simulation.
+//!
+//! This backend lets an image boot as an ordinary (32-bit) Linux process, so
+//! that the portable parts of the kernel -- syscalls, IPC, the scheduler,
+//! timers, fault handling -- can be exercised in CI and under a normal
+//! debugger without any hardware attached.
+//!
+//! # Images
+//!
+//! Tasks are built for `i686-unknown-linux-gnu` but linked exactly as they
+//! would be for hardware: as freestanding static images at the addresses the
+//! build system allocated for them. The kernel is the host executable, and is
+//! handed the combined task image (`final.elf`) through `load_image` before
+//! `start_kernel` is called. When a task is first initialized, we map each of
+//! its regions at its real address and copy in the image contents, so task
+//! addresses, region tables, leases and `Task::can_access` all work exactly as
+//! they do on hardware.
+//!
+//! # Tasks are threads
+//!
+//! Each task runs on its own host thread, created when the task is first
+//! initialized and kept for the life of the process. The thread runs user code
+//! on the task's own stack (in task RAM), and switches back to the thread's
+//! host stack whenever it enters the kernel.
+//!
+//! The kernel itself does not have a thread; instead, it runs on whichever
+//! thread entered it (a task making a syscall, the tick thread, or a simulated
+//! interrupt source), with the "Big Kernel Lock" `KERNEL` held. This preserves
+//! the kernel's non-preemptive execution model: only one kernel entry is ever
+//! in progress at a time, so `with_task_table` never observes contention.
+//!
+//! A task thread only executes user code while it is the current task (as
+//! recorded in `CURRENT_TASK_PTR`). When a task enters the kernel and the
+//! kernel decides to switch away, the thread parks on `SCHEDULE` until it's
+//! chosen again.
+//!
+//! # Preemption
+//!
+//! We can't yank a host thread off the CPU at an arbitrary instruction, so
+//! preemption in this backend is *lazy*: when the tick thread (or an
+//! interrupt) selects a new task, the new task's thread is released
+//! immediately, but the preempted thread keeps running user code until its
+//! next kernel entry, where it parks until it's rescheduled. Because tasks
+//! share no memory except through the kernel, this is indistinguishable from
+//! real preemption as far as any task can observe -- it just costs host CPU
+//! time.
+//!
+//! # Memory protection
+//!
+//! All tasks share the host address space, so there is no hardware MPU to
+//! program. Kernel-mediated accesses (message copies, leases, etc.) are still
+//! checked against each task's region table via `Task::can_access`, exactly
+//! as on hardware. Direct accesses by task code to another task's memory are
+//! not caught, and accesses outside any mapped region kill the simulator with
+//! `SIGSEGV` rather than producing a task fault.
+//!
+//! # Task restart
+//!
+//! Host threads can't be reset in place, but they don't need to be. When a
+//! task is reinitialized we bump a generation counter in its `SavedState`.
+//! The next time the task's thread enters the kernel or is woken, it notices
+//! that its generation is stale, abandons everything on both its stacks, and
+//! starts over from the task entry point on a fresh host stack frame. Because
+//! of lazy preemption, a task restarted while its thread is still running
+//! user code keeps running until its next syscall.
+//!
+//! # Host calls
+//!
+//! Syscall numbers from `abi::Hostcall::Write` upward are reserved for talking
+//! to the simulator itself: writing to the console, ending the simulation, and
+//! idling until something happens. These have no hardware equivalent; they
+//! exist so that a test image can report results and exit with a status.
+
+use std::cell::Cell;
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicPtr, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use abi::{FaultInfo, Hostcall};
+
+use crate::descs::RegionAttributes;
+use crate::startup::with_task_table;
+use crate::task;
+use crate::time::Timestamp;
+use crate::umem::USlice;
+
+macro_rules! uassert {
+    ($cond : expr) => {
+        if !$cond {
+            panic!("Assertion failed!");
+        }
+    };
+}
+
+/// Pointer to the current task, as on ARM. Only modified with `KERNEL` held.
+static CURRENT_TASK_PTR: AtomicPtr<task::Task> =
+    AtomicPtr::new(core::ptr::null_mut());
+
+/// Frequency of the simulated core clock. The simulated core runs off the
+/// host's monotonic clock, so one cycle is one nanosecond.
+const CORE_CLOCK_KHZ: u64 = 1_000_000;
+
+/// Number of core clock cycles per kernel tick, as passed to `start_kernel`.
+/// Like the SysTick reload value on ARM, this sets the tick period.
+static TICK_DIVISOR: AtomicU32 = AtomicU32::new(0);
+
+/// Kernel timestamp, measured in ticks. Unlike ARMv7-M, the host has 64-bit
+/// atomics, so this needn't be split.
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Big Kernel Lock: held for the duration of every kernel entry.
+static KERNEL: Mutex<()> = Mutex::new(());
+
+/// Signalled whenever the current task may have changed.
+static SCHEDULE: Condvar = Condvar::new();
+
+/// Enabled-interrupt bitmap, indexed like the NVIC's ISER registers.
+static IRQ_ENABLED: [AtomicU32; 16] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicU32 = AtomicU32::new(0);
+    [ZERO; 16]
+};
+
+/// Contents of the image file passed to `load_image`.
+static IMAGE: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+/// Host pages we've mapped for task regions. Regions can share pages (and
+/// shared regions are listed by several tasks), so we track pages rather than
+/// regions.
+static MAPPED_PAGES: Mutex<BTreeSet<usize>> = Mutex::new(BTreeSet::new());
+
+const PAGE_SIZE: usize = 4096;
+
+thread_local! {
+    /// Identity of the task running on this host thread, as (index,
+    /// generation). `None` on threads that aren't task threads, and on task
+    /// threads that haven't started their task yet.
+    static THIS_TASK: Cell<Option<(usize, u32)>> = Cell::new(None);
+
+    /// Top of this task thread's host stack, where the kernel runs on its
+    /// behalf, and where we start over when the task is restarted.
+    static HOST_STACK: Cell<usize> = Cell::new(0);
+}
+
+/// Volatile state that must be saved across context switches.
+///
+/// There are no real registers to save here; this just holds syscall
+/// arguments and results while a task is in the kernel.
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct SavedState {
+    /// Syscall arguments on the way in, and results on the way out.
+    regs: [u32; 7],
+    /// Syscall number.
+    descriptor: u32,
+    /// Nominal stack pointer, from the task descriptor.
+    sp: u32,
+    /// Bumped each time the task is reinitialized, so that threads belonging
+    /// to a previous incarnation of the task can tell they've been retired.
+    generation: u32,
+}
+
+/// Map the saved state to (architecture-independent) syscall argument and
+/// return slots.
+impl task::ArchState for SavedState {
+    fn stack_pointer(&self) -> u32 {
+        self.sp
+    }
+
+    fn arg0(&self) -> u32 {
+        self.regs[0]
+    }
+    fn arg1(&self) -> u32 {
+        self.regs[1]
+    }
+    fn arg2(&self) -> u32 {
+        self.regs[2]
+    }
+    fn arg3(&self) -> u32 {
+        self.regs[3]
+    }
+    fn arg4(&self) -> u32 {
+        self.regs[4]
+    }
+    fn arg5(&self) -> u32 {
+        self.regs[5]
+    }
+    fn arg6(&self) -> u32 {
+        self.regs[6]
+    }
+
+    fn syscall_descriptor(&self) -> u32 {
+        self.descriptor
+    }
+
+    fn ret0(&mut self, x: u32) {
+        self.regs[0] = x
+    }
+    fn ret1(&mut self, x: u32) {
+        self.regs[1] = x
+    }
+    fn ret2(&mut self, x: u32) {
+        self.regs[2] = x
+    }
+    fn ret3(&mut self, x: u32) {
+        self.regs[3] = x
+    }
+    fn ret4(&mut self, x: u32) {
+        self.regs[4] = x
+    }
+    fn ret5(&mut self, x: u32) {
+        self.regs[5] = x
+    }
+}
+
+pub unsafe fn set_clock_freq(tick_divisor: u32) {
+    TICK_DIVISOR.store(tick_divisor, Ordering::Relaxed);
+}
+
+/// Hands the kernel the task image to run.
+///
+/// `elf` is the contents of the image's `final.elf`. It must be loaded before
+/// `start_kernel` is called, because that's when tasks get mapped.
+pub fn load_image(elf: &[u8]) {
+    uassert!(elf.len() >= 52 && elf[..4] == *b"\x7fELF");
+    uassert!(elf[4] == 1 && elf[5] == 1); // 32-bit, little-endian
+    *IMAGE.lock().unwrap() = elf.to_vec();
+}
+
+pub fn reinitialize(task: &mut task::Task) {
+    let generation = task.save().generation.wrapping_add(1);
+    *task.save_mut() = SavedState {
+        sp: task.descriptor().initial_stack,
+        generation,
+        ..SavedState::default()
+    };
+
+    // The first time through, give the task its memory and a thread to run on.
+    // Later restarts reuse both; see the module docs.
+    if generation == 1 {
+        for region in task.region_table() {
+            map_region(region);
+        }
+
+        let index = usize::from(task.descriptor().index);
+        uassert!(task.descriptor().entry_point != 0);
+        std::thread::Builder::new()
+            .name(format!("task{index}"))
+            .spawn(move || task_thread(index))
+            .unwrap();
+    }
+}
+
+/// Maps the pages covering `region` at its real address (if they aren't
+/// already mapped), filling them from the image.
+fn map_region(region: &crate::descs::RegionDesc) {
+    let atts = region.attributes;
+    if atts.contains(RegionAttributes::DEVICE)
+        || !atts.intersects(
+            RegionAttributes::READ
+                | RegionAttributes::WRITE
+                | RegionAttributes::EXECUTE,
+        )
+    {
+        // Peripherals aren't modeled, and no-access regions (like the null
+        // region) must stay unmapped.
+        return;
+    }
+
+    let image = IMAGE.lock().unwrap();
+    let mut mapped = MAPPED_PAGES.lock().unwrap();
+    let base = region.base as usize & !(PAGE_SIZE - 1);
+    let end = region.base as usize + region.size as usize;
+    for page in (base..end).step_by(PAGE_SIZE) {
+        if !mapped.insert(page) {
+            continue;
+        }
+        // Safety: MAP_FIXED_NOREPLACE won't disturb an existing mapping, and
+        // we check below that we got the address we asked for.
+        let p = unsafe {
+            libc::mmap(
+                page as *mut libc::c_void,
+                PAGE_SIZE,
+                libc::PROT_READ | libc::PROT_WRITE | libc::PROT_EXEC,
+                libc::MAP_PRIVATE
+                    | libc::MAP_ANONYMOUS
+                    | libc::MAP_FIXED_NOREPLACE,
+                -1,
+                0,
+            )
+        };
+        if p as usize != page {
+            panic!("can't map task memory at {page:#x}; is it in use?");
+        }
+        for (addr, bytes) in load_segments(&image) {
+            let lo = addr.max(page);
+            let hi = (addr + bytes.len()).min(page + PAGE_SIZE);
+            if lo < hi {
+                // Safety: we just mapped this page, and nothing else can see
+                // it yet.
+                unsafe {
+                    core::ptr::copy_nonoverlapping(
+                        bytes[lo - addr..].as_ptr(),
+                        lo as *mut u8,
+                        hi - lo,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Iterates over the loadable segments of the ELF image in `elf`, as (load
+/// address, contents) pairs.
+fn load_segments(elf: &[u8]) -> impl Iterator<Item = (usize, &[u8])> {
+    let word = move |at: usize| {
+        u32::from_le_bytes(elf[at..at + 4].try_into().unwrap()) as usize
+    };
+    let half = move |at: usize| {
+        usize::from(u16::from_le_bytes(elf[at..at + 2].try_into().unwrap()))
+    };
+    const PT_LOAD: usize = 1;
+
+    let phoff = word(28);
+    let phentsize = half(42);
+    let phnum = half(44);
+    (0..phnum).filter_map(move |i| {
+        let ph = phoff + i * phentsize;
+        if word(ph) != PT_LOAD {
+            return None;
+        }
+        let offset = word(ph + 4);
+        let paddr = word(ph + 12);
+        let filesz = word(ph + 16);
+        Some((paddr, &elf[offset..offset + filesz]))
+    })
+}
+
+/// Pattern painted over unused task stack before each incarnation of a task
+/// starts, so that we can later find out how deep the stack has gone.
+const STACK_CANARY: u32 = 0xbaddcafe;
+
+/// Paints `task`'s stack, from the base of the region holding it up to its
+/// initial stack pointer, with `STACK_CANARY`.
+///
+/// Unlike on ARM, `reinitialize` can't do this: because preemption is lazy, an
+/// earlier incarnation of the task may still be running on that stack. So
+/// `run_task` does it instead, once the task's thread has left it.
+fn paint_stack(task: &mut task::Task) {
+    let initial_stack = task.descriptor().initial_stack as usize;
+    if let Some(region) = task
+        .region_table()
+        .iter()
+        .find(|region| region.contains(initial_stack))
+    {
+        let mut uslice: USlice<u32> = USlice::from_raw(
+            region.base as usize,
+            (initial_stack - region.base as usize) >> 2,
+        )
+        .unwrap();
+
+        for word in task.try_write(&mut uslice).unwrap() {
+            *word = STACK_CANARY;
+        }
+    }
+}
+
+/// Measures the stack usage of `task`, by finding the deepest word that no
+/// longer holds the `STACK_CANARY` painted by `paint_stack`. Between a restart
+/// and the task next running, this still describes the previous incarnation.
+pub fn stack_usage(task: &task::Task) -> abi::StackUsage {
+    let initial_stack = task.descriptor().initial_stack as usize;
+    let Some(region) = task
+        .region_table()
+        .iter()
+        .find(|region| region.contains(initial_stack))
+    else {
+        // No stack region means no stack to measure.
+        return abi::StackUsage::default();
+    };
+
+    let size = initial_stack - region.base as usize;
+    let uslice: USlice<u32> =
+        USlice::from_raw(region.base as usize, size >> 2).unwrap();
+    let stack = task.try_read(&uslice).unwrap();
+
+    // The stack grows down, so the first word (from the bottom) that's been
+    // disturbed marks the high-water mark.
+    let untouched = stack
+        .iter()
+        .take_while(|&&word| word == STACK_CANARY)
+        .count();
+
+    abi::StackUsage {
+        size: size as u32,
+        high_water: (size - untouched * 4) as u32,
+    }
+}
+
+pub fn apply_memory_protection(_task: &task::Task) {
+    // Nothing to do; see the module docs. Access checks for kernel-mediated
+    // copies happen in `umem` regardless of architecture.
+}
+
+pub fn start_first_task(_tick_divisor: u32, task: &mut task::Task) -> ! {
+    // A kernel panic here would otherwise unwind whatever task thread happened
+    // to be in the kernel, poisoning `KERNEL` and wedging the simulation. Report
+    // it and take the whole process down instead.
+    std::panic::set_hook(Box::new(|info| {
+        eprintln!("kernel panic: {info}");
+        std::process::abort();
+    }));
+
+    {
+        let _guard = KERNEL.lock().unwrap();
+        // Safety: task comes from the task table and we don't use it again,
+        // so we meet set_current_task's requirements.
+        unsafe {
+            set_current_task(task);
+        }
+        SCHEDULE.notify_all();
+    }
+
+    std::thread::Builder::new()
+        .name("systick".into())
+        .spawn(tick_thread)
+        .unwrap();
+
+    // The boot thread has nothing further to do.
+    loop {
+        std::thread::park();
+    }
+}
+
+/// Body of the host thread for task `index`.
+fn task_thread(index: usize) -> ! {
+    let sp: usize;
+    // Safety: just reads the stack pointer.
+    unsafe {
+        core::arch::asm!("mov {}, esp", out(reg) sp, options(nomem, nostack));
+    }
+    // Nothing above this point on the stack is needed again, so this is where
+    // we start over each time the task restarts.
+    let sp = sp & !15;
+    HOST_STACK.with(|s| s.set(sp));
+    // Safety: sp is the top of our own, otherwise unused, host stack.
+    unsafe { run_task_on(sp, index) }
+}
+
+/// Discards everything on the current stack down to `sp`, and calls
+/// `run_task(index)` from there.
+///
+/// # Safety
+///
+/// `sp` must be this thread's `HOST_STACK`, and nothing on the stack below it
+/// (including the caller's frames) may be used again.
+unsafe fn run_task_on(sp: usize, index: usize) -> ! {
+    unsafe {
+        core::arch::asm!(
+            "mov esp, {sp}",
+            "sub esp, 12",
+            "push {index}",
+            "call {run}",
+            "ud2",
+            sp = in(reg) sp,
+            index = in(reg) index,
+            run = sym run_task,
+            options(noreturn),
+        )
+    }
+}
+
+/// Waits for task `index` to be scheduled, then runs it from its entry point.
+extern "C" fn run_task(index: usize) -> ! {
+    let (guard, generation) =
+        wait_until_current(KERNEL.lock().unwrap(), index, None);
+    THIS_TASK.with(|t| t.set(Some((index, generation))));
+
+    // Safety: we hold the kernel lock and this is the current task, so the
+    // pointer is valid and unaliased.
+    let task = unsafe { &mut *CURRENT_TASK_PTR.load(Ordering::Relaxed) };
+    // Nothing is running on the task's stack any more, so it's safe to paint.
+    paint_stack(task);
+    let descriptor = task.descriptor();
+    let entry = descriptor.entry_point as usize;
+    let sp = descriptor.initial_stack as usize & !15;
+    drop(guard);
+
+    // Enter the task's `_start` on its own stack, passing it our syscall entry
+    // point in place of an `svc` instruction. `_start` never returns.
+    //
+    // Safety: the entry point and stack come from the task table, and the
+    // memory behind them was mapped by `reinitialize`.
+    unsafe {
+        core::arch::asm!(
+            "mov esp, {sp}",
+            "sub esp, 12",
+            "push {syscall}",
+            "call {entry}",
+            "ud2",
+            sp = in(reg) sp,
+            entry = in(reg) entry,
+            syscall = in(reg) sim_syscall as usize,
+            options(noreturn),
+        )
+    }
+}
+
+/// Blocks the calling task thread until task `index` is current, returning
+/// the task's generation.
+///
+/// If `generation` is given and the task has been reinitialized since then,
+/// this doesn't return; instead, the thread starts the task over.
+fn wait_until_current(
+    mut guard: MutexGuard<'static, ()>,
+    index: usize,
+    generation: Option<u32>,
+) -> (MutexGuard<'static, ()>, u32) {
+    loop {
+        let current = CURRENT_TASK_PTR.load(Ordering::Relaxed);
+        if !current.is_null() {
+            // Safety: we hold the kernel lock, so nobody holds a reference to
+            // the task table that this could alias.
+            let (i, g) = unsafe {
+                let t = &*current;
+                (usize::from(t.descriptor().index), t.save().generation)
+            };
+            if i == index {
+                match generation {
+                    Some(mine) if mine != g => retire(guard, index),
+                    _ => return (guard, g),
+                }
+            }
+        }
+        guard = SCHEDULE.wait(guard).unwrap();
+    }
+}
+
+/// Abandons the current incarnation of task `index`, whose task has been
+/// reinitialized out from under it, and starts the new one on this thread.
+fn retire(guard: MutexGuard<'static, ()>, index: usize) -> ! {
+    drop(guard);
+    THIS_TASK.with(|t| t.set(None));
+    let sp = HOST_STACK.with(|s| s.get());
+    // Safety: we're a task thread (or we couldn't have got here), and the
+    // frames we're discarding hold nothing that needs dropping.
+    unsafe { run_task_on(sp, index) }
+}
+
+/// Kernel entry point for syscalls made by simulated tasks.
+///
+/// `regs` holds the seven syscall argument registers on entry, and the six
+/// result registers on return. This is the sim equivalent of `SVC`: it's
+/// passed to each task's `_start`, and userlib's hosted syscall stubs call it
+/// in place of trapping.
+///
+/// This is called on the task's stack, which is sized for the task rather
+/// than the kernel, so all it does is switch to the thread's host stack.
+///
+/// # Safety
+///
+/// This must only be called from a task thread started by `reinitialize`.
+unsafe extern "C" fn sim_syscall(nr: u32, regs: *mut [u32; 7]) {
+    let sp = HOST_STACK.with(|s| s.get());
+    // Safety: the host stack isn't in use while we're running user code, so we
+    // can borrow it; `edi` is callee-saved, so it survives the call.
+    unsafe {
+        core::arch::asm!(
+            "mov edi, esp",
+            "mov esp, {sp}",
+            "sub esp, 8",
+            "push {regs}",
+            "push {nr}",
+            "call {entry}",
+            "mov esp, edi",
+            sp = in(reg) sp,
+            regs = in(reg) regs,
+            nr = in(reg) nr,
+            entry = sym syscall_on_host_stack,
+            out("edi") _,
+            clobber_abi("C"),
+        )
+    }
+}
+
+/// The body of `sim_syscall`, running on the host stack.
+extern "C" fn syscall_on_host_stack(nr: u32, regs: *mut [u32; 7]) {
+    let (index, generation) = THIS_TASK
+        .with(|t| t.get())
+        .expect("syscall from non-task thread");
+    // Safety: `regs` points into the calling task's stack frame, which stays
+    // put until we return.
+    let regs = unsafe { &mut *regs };
+
+    // If we were lazily preempted, this is where we actually stop.
+    let (mut guard, _) =
+        wait_until_current(KERNEL.lock().unwrap(), index, Some(generation));
+
+    if let Ok(call) = Hostcall::try_from(nr) {
+        guard = hostcall(guard, index, call, regs);
+    } else {
+        let task = CURRENT_TASK_PTR.load(Ordering::Relaxed);
+        // Safety: we hold the kernel lock and are the current task, so this
+        // pointer is valid and unaliased until we call into the kernel proper.
+        unsafe {
+            let save = (*task).save_mut();
+            save.regs = *regs;
+            save.descriptor = nr;
+        }
+
+        // Safety: task is the current task pointer, which is what
+        // syscall_entry expects.
+        unsafe {
+            crate::syscalls::syscall_entry(nr, task);
+        }
+        SCHEDULE.notify_all();
+    }
+
+    // We may have blocked, or been faulted (in which case we may be restarted
+    // rather than resumed).
+    let _guard = wait_until_current(guard, index, Some(generation)).0;
+
+    let task = CURRENT_TASK_PTR.load(Ordering::Relaxed);
+    // Safety: as above.
+    *regs = unsafe { (*task).save().regs };
+}
+
+/// Handles a host call from task `index`, which is current.
+fn hostcall(
+    mut guard: MutexGuard<'static, ()>,
+    index: usize,
+    call: Hostcall,
+    regs: &[u32; 7],
+) -> MutexGuard<'static, ()> {
+    match call {
+        Hostcall::Write => with_task_table(|tasks| {
+            if let Err(fault) = console_write(&tasks[index], regs) {
+                if task::force_fault(tasks, index, fault)
+                    != task::NextTask::Same
+                {
+                    reschedule(index, tasks);
+                }
+            }
+        }),
+        Hostcall::Exit => std::process::exit(regs[0] as i32),
+        Hostcall::WaitForInterrupt => {
+            // Idle until the scheduler has something new to say; the caller
+            // then waits until we're current again, as usual.
+            guard = SCHEDULE.wait(guard).unwrap();
+        }
+    }
+    guard
+}
+
+/// Copies the message described by `regs` from `task` to the console.
+fn console_write(task: &task::Task, regs: &[u32; 7]) -> Result<(), FaultInfo> {
+    let slice = USlice::<u8>::from_raw(regs[0] as usize, regs[1] as usize)?;
+    let msg = task.try_read(&slice)?;
+    // There's nobody to report a console error to.
+    let _ = std::io::Write::write_all(&mut std::io::stderr().lock(), msg);
+    Ok(())
+}
+
+/// Records the address of `task` as the current user task.
+///
+/// # Safety
+///
+/// This records a pointer that aliases `task`. As long as you don't read that
+/// pointer while you have access to `task`, and as long as the `task` being
+/// stored is actually in the task table, you'll be okay.
+pub unsafe fn set_current_task(task: &mut task::Task) {
+    CURRENT_TASK_PTR.store(task, Ordering::Relaxed);
+    crate::profiling::event_context_switch(task as *mut _ as usize);
+    #[cfg(feature = "accounting")]
+    crate::accounting::switch_to(usize::from(task.descriptor().index));
+}
+
+/// Reads the tick counter.
+pub fn now() -> Timestamp {
+    let t = TICKS.load(Ordering::Relaxed);
+    Timestamp::from([t as u32, (t >> 32) as u32])
+}
+
+/// Reads the free-running counter used for task accounting: the simulated
+/// core clock, i.e. nanoseconds since the simulation started.
+#[cfg(feature = "accounting")]
+pub fn cycle_count() -> u32 {
+    (epoch().elapsed().as_nanos() as u64 * CORE_CLOCK_KHZ / 1_000_000) as u32
+}
+
+/// Returns the instant the simulated core clock started counting.
+fn epoch() -> Instant {
+    static EPOCH: Mutex<Option<Instant>> = Mutex::new(None);
+
+    *EPOCH.lock().unwrap().get_or_insert_with(Instant::now)
+}
+
+/// Software SysTick: advances the kernel timestamp once every `TICK_DIVISOR`
+/// cycles of the simulated core clock and processes timers, rescheduling if
+/// any fired.
+///
+/// Ticks are scheduled against absolute deadlines, so if the host falls
+/// behind, the kernel's notion of time catches up rather than drifting.
+fn tick_thread() {
+    let mut deadline = epoch();
+    loop {
+        let divisor = u64::from(TICK_DIVISOR.load(Ordering::Relaxed));
+        uassert!(divisor != 0);
+        deadline += Duration::from_nanos(divisor * 1_000_000 / CORE_CLOCK_KHZ);
+        std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+
+        let _guard = KERNEL.lock().unwrap();
+        crate::profiling::event_timer_isr_enter();
+        #[cfg(feature = "accounting")]
+        crate::accounting::tick();
+        let now = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+        let now = Timestamp::from([now as u32, (now >> 32) as u32]);
+        let current = current_index();
+        with_task_table(|tasks| {
+            if task::process_timers(tasks, now) != task::NextTask::Same {
+                reschedule(current, tasks);
+            }
+        });
+        crate::profiling::event_timer_isr_exit();
+    }
+}
+
+/// Returns the index of the current task. Must be called with the kernel lock
+/// held, and outside `with_task_table`.
+fn current_index() -> usize {
+    let current = CURRENT_TASK_PTR.load(Ordering::Relaxed);
+    uassert!(!current.is_null()); // irq before kernel started?
+
+    // Safety: the current task pointer is maintained by this module, and we
+    // discard the reference immediately so it doesn't alias the task table.
+    usize::from(unsafe { (*current).descriptor().index })
+}
+
+/// Equivalent of `pendsv_entry`: picks the next task to run after `current`
+/// and makes it current. Must be called with the kernel lock held.
+fn reschedule(current: usize, tasks: &mut [task::Task]) {
+    let next = task::select(current, tasks);
+    let next = &mut tasks[next];
+    apply_memory_protection(next);
+    // Safety: next comes from the task table and we don't use it again
+    // until next kernel entry, so we meet set_current_task's requirements.
+    unsafe {
+        set_current_task(next);
+    }
+    SCHEDULE.notify_all();
+}
+
+/// Simulates assertion of hardware interrupt `irq_num`.
+///
+/// This is the hook for host-side device models. As on hardware, if the
+/// interrupt is enabled, it is disabled and the owning task is notified; if
+/// it's disabled, nothing happens.
+pub fn raise_irq(irq_num: u32) {
+    let bit = 1 << (irq_num % 32);
+    let reg = &IRQ_ENABLED[(irq_num / 32) as usize];
+
+    let _guard = KERNEL.lock().unwrap();
+    if reg.load(Ordering::Relaxed) & bit == 0 {
+        return;
+    }
+
+    crate::profiling::event_isr_enter();
+    let current = current_index();
+    let owner = crate::startup::HUBRIS_IRQ_TASK_LOOKUP
+        .get(abi::InterruptNum(irq_num))
+        .unwrap_or_else(|| panic!("unhandled IRQ {irq_num}"));
+
+    with_task_table(|tasks| {
+        disable_irq(irq_num);
+
+        let n = task::NotificationSet(owner.notification);
+        if tasks[owner.task as usize].post(n) {
+            reschedule(current, tasks);
+        }
+    });
+    crate::profiling::event_isr_exit();
+}
+
+pub fn disable_irq(n: u32) {
+    IRQ_ENABLED[(n / 32) as usize]
+        .fetch_and(!(1 << (n % 32)), Ordering::Relaxed);
+}
+
+pub fn enable_irq(n: u32) {
+    IRQ_ENABLED[(n / 32) as usize].fetch_or(1 << (n % 32), Ordering::Relaxed);
+}
+
+pub fn reset() -> ! {
+    // There's no hardware to reset. Exit with a distinctive status so that a
+    // harness can tell a requested reset from a crash, and restart us if it
+    // wants to.
+    std::process::exit(RESET_EXIT_STATUS)
+}
+
+/// Process exit status used to indicate a requested system reset.
+pub const RESET_EXIT_STATUS: i32 = 0x5e;
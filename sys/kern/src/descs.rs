@@ -67,6 +67,9 @@ pub struct TaskDesc {
     /// The index is a u16 to save space in the `TaskDesc` struct; in practice
     /// other factors limit us to fewer than `2**16` tasks.
     pub index: u16,
+    /// Bitmask of the kernel locks this task is permitted to acquire, indexed
+    /// by lock number.
+    pub locks: u32,
 }
 
 bitflags::bitflags! {
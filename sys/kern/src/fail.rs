@@ -98,13 +98,15 @@ impl Write for Eulogist {
     }
 }
 
-#[cfg(not(feature = "nano"))]
+// Hosted (simulated) builds link std, which provides the panic handler; see
+// `arch::sim` for how kernel panics are reported there.
+#[cfg(all(target_os = "none", not(feature = "nano")))]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo<'_>) -> ! {
     die(info)
 }
 
-#[cfg(feature = "nano")]
+#[cfg(all(target_os = "none", feature = "nano"))]
 #[panic_handler]
 fn panic(_info: &core::panic::PanicInfo<'_>) -> ! {
     unsafe {
@@ -39,6 +39,17 @@ pub fn handle_kernel_message(
         Ok(Kipcnum::ReadTaskDumpRegion) => {
             read_task_dump_region(tasks, caller, args.message?, args.response?)
         }
+        Ok(Kipcnum::FindFaultedTask) => {
+            find_faulted_task(tasks, caller, args.message?, args.response?)
+        }
+        Ok(Kipcnum::ReadTaskStackUsage) => {
+            read_task_stack_usage(tasks, caller, args.message?, args.response?)
+        }
+        #[cfg(feature = "accounting")]
+        Ok(Kipcnum::ReadTaskAccounting) => {
+            read_task_accounting(tasks, caller, args.message?, args.response?)
+        }
+        Ok(Kipcnum::PetWatchdog) => pet_watchdog(tasks, caller),
 
         _ => {
             // Task has sent an unknown message to the kernel. That's bad.
@@ -126,6 +137,7 @@ fn restart_task(
     if start {
         tasks[index].set_healthy_state(SchedState::Runnable);
     }
+    crate::lock::abandon(tasks, index);
 
     // Restarting a task can have implications for other tasks. We don't want to
     // leave tasks sitting around waiting for a reply that will never come, for
@@ -223,6 +235,100 @@ fn read_image_id(
     Ok(NextTask::Same)
 }
 
+/// Scans the task table, starting at the given index, for a task in the
+/// `Faulted` state. Returns the index of the first such task, or zero if there
+/// are none. (Zero can't be confused with a real answer, because the
+/// supervisor can't very well be faulted while it's asking.)
+fn find_faulted_task(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let index: u32 = deserialize_message(&tasks[caller], message)?;
+    let index = index as usize;
+    // Note that we deliberately accept `index == tasks.len()`, to make it easy
+    // for callers to resume a scan after finding the last task.
+    if index > tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+
+    let found = tasks[index..]
+        .iter()
+        .position(|task| matches!(task.state(), TaskState::Faulted { .. }))
+        .map(|offset| (index + offset) as u32)
+        .unwrap_or(0);
+
+    let response_len =
+        serialize_response(&mut tasks[caller], response, &found)?;
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
+fn read_task_stack_usage(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let index: u32 = deserialize_message(&tasks[caller], message)?;
+    if index as usize >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+
+    let usage = arch::stack_usage(&tasks[index as usize]);
+    let response_len =
+        serialize_response(&mut tasks[caller], response, &usage)?;
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
+#[cfg(feature = "accounting")]
+fn read_task_accounting(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let index: u32 = deserialize_message(&tasks[caller], message)?;
+    if index as usize >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+
+    let accounting = crate::accounting::read(index as usize);
+    let response_len =
+        serialize_response(&mut tasks[caller], response, &accounting)?;
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
+fn pet_watchdog(
+    tasks: &mut [Task],
+    caller: usize,
+) -> Result<NextTask, UserError> {
+    if caller != 0 {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::NotSupervisor,
+        )));
+    }
+
+    crate::watchdog::pet();
+    tasks[caller].save_mut().set_send_response_and_length(0, 0);
+    Ok(NextTask::Same)
+}
+
 #[cfg(feature = "dump")]
 fn get_task_dump_region(
     tasks: &mut [Task],
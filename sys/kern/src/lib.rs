@@ -36,12 +36,15 @@
 #[macro_use]
 pub mod arch;
 
+#[cfg(feature = "accounting")]
+pub mod accounting;
 pub mod atomic;
 mod descs;
 pub mod err;
 pub mod fail;
 pub mod header;
 pub mod kipc;
+pub mod lock;
 pub mod profiling;
 pub mod startup;
 pub mod syscalls;
@@ -49,3 +52,4 @@ pub mod task;
 pub mod time;
 pub mod umem;
 pub mod util;
+pub mod watchdog;
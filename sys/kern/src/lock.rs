@@ -0,0 +1,227 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Kernel-managed locks.
+//!
+//! A kernel lock gives a set of tasks exclusive access to some shared
+//! resource, such as a bus whose registers are mapped into each of them,
+//! without needing a server task to arbitrate. Locks are declared implicitly
+//! by naming them in the `locks` list of each task that uses them in the
+//! app.toml; only those tasks may acquire the lock.
+//!
+//! Locks are acquired and released with the `LOCK` syscall. A task that
+//! attempts to acquire a held lock blocks (in `SchedState::InLock`) until the
+//! lock is released, at which point it's handed to the most important waiter.
+//!
+//! To avoid unbounded priority inversion, a lock holder inherits the priority
+//! of the most important task waiting on any lock it holds, transitively
+//! through chains of waiting holders. Its priority reverts when it releases
+//! the lock.
+//!
+//! If a lock holder faults or is restarted, the kernel releases its locks on
+//! its behalf. Whatever the lock was protecting may be in an inconsistent
+//! state; the next holder must be prepared for that.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use abi::{FaultInfo, SchedState, TaskState, UsageError};
+
+use crate::descs::Priority;
+use crate::err::UserError;
+use crate::startup::HUBRIS_LOCK_COUNT;
+use crate::task::{self, ArchState, NextTask, Task};
+
+/// Response code returned by a non-blocking acquire of a held lock.
+const LOCK_BUSY: u32 = 1;
+
+/// Owner of each lock, stored as the owning task's index plus one, or zero if
+/// the lock is free. As with the kernel's `TICKS`, these are atomics only for
+/// interior mutability.
+static OWNERS: [AtomicU32; HUBRIS_LOCK_COUNT] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const FREE: AtomicU32 = AtomicU32::new(0);
+    [FREE; HUBRIS_LOCK_COUNT]
+};
+
+fn owner(lock: usize) -> Option<usize> {
+    match OWNERS[lock].load(Ordering::Relaxed) {
+        0 => None,
+        n => Some(n as usize - 1),
+    }
+}
+
+fn set_owner(lock: usize, task: Option<usize>) {
+    let n = task.map(|t| t as u32 + 1).unwrap_or(0);
+    OWNERS[lock].store(n, Ordering::Relaxed);
+}
+
+fn is_waiting_on(task: &Task, lock: usize) -> bool {
+    task.state() == &TaskState::Healthy(SchedState::InLock(lock as u16))
+}
+
+/// Implementation of the `LOCK` syscall.
+pub fn lock(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
+    let args = tasks[caller].save().as_lock_args();
+    let lock = args.lock as usize;
+
+    // The build system only grants locks that exist, so this also checks that
+    // `lock` is in range.
+    let permitted = 1u32
+        .checked_shl(args.lock)
+        .map_or(false, |bit| tasks[caller].descriptor().locks & bit != 0);
+    if !permitted {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::NoLock,
+        )));
+    }
+
+    match args.operation {
+        0 => release(tasks, caller, lock),
+        1 => acquire(tasks, caller, lock, true),
+        2 => acquire(tasks, caller, lock, false),
+        _ => Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::BadLockOperation,
+        ))),
+    }
+}
+
+fn acquire(
+    tasks: &mut [Task],
+    caller: usize,
+    lock: usize,
+    block: bool,
+) -> Result<NextTask, UserError> {
+    let Some(holder) = owner(lock) else {
+        set_owner(lock, Some(caller));
+        tasks[caller].save_mut().set_error_response(0);
+        return Ok(NextTask::Same);
+    };
+
+    if !block {
+        tasks[caller].save_mut().set_error_response(LOCK_BUSY);
+        return Ok(NextTask::Same);
+    }
+
+    // Refuse to block if the holder is (transitively) waiting on a lock held
+    // by the caller -- including the case where the caller is the holder.
+    // Otherwise, neither would ever run again.
+    let mut t = holder;
+    loop {
+        if t == caller {
+            return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+                UsageError::BadLockOperation,
+            )));
+        }
+        match tasks[t].state() {
+            TaskState::Healthy(SchedState::InLock(l)) => {
+                match owner(*l as usize) {
+                    Some(next) => t = next,
+                    None => break,
+                }
+            }
+            _ => break,
+        }
+    }
+
+    // Our response will be filled in when the lock is handed to us.
+    tasks[caller].set_healthy_state(SchedState::InLock(lock as u16));
+    update_priority(tasks, holder);
+    Ok(NextTask::Other)
+}
+
+fn release(
+    tasks: &mut [Task],
+    caller: usize,
+    lock: usize,
+) -> Result<NextTask, UserError> {
+    if owner(lock) != Some(caller) {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::BadLockOperation,
+        )));
+    }
+
+    tasks[caller].save_mut().set_error_response(0);
+    let woke = hand_off(tasks, lock);
+    let was = tasks[caller].priority();
+    update_priority(tasks, caller);
+
+    // If we woke someone, or shed an inherited priority, someone else may now
+    // deserve the CPU more than the caller does.
+    if woke || tasks[caller].priority() != was {
+        Ok(NextTask::Other)
+    } else {
+        Ok(NextTask::Same)
+    }
+}
+
+/// Passes `lock` from its current owner to its most important waiter, or
+/// frees it if there are none. Returns `true` if a waiter was woken.
+fn hand_off(tasks: &mut [Task], lock: usize) -> bool {
+    let previous = owner(lock).unwrap_or(0);
+    match task::priority_scan(previous, tasks, |t| is_waiting_on(t, lock)) {
+        Some(next) => {
+            set_owner(lock, Some(next));
+            tasks[next].set_healthy_state(SchedState::Runnable);
+            tasks[next].save_mut().set_error_response(0);
+            // The new owner may inherit from the remaining waiters.
+            update_priority(tasks, next);
+            true
+        }
+        None => {
+            set_owner(lock, None);
+            false
+        }
+    }
+}
+
+/// Recomputes the priority of `task` from its base priority and the
+/// waiters on any locks it holds, propagating any change along the chain of
+/// lock holders that `task` is waiting on.
+fn update_priority(tasks: &mut [Task], mut task: usize) {
+    loop {
+        let mut priority = Priority(tasks[task].descriptor().priority);
+        for lock in 0..HUBRIS_LOCK_COUNT {
+            if owner(lock) != Some(task) {
+                continue;
+            }
+            for waiter in tasks.iter().filter(|t| is_waiting_on(t, lock)) {
+                if waiter.priority().is_more_important_than(priority) {
+                    priority = waiter.priority();
+                }
+            }
+        }
+
+        if tasks[task].priority() == priority {
+            // Nothing changed, so nothing downstream will change either.
+            return;
+        }
+        tasks[task].set_priority(priority);
+
+        match tasks[task].state() {
+            TaskState::Healthy(SchedState::InLock(l)) => {
+                match owner(*l as usize) {
+                    Some(holder) => task = holder,
+                    None => return,
+                }
+            }
+            _ => return,
+        }
+    }
+}
+
+/// Cleans up after a task that has faulted or been restarted: any locks it
+/// holds are handed to their waiters, and, since it may have been waiting on
+/// a lock itself, lock holders' inherited priorities are recomputed.
+pub fn abandon(tasks: &mut [Task], index: usize) {
+    for lock in 0..HUBRIS_LOCK_COUNT {
+        if owner(lock) == Some(index) {
+            hand_off(tasks, lock);
+        }
+    }
+    for lock in 0..HUBRIS_LOCK_COUNT {
+        if let Some(holder) = owner(lock) {
+            update_priority(tasks, holder);
+        }
+    }
+}
@@ -85,6 +85,10 @@ pub unsafe fn start_kernel(tick_divisor: u32) -> ! {
         crate::task::select(task_table.len() - 1, task_table);
 
     crate::arch::apply_memory_protection(&task_table[first_task_index]);
+
+    // Once armed, the watchdog is the supervisor's problem.
+    crate::watchdog::arm();
+
     TASK_TABLE_IN_USE.store(false, Ordering::Release);
     crate::arch::start_first_task(
         tick_divisor,
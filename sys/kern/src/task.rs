@@ -323,6 +323,8 @@ impl Task {
         self.timer = TimerState::default();
         self.notifications = 0;
         self.state = TaskState::default();
+        // Shed any priority inherited through kernel locks.
+        self.priority = Priority(self.descriptor.priority);
 
         crate::arch::reinitialize(self);
     }
@@ -349,6 +351,13 @@ impl Task {
         self.priority
     }
 
+    /// Changes this task's current priority. This is used by kernel locks to
+    /// implement priority inheritance; the priority reverts to the one in the
+    /// task's descriptor when it's reinitialized.
+    pub fn set_priority(&mut self, priority: Priority) {
+        self.priority = priority;
+    }
+
     /// Returns a reference to this task's current state, for inspection.
     pub fn state(&self) -> &TaskState {
         &self.state
@@ -561,6 +570,14 @@ pub trait ArchState: Default {
         }
     }
 
+    /// Interprets arguments as for the `LOCK` syscall and returns the results.
+    fn as_lock_args(&self) -> LockArgs {
+        LockArgs {
+            lock: self.arg0(),
+            operation: self.arg1(),
+        }
+    }
+
     /// Sets a recoverable error code using the generic ABI.
     fn set_error_response(&mut self, resp: u32) {
         self.ret0(resp);
@@ -702,6 +719,13 @@ pub struct PostArgs {
     pub notification_bits: NotificationSet,
 }
 
+/// Decoded arguments for the `LOCK` syscall.
+#[derive(Clone, Debug)]
+pub struct LockArgs {
+    pub lock: u32,
+    pub operation: u32,
+}
+
 /// State for a task timer.
 ///
 /// Task timers are used to multiplex the hardware timer.
@@ -885,6 +909,8 @@ pub fn force_fault(
             }
         }
     };
+    // Don't let the faulted task sit on any kernel locks.
+    crate::lock::abandon(tasks, index);
     let supervisor_awoken =
         tasks[0].post(NotificationSet(HUBRIS_FAULT_NOTIFICATION));
     if supervisor_awoken {
@@ -0,0 +1,81 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Hardware watchdog support.
+//!
+//! If the application's `app.toml` sets `watchdog-ms` in its `[kernel]`
+//! section, the kernel arms a hardware watchdog with that timeout just before
+//! starting the first task. From then on, it's the supervisor's job to keep
+//! the watchdog fed, using the `PetWatchdog` kipc; if the supervisor stops
+//! doing so -- because it's wedged, or starved, or has decided that the tasks
+//! it's watching aren't making progress -- the watchdog resets the system.
+//!
+//! As with profiling, the kernel doesn't know how to drive any particular
+//! watchdog peripheral. A target that enables the watchdog must populate a
+//! `WatchdogTable` and provide it to `kern::watchdog::configure_watchdog` from
+//! its startup routine, before calling `start_kernel`; the STM32H7 and LPC55
+//! startup crates do this for the apps that use them. Configuring a timeout
+//! without providing a table is a fatal error at boot, since silently running
+//! without the watchdog you asked for is worse than not booting.
+//!
+//! Note that most watchdogs can't be disarmed once started, even by a
+//! debugger halting the processor, unless the target's hooks arrange for it.
+
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::startup::HUBRIS_WATCHDOG_MS;
+
+/// Hooks that must be provided by the board setup code if it configures a
+/// watchdog timeout.
+pub struct WatchdogTable {
+    /// Starts the watchdog with the given timeout, in milliseconds. This is
+    /// called once, from `start_kernel`.
+    pub arm: fn(u32),
+    /// Restarts the watchdog's countdown.
+    pub pet: fn(),
+}
+
+/// Supplies the kernel with a watchdog table.
+pub fn configure_watchdog(table: &'static WatchdogTable) {
+    WATCHDOG_TABLE.store(table as *const _ as *mut _, Ordering::Relaxed);
+}
+
+/// Internal pointer written by `configure_watchdog` and read by `table`. If
+/// this is null, no watchdog table has been provided.
+static WATCHDOG_TABLE: AtomicPtr<WatchdogTable> =
+    AtomicPtr::new(core::ptr::null_mut());
+
+/// Grabs a reference to the configured table, if any.
+fn table() -> Option<&'static WatchdogTable> {
+    let p = WATCHDOG_TABLE.load(Ordering::Relaxed);
+    if p.is_null() {
+        None
+    } else {
+        // We only write this pointer from a valid `&'static`, and we're handing
+        // out a shared reference, so this should be ok...
+        unsafe { Some(&*p) }
+    }
+}
+
+/// Arms the watchdog, if the application asked for one.
+pub(crate) fn arm() {
+    if let Some(ms) = HUBRIS_WATCHDOG_MS {
+        match table() {
+            Some(t) => (t.arm)(ms),
+            None => panic!(
+                "app.toml sets watchdog-ms, but the target didn't call \
+                 configure_watchdog"
+            ),
+        }
+    }
+}
+
+/// Feeds the watchdog. This is a no-op if no watchdog is configured.
+pub(crate) fn pet() {
+    if HUBRIS_WATCHDOG_MS.is_some() {
+        if let Some(t) = table() {
+            (t.pet)()
+        }
+    }
+}
@@ -5,8 +5,10 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     build_util::expose_m_profile();
 
-    // Do an architecture check.
-    if build_util::target_os() != "none" {
+    // Do an architecture check. The simulator target is the one Linux target
+    // we accept, because its tasks are linked into freestanding images anyway.
+    let target = build_util::target();
+    if build_util::target_os() != "none" && !target.starts_with("i686") {
         eprintln!("***********************************************");
         eprintln!("Hi!");
         eprintln!("You appear to be building this natively,");
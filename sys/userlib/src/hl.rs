@@ -14,8 +14,9 @@ use zerocopy::{AsBytes, FromBytes, LayoutVerified};
 
 use crate::{
     sys_borrow_info, sys_borrow_read, sys_borrow_write, sys_get_timer,
-    sys_recv, sys_recv_closed, sys_recv_open, sys_reply, sys_reply_fault,
-    sys_set_timer, BorrowInfo, ClosedRecvError, FromPrimitive,
+    sys_lock, sys_recv, sys_recv_closed, sys_recv_open, sys_reply,
+    sys_reply_fault, sys_set_timer, sys_try_lock, sys_unlock, BorrowInfo,
+    ClosedRecvError, FromPrimitive,
 };
 
 const INTERNAL_TIMER_NOTIFICATION: u32 = 1 << 31;
@@ -472,3 +473,32 @@ pub fn sleep_for(ticks: u64) {
         .unwrap_lite();
     sleep_until(deadline)
 }
+
+/// Acquires the kernel lock `lock`, blocking until it's available, and returns
+/// a guard that releases it when dropped.
+pub fn lock(lock: u32) -> LockGuard {
+    sys_lock(lock);
+    LockGuard { lock }
+}
+
+/// Attempts to acquire the kernel lock `lock` without blocking, returning a
+/// guard that releases it when dropped, or `None` if the lock is held.
+pub fn try_lock(lock: u32) -> Option<LockGuard> {
+    if sys_try_lock(lock) {
+        Some(LockGuard { lock })
+    } else {
+        None
+    }
+}
+
+/// Holds a kernel lock, releasing it when dropped.
+#[must_use]
+pub struct LockGuard {
+    lock: u32,
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        sys_unlock(self.lock);
+    }
+}
@@ -5,6 +5,7 @@
 //! Operations implemented by IPC with the kernel task.
 
 use crate::UnwrapLite;
+use core::num::NonZeroUsize;
 use zerocopy::AsBytes;
 
 use crate::*;
@@ -24,6 +25,58 @@ pub fn read_task_status(task: usize) -> abi::TaskState {
     ssmarshal::deserialize(&response[..len]).unwrap_lite().0
 }
 
+/// Returns the index of the first task at or after `task` that is in the
+/// `Faulted` state, or `None` if there are no such tasks.
+pub fn find_faulted_task(task: usize) -> Option<NonZeroUsize> {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let mut response = [0; core::mem::size_of::<u32>()];
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::FindFaultedTask as u16,
+        task.as_bytes(),
+        &mut response,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    let index: u32 = ssmarshal::deserialize(&response[..len]).unwrap_lite().0;
+    NonZeroUsize::new(index as usize)
+}
+
+/// Returns the size of `task`'s stack, and the deepest it has been used since
+/// the task was last started.
+pub fn read_task_stack_usage(task: usize) -> abi::StackUsage {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let mut response = [0; core::mem::size_of::<abi::StackUsage>()];
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadTaskStackUsage as u16,
+        task.as_bytes(),
+        &mut response,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    ssmarshal::deserialize(&response[..len]).unwrap_lite().0
+}
+
+/// Returns the CPU time accounting for `task`. This will fault the caller if
+/// the kernel wasn't built with the `accounting` feature.
+pub fn read_task_accounting(task: usize) -> abi::TaskAccounting {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let mut response = [0; core::mem::size_of::<abi::TaskAccounting>()];
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadTaskAccounting as u16,
+        task.as_bytes(),
+        &mut response,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    ssmarshal::deserialize(&response[..len]).unwrap_lite().0
+}
+
 pub fn get_task_dump_region(
     task: usize,
     region: usize,
@@ -97,6 +150,19 @@ pub fn system_restart() -> ! {
     panic!();
 }
 
+/// Feeds the hardware watchdog, if the kernel has armed one. Only the
+/// supervisor may do this.
+pub fn pet_watchdog() {
+    let (rc, _len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::PetWatchdog as u16,
+        &[],
+        &mut [],
+        &[],
+    );
+    assert_eq!(rc, 0);
+}
+
 pub fn read_image_id() -> u64 {
     let mut response = [0; core::mem::size_of::<u64>()];
     let (rc, len) = sys_send(
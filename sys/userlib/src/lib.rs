@@ -23,6 +23,9 @@
 //! all registers.
 //!
 //! See: https://github.com/rust-lang/rust/issues/73450#issuecomment-650463347
+//!
+//! In the hosted simulator there's no `svc`; each stub instead jumps to a
+//! function in the `sim` module that calls into the kernel directly.
 
 #![no_std]
 #![feature(asm_const)]
@@ -41,6 +44,8 @@ use core::marker::PhantomData;
 
 pub mod hl;
 pub mod kipc;
+#[cfg(target_arch = "x86")]
+pub mod sim;
 pub mod task_slot;
 pub mod units;
 
@@ -213,6 +218,12 @@ unsafe extern "C" fn sys_send_stub(_args: &mut SendArgs<'_>) -> RcLen {
                 sysnum = const Sysnum::Send as u32,
                 options(noreturn),
             )
+        } else if #[cfg(target_arch = "x86")] {
+            arch::asm!(
+                "jmp {imp}",
+                imp = sym sim::sys_send,
+                options(noreturn),
+            )
         } else {
             compile_error!("missing sys_send_stub for ARM profile");
         }
@@ -415,6 +426,12 @@ unsafe extern "C" fn sys_recv_stub(
                 sysnum = const Sysnum::Recv as u32,
                 options(noreturn),
             )
+        } else if #[cfg(target_arch = "x86")] {
+            arch::asm!(
+                "jmp {imp}",
+                imp = sym sim::sys_recv,
+                options(noreturn),
+            )
         } else {
             compile_error!("missing sys_recv_stub for ARM profile");
         }
@@ -510,6 +527,12 @@ unsafe extern "C" fn sys_reply_stub(
                 sysnum = const Sysnum::Reply as u32,
                 options(noreturn),
             )
+        } else if #[cfg(target_arch = "x86")] {
+            arch::asm!(
+                "jmp {imp}",
+                imp = sym sim::sys_reply,
+                options(noreturn),
+            )
         } else {
             compile_error!("missing sys_reply_stub for ARM profile");
         }
@@ -605,6 +628,12 @@ unsafe extern "C" fn sys_set_timer_stub(
                 sysnum = const Sysnum::SetTimer as u32,
                 options(noreturn),
             )
+        } else if #[cfg(target_arch = "x86")] {
+            arch::asm!(
+                "jmp {imp}",
+                imp = sym sim::sys_set_timer,
+                options(noreturn),
+            )
         } else {
             compile_error!("missing sys_set_timer_stub for ARM profile")
         }
@@ -691,6 +720,12 @@ unsafe extern "C" fn sys_borrow_read_stub(_args: *mut BorrowReadArgs) -> RcLen {
                 sysnum = const Sysnum::BorrowRead as u32,
                 options(noreturn),
             )
+        } else if #[cfg(target_arch = "x86")] {
+            arch::asm!(
+                "jmp {imp}",
+                imp = sym sim::sys_borrow_read,
+                options(noreturn),
+            )
         } else {
             compile_error!("missing sys_borrow_read_stub for ARM profile")
         }
@@ -789,6 +824,12 @@ unsafe extern "C" fn sys_borrow_write_stub(
                 sysnum = const Sysnum::BorrowWrite as u32,
                 options(noreturn),
             )
+        } else if #[cfg(target_arch = "x86")] {
+            arch::asm!(
+                "jmp {imp}",
+                imp = sym sim::sys_borrow_write,
+                options(noreturn),
+            )
         } else {
             compile_error!("missing sys_borrow_write_stub for ARM profile")
         }
@@ -903,6 +944,12 @@ unsafe extern "C" fn sys_borrow_info_stub(
                 sysnum = const Sysnum::BorrowInfo as u32,
                 options(noreturn),
             )
+        } else if #[cfg(target_arch = "x86")] {
+            arch::asm!(
+                "jmp {imp}",
+                imp = sym sim::sys_borrow_info,
+                options(noreturn),
+            )
         } else {
             compile_error!("missing sys_borrow_write_stub for ARM profile")
         }
@@ -972,6 +1019,12 @@ unsafe extern "C" fn sys_irq_control_stub(_mask: u32, _enable: u32) {
                 sysnum = const Sysnum::IrqControl as u32,
                 options(noreturn),
             )
+        } else if #[cfg(target_arch = "x86")] {
+            arch::asm!(
+                "jmp {imp}",
+                imp = sym sim::sys_irq_control,
+                options(noreturn),
+            )
         } else {
             compile_error!("missing sys_irq_control stub for ARM profile")
         }
@@ -1033,6 +1086,12 @@ unsafe extern "C" fn sys_panic_stub(_msg: *const u8, _len: usize) -> ! {
                 sysnum = const Sysnum::Panic as u32,
                 options(noreturn),
             )
+        } else if #[cfg(target_arch = "x86")] {
+            arch::asm!(
+                "jmp {imp}",
+                imp = sym sim::sys_panic,
+                options(noreturn),
+            )
         } else {
             compile_error!("missing sys_panic_stub for ARM profile")
         }
@@ -1152,6 +1211,12 @@ unsafe extern "C" fn sys_get_timer_stub(_out: *mut RawTimerState) {
                 sysnum = const Sysnum::GetTimer as u32,
                 options(noreturn),
             )
+        } else if #[cfg(target_arch = "x86")] {
+            arch::asm!(
+                "jmp {imp}",
+                imp = sym sim::sys_get_timer,
+                options(noreturn),
+            )
         } else {
             compile_error!("missing sys_get_timer_stub for ARM profile")
         }
@@ -1279,6 +1344,46 @@ pub unsafe extern "C" fn _start() -> ! {
                 main = sym main,
                 options(noreturn),
             )
+        } else if #[cfg(target_arch = "x86")] {
+            // In the hosted simulator, the kernel calls us with the address of
+            // its syscall entry point as our only argument; see `sim`.
+            arch::asm!("
+                # Hang on to the kernel entry point in a register that the
+                # copies below leave alone.
+                mov ebx, [esp + 4]
+
+                # Copy data initialization image into data section.
+                # Note: this assumes that both source and destination are
+                # 32-bit aligned and padded to 4-byte boundary.
+                mov esi, offset __sidata
+                mov edi, offset __sdata
+                mov ecx, offset __edata
+                sub ecx, edi
+                shr ecx, 2
+                cld
+                rep movsd
+
+                # Zero BSS section.
+                mov edi, offset __sbss
+                mov ecx, offset __ebss
+                sub ecx, edi
+                shr ecx, 2
+                xor eax, eax
+                rep stosd
+
+                # Only now that BSS is clear can we record the entry point.
+                mov dword ptr [{entry}], ebx
+
+                # To the user entry point, with the stack aligned the way the
+                # SysV ABI wants it. (It's not supposed to return.)
+                and esp, -16
+                call {main}
+                ud2
+                ",
+                entry = sym sim::KERNEL_ENTRY,
+                main = sym main,
+                options(noreturn),
+            )
         } else {
             compile_error!("missing .start routine for ARM profile")
         }
@@ -1510,6 +1615,12 @@ unsafe extern "C" fn sys_refresh_task_id_stub(_tid: u32) -> u32 {
                 sysnum = const Sysnum::RefreshTaskId as u32,
                 options(noreturn),
             )
+        } else if #[cfg(target_arch = "x86")] {
+            arch::asm!(
+                "jmp {imp}",
+                imp = sym sim::sys_refresh_task_id,
+                options(noreturn),
+            )
         } else {
             compile_error!("missing sys_refresh_task_id stub for ARM profile")
         }
@@ -1580,12 +1691,124 @@ unsafe extern "C" fn sys_post_stub(_tid: u32, _mask: u32) -> u32 {
                 sysnum = const Sysnum::Post as u32,
                 options(noreturn),
             )
+        } else if #[cfg(target_arch = "x86")] {
+            arch::asm!(
+                "jmp {imp}",
+                imp = sym sim::sys_post,
+                options(noreturn),
+            )
         } else {
             compile_error!("missing sys_post_stub for ARM profile")
         }
     }
 }
 
+/// Acquires the kernel lock `lock`, blocking until it's available.
+///
+/// Lock numbers are generated for each task by `build_util::build_locks`. The
+/// caller must be permitted to use the lock (by naming it in its `locks` list
+/// in the app.toml) and must not already hold it, or it will be faulted. See
+/// `hl::lock` for a guard that releases the lock when dropped.
+#[inline(always)]
+pub fn sys_lock(lock: u32) {
+    let rc = unsafe { sys_lock_stub(lock, LockOp::Acquire as u32) };
+    // Blocking acquires can only succeed or fault.
+    debug_assert_eq!(rc, 0);
+}
+
+/// Attempts to acquire the kernel lock `lock` without blocking. Returns `true`
+/// if the lock was acquired, or `false` if it's held by another task.
+#[inline(always)]
+pub fn sys_try_lock(lock: u32) -> bool {
+    unsafe { sys_lock_stub(lock, LockOp::TryAcquire as u32) == 0 }
+}
+
+/// Releases the kernel lock `lock`, which the caller must hold.
+#[inline(always)]
+pub fn sys_unlock(lock: u32) {
+    unsafe {
+        sys_lock_stub(lock, LockOp::Release as u32);
+    }
+}
+
+/// Operations for the LOCK syscall.
+enum LockOp {
+    Release = 0,
+    Acquire = 1,
+    TryAcquire = 2,
+}
+
+/// Core implementation of the LOCK syscall.
+///
+/// See the note on syscall stubs at the top of this module for rationale.
+#[naked]
+unsafe extern "C" fn sys_lock_stub(_lock: u32, _op: u32) -> u32 {
+    cfg_if::cfg_if! {
+        if #[cfg(armv6m)] {
+            arch::asm!("
+                @ Spill the registers we're about to use to pass stuff.
+                push {{r4, r5, lr}}
+                mov r4, r11
+                push {{r4}}
+
+                @ Load the constant syscall number.
+                movs r4, #0
+                adds r4, #{sysnum}
+                mov r11, r4
+
+                @ Move register arguments into place.
+                mov r4, r0
+                mov r5, r1
+
+                @ To the kernel!
+                svc #0
+
+                @ Move result into place.
+                mov r0, r4
+
+                @ Restore the registers we used and return.
+                pop {{r4}}
+                mov r11, r4
+                pop {{r4, r5, pc}}
+                ",
+                sysnum = const Sysnum::Lock as u32,
+                options(noreturn),
+            )
+        } else if #[cfg(any(armv7m, armv8m))] {
+            arch::asm!("
+                @ Spill the registers we're about to use to pass stuff.
+                push {{r4, r5, r11, lr}}
+
+                @ Move register arguments into place.
+                mov r4, r0
+                mov r5, r1
+                @ Load the constant syscall number.
+                mov r11, {sysnum}
+
+                @ To the kernel!
+                svc #0
+
+                @ Move result into place.
+                mov r0, r4
+
+                @ Restore the registers we used and return.
+                pop {{r4, r5, r11, pc}}
+                ",
+                sysnum = const Sysnum::Lock as u32,
+                options(noreturn),
+            )
+        } else if #[cfg(target_arch = "x86")] {
+            arch::asm!(
+                "jmp {imp}",
+                imp = sym sim::sys_lock,
+                options(noreturn),
+            )
+        } else {
+            compile_error!("missing sys_lock_stub for ARM profile")
+        }
+    }
+}
+
 #[inline(always)]
 pub fn sys_reply_fault(task_id: TaskId, reason: ReplyFaultReason) {
     unsafe { sys_reply_fault_stub(task_id.0 as u32, reason as u32) }
@@ -1647,6 +1870,12 @@ unsafe extern "C" fn sys_reply_fault_stub(_tid: u32, _reason: u32) {
                 sysnum = const Sysnum::ReplyFault as u32,
                 options(noreturn),
             )
+        } else if #[cfg(target_arch = "x86")] {
+            arch::asm!(
+                "jmp {imp}",
+                imp = sym sim::sys_reply_fault,
+                options(noreturn),
+            )
         } else {
             compile_error!("missing sys_reply_fault_stub for ARM profile")
         }
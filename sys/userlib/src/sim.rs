@@ -0,0 +1,318 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Support for running tasks under the hosted simulator (`kern::arch::sim`).
+//!
+//! In a simulated image the kernel is ordinary code in the same host process,
+//! and it passes `_start` the address of its syscall entry point. The
+//! functions here stand in for the `svc`-based syscall stubs: each one packs
+//! its arguments into the seven registers the ARM stub would have used, calls
+//! the kernel, and unpacks results from those same registers, so the kernel's
+//! syscall implementations can't tell the difference.
+//!
+//! This module also provides *host calls*, which talk to the simulator
+//! itself. These have no hardware equivalent, so code that uses them has to be
+//! conditional on `target_arch = "x86"`.
+
+use crate::*;
+
+/// Kernel syscall entry point, as handed to `_start`.
+///
+/// Written exactly once, by `_start`, before any Rust code runs.
+pub(crate) static mut KERNEL_ENTRY: usize = 0;
+
+/// Calls into the kernel with syscall (or host call) number `nr`.
+///
+/// `regs` holds the seven argument registers on the way in, and the six result
+/// registers on the way out.
+#[inline(always)]
+unsafe fn syscall(nr: u32, regs: &mut [u32; 7]) {
+    // Safety: `_start` has stored the entry point the kernel gave it, which is
+    // a function with this signature.
+    let entry: unsafe extern "C" fn(u32, *mut [u32; 7]) =
+        unsafe { core::mem::transmute(KERNEL_ENTRY) };
+    unsafe { entry(nr, regs) }
+}
+
+fn rc_len(regs: &[u32; 7]) -> RcLen {
+    RcLen(u64::from(regs[0]) | u64::from(regs[1]) << 32)
+}
+
+pub(crate) unsafe extern "C" fn sys_send(args: &mut SendArgs<'_>) -> RcLen {
+    let mut regs = [
+        args.packed_target_operation,
+        args.outgoing_ptr as u32,
+        args.outgoing_len as u32,
+        args.incoming_ptr as u32,
+        args.incoming_len as u32,
+        args.lease_ptr as u32,
+        args.lease_len as u32,
+    ];
+    unsafe { syscall(Sysnum::Send as u32, &mut regs) };
+    rc_len(&regs)
+}
+
+pub(crate) unsafe extern "C" fn sys_recv(
+    buffer_ptr: *mut u8,
+    buffer_len: usize,
+    notification_mask: u32,
+    specific_sender: u32,
+    out: *mut RawRecvMessage,
+) -> u32 {
+    let mut regs = [
+        buffer_ptr as u32,
+        buffer_len as u32,
+        notification_mask,
+        specific_sender,
+        0,
+        0,
+        0,
+    ];
+    unsafe { syscall(Sysnum::Recv as u32, &mut regs) };
+    let msg = RawRecvMessage {
+        sender: regs[1],
+        operation: regs[2],
+        message_len: regs[3] as usize,
+        response_capacity: regs[4] as usize,
+        lease_count: regs[5] as usize,
+    };
+    // Safety: our caller passes a pointer to space for the result.
+    unsafe { out.write(msg) };
+    regs[0]
+}
+
+pub(crate) unsafe extern "C" fn sys_reply(
+    peer: u32,
+    code: u32,
+    message_ptr: *const u8,
+    message_len: usize,
+) {
+    let mut regs =
+        [peer, code, message_ptr as u32, message_len as u32, 0, 0, 0];
+    unsafe { syscall(Sysnum::Reply as u32, &mut regs) };
+}
+
+pub(crate) unsafe extern "C" fn sys_set_timer(
+    set_timer: u32,
+    deadline_lo: u32,
+    deadline_hi: u32,
+    notification: u32,
+) {
+    let mut regs = [set_timer, deadline_lo, deadline_hi, notification, 0, 0, 0];
+    unsafe { syscall(Sysnum::SetTimer as u32, &mut regs) };
+}
+
+pub(crate) unsafe extern "C" fn sys_borrow_read(
+    args: *mut BorrowReadArgs,
+) -> RcLen {
+    // Safety: our caller passes a valid argument block.
+    let args = unsafe { &*args };
+    let mut regs = [
+        args.lender,
+        args.index as u32,
+        args.offset as u32,
+        args.dest as u32,
+        args.dest_len as u32,
+        0,
+        0,
+    ];
+    unsafe { syscall(Sysnum::BorrowRead as u32, &mut regs) };
+    rc_len(&regs)
+}
+
+pub(crate) unsafe extern "C" fn sys_borrow_write(
+    args: *mut BorrowWriteArgs,
+) -> RcLen {
+    // Safety: our caller passes a valid argument block.
+    let args = unsafe { &*args };
+    let mut regs = [
+        args.lender,
+        args.index as u32,
+        args.offset as u32,
+        args.src as u32,
+        args.src_len as u32,
+        0,
+        0,
+    ];
+    unsafe { syscall(Sysnum::BorrowWrite as u32, &mut regs) };
+    rc_len(&regs)
+}
+
+pub(crate) unsafe extern "C" fn sys_borrow_info(
+    lender: u32,
+    index: usize,
+    out: *mut RawBorrowInfo,
+) {
+    let mut regs = [lender, index as u32, 0, 0, 0, 0, 0];
+    unsafe { syscall(Sysnum::BorrowInfo as u32, &mut regs) };
+    let info = RawBorrowInfo {
+        rc: regs[0],
+        atts: regs[1],
+        length: regs[2] as usize,
+    };
+    // Safety: our caller passes a pointer to space for the result.
+    unsafe { out.write(info) };
+}
+
+pub(crate) unsafe extern "C" fn sys_irq_control(mask: u32, enable: u32) {
+    let mut regs = [mask, enable, 0, 0, 0, 0, 0];
+    unsafe { syscall(Sysnum::IrqControl as u32, &mut regs) };
+}
+
+pub(crate) unsafe extern "C" fn sys_panic(msg: *const u8, len: usize) -> ! {
+    let mut regs = [msg as u32, len as u32, 0, 0, 0, 0, 0];
+    unsafe { syscall(Sysnum::Panic as u32, &mut regs) };
+    // The kernel doesn't return from a panic; if it somehow did, stop here
+    // rather than running off into the caller.
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+pub(crate) unsafe extern "C" fn sys_get_timer(out: *mut RawTimerState) {
+    let mut regs = [0; 7];
+    unsafe { syscall(Sysnum::GetTimer as u32, &mut regs) };
+    let state = RawTimerState {
+        now_lo: regs[0],
+        now_hi: regs[1],
+        set: regs[2],
+        dl_lo: regs[3],
+        dl_hi: regs[4],
+        on_dl: regs[5],
+    };
+    // Safety: our caller passes a pointer to space for the result.
+    unsafe { out.write(state) };
+}
+
+pub(crate) unsafe extern "C" fn sys_refresh_task_id(tid: u32) -> u32 {
+    let mut regs = [tid, 0, 0, 0, 0, 0, 0];
+    unsafe { syscall(Sysnum::RefreshTaskId as u32, &mut regs) };
+    regs[0]
+}
+
+pub(crate) unsafe extern "C" fn sys_post(tid: u32, mask: u32) -> u32 {
+    let mut regs = [tid, mask, 0, 0, 0, 0, 0];
+    unsafe { syscall(Sysnum::Post as u32, &mut regs) };
+    regs[0]
+}
+
+pub(crate) unsafe extern "C" fn sys_lock(lock: u32, op: u32) -> u32 {
+    let mut regs = [lock, op, 0, 0, 0, 0, 0];
+    unsafe { syscall(Sysnum::Lock as u32, &mut regs) };
+    regs[0]
+}
+
+pub(crate) unsafe extern "C" fn sys_reply_fault(tid: u32, reason: u32) {
+    let mut regs = [tid, reason, 0, 0, 0, 0, 0];
+    unsafe { syscall(Sysnum::ReplyFault as u32, &mut regs) };
+}
+
+/// Writes `msg` to the simulator's console (its standard error).
+pub fn write(msg: &[u8]) {
+    let mut regs = [msg.as_ptr() as u32, msg.len() as u32, 0, 0, 0, 0, 0];
+    unsafe { syscall(Hostcall::Write as u32, &mut regs) };
+}
+
+/// Ends the simulation, making `status` the exit status of the simulator
+/// process.
+pub fn exit(status: u32) -> ! {
+    let mut regs = [status, 0, 0, 0, 0, 0, 0];
+    unsafe { syscall(Hostcall::Exit as u32, &mut regs) };
+    unreachable!()
+}
+
+/// Waits until the scheduler next runs, which is as close as the simulator
+/// gets to waiting for an interrupt. This is meant for the idle task.
+pub fn wfi() {
+    let mut regs = [0; 7];
+    unsafe { syscall(Hostcall::WaitForInterrupt as u32, &mut regs) };
+}
+
+// Tasks for the simulator are built for a hosted target, where the compiler
+// expects the C library to provide the memory primitives it calls. Tasks have
+// no C library, so we provide them here. They're written with string
+// instructions so that the compiler can't "helpfully" turn them back into
+// calls to themselves.
+
+#[no_mangle]
+pub unsafe extern "C" fn memcpy(
+    dest: *mut u8,
+    src: *const u8,
+    n: usize,
+) -> *mut u8 {
+    // `esi` is reserved by the compiler, so we have to save it ourselves.
+    unsafe {
+        core::arch::asm!(
+            "xchg esi, {src}",
+            "rep movsb",
+            "mov esi, {src}",
+            src = inout(reg) src => _,
+            inout("edi") dest => _,
+            inout("ecx") n => _,
+            options(nostack, preserves_flags),
+        );
+    }
+    dest
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn memmove(
+    dest: *mut u8,
+    src: *const u8,
+    n: usize,
+) -> *mut u8 {
+    if (dest as usize).wrapping_sub(src as usize) >= n {
+        // Either dest is below src, or the two don't overlap, so copying
+        // forward is safe.
+        return unsafe { memcpy(dest, src, n) };
+    }
+    if n != 0 {
+        // dest overlaps the end of src, so copy backward.
+        unsafe {
+            core::arch::asm!(
+                "xchg esi, {src}",
+                "std",
+                "rep movsb",
+                "cld",
+                "mov esi, {src}",
+                src = inout(reg) src.add(n - 1) => _,
+                inout("edi") dest.add(n - 1) => _,
+                inout("ecx") n => _,
+                options(nostack),
+            );
+        }
+    }
+    dest
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn memset(dest: *mut u8, c: i32, n: usize) -> *mut u8 {
+    unsafe {
+        core::arch::asm!(
+            "rep stosb",
+            inout("edi") dest => _,
+            inout("ecx") n => _,
+            in("al") c as u8,
+            options(nostack, preserves_flags),
+        );
+    }
+    dest
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn memcmp(a: *const u8, b: *const u8, n: usize) -> i32 {
+    for i in 0..n {
+        // Safety: our caller promises both buffers are n bytes long.
+        let (x, y) = unsafe { (*a.add(i), *b.add(i)) };
+        if x != y {
+            return i32::from(x) - i32::from(y);
+        }
+    }
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn bcmp(a: *const u8, b: *const u8, n: usize) -> i32 {
+    unsafe { memcmp(a, b, n) }
+}
@@ -283,6 +283,13 @@ impl idl::InOrderControlPlaneAgentImpl for ServerImpl {
         Ok(self.mgs_handler.identity())
     }
 
+    fn sp_reset_pending(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+    ) -> Result<bool, RequestError<core::convert::Infallible>> {
+        Ok(self.mgs_handler.sp_reset_pending())
+    }
+
     #[cfg(feature = "gimlet")]
     fn get_installinator_image_id(
         &mut self,
@@ -182,6 +182,12 @@ impl MgsCommon {
         }
     }
 
+    /// Returns true if we've been asked to prepare to reset the SP itself,
+    /// and are waiting for the trigger.
+    pub(crate) fn sp_reset_pending(&self) -> bool {
+        self.reset_component_requested == Some(SpComponent::SP_ITSELF)
+    }
+
     /// If the targeted component is the SP_ITSELF, then having reset itself,
     /// it will not be able to respond to the later reset_trigger message.
     ///
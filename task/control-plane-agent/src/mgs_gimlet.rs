@@ -159,6 +159,10 @@ impl MgsHandler {
         self.common.identity()
     }
 
+    pub(crate) fn sp_reset_pending(&self) -> bool {
+        self.common.sp_reset_pending()
+    }
+
     pub(crate) fn installinator_image_id(&self) -> &[u8] {
         self.installinator_image_id
     }
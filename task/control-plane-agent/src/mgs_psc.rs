@@ -66,6 +66,10 @@ impl MgsHandler {
         self.common.identity()
     }
 
+    pub(crate) fn sp_reset_pending(&self) -> bool {
+        self.common.sp_reset_pending()
+    }
+
     /// If we want to be woken by the system timer, we return a deadline here.
     /// `main()` is responsible for calling this method and actually setting the
     /// timer.
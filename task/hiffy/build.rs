@@ -2,7 +2,81 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-fn main() {
+use anyhow::{bail, Context, Result};
+use build_util::{AllowSend, HmacKey};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Config {
+    net: Option<NetConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct NetConfig {
+    /// Keys with which clients may authenticate programs sent over the
+    /// network. A request names its key by index in this list.
+    keys: Vec<HmacKey>,
+
+    /// Tasks that programs from the network may send to. Anything not listed
+    /// is refused, so by default they can't send at all.
+    #[serde(default)]
+    allow: Vec<AllowSend>,
+}
+
+/// The part of `udprpc`'s config that we check our keys against.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct UdprpcConfig {
+    keys: Vec<HmacKey>,
+}
+
+fn main() -> Result<()> {
     build_util::expose_m_profile();
     build_util::expose_target_board();
+
+    let cfg =
+        build_util::task_maybe_config::<Config>()?.and_then(|cfg| cfg.net);
+    match (build_util::has_feature("net"), cfg) {
+        (true, Some(cfg)) => generate_net_config(&cfg)?,
+        (true, None) => {
+            bail!("the net feature needs [tasks.hiffy.config.net]")
+        }
+        (false, Some(_)) => {
+            bail!("[tasks.hiffy.config.net] needs the net feature")
+        }
+        (false, None) => (),
+    }
+
+    Ok(())
+}
+
+fn generate_net_config(cfg: &NetConfig) -> Result<()> {
+    build_util::build_notifications()?;
+
+    // A client trusted to make RPC calls shouldn't thereby be able to run
+    // arbitrary programs, so we refuse to share keys with `udprpc`.
+    if let Ok(udprpc) = build_util::other_task_full_config_toml("udprpc") {
+        if let Some(config) = udprpc.config {
+            let udprpc: UdprpcConfig =
+                config.try_into().context("parsing udprpc keys")?;
+            for (i, key) in cfg.keys.iter().enumerate() {
+                let key = key
+                    .resolve()
+                    .with_context(|| format!("parsing hiffy key {i}"))?;
+                for other in &udprpc.keys {
+                    if other.resolve().ok() == Some(key) {
+                        bail!("hiffy key {i} is also a udprpc key");
+                    }
+                }
+            }
+        }
+    }
+
+    let out_dir = build_util::out_dir();
+    let dest_path = out_dir.join("hiffy_net_config.rs");
+    let mut out = std::fs::File::create(dest_path)
+        .context("creating hiffy_net_config.rs")?;
+    build_util::write_net_auth_config(&mut out, "hiffy", &cfg.keys, &cfg.allow)
 }
@@ -124,6 +124,9 @@ pub(crate) fn send(
         }
     };
 
+    #[cfg(feature = "net")]
+    crate::net::check_send(task.index(), op)?;
+
     //
     // Time to assemble the actual bytes of our payload.
     //
@@ -249,6 +252,9 @@ pub(crate) fn send_lease_read(
         }
     };
 
+    #[cfg(feature = "net")]
+    crate::net::check_send(task.index(), op)?;
+
     //
     // Time to assemble the actual bytes of our payload.
     //
@@ -376,6 +382,9 @@ pub(crate) fn send_lease_write(
         }
     };
 
+    #[cfg(feature = "net")]
+    crate::net::check_send(task.index(), op)?;
+
     //
     // Time to assemble the actual bytes of our payload.
     //
@@ -529,6 +538,9 @@ pub(crate) fn send_lease_read_write(
         }
     };
 
+    #[cfg(feature = "net")]
+    crate::net::check_send(task.index(), op)?;
+
     if nbytes > payload.len() {
         return Err(Failure::Fault(Fault::StackUnderflow));
     }
@@ -1000,7 +1012,7 @@ pub(crate) fn hash_finalize_sha256(
 }
 
 #[cfg(feature = "rng")]
-task_slot!(RNG, rng_driver);
+task_slot!(pub(crate) RNG, rng_driver);
 
 #[cfg(feature = "rng")]
 pub(crate) fn rng_fill(
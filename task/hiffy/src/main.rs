@@ -11,6 +11,10 @@
 //! debugger places HIF in [`HIFFY_TEXT`], and then indicates that text is
 //! present by incrementing [`HIFFY_KICK`].  This task executes the specified
 //! HIF, with the return stack located in [`HIFFY_RSTACK`].
+//!
+//! With the `net` feature, this task also accepts HIF over the network, so
+//! that racked systems can be diagnosed without a debug probe; see the `net`
+//! module.
 
 #![no_std]
 #![no_main]
@@ -25,6 +29,9 @@ use armv6m_atomic_hack::AtomicU32Ext;
 
 mod common;
 
+#[cfg(feature = "net")]
+mod net;
+
 cfg_if::cfg_if! {
     if #[cfg(feature = "stm32h7")] {
         pub mod stm32h7;
@@ -127,12 +134,48 @@ static HIFFY_VERSION_MAJOR: AtomicU32 = AtomicU32::new(HIF_VERSION_MAJOR);
 static HIFFY_VERSION_MINOR: AtomicU32 = AtomicU32::new(HIF_VERSION_MINOR);
 static HIFFY_VERSION_PATCH: AtomicU32 = AtomicU32::new(HIF_VERSION_PATCH);
 
+const NLABELS: usize = 4;
+
+/// Executes the HIF in `text`, with results going to `rstack`.
+fn run(
+    text: &[u8],
+    data: &[u8],
+    stack: &mut [Option<u32>],
+    rstack: &mut [u8],
+) -> Result<(), Failure> {
+    let check = |offset: usize, op: &Op| -> Result<(), Failure> {
+        trace_execute(offset, *op);
+        Ok(())
+    };
+
+    // XXX: workaround for false-positive due to rust-lang/rust-clippy#9126
+    #[allow(clippy::explicit_auto_deref)]
+    let rv = execute::<_, NLABELS>(
+        text,
+        HIFFY_FUNCS,
+        data,
+        stack,
+        rstack,
+        &mut *HIFFY_SCRATCH.borrow_mut(),
+        check,
+    );
+
+    match rv {
+        Ok(_) => trace_success(),
+        Err(failure) => trace_failure(failure),
+    }
+
+    rv
+}
+
 #[export_name = "main"]
 fn main() -> ! {
     let mut sleep_ms = 250;
     let mut sleeps = 0;
     let mut stack = [None; 32];
-    const NLABELS: usize = 4;
+
+    #[cfg(feature = "net")]
+    let mut net = net::NetServer::new();
 
     //
     // Sadly, there seems to be no other way to force these variables to
@@ -144,9 +187,15 @@ fn main() -> ! {
 
     loop {
         HIFFY_READY.fetch_add(1, Ordering::SeqCst);
+        #[cfg(not(feature = "net"))]
         hl::sleep_for(sleep_ms);
+        #[cfg(feature = "net")]
+        net.sleep_for(sleep_ms);
         HIFFY_READY.fetch_sub(1, Ordering::SeqCst);
 
+        #[cfg(feature = "net")]
+        net.poll(&mut stack);
+
         if HIFFY_KICK.load(Ordering::SeqCst) == 0 {
             sleeps += 1;
 
@@ -171,35 +220,17 @@ fn main() -> ! {
         let data = unsafe { &HIFFY_DATA };
         let rstack = unsafe { &mut HIFFY_RSTACK[0..] };
 
-        let check = |offset: usize, op: &Op| -> Result<(), Failure> {
-            trace_execute(offset, *op);
-            Ok(())
-        };
-
-        // XXX: workaround for false-positive due to rust-lang/rust-clippy#9126
-        #[allow(clippy::explicit_auto_deref)]
-        let rv = execute::<_, NLABELS>(
-            text,
-            HIFFY_FUNCS,
-            data,
-            &mut stack,
-            rstack,
-            &mut *HIFFY_SCRATCH.borrow_mut(),
-            check,
-        );
+        let rv = run(text, data, &mut stack, rstack);
 
         match rv {
             Ok(_) => {
                 HIFFY_REQUESTS.fetch_add(1, Ordering::SeqCst);
-                trace_success();
             }
             Err(failure) => {
                 HIFFY_ERRORS.fetch_add(1, Ordering::SeqCst);
                 unsafe {
                     HIFFY_FAILURE = Some(failure);
                 }
-
-                trace_failure(failure);
             }
         }
     }
@@ -0,0 +1,383 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Running HIF over the network
+//!
+//! This lets Humility run HIF (e.g. for `humility i2c` or `humility pmbus`)
+//! against a system with no debug probe attached, by sending it to our UDP
+//! socket. HIF can do nearly anything, so, as with `udprpc`, every request
+//! is authenticated (as described in `net-auth`) under one of the keys in our
+//! `app.toml` config. Those keys should differ from `udprpc`'s, so that a
+//! client trusted with one isn't trusted with the other.
+//!
+//! A request is a `NetHeader`, then `ntext` bytes of HIF text, then `ndata`
+//! bytes of data, then the HMAC of all that under the key that the header
+//! names. A client that doesn't know the session can send any properly signed
+//! request, and learn the session from the `BadSession` reply.
+//!
+//! Programs from the network run with their own return stack, and don't
+//! touch [`HIFFY_REQUESTS`](crate::HIFFY_REQUESTS) and friends, so that they
+//! can't be mistaken for programs from the debugger. They also may only
+//! `Send` (or `SendLease*`) to the tasks and operations in our config's
+//! `allow` list, which is empty unless configured; see [`check_send`].
+//!
+//! Every reply packet starts with a one-byte `NetReply`; see `NetServer::poll`
+//! for what follows it. For `Ok`, the rest of the `ReplyHeader` says whether
+//! the program failed, and then comes the result:
+//!
+//! - if the program succeeded, its return stack, less any trailing zeroes
+//!   (which the client should put back to get the full return stack);
+//! - if the program failed, its `hif::Failure`, hubpacked.
+//!
+//! A result too big for one packet is split across several, each giving its
+//! offset into the whole. Every packet of an `Ok` reply ends with a tag
+//! binding it to the request, per `net_auth::Verified::sign`.
+
+use crate::common::RNG;
+use core::sync::atomic::{AtomicBool, Ordering};
+use drv_rng_api::Rng;
+use hif::{Failure, Fault};
+use net_auth::{AuthError, Authenticator, Credentials, Verified, TAG_SIZE};
+use static_cell::StaticCell;
+use task_net_api::*;
+use userlib::*;
+use zerocopy::{AsBytes, FromBytes, LittleEndian, U16, U64};
+
+task_slot!(NET, net);
+
+const SOCKET: SocketName = SocketName::hiffy;
+
+/// Version of the wire protocol described above.
+const NET_VERSION: u8 = 1;
+
+/// Largest packet that we send or receive.
+const PACKET_SIZE: usize = 1024;
+
+#[derive(Copy, Clone, Debug)]
+#[repr(u8)]
+enum NetReply {
+    Ok,
+    /// The packet was too short to include the complete header
+    TooShort,
+    /// The packet's protocol version does not match ours
+    BadVersion,
+    /// The size of the packet does not agree with the `ntext` and `ndata`
+    /// fields of the header
+    LengthMismatch,
+    /// The packet names a key that we don't have
+    BadKey,
+    /// The packet's HMAC is wrong
+    BadMac,
+    /// The packet's image ID does not match ours
+    BadImageId,
+    /// The packet's session ID does not match ours
+    BadSession,
+    /// The packet's sequence number has already been used
+    Replayed,
+}
+
+impl From<AuthError> for NetReply {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::BadKey => Self::BadKey,
+            AuthError::BadMac => Self::BadMac,
+            AuthError::BadImageId => Self::BadImageId,
+            AuthError::BadSession => Self::BadSession,
+            AuthError::Replayed => Self::Replayed,
+        }
+    }
+}
+
+/// Header for a request
+#[derive(Copy, Clone, Debug, FromBytes)]
+#[repr(C)]
+struct NetHeader {
+    version: u8,
+    key: u8,
+    _reserved: [u8; 6],
+    image_id: U64<LittleEndian>,
+    session: U64<LittleEndian>,
+    sequence: U64<LittleEndian>,
+    ntext: U16<LittleEndian>,
+    ndata: U16<LittleEndian>,
+}
+
+/// Header for each packet of an `Ok` reply
+#[derive(Copy, Clone, Debug, AsBytes)]
+#[repr(C)]
+struct ReplyHeader {
+    reply: u8,
+    /// Non-zero if the result is a `hif::Failure`
+    failed: u8,
+    /// Length of the whole result
+    total: U16<LittleEndian>,
+    /// Offset into the result of this packet's piece of it
+    offset: U16<LittleEndian>,
+}
+
+const HEADER_SIZE: usize = core::mem::size_of::<NetHeader>();
+const REPLY_HEADER_SIZE: usize = core::mem::size_of::<ReplyHeader>();
+
+/// Return stack for programs from the network.
+static NET_RSTACK: StaticCell<[u8; crate::HIFFY_RSTACK_SIZE]> =
+    StaticCell::new([0; crate::HIFFY_RSTACK_SIZE]);
+
+/// Set while a program from the network is running.
+static FROM_NETWORK: AtomicBool = AtomicBool::new(false);
+
+/// Checks whether the running program may send `op` to the task with index
+/// `task`. Programs from the debugger may send anything; programs from the
+/// network may only send what our `allow` list permits.
+pub(crate) fn check_send(task: usize, op: u16) -> Result<(), Failure> {
+    if !FROM_NETWORK.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    if !generated::ALLOWED.iter().any(|&(t, _)| t == task) {
+        return Err(Failure::Fault(Fault::BadParameter(0)));
+    }
+    if !net_auth::is_allowed(&generated::ALLOWED, task, op) {
+        return Err(Failure::Fault(Fault::BadParameter(1)));
+    }
+    Ok(())
+}
+
+/// A request that has passed all our checks.
+struct Request {
+    verified: Verified,
+    ntext: usize,
+    ndata: usize,
+}
+
+pub(crate) struct NetServer {
+    net: Net,
+    auth: Authenticator<{ generated::KEY_COUNT }>,
+}
+
+impl NetServer {
+    pub(crate) fn new() -> Self {
+        // Picking the session at random means that requests captured before
+        // we (or the whole SP) restarted can't be replayed afterwards.
+        let mut session = 0u64;
+        Rng::from(RNG.get_task_id())
+            .fill(session.as_bytes_mut())
+            .unwrap_lite();
+
+        Self {
+            net: Net::from(NET.get_task_id()),
+            // Function indices in HIF text are specific to an image, so the
+            // client must be built against exactly this one.
+            auth: Authenticator::new(
+                &generated::KEYS,
+                kipc::read_image_id(),
+                session,
+            ),
+        }
+    }
+
+    /// Sleeps for `ms`, or until a packet arrives on our socket, whichever
+    /// is sooner.
+    pub(crate) fn sleep_for(&self, ms: u64) {
+        let deadline = sys_get_timer().now.saturating_add(ms + 1);
+        sys_set_timer(Some(deadline), notifications::TIMER_MASK);
+        sys_recv_closed(
+            &mut [],
+            notifications::TIMER_MASK | notifications::SOCKET_MASK,
+            TaskId::KERNEL,
+        )
+        .unwrap_lite();
+    }
+
+    /// Runs every program waiting on our socket, using `stack` as the HIF
+    /// stack.
+    pub(crate) fn poll(&mut self, stack: &mut [Option<u32>]) {
+        // We're short on RAM, so replies are built in the same buffer that
+        // their request arrived in.
+        let mut buf = [0u8; PACKET_SIZE];
+
+        loop {
+            let mut meta = match self.net.recv_packet(
+                SOCKET,
+                LargePayloadBehavior::Discard,
+                &mut buf,
+            ) {
+                Ok(meta) => meta,
+                Err(RecvError::QueueEmpty) => return,
+                // `net` restarted (probably due to the watchdog); just retry.
+                Err(RecvError::ServerRestarted) => continue,
+                Err(RecvError::NotYours | RecvError::Other) => panic!(),
+            };
+
+            let request = match self.check(&buf[..meta.size as usize]) {
+                Ok(request) => request,
+                Err(reply) => {
+                    // The error replies are unsigned, and are followed by
+                    // - for `BadVersion`, our version, as a `u8`
+                    // - for `BadImageId` and `BadSession`, our image or
+                    //   session ID, as a 64-bit little-endian value
+                    // - for everything else, nothing
+                    buf[0] = reply as u8;
+                    let len = match reply {
+                        NetReply::BadVersion => {
+                            buf[1] = NET_VERSION;
+                            2
+                        }
+                        NetReply::BadImageId => {
+                            buf[1..9].copy_from_slice(
+                                &self.auth.image_id().to_le_bytes(),
+                            );
+                            9
+                        }
+                        NetReply::BadSession => {
+                            buf[1..9].copy_from_slice(
+                                &self.auth.session().to_le_bytes(),
+                            );
+                            9
+                        }
+                        _ => 1,
+                    };
+                    meta.size = len as u32;
+                    self.send(meta, &buf[..len]);
+                    continue;
+                }
+            };
+
+            let mut rstack = NET_RSTACK.borrow_mut();
+            rstack.fill(0);
+
+            let (text, rest) = buf[HEADER_SIZE..].split_at(request.ntext);
+            let data = &rest[..request.ndata];
+
+            FROM_NETWORK.store(true, Ordering::Relaxed);
+            let result = crate::run(text, data, stack, &mut *rstack);
+            FROM_NETWORK.store(false, Ordering::Relaxed);
+
+            match result {
+                Ok(()) => {
+                    let len = rstack
+                        .iter()
+                        .rposition(|&b| b != 0)
+                        .map(|i| i + 1)
+                        .unwrap_or(0);
+                    self.reply(meta, &request, false, &rstack[..len], &mut buf);
+                }
+                Err(failure) => {
+                    let mut encoded = [0u8; 32];
+                    let len = hubpack::serialize(&mut encoded, &failure)
+                        .unwrap_lite();
+                    self.reply(meta, &request, true, &encoded[..len], &mut buf);
+                }
+            }
+        }
+    }
+
+    /// Checks the request in `rx`, returning the reply to send if it fails.
+    fn check(&mut self, rx: &[u8]) -> Result<Request, NetReply> {
+        if rx.len() < HEADER_SIZE + TAG_SIZE {
+            return Err(NetReply::TooShort);
+        }
+
+        // We can always read the header, since it's raw data
+        let header = NetHeader::read_from(&rx[..HEADER_SIZE]).unwrap_lite();
+        let ntext = usize::from(header.ntext.get());
+        let ndata = usize::from(header.ndata.get());
+
+        if header.version != NET_VERSION {
+            return Err(NetReply::BadVersion);
+        }
+        if rx.len() != HEADER_SIZE + ntext + ndata + TAG_SIZE {
+            return Err(NetReply::LengthMismatch);
+        }
+
+        let (signed, tag) = rx.split_at(HEADER_SIZE + ntext + ndata);
+        let credentials = Credentials {
+            key: header.key,
+            image_id: header.image_id.get(),
+            session: header.session.get(),
+            sequence: header.sequence.get(),
+        };
+        let verified = self.auth.check(&credentials, signed, tag)?;
+
+        Ok(Request {
+            verified,
+            ntext,
+            ndata,
+        })
+    }
+
+    /// Sends `result` in as many signed packets as it takes.
+    fn reply(
+        &self,
+        mut meta: UdpMetadata,
+        request: &Request,
+        failed: bool,
+        result: &[u8],
+        buf: &mut [u8; PACKET_SIZE],
+    ) {
+        const CHUNK_SIZE: usize = PACKET_SIZE - REPLY_HEADER_SIZE - TAG_SIZE;
+
+        // An empty result still gets one packet, to say so.
+        let mut offset = 0;
+        loop {
+            let chunk =
+                &result[offset..][..(result.len() - offset).min(CHUNK_SIZE)];
+            let header = ReplyHeader {
+                reply: NetReply::Ok as u8,
+                failed: failed as u8,
+                total: U16::new(result.len() as u16),
+                offset: U16::new(offset as u16),
+            };
+            buf[..REPLY_HEADER_SIZE].copy_from_slice(header.as_bytes());
+            let end = REPLY_HEADER_SIZE + chunk.len();
+            buf[REPLY_HEADER_SIZE..end].copy_from_slice(chunk);
+
+            let tag = request.verified.sign(&buf[..end]);
+            buf[end..][..TAG_SIZE].copy_from_slice(&tag);
+
+            let len = end + TAG_SIZE;
+            meta.size = len as u32;
+            self.send(meta, &buf[..len]);
+
+            offset += chunk.len();
+            if offset >= result.len() {
+                break;
+            }
+        }
+    }
+
+    fn send(&self, meta: UdpMetadata, packet: &[u8]) {
+        loop {
+            match self.net.send_packet(SOCKET, meta, packet) {
+                Ok(()) => break,
+                // If `net` just restarted, immediately retry our send.
+                Err(SendError::ServerRestarted) => continue,
+                // If our tx queue is full, wait for space. Any packets that
+                // arrive meanwhile will be picked up by `poll`.
+                Err(SendError::QueueFull) => {
+                    sys_recv_closed(
+                        &mut [],
+                        notifications::SOCKET_MASK,
+                        TaskId::KERNEL,
+                    )
+                    .unwrap_lite();
+                }
+                // These errors should be impossible if we're configured
+                // correctly.
+                // (We only ever reply to the address a packet came from, which
+                // the socket must be able to reach.)
+                Err(
+                    SendError::NotYours
+                    | SendError::InvalidVLan
+                    | SendError::Unaddressable,
+                ) => unreachable!(),
+                Err(SendError::Other) => panic!(),
+            }
+        }
+    }
+}
+
+mod generated {
+    include!(concat!(env!("OUT_DIR"), "/hiffy_net_config.rs"));
+}
+
+include!(concat!(env!("OUT_DIR"), "/notifications.rs"));
@@ -0,0 +1,170 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Alerts for the host
+//!
+//! The tasks that know about the events we report to the host all run at a
+//! higher priority than we do, so they can't call into us; instead, we pull
+//! from them, either when jefe tells us about a power state change or when
+//! one of our polling timers fires. Alerts are queued until the host drains
+//! them with `GetAlert`; while any are queued, we hold
+//! `Status::ALERTS_AVAILABLE` (and therefore the SP-to-SP3 interrupt).
+
+use crate::Trace;
+use drv_sprot_api::SpRot;
+use heapless::Deque;
+use host_sp_messages::{Alert, SpToHost};
+use ringbuf::ringbuf_entry_root as ringbuf_entry;
+use task_control_plane_agent_api::ControlPlaneAgent;
+
+#[cfg(feature = "gimlet")]
+use userlib::{sys_refresh_task_id, TaskId};
+
+#[cfg(feature = "gimlet")]
+use task_power_api::{FaultCause, Power};
+
+#[cfg(feature = "gimlet")]
+userlib::task_slot!(POWER, power);
+
+/// Maximum number of alerts we hold for the host; if the host doesn't keep
+/// up, the oldest are dropped.
+const MAX_QUEUED_ALERTS: usize = 16;
+
+pub(crate) struct AlertQueue {
+    alerts: Deque<Alert, MAX_QUEUED_ALERTS>,
+    // Number of alerts dropped since the last `GetAlert`
+    dropped: u8,
+}
+
+impl AlertQueue {
+    pub(crate) fn new() -> Self {
+        Self {
+            alerts: Deque::new(),
+            dropped: 0,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.alerts.is_empty()
+    }
+
+    pub(crate) fn push(&mut self, alert: Alert) {
+        ringbuf_entry!(Trace::Alert(alert));
+        if self.alerts.is_full() {
+            self.alerts.pop_front();
+            self.dropped = self.dropped.saturating_add(1);
+        }
+        // We made room above, so this can't fail.
+        let _ = self.alerts.push_back(alert);
+    }
+
+    /// Removes the oldest alert from the queue, returning the response to a
+    /// `GetAlert` request of protocol version `version`.
+    ///
+    /// Hosts older than `version::V3` can't be told what the alert was, but
+    /// we still remove it so that they can clear `ALERTS_AVAILABLE` by
+    /// draining the queue.
+    pub(crate) fn pop(&mut self, version: u32) -> SpToHost {
+        let alert = self.alerts.pop_front();
+        if version < host_sp_messages::version::V3 {
+            return SpToHost::Alert { action: 0 };
+        }
+        SpToHost::QueuedAlert {
+            alert,
+            remaining: self.alerts.len() as u8,
+            dropped: core::mem::take(&mut self.dropped),
+        }
+    }
+}
+
+/// State for the alert sources we have to poll, which we use to only raise an
+/// alert when something changes.
+pub(crate) struct AlertSources {
+    rot_update_pending: bool,
+    sp_reset_pending: bool,
+    #[cfg(feature = "gimlet")]
+    power: Power,
+    // The `power` task as of our last poll, so we can tell when it restarts
+    #[cfg(feature = "gimlet")]
+    power_id: TaskId,
+    // Sequence number of the next power fault log capture we haven't seen
+    #[cfg(feature = "gimlet")]
+    next_fault_seq: u32,
+}
+
+impl AlertSources {
+    pub(crate) fn new() -> Self {
+        Self {
+            rot_update_pending: false,
+            sp_reset_pending: false,
+            #[cfg(feature = "gimlet")]
+            power: Power::from(POWER.get_task_id()),
+            #[cfg(feature = "gimlet")]
+            power_id: POWER.get_task_id(),
+            // Capture 0 is taken when `power` starts, and only records faults
+            // that were already present; we only alert on new ones.
+            #[cfg(feature = "gimlet")]
+            next_fault_seq: 1,
+        }
+    }
+
+    /// Checks the sources that are cheap to poll.
+    pub(crate) fn poll(
+        &mut self,
+        cp_agent: &ControlPlaneAgent,
+        queue: &mut AlertQueue,
+    ) {
+        let sp_reset_pending = cp_agent.sp_reset_pending();
+        if sp_reset_pending && !self.sp_reset_pending {
+            queue.push(Alert::SpResetImminent);
+        }
+        self.sp_reset_pending = sp_reset_pending;
+
+        #[cfg(feature = "gimlet")]
+        self.poll_power_faults(queue);
+    }
+
+    /// Checks whether the RoT will boot a different image when it next
+    /// resets, which requires talking to it over SPI (so we do so less often
+    /// than our other sources).
+    pub(crate) fn poll_rot(&mut self, sprot: &SpRot, queue: &mut AlertQueue) {
+        // If we can't reach the RoT, keep our last opinion.
+        let Ok(info) = sprot.rot_boot_info() else {
+            return;
+        };
+        let pending = info.pending_persistent_boot_preference.is_some()
+            || info.transient_boot_preference.is_some()
+            || info.persistent_boot_preference != info.active;
+        if pending && !self.rot_update_pending {
+            queue.push(Alert::RotUpdatePending);
+        }
+        self.rot_update_pending = pending;
+    }
+
+    #[cfg(feature = "gimlet")]
+    fn poll_power_faults(&mut self, queue: &mut AlertQueue) {
+        // The fault log lives in `power`'s RAM, so a restart starts it over
+        // from capture 0; start over with it.
+        let power_id = sys_refresh_task_id(self.power_id);
+        if power_id != self.power_id {
+            self.power_id = power_id;
+            self.next_fault_seq = 1;
+        }
+
+        // Each read returns the oldest capture at or after the sequence
+        // number we ask for, so walk forward until we've seen them all.
+        while let Ok(entry) = self.power.read_fault_log(self.next_fault_seq, 0)
+        {
+            self.next_fault_seq = entry.seq.wrapping_add(1);
+            let FaultCause::Fault { index } = entry.cause else {
+                continue;
+            };
+            // Every entry reports the rail of the device it was read for, so
+            // ask for the device that faulted.
+            if let Ok(faulted) = self.power.read_fault_log(entry.seq, index) {
+                queue.push(Alert::PowerFault { rail: faulted.rail });
+            }
+        }
+    }
+}
@@ -8,16 +8,18 @@
 #[cfg(any(feature = "stm32h743", feature = "stm32h753"))]
 use drv_stm32h7_usart as drv_usart;
 
+use attest_api::{AttestError, HashAlgorithm};
 use drv_gimlet_hf_api::{HfDevSelect, HfMuxState, HostFlash};
 use drv_gimlet_seq_api::{PowerState, SeqError, Sequencer};
+use drv_sprot_api::{AttestOrSprotError, SpRot, SprotError};
 use drv_stm32xx_sys_api as sys_api;
 use drv_usart::Usart;
 use enum_map::Enum;
 use heapless::Vec;
 use host_sp_messages::{
-    Bsu, DecodeFailureReason, Header, HostToSp, Key, KeyLookupResult,
-    KeySetResult, SpToHost, Status, MAX_MESSAGE_SIZE,
-    MIN_SP_TO_HOST_FILL_DATA_LEN,
+    version, Alert, Bsu, DecodeFailureReason, Header, HostToSp, Key,
+    KeyLookupResult, KeySetResult, RotRequest, RotResult, SpToHost, Status,
+    MAX_MESSAGE_SIZE, MIN_SP_TO_HOST_FILL_DATA_LEN,
 };
 use hubpack::SerializedSize;
 use idol_runtime::{NotificationHandler, RequestError};
@@ -35,6 +37,9 @@ use userlib::{
     hl, sys_get_timer, sys_irq_control, task_slot, FromPrimitive, UnwrapLite,
 };
 
+mod alerts;
+use alerts::{AlertQueue, AlertSources};
+
 mod inventory;
 use inventory::INVENTORY_API_VERSION;
 
@@ -60,6 +65,7 @@ task_slot!(HOST_FLASH, hf);
 task_slot!(PACKRAT, packrat);
 task_slot!(NET, net);
 task_slot!(SYS, sys);
+task_slot!(SPROT, sprot);
 
 // TODO: When rebooting the host, we need to wait for the relevant power rails
 // to decay. We ought to do this properly by monitoring the rails, but for now,
@@ -72,6 +78,15 @@ const A2_REBOOT_DELAY: u64 = 5_000;
 // response to send, and we haven't yet started to receive a request).
 const UART_ZERO_DELAY: u64 = 200;
 
+// How frequently should we poll the tasks that can raise alerts for the host?
+// Talking to the RoT is more expensive, so we poll it less often.
+const ALERT_POLL_INTERVAL: u64 = 1_000;
+const ROT_ALERT_POLL_INTERVAL: u64 = 10_000;
+
+// Length of each digest in a `RotAddHostMeasurements` request, which are all
+// SHA3-256
+const HOST_MEASUREMENT_LEN: usize = 32;
+
 // How long of a host panic / boot fail message are we willing to keep?
 const MAX_HOST_FAIL_MESSAGE_LEN: usize = 4096;
 
@@ -121,6 +136,10 @@ enum Trace {
         sequence: u64,
         message: SpToHost,
     },
+    RotRequest(RotRequest),
+    RotSprotError(SprotError),
+    RotAttestError(AttestError),
+    Alert(Alert),
 }
 
 ringbuf!(Trace, 20, Trace::None);
@@ -145,6 +164,10 @@ enum Timers {
     WaitingInA2ToReboot,
     /// Timer set when we want to send periodic 0x00 bytes on the uart.
     TxPeriodicZeroByte,
+    /// Repeating timer for polling alert sources.
+    PollAlertSources,
+    /// Repeating timer for polling the RoT for alerts.
+    PollRotAlerts,
 }
 
 #[export_name = "main"]
@@ -255,9 +278,12 @@ struct ServerImpl {
     net: Net,
     cp_agent: ControlPlaneAgent,
     packrat: Packrat,
+    sprot: SpRot,
     reboot_state: Option<RebootState>,
     host_kv_storage: HostKeyValueStorage,
     hf_mux_state: Option<HfMuxState>,
+    alerts: AlertQueue,
+    alert_sources: AlertSources,
 }
 
 impl ServerImpl {
@@ -272,6 +298,16 @@ impl ServerImpl {
             sys_get_timer().now,
             Some(Repeat::AfterWake(UART_ZERO_DELAY)),
         );
+        timers.set_timer(
+            Timers::PollAlertSources,
+            sys_get_timer().now,
+            Some(Repeat::AfterWake(ALERT_POLL_INTERVAL)),
+        );
+        timers.set_timer(
+            Timers::PollRotAlerts,
+            sys_get_timer().now,
+            Some(Repeat::AfterWake(ROT_ALERT_POLL_INTERVAL)),
+        );
 
         Self {
             uart,
@@ -287,9 +323,12 @@ impl ServerImpl {
                 CONTROL_PLANE_AGENT.get_task_id(),
             ),
             packrat: Packrat::from(PACKRAT.get_task_id()),
+            sprot: SpRot::from(SPROT.get_task_id()),
             reboot_state: None,
             host_kv_storage: HostKeyValueStorage::claim_static_resources(),
             hf_mux_state: None,
+            alerts: AlertQueue::new(),
+            alert_sources: AlertSources::new(),
         }
     }
 
@@ -306,6 +345,17 @@ impl ServerImpl {
         }
     }
 
+    // Sets or clears `ALERTS_AVAILABLE` depending on whether we have any
+    // alerts queued for the host.
+    fn update_alert_status(&mut self) {
+        let status = if self.alerts.is_empty() {
+            self.status.difference(Status::ALERTS_AVAILABLE)
+        } else {
+            self.status | Status::ALERTS_AVAILABLE
+        };
+        self.set_status_impl(status);
+    }
+
     fn update_hf_mux_state(&mut self) {
         self.hf_mux_state = self.hf.get_mux().ok();
         ringbuf_entry!(Trace::HfMux {
@@ -435,7 +485,14 @@ impl ServerImpl {
                 self.power_off_host(true);
             }
 
-            PowerState::A0 | PowerState::A0PlusHP | PowerState::A0Thermtrip => {
+            PowerState::A0Thermtrip => {
+                // The sequencer has already powered off the host's rails; the
+                // host will only see this alert once it comes back up.
+                self.alerts.push(Alert::ThermalTrip);
+                self.update_alert_status();
+            }
+
+            PowerState::A0 | PowerState::A0PlusHP => {
                 // TODO should we clear self.reboot_state here? What if we
                 // transitioned from one A0 state to another? For now, leave it
                 // set, and we'll move back to A0 whenever we transition to
@@ -698,6 +755,7 @@ impl ServerImpl {
         if reset_tx_buf {
             self.tx_buf.reset();
         }
+        self.tx_buf.set_version(header.version);
 
         // We defer any actions until after we've serialized our response to
         // avoid borrow checker issues with calling methods on `self`.
@@ -816,17 +874,38 @@ impl ServerImpl {
                 Some(SpToHost::Ack)
             }
             HostToSp::GetAlert => {
-                // TODO define alerts
-                Some(SpToHost::Alert { action: 0 })
+                let response = self.alerts.pop(header.version);
+                action = Some(Action::UpdateAlertStatus);
+                Some(response)
             }
-            HostToSp::RotRequest => {
-                // TODO forward request to RoT
+            // V1 hosts don't know how to talk to the RoT through us, so get
+            // the same empty replies that they always have.
+            HostToSp::RotRequest if header.version < version::V2 => {
                 Some(SpToHost::RotResponse)
             }
-            HostToSp::RotAddHostMeasurements => {
-                // TODO forward request to RoT
+            HostToSp::RotAddHostMeasurements
+                if header.version < version::V2 =>
+            {
                 Some(SpToHost::Ack)
             }
+            HostToSp::RotRequest => {
+                perform_rot_request(
+                    &self.sprot,
+                    &mut self.tx_buf,
+                    header.sequence,
+                    data,
+                );
+                None
+            }
+            HostToSp::RotAddHostMeasurements => {
+                let result = record_host_measurements(&self.sprot, data);
+                self.tx_buf.encode_response(
+                    header.sequence,
+                    &SpToHost::RotResponse,
+                    |buf| hubpack::serialize(buf, &result).unwrap_lite(),
+                );
+                None
+            }
             HostToSp::GetPhase2Data { hash, offset } => {
                 // We don't have a response to transmit now, but need to avoid
                 // sending periodic 0s until we do have a response. Instruct
@@ -895,6 +974,7 @@ impl ServerImpl {
                     self.set_status_impl(self.status.difference(to_clear))
                 }
                 Action::HfMuxToSP => self.set_hf_mux_to_sp(),
+                Action::UpdateAlertStatus => self.update_alert_status(),
             }
         }
 
@@ -1113,8 +1193,15 @@ impl NotificationHandler for ServerImpl {
                         self.rx_buf,
                     );
                 }
+                Timers::PollAlertSources => {
+                    self.alert_sources.poll(&self.cp_agent, &mut self.alerts);
+                }
+                Timers::PollRotAlerts => {
+                    self.alert_sources.poll_rot(&self.sprot, &mut self.alerts);
+                }
             }
         }
+        self.update_alert_status();
 
         match tx_timer_disposition {
             TimerDisposition::LeaveRunning => (),
@@ -1141,7 +1228,7 @@ fn parse_received_message(
         return Err(DecodeFailureReason::MagicMismatch);
     }
 
-    if header.version != host_sp_messages::version::V1 {
+    if !(version::V1..=version::CURRENT).contains(&header.version) {
         return Err(DecodeFailureReason::VersionMismatch);
     }
 
@@ -1152,6 +1239,117 @@ fn parse_received_message(
     Ok((header, request, data))
 }
 
+// This is conceptually a method on `ServerImpl`, but it takes references to
+// several of its fields instead of `self` to avoid borrow checker issues (our
+// caller's `data` is borrowed from `rx_buf`).
+//
+// Always fills `tx_buf` with our response: a `SpToHost::RotResponse`, whose
+// data is a `RotResult` followed (on success) by what the RoT gave us, which
+// it writes directly into our outgoing buffer.
+fn perform_rot_request(
+    sprot: &SpRot,
+    tx_buf: &mut TxBuf,
+    sequence: u64,
+    data: &[u8],
+) {
+    tx_buf.encode_response(sequence, &SpToHost::RotResponse, |buf| {
+        // The RoT can't send us more than this in one go.
+        const_assert!(
+            MIN_SP_TO_HOST_FILL_DATA_LEN
+                >= RotResult::MAX_SIZE + drv_sprot_api::MAX_BLOB_SIZE
+        );
+        let (result_buf, buf) = buf.split_at_mut(RotResult::MAX_SIZE);
+        let buf = &mut buf[..drv_sprot_api::MAX_BLOB_SIZE];
+
+        let (result, n) = match hubpack::deserialize::<RotRequest>(data) {
+            Ok((request, data)) => {
+                ringbuf_entry!(Trace::RotRequest(request));
+                match fill_rot_response(sprot, request, data, buf) {
+                    Ok(n) => (RotResult::Ok, n),
+                    Err(err) => (err, 0),
+                }
+            }
+            Err(_) => (RotResult::BadRequest, 0),
+        };
+        // `RotResult` is a single byte, so the response data that follows
+        // it is contiguous.
+        hubpack::serialize(result_buf, &result).unwrap_lite() + n
+    });
+}
+
+fn fill_rot_response(
+    sprot: &SpRot,
+    request: RotRequest,
+    data: &[u8],
+    buf: &mut [u8],
+) -> Result<usize, RotResult> {
+    // Returns how much of an object of length `len` to read from `offset`
+    let cap = buf.len();
+    let chunk = |len: u32, offset: u32| -> Result<usize, RotResult> {
+        let remaining = len.checked_sub(offset).ok_or(RotResult::BadRequest)?;
+        Ok(usize::min(remaining as usize, cap))
+    };
+
+    let len = match request {
+        RotRequest::CertChainLen => sprot.cert_chain_len(),
+        RotRequest::CertLen { index } => sprot.cert_len(index),
+        RotRequest::LogLen => sprot.log_len(),
+        RotRequest::AttestLen => sprot.attest_len(),
+        RotRequest::Cert { index, offset } => {
+            let n = chunk(sprot.cert_len(index).map_err(rot_error)?, offset)?;
+            if n > 0 {
+                sprot
+                    .cert(index, offset, &mut buf[..n])
+                    .map_err(rot_error)?;
+            }
+            return Ok(n);
+        }
+        RotRequest::Log { offset } => {
+            let n = chunk(sprot.log_len().map_err(rot_error)?, offset)?;
+            if n > 0 {
+                sprot.log(offset, &mut buf[..n]).map_err(rot_error)?;
+            }
+            return Ok(n);
+        }
+        RotRequest::Attest => {
+            let n = sprot.attest_len().map_err(rot_error)? as usize;
+            if n > buf.len() {
+                return Err(RotResult::ResponseTooLong);
+            }
+            sprot.attest(data, &mut buf[..n]).map_err(rot_error)?;
+            return Ok(n);
+        }
+    }
+    .map_err(rot_error)?;
+
+    Ok(hubpack::serialize(buf, &len).unwrap_lite())
+}
+
+fn record_host_measurements(sprot: &SpRot, data: &[u8]) -> RotResult {
+    if data.is_empty() || data.len() % HOST_MEASUREMENT_LEN != 0 {
+        return RotResult::BadRequest;
+    }
+    for digest in data.chunks_exact(HOST_MEASUREMENT_LEN) {
+        if let Err(err) = sprot.record(HashAlgorithm::Sha3_256, digest) {
+            return rot_error(err);
+        }
+    }
+    RotResult::Ok
+}
+
+fn rot_error(err: AttestOrSprotError) -> RotResult {
+    match err {
+        AttestOrSprotError::Sprot(e) => {
+            ringbuf_entry!(Trace::RotSprotError(e));
+            RotResult::CommsFailed
+        }
+        AttestOrSprotError::Attest(e) => {
+            ringbuf_entry!(Trace::RotAttestError(e));
+            RotResult::AttestFailed
+        }
+    }
+}
+
 // This is conceptually a method on `ServerImpl`, but it takes references to
 // several of its fields instead of `self` to avoid borrow checker issues.
 fn handle_reboot_waiting_in_a2_timer(
@@ -1243,6 +1441,7 @@ enum Action {
     PowerOffHost,
     ClearStatusBits(Status),
     HfMuxToSP,
+    UpdateAlertStatus,
 }
 
 #[cfg(any(feature = "stm32h743", feature = "stm32h753"))]
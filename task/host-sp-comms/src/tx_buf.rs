@@ -32,6 +32,8 @@ pub(super) struct TxBuf {
     // of `pkt`.
     pkt: &'static mut [u8; MAX_PACKET_SIZE + 1],
     state: State,
+    // Protocol version of the request we're answering, which we reply with.
+    version: u32,
 }
 
 impl TxBuf {
@@ -44,9 +46,16 @@ impl TxBuf {
             msg,
             pkt,
             state: State::Idle,
+            version: host_sp_messages::version::V1,
         }
     }
 
+    /// Sets the protocol version of our responses, which should be that of
+    /// the request being answered.
+    pub(crate) fn set_version(&mut self, version: u32) {
+        self.version = version;
+    }
+
     /// Reset to an idle state; if we had data we were sending, it's possible
     /// we've sent a partial packet. We always prefix packets with a 0x00
     /// terminator, but such a case means the host will receive an incomplete
@@ -142,6 +151,8 @@ impl TxBuf {
         assert!(!matches!(self.state, State::ToSend(_)));
         let header = Header {
             magic: host_sp_messages::MAGIC,
+            // We failed to decode, so don't know what version the host
+            // speaks; every host understands V1.
             version: host_sp_messages::version::V1,
             // We failed to decode, so don't know the sequence number.
             sequence: 0xffff_ffff_ffff_ffff,
@@ -248,7 +259,7 @@ impl TxBuf {
     {
         let header = Header {
             magic: host_sp_messages::MAGIC,
-            version: host_sp_messages::version::V1,
+            version: self.version,
             sequence: sequence | SEQ_REPLY,
         };
 
@@ -25,7 +25,12 @@ fn main() -> ! {
         } else {
             // Wait For Interrupt to pause the processor until an ISR arrives,
             // which could wake some higher-priority task.
+            #[cfg(not(target_arch = "x86"))]
             cortex_m::asm::wfi();
+            // The simulator's equivalent, which keeps us from spinning a host
+            // CPU while nothing's happening.
+            #[cfg(target_arch = "x86")]
+            userlib::sim::wfi();
         }
     }
 }
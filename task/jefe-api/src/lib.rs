@@ -8,6 +8,7 @@
 
 use derive_idol_err::IdolError;
 pub use dump_agent_api::DumpAgentError;
+use hubpack::SerializedSize;
 use serde::{Deserialize, Serialize};
 use userlib::*;
 
@@ -27,6 +28,81 @@ pub enum ResetReason {
     Unknown, // TODO remove and use `Option<ResetReason>` once we switch to hubpack
 }
 
+/// Restart counters for a single task, as tracked by the supervisor.
+#[derive(
+    Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize, SerializedSize,
+)]
+pub struct RestartStats {
+    /// Number of times the task has been restarted after a fault.
+    pub restarts: u32,
+    /// Number of faults within the task's current restart policy window.
+    pub faults_in_window: u32,
+    /// The task has exceeded its restart policy and has been escalated.
+    pub crash_looping: bool,
+    /// A restart has been scheduled, but is waiting out a backoff delay.
+    pub restart_pending: bool,
+}
+
+/// Stack usage of a single task, as measured by the kernel.
+#[derive(
+    Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize, SerializedSize,
+)]
+pub struct StackUsage {
+    /// Size of the task's stack, in bytes.
+    pub size: u32,
+    /// Deepest stack use since the task was last started, in bytes.
+    pub high_water: u32,
+}
+
+/// Record of task dumps lost to eviction, as tracked by the supervisor.
+#[derive(
+    Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize, SerializedSize,
+)]
+pub struct DumpEvictions {
+    /// Number of times dump areas have been evicted.
+    pub count: u32,
+    /// Bitmask of dump area indices (modulo 32) that have been evicted at
+    /// least once since the dump areas were last initialized.
+    pub overwritten: u32,
+    /// Index of the dump area released by the most recent eviction, or
+    /// `u8::MAX` if there have been none.
+    pub last_from: u8,
+}
+
+/// Task lifecycle events accumulated for a subscriber since it last asked.
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    Eq,
+    PartialEq,
+    Serialize,
+    SerializedSize,
+)]
+pub struct LifecycleEvents {
+    /// Bitmask of task indices that have faulted.
+    pub faulted: u64,
+    /// Bitmask of task indices that have been restarted.
+    pub restarted: u64,
+    /// The system is about to be reset.
+    pub reset_imminent: bool,
+}
+
+#[derive(Copy, Clone, Debug, FromPrimitive, Eq, PartialEq, IdolError)]
+#[repr(C)]
+pub enum SubscribeError {
+    TooManySubscribers = 1,
+    NotSubscribed,
+}
+
+#[derive(Copy, Clone, Debug, FromPrimitive, Eq, PartialEq, IdolError)]
+#[repr(C)]
+pub enum RestartStatsError {
+    BadTaskIndex = 1,
+}
+
 #[derive(Copy, Clone, Debug, FromPrimitive, Eq, PartialEq, IdolError)]
 #[repr(C)]
 pub enum DumpAreaError {
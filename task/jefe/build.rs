@@ -57,8 +57,67 @@ fn main() -> Result<()> {
         writeln!(out, "];")?;
     }
 
+    {
+        let count = cfg.restart_policies.len();
+        writeln!(
+            out,
+            "pub(crate) static RESTART_POLICIES: \
+             [(usize, restart_policy::Policy); {count}] = [",
+        )?;
+        for (name, policy) in cfg.restart_policies {
+            if policy.window_ms == 0 {
+                anyhow::bail!(
+                    "restart policy for {name}: window-ms must be nonzero"
+                );
+            }
+            let max_backoff = policy.max_backoff_ms.unwrap_or(u64::MAX);
+            if max_backoff < policy.backoff_ms {
+                anyhow::bail!(
+                    "restart policy for {name}: max-backoff-ms ({max_backoff}) \
+                     is less than backoff-ms ({})",
+                    policy.backoff_ms,
+                );
+            }
+            let escalation = match policy.escalate {
+                Escalation::Hold => "Hold",
+                Escalation::Reset => "Reset",
+            };
+            writeln!(
+                out,
+                "    ({task}::{name} as usize, restart_policy::Policy {{
+        max_restarts: {},
+        window: {},
+        backoff: {},
+        max_backoff: {max_backoff},
+        escalation: restart_policy::Escalation::{escalation},
+    }}),",
+                policy.max_restarts, policy.window_ms, policy.backoff_ms,
+            )?;
+        }
+        writeln!(out, "];")?;
+    }
+
+    {
+        let (tasks, timeout) = match cfg.watchdog {
+            Some(w) => {
+                if w.timeout_ms == 0 {
+                    anyhow::bail!("watchdog timeout-ms must be nonzero");
+                }
+                (w.tasks, w.timeout_ms)
+            }
+            None => (BTreeSet::new(), u64::MAX),
+        };
+        let count = tasks.len();
+        writeln!(out, "pub(crate) const WATCHDOG_TASKS: [usize; {count}] = [")?;
+        for name in tasks {
+            writeln!(out, "    {task}::{name} as usize,")?;
+        }
+        writeln!(out, "];")?;
+        writeln!(out, "pub(crate) const WATCHDOG_TIMEOUT: u64 = {timeout};")?;
+    }
+
     #[cfg(feature = "dump")]
-    output_dump_areas(&mut out)?;
+    output_dump_areas(&mut out, cfg.dump_keep_first)?;
     Ok(())
 }
 
@@ -77,6 +136,57 @@ struct Config {
     /// failure, unless overridden at runtime through Humility.
     #[serde(default)]
     tasks_to_hold: BTreeSet<String>,
+    /// Restart policies for tasks that shouldn't simply be restarted
+    /// immediately on every fault, as a map from task name to policy.
+    #[serde(default)]
+    restart_policies: BTreeMap<String, RestartPolicy>,
+    /// Tasks whose liveness gates petting of the hardware watchdog.
+    #[serde(default)]
+    watchdog: Option<WatchdogConfig>,
+    /// Number of dump areas (starting from the first) that are never evicted
+    /// to make room for new dumps. Defaults to 1.
+    #[serde(default)]
+    #[cfg_attr(not(feature = "dump"), allow(dead_code))]
+    dump_keep_first: Option<u8>,
+}
+
+/// Restart policy for a single task.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct RestartPolicy {
+    /// Number of restarts allowed within `window_ms` before escalating.
+    max_restarts: u32,
+    /// Length of the window over which restarts are counted.
+    window_ms: u64,
+    /// Delay before the first restart in a window; doubled for each
+    /// subsequent restart in the same window.
+    #[serde(default)]
+    backoff_ms: u64,
+    /// Upper bound on the backoff delay.
+    #[serde(default)]
+    max_backoff_ms: Option<u64>,
+    /// What to do once `max_restarts` is exceeded.
+    #[serde(default)]
+    escalate: Escalation,
+}
+
+/// Watchdog liveness configuration.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct WatchdogConfig {
+    /// Names of tasks that must call `watchdog_checkin` periodically.
+    tasks: BTreeSet<String>,
+    /// Longest a watched task may go between check-ins before we stop petting
+    /// the watchdog.
+    timeout_ms: u64,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+enum Escalation {
+    #[default]
+    Hold,
+    Reset,
 }
 
 #[cfg(feature = "dump")]
@@ -94,7 +204,10 @@ struct DumpRegion {
 /// exactly what the dump agent itself is using.
 ///
 #[cfg(feature = "dump")]
-fn output_dump_areas(out: &mut std::fs::File) -> Result<()> {
+fn output_dump_areas(
+    out: &mut std::fs::File,
+    keep_first: Option<u8>,
+) -> Result<()> {
     let dump_regions = build_util::task_extern_regions::<DumpRegion>()?;
 
     if dump_regions.is_empty() {
@@ -160,5 +273,22 @@ pub(crate) const DUMP_ADDRESS_MIN: u32 = {min:#x};
 pub(crate) const DUMP_ADDRESS_MAX: u32 = {max:#x};"##
     )?;
 
+    // Jefe has humpty split each region into areas of (at most) 4 KiB; see
+    // `dump::initialize_dump_areas`. Make sure that leaves at least one area to
+    // evict once the kept ones are full.
+    let keep_first = keep_first.unwrap_or(1);
+    let areas: u32 = dump_regions
+        .values()
+        .map(|r| std::cmp::max(r.size / 0x1000, 1))
+        .sum();
+    if u32::from(keep_first) >= areas {
+        anyhow::bail!(
+            "dump-keep-first ({keep_first}) must be less than the number of \
+             dump areas ({areas}), or there will be nothing to evict"
+        );
+    }
+
+    writeln!(out, "pub(crate) const DUMP_KEEP_FIRST: u8 = {keep_first};")?;
+
     Ok(())
 }
@@ -3,11 +3,38 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 //! Dump support for Jefe
-
-use crate::generated::{DUMP_ADDRESS_MAX, DUMP_ADDRESS_MIN, DUMP_AREAS};
-use humpty::{DumpArea, DumpContents};
+//!
+//! # Eviction
+//!
+//! Dump areas are a finite resource: once they've all been claimed, there's
+//! nowhere to put a new dump, and (absent eviction) the most recent fault --
+//! often the most interesting one -- would be lost. So when we fail to claim
+//! an area because they're all in use, we evict old dumps to make room.
+//!
+//! The first `DUMP_KEEP_FIRST` areas are never evicted, preserving the
+//! earliest dumps since the areas were last initialized (which are often the
+//! root cause of later trouble). The remaining areas are used as a ring:
+//! humpty claims the first available area, so they fill in order, and once
+//! they're full we free them one at a time, oldest first, by resetting the
+//! header of the area at a cursor that walks the ring. Each eviction thus
+//! loses exactly one dump, and the most recent dump is always captured.
+//!
+//! (humpty's own `release_dump_areas_from` would release the chosen area along
+//! with every area after it, so we don't use it here.)
+//!
+//! A whole-system dump needs every area, kept ones included, so we never evict
+//! on its behalf: doing so would destroy a dump without making room.
+//!
+//! Every eviction is recorded in `Evictions`, which is available through the
+//! `get_dump_evictions` operation, so that a tool retrieving dumps can tell
+//! that some were lost.
+
+use crate::generated::{
+    DUMP_ADDRESS_MAX, DUMP_ADDRESS_MIN, DUMP_AREAS, DUMP_KEEP_FIRST,
+};
+use humpty::{DumpArea, DumpAreaHeader, DumpContents};
 use ringbuf::*;
-use task_jefe_api::DumpAgentError;
+use task_jefe_api::{DumpAgentError, DumpEvictions};
 use userlib::*;
 
 #[cfg(all(
@@ -55,11 +82,132 @@ enum Trace {
     },
     DumpRead(usize),
     DumpDone(Result<(), humpty::DumpError<()>>),
+    Evicting {
+        from: u8,
+    },
 }
 
 ringbuf!(Trace, 8, Trace::None);
 
+/// Record of dump areas that have been evicted to make room for new dumps.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Evictions {
+    /// Number of evictions performed.
+    count: u32,
+    /// Bitmask of area indices (modulo 32) that have been released by
+    /// eviction at least once -- i.e., areas whose original dump was lost.
+    overwritten: u32,
+    /// Index of the area released by the most recent eviction.
+    last_from: Option<u8>,
+    /// Index of the area to release next, i.e. the oldest in the ring; `None`
+    /// means `DUMP_KEEP_FIRST`.
+    next_victim: Option<u8>,
+}
+
+impl Evictions {
+    pub fn get(&self) -> DumpEvictions {
+        DumpEvictions {
+            count: self.count,
+            overwritten: self.overwritten,
+            last_from: self.last_from.unwrap_or(u8::MAX),
+        }
+    }
+}
+
+/// Tries to make room for a `contents` dump by releasing the oldest evictable
+/// dump area, recording what was released in `evictions`. Returns `true` if
+/// an area was released, in which case a claim for `contents` will succeed.
+fn evict(base: u32, contents: DumpContents, evictions: &mut Evictions) -> bool {
+    // Releasing one area can't make room for a dump that needs all of them.
+    if contents == DumpContents::WholeSystem {
+        return false;
+    }
+
+    // We call into humpty directly rather than through `get_dump_area` to
+    // avoid flooding our ringbuf.
+    //
+    // SAFETY: as in `get_dump_area`.
+    let area = |index: u8| {
+        humpty::get_dump_area(base, index, |addr, buf, _| unsafe {
+            humpty::from_mem(addr, buf)
+        })
+        .ok()
+    };
+
+    // Walking off the end of the list of areas means we've gone around the
+    // ring, and the oldest dump is back at its start. (If there's no area at
+    // the start, there's no ring at all.)
+    let mut index = evictions.next_victim.unwrap_or(DUMP_KEEP_FIRST);
+    let victim = match area(index) {
+        Some(victim) => victim,
+        None if index != DUMP_KEEP_FIRST => {
+            index = DUMP_KEEP_FIRST;
+            match area(index) {
+                Some(victim) => victim,
+                None => return false,
+            }
+        }
+        None => return false,
+    };
+
+    // Don't tear up a whole-system dump: it spans every area, and releasing
+    // part of it would leave the rest useless.
+    if victim.contents == DumpContents::WholeSystem {
+        return false;
+    }
+
+    ringbuf_entry!(Trace::Evicting { from: index });
+
+    let header = victim.region.address as *mut DumpAreaHeader;
+
+    // SAFETY: dump areas are in memory that we own, and `victim` came from
+    // walking the list of areas, so `header` points to an initialized header.
+    // We only change the fields that mark the area as empty, leaving its
+    // address, length and link to the next area alone.
+    unsafe {
+        let mut h = core::ptr::read_volatile(header);
+        h.contents = DumpContents::Available as u8;
+        h.nsegments = 0;
+        h.written = core::mem::size_of::<DumpAreaHeader>() as u32;
+        core::ptr::write_volatile(header, h);
+    }
+
+    evictions.next_victim = Some(index.wrapping_add(1));
+    evictions.count = evictions.count.wrapping_add(1);
+    evictions.overwritten |= 1 << (index % 32);
+    evictions.last_from = Some(index);
+    true
+}
+
+/// Claims a dump area for `contents`, evicting old dumps if every area is
+/// already in use.
+fn claim(
+    base: u32,
+    contents: DumpContents,
+    evictions: &mut Evictions,
+) -> Result<Option<DumpArea>, humpty::DumpError<()>> {
+    // SAFETY: we have configured memory so that humpty should only read
+    // headers which are properly initialized and readable by this task, and
+    // should only write memory which is writeable by this task (i.e. the dump
+    // areas).
+    let try_claim = || {
+        humpty::claim_dump_area(
+            base,
+            contents,
+            |addr, buf, _| unsafe { humpty::from_mem(addr, buf) },
+            |addr, buf| unsafe { humpty::to_mem(addr, buf) },
+        )
+    };
+
+    match try_claim() {
+        Ok(None) if evict(base, contents, evictions) => try_claim(),
+        r => r,
+    }
+}
+
 pub fn initialize_dump_areas() -> u32 {
+    // If you change the area size, change the check on `dump-keep-first` in
+    // build.rs to match.
     let areas = humpty::initialize_dump_areas(
         &crate::generated::DUMP_AREAS,
         Some(0x1000),
@@ -111,18 +259,12 @@ pub fn get_dump_area(base: u32, index: u8) -> Result<DumpArea, DumpAgentError> {
     }
 }
 
-pub fn claim_dump_area(base: u32) -> Result<DumpArea, DumpAgentError> {
+pub fn claim_dump_area(
+    base: u32,
+    evictions: &mut Evictions,
+) -> Result<DumpArea, DumpAgentError> {
     ringbuf_entry!(Trace::Claiming);
-    // SAFETY: we have configured memory so that humpty should only read
-    // headers which are properly initialized and readable by this task, and
-    // should only write memory which is writeable by this task (i.e. the dump
-    // areas).
-    match humpty::claim_dump_area(
-        base,
-        DumpContents::WholeSystem,
-        |addr, buf, _| unsafe { humpty::from_mem(addr, buf) },
-        |addr, buf| unsafe { humpty::to_mem(addr, buf) },
-    ) {
+    match claim(base, DumpContents::WholeSystem, evictions) {
         Err(e) => {
             ringbuf_entry!(Trace::ClaimDumpAreaFailed(e));
             Err(DumpAgentError::CannotClaimDumpArea)
@@ -151,21 +293,14 @@ impl From<DumpTaskContents> for DumpContents {
 fn dump_task_setup(
     base: u32,
     contents: DumpTaskContents,
+    evictions: &mut Evictions,
 ) -> Result<DumpArea, DumpAgentError> {
     //
-    // We need to claim a dump area.  Once it's claimed, we have committed
-    // to dumping into it:  any failure will result in a partial or otherwise
-    // corrupted dump.
+    // We need to claim a dump area (evicting older dumps if need be).  Once
+    // it's claimed, we have committed to dumping into it:  any failure will
+    // result in a partial or otherwise corrupted dump.
     //
-    // SAFETY: we have set up the memory correctly, and we're trusting
-    // Humpty to do the right thing here, but ideally we could do this without
-    // `unsafe` (given sufficient changes to `humpty`)
-    let area = humpty::claim_dump_area(
-        base,
-        contents.into(),
-        |addr, buf, _| unsafe { humpty::from_mem(addr, buf) },
-        |addr, buf| unsafe { humpty::to_mem(addr, buf) },
-    );
+    let area = claim(base, contents.into(), evictions);
     ringbuf_entry!(Trace::DumpArea(area));
 
     match area {
@@ -229,10 +364,14 @@ fn dump_task_run(base: u32, task: usize) -> Result<(), DumpAgentError> {
     Ok(())
 }
 
-pub fn dump_task(base: u32, task: usize) -> Result<u8, DumpAgentError> {
+pub fn dump_task(
+    base: u32,
+    evictions: &mut Evictions,
+    task: usize,
+) -> Result<u8, DumpAgentError> {
     ringbuf_entry!(Trace::Dumping { task, base });
 
-    let area = dump_task_setup(base, DumpTaskContents::SingleTask)?;
+    let area = dump_task_setup(base, DumpTaskContents::SingleTask, evictions)?;
 
     for ndx in 0.. {
         //
@@ -279,6 +418,7 @@ pub fn dump_task(base: u32, task: usize) -> Result<u8, DumpAgentError> {
 /// Dumps a specific region from the given task
 pub fn dump_task_region(
     base: u32,
+    evictions: &mut Evictions,
     task: usize,
     start: u32,
     length: u32,
@@ -298,7 +438,7 @@ pub fn dump_task_region(
         return Err(DumpAgentError::UnalignedSegmentLength);
     }
 
-    let area = dump_task_setup(base, DumpTaskContents::TaskRegion)?;
+    let area = dump_task_setup(base, DumpTaskContents::TaskRegion, evictions)?;
 
     // We don't trust the caller; it may request to dump a region that isn't
     // owned by this particular task!  To check this, we iterate over all of the
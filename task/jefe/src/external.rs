@@ -43,6 +43,7 @@
 //! sands...
 //!
 
+use crate::lifecycle::Subscribers;
 use crate::{Disposition, TaskStatus};
 use core::sync::atomic::{AtomicU32, Ordering};
 
@@ -100,10 +101,10 @@ static JEFE_EXTERNAL_ERRORS: AtomicU32 = AtomicU32::new(0);
 /// potentially modifying the passed array.  Returns a boolean to indicate if
 /// a valid external request was received.
 ///
-pub(crate) fn check(states: &mut [TaskStatus]) {
+pub(crate) fn check(states: &mut [TaskStatus], lifecycle: &mut Subscribers) {
     // This wrapper is responsible for updating operation counters, and allowing
     // the inner function to use Result for convenience.
-    match check_inner(states) {
+    match check_inner(states, lifecycle) {
         Ok(true) => {
             JEFE_EXTERNAL_REQUESTS.fetch_add(1, Ordering::SeqCst);
         }
@@ -118,7 +119,10 @@ pub(crate) fn check(states: &mut [TaskStatus]) {
 }
 
 // Implementation factor of `check` that can use Result.
-fn check_inner(states: &mut [TaskStatus]) -> Result<bool, Error> {
+fn check_inner(
+    states: &mut [TaskStatus],
+    lifecycle: &mut Subscribers,
+) -> Result<bool, Error> {
     if JEFE_EXTERNAL_KICK.swap(0, Ordering::SeqCst) == 0 {
         return Ok(false);
     }
@@ -160,7 +164,9 @@ fn check_inner(states: &mut [TaskStatus]) -> Result<bool, Error> {
             // Note that this command does _not_ clear task holds! For that, you
             // must issue Release, below. This means it's useful for starting
             // the task but still catching it on the _next_ fault.
+            state.restart.clear();
             kipc::restart_task(ndx, true);
+            lifecycle.restarted(ndx);
         }
 
         Request::Release => {
@@ -170,7 +176,9 @@ fn check_inner(states: &mut [TaskStatus]) -> Result<bool, Error> {
             state.disposition = Disposition::Restart;
             if state.holding_fault {
                 state.holding_fault = false;
+                state.restart.clear();
                 kipc::restart_task(ndx, true);
+                lifecycle.restarted(ndx);
             }
         }
 
@@ -0,0 +1,131 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Task lifecycle event broadcast.
+//!
+//! Tasks may subscribe (through the `subscribe_lifecycle` operation) to hear
+//! about faults and restarts of their peers, and about impending system
+//! resets. Since the supervisor mustn't send to other tasks, events are
+//! delivered by posting a notification chosen by the subscriber; the
+//! subscriber then calls `take_lifecycle_events` to find out what happened.
+//! Events accumulate until taken, so a slow subscriber sees the union of
+//! everything that happened since it last looked.
+//!
+//! Subscriptions don't survive a restart of the subscribing task: a new
+//! incarnation must subscribe again.
+
+use hubris_num_tasks::NUM_TASKS;
+use task_jefe_api::{LifecycleEvents, SubscribeError};
+use userlib::*;
+
+// Events identify tasks by bit position in a `u64`.
+const _: () = assert!(NUM_TASKS <= 64);
+
+/// Maximum number of simultaneous subscribers. We expect this to be a handful
+/// of servers, and this table lives in the supervisor's precious RAM.
+const MAX_SUBSCRIBERS: usize = 8;
+
+#[derive(Copy, Clone, Debug)]
+struct Subscription {
+    /// Index of the subscribing task.
+    task: usize,
+    /// Notification bits to post.
+    mask: u32,
+    /// Events that have occurred but not yet been taken.
+    pending: LifecycleEvents,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Subscribers {
+    subs: [Option<Subscription>; MAX_SUBSCRIBERS],
+}
+
+impl Subscribers {
+    pub fn subscribe(
+        &mut self,
+        task: usize,
+        mask: u32,
+    ) -> Result<(), SubscribeError> {
+        let new = Some(Subscription {
+            task,
+            mask,
+            pending: LifecycleEvents::default(),
+        });
+
+        // Resubscribing replaces any existing subscription.
+        if let Some(slot) = self.find(task) {
+            *slot = new;
+        } else if let Some(slot) = self.subs.iter_mut().find(|s| s.is_none()) {
+            *slot = new;
+        } else {
+            return Err(SubscribeError::TooManySubscribers);
+        }
+        Ok(())
+    }
+
+    pub fn unsubscribe(&mut self, task: usize) {
+        if let Some(slot) = self.find(task) {
+            *slot = None;
+        }
+    }
+
+    /// Returns, and clears, the events pending for `task`.
+    pub fn take(
+        &mut self,
+        task: usize,
+    ) -> Result<LifecycleEvents, SubscribeError> {
+        match self.find(task) {
+            Some(Some(sub)) => Ok(core::mem::take(&mut sub.pending)),
+            _ => Err(SubscribeError::NotSubscribed),
+        }
+    }
+
+    /// Returns `true` if anyone is listening.
+    pub fn any(&self) -> bool {
+        self.subs.iter().any(Option::is_some)
+    }
+
+    /// Records that `task` has faulted.
+    pub fn faulted(&mut self, task: usize) {
+        self.broadcast(task, |p| p.faulted |= 1 << task);
+    }
+
+    /// Records that `task` has been restarted. This also drops any
+    /// subscription held by the previous incarnation of `task`.
+    pub fn restarted(&mut self, task: usize) {
+        self.unsubscribe(task);
+        self.broadcast(task, |p| p.restarted |= 1 << task);
+    }
+
+    /// Records that the system is about to be reset.
+    pub fn reset_imminent(&mut self) {
+        // Nobody's excluded from this one -- we pass an out-of-range index.
+        self.broadcast(NUM_TASKS, |p| p.reset_imminent = true);
+    }
+
+    fn find(&mut self, task: usize) -> Option<&mut Option<Subscription>> {
+        self.subs
+            .iter_mut()
+            .find(|s| matches!(s, Some(sub) if sub.task == task))
+    }
+
+    fn broadcast(
+        &mut self,
+        subject: usize,
+        mut record: impl FnMut(&mut LifecycleEvents),
+    ) {
+        for sub in self.subs.iter_mut().flatten() {
+            // Don't tell a task about itself; it either knows, or it's in no
+            // state to hear about it.
+            if sub.task == subject {
+                continue;
+            }
+            record(&mut sub.pending);
+
+            let taskid = TaskId::for_index_and_gen(sub.task, Generation::ZERO);
+            let taskid = sys_refresh_task_id(taskid);
+            sys_post(taskid, sub.mask);
+        }
+    }
+}
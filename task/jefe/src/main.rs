@@ -8,12 +8,13 @@
 //!
 //! - Maintaining the system console output (currently via semihosting).
 //! - Monitoring tasks for failures and restarting them.
+//! - Feeding the hardware watchdog, if one is armed, while watched tasks are
+//!   live.
 //!
 //! It will probably become responsible for:
 //!
 //! - Evacuating kernel log information.
 //! - Coordinating certain shared resources, such as the RCC and GPIO muxing.
-//! - Managing a watchdog timer.
 //!
 //! It's unwise for the supervisor to use `SEND`, ever, except to talk to the
 //! kernel. This is because a `SEND` to a misbehaving task could block forever,
@@ -30,13 +31,18 @@
 mod dump;
 
 mod external;
+mod lifecycle;
+mod watchdog;
 
 use core::convert::Infallible;
 
 use hubris_num_tasks::NUM_TASKS;
 use humpty::DumpArea;
-use idol_runtime::RequestError;
-use task_jefe_api::{DumpAgentError, ResetReason};
+use idol_runtime::{ClientError, RequestError};
+use task_jefe_api::{
+    DumpAgentError, DumpEvictions, LifecycleEvents, ResetReason, RestartStats,
+    RestartStatsError, StackUsage, SubscribeError,
+};
 use userlib::*;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Default)]
@@ -53,12 +59,20 @@ pub enum Disposition {
 // notification, but can otherwise be arbitrary.
 const TIMER_INTERVAL: u64 = 100;
 
+// When a reset is requested and there are tasks subscribed to lifecycle
+// events, we give them this long (in ms) to get their affairs in order before
+// pulling the plug.
+const RESET_GRACE_PERIOD: u64 = 50;
+
 #[export_name = "main"]
 fn main() -> ! {
     let mut task_states = [TaskStatus::default(); hubris_num_tasks::NUM_TASKS];
     for held_task in generated::HELD_TASKS {
         task_states[held_task as usize].disposition = Disposition::Hold;
     }
+    for (index, policy) in &generated::RESTART_POLICIES {
+        task_states[*index].policy = Some(policy);
+    }
 
     let deadline = sys_get_timer().now + TIMER_INTERVAL;
 
@@ -71,8 +85,12 @@ fn main() -> ! {
         deadline,
         task_states: &mut task_states,
         reset_reason: ResetReason::Unknown,
+        lifecycle: lifecycle::Subscribers::default(),
+        watchdog: watchdog::Watchdog::new(),
         #[cfg(feature = "dump")]
         dump_areas: dump::initialize_dump_areas(),
+        #[cfg(feature = "dump")]
+        dump_evictions: dump::Evictions::default(),
     };
     let mut buf = [0u8; idl::INCOMING_SIZE];
 
@@ -86,8 +104,25 @@ struct ServerImpl<'s> {
     task_states: &'s mut [TaskStatus; NUM_TASKS],
     deadline: u64,
     reset_reason: ResetReason,
+    lifecycle: lifecycle::Subscribers,
+    watchdog: watchdog::Watchdog,
     #[cfg(feature = "dump")]
     dump_areas: u32,
+    #[cfg(feature = "dump")]
+    dump_evictions: dump::Evictions,
+}
+
+impl ServerImpl<'_> {
+    /// Resets the system, first warning any lifecycle subscribers.
+    fn reset(&mut self) -> ! {
+        if self.lifecycle.any() {
+            self.lifecycle.reset_imminent();
+            // Subscribers are all lower priority than us, so they'll only get
+            // to run if we block.
+            hl::sleep_for(RESET_GRACE_PERIOD);
+        }
+        kipc::system_restart();
+    }
 }
 
 impl idl::InOrderJefeImpl for ServerImpl<'_> {
@@ -95,9 +130,7 @@ impl idl::InOrderJefeImpl for ServerImpl<'_> {
         &mut self,
         _msg: &userlib::RecvMessage,
     ) -> Result<(), RequestError<Infallible>> {
-        // If we wanted to broadcast to other tasks that a restart is occuring
-        // here is where we would do so!
-        kipc::system_restart();
+        self.reset();
     }
 
     fn get_reset_reason(
@@ -141,11 +174,87 @@ impl idl::InOrderJefeImpl for ServerImpl<'_> {
         Ok(())
     }
 
+    fn subscribe_lifecycle(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        notification_mask: u32,
+    ) -> Result<(), RequestError<SubscribeError>> {
+        if notification_mask == 0 {
+            return Err(RequestError::Fail(ClientError::BadMessageContents));
+        }
+        self.lifecycle
+            .subscribe(msg.sender.index(), notification_mask)
+            .map_err(RequestError::from)
+    }
+
+    fn unsubscribe_lifecycle(
+        &mut self,
+        msg: &userlib::RecvMessage,
+    ) -> Result<(), RequestError<Infallible>> {
+        self.lifecycle.unsubscribe(msg.sender.index());
+        Ok(())
+    }
+
+    fn take_lifecycle_events(
+        &mut self,
+        msg: &userlib::RecvMessage,
+    ) -> Result<LifecycleEvents, RequestError<SubscribeError>> {
+        self.lifecycle
+            .take(msg.sender.index())
+            .map_err(RequestError::from)
+    }
+
+    fn get_restart_stats(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        task_index: u32,
+    ) -> Result<RestartStats, RequestError<RestartStatsError>> {
+        let status = self
+            .task_states
+            .get(task_index as usize)
+            .ok_or(RestartStatsError::BadTaskIndex)?;
+        Ok(RestartStats {
+            restarts: status.restart.restarts(),
+            faults_in_window: status.restart.faults_in_window(),
+            crash_looping: status.restart.is_crash_looping(),
+            restart_pending: status.restart.is_pending(),
+        })
+    }
+
+    fn get_stack_usage(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        task_index: u32,
+    ) -> Result<StackUsage, RequestError<Infallible>> {
+        // Check this ourselves: the kernel would fault us for a bad index.
+        if task_index as usize >= self.task_states.len() {
+            return Err(RequestError::Fail(ClientError::BadMessageContents));
+        }
+        let usage = kipc::read_task_stack_usage(task_index as usize);
+        Ok(StackUsage {
+            size: usage.size,
+            high_water: usage.high_water,
+        })
+    }
+
+    fn watchdog_checkin(
+        &mut self,
+        msg: &userlib::RecvMessage,
+    ) -> Result<(), RequestError<Infallible>> {
+        // Only tasks named in our watchdog config may check in; anyone else
+        // is confused about how they're configured.
+        if !self.watchdog.checkin(msg.sender, sys_get_timer().now) {
+            return Err(RequestError::Fail(ClientError::BadMessageContents));
+        }
+        Ok(())
+    }
+
     fn restart_me_raw(
         &mut self,
         msg: &userlib::RecvMessage,
     ) -> Result<(), RequestError<Infallible>> {
         kipc::restart_task(msg.sender.index(), true);
+        self.lifecycle.restarted(msg.sender.index());
 
         // Note: the returned value here won't go anywhere because we just
         // unblocked the caller. So this is doing a small amount of unnecessary
@@ -169,7 +278,8 @@ impl idl::InOrderJefeImpl for ServerImpl<'_> {
                 &mut self,
                 _msg: &userlib::RecvMessage,
             ) -> Result<DumpArea, RequestError<DumpAgentError>> {
-                dump::claim_dump_area(self.dump_areas).map_err(|e| e.into())
+                dump::claim_dump_area(self.dump_areas, &mut self.dump_evictions)
+                    .map_err(|e| e.into())
             }
 
             fn reinitialize_dump_areas(
@@ -177,6 +287,7 @@ impl idl::InOrderJefeImpl for ServerImpl<'_> {
                 _msg: &userlib::RecvMessage,
             ) -> Result<(), RequestError<DumpAgentError>> {
                 self.dump_areas = dump::initialize_dump_areas();
+                self.dump_evictions = dump::Evictions::default();
                 Ok(())
             }
 
@@ -194,8 +305,11 @@ impl idl::InOrderJefeImpl for ServerImpl<'_> {
                     // Can't dump a non-existent task
                     return Err(DumpAgentError::BadOffset.into());
                 }
-                dump::dump_task(self.dump_areas, task_index as usize)
-                    .map_err(|e| e.into())
+                dump::dump_task(
+                    self.dump_areas,
+                    &mut self.dump_evictions,
+                    task_index as usize,
+                ).map_err(|e| e.into())
             }
 
             fn dump_task_region(
@@ -211,7 +325,11 @@ impl idl::InOrderJefeImpl for ServerImpl<'_> {
                     return Err(DumpAgentError::BadOffset.into());
                 }
                 dump::dump_task_region(
-                    self.dump_areas, task_index as usize, address, length
+                    self.dump_areas,
+                    &mut self.dump_evictions,
+                    task_index as usize,
+                    address,
+                    length,
                 ).map_err(|e| e.into())
             }
 
@@ -223,6 +341,13 @@ impl idl::InOrderJefeImpl for ServerImpl<'_> {
                 dump::reinitialize_dump_from(self.dump_areas, index)
                     .map_err(|e| e.into())
             }
+
+            fn get_dump_evictions(
+                &mut self,
+                _msg: &userlib::RecvMessage,
+            ) -> Result<DumpEvictions, RequestError<DumpAgentError>> {
+                Ok(self.dump_evictions.get())
+            }
         } else {
             fn get_dump_area(
                 &mut self,
@@ -271,6 +396,13 @@ impl idl::InOrderJefeImpl for ServerImpl<'_> {
             ) -> Result<(), RequestError<DumpAgentError>> {
                 Err(DumpAgentError::DumpAgentUnsupported.into())
             }
+
+            fn get_dump_evictions(
+                &mut self,
+                _msg: &userlib::RecvMessage,
+            ) -> Result<DumpEvictions, RequestError<DumpAgentError>> {
+                Err(DumpAgentError::DumpAgentUnsupported.into())
+            }
         }
     }
 }
@@ -281,6 +413,8 @@ impl idl::InOrderJefeImpl for ServerImpl<'_> {
 struct TaskStatus {
     disposition: Disposition,
     holding_fault: bool,
+    policy: Option<&'static restart_policy::Policy>,
+    restart: restart_policy::RestartState,
 }
 
 impl idol_runtime::NotificationHandler for ServerImpl<'_> {
@@ -290,14 +424,31 @@ impl idol_runtime::NotificationHandler for ServerImpl<'_> {
 
     fn handle_notification(&mut self, bits: u32) {
         // Handle any external (debugger) requests.
-        external::check(self.task_states);
+        external::check(self.task_states, &mut self.lifecycle);
 
         if bits & notifications::TIMER_MASK != 0 {
             // If our timer went off, we need to reestablish it
-            if sys_get_timer().now >= self.deadline {
+            let now = sys_get_timer().now;
+            if now >= self.deadline {
                 self.deadline += TIMER_INTERVAL;
                 sys_set_timer(Some(self.deadline), notifications::TIMER_MASK);
             }
+
+            // Restart any tasks whose backoff has expired.
+            for (i, status) in self.task_states.iter_mut().enumerate() {
+                if status.restart.take_due(now) {
+                    kipc::restart_task(i, true);
+                    self.lifecycle.restarted(i);
+                }
+            }
+
+            // Feed the watchdog, unless a watched task has gone quiet without
+            // our knowing why.
+            let task_states = &*self.task_states;
+            self.watchdog.check(now, |i| {
+                let status = &task_states[i];
+                status.holding_fault || status.restart.is_pending()
+            });
         }
 
         if bits & notifications::FAULT_MASK != 0 {
@@ -306,36 +457,61 @@ impl idol_runtime::NotificationHandler for ServerImpl<'_> {
             // unlikely since a fault causes us to immediately preempt. In any
             // case, let's assume we might have to handle multiple tasks.
             //
-            // TODO: it would be fantastic to have a way of finding this out in
-            // one syscall.
-            for (i, status) in self.task_states.iter_mut().enumerate() {
-                // If we're aware that this task is in a fault state, don't
-                // bother making a syscall to enquire.
-                if status.holding_fault {
+            // The kernel does the scanning for us, so this costs one syscall
+            // per faulted task (plus one), rather than one per task.
+            let mut next_task = 1;
+            while let Some(i) = kipc::find_faulted_task(next_task) {
+                let i = i.get();
+                next_task = i + 1;
+
+                let status = &mut self.task_states[i];
+
+                // If we're aware that this task is in a fault state, we've
+                // already dealt with it.
+                if status.holding_fault || status.restart.is_pending() {
                     continue;
                 }
 
-                if let abi::TaskState::Faulted { .. } =
-                    kipc::read_task_status(i)
+                self.lifecycle.faulted(i);
+
+                #[cfg(feature = "dump")]
                 {
-                    #[cfg(feature = "dump")]
-                    {
-                        // We'll ignore the result of dumping; it could fail
-                        // if we're out of space, but we don't have a way of
-                        // dealing with that right now.
-                        //
-                        // TODO: some kind of circular buffer?
-                        _ = dump::dump_task(self.dump_areas, i);
-                    }
+                    // We'll ignore the result of dumping; if we're out of
+                    // space, older dumps are evicted to make room, so a
+                    // failure here means something has gone wrong that we
+                    // can't do anything about.
+                    _ = dump::dump_task(
+                        self.dump_areas,
+                        &mut self.dump_evictions,
+                        i,
+                    );
+                }
+
+                let action = if status.disposition == Disposition::Restart {
+                    let now = sys_get_timer().now;
+                    status.restart.fault(status.policy, now)
+                } else {
+                    restart_policy::Action::Hold
+                };
 
-                    if status.disposition == Disposition::Restart {
+                match action {
+                    restart_policy::Action::Restart => {
                         // Stand it back up
                         kipc::restart_task(i, true);
-                    } else {
+                        self.lifecycle.restarted(i);
+                    }
+                    restart_policy::Action::RestartAt(_) => {
+                        // We'll pick this up from the timer once its backoff
+                        // expires.
+                    }
+                    restart_policy::Action::Hold => {
                         // Mark this one off so we don't revisit it until
                         // requested.
                         status.holding_fault = true;
                     }
+                    restart_policy::Action::Reset => {
+                        self.reset();
+                    }
                 }
             }
         }
@@ -351,6 +527,9 @@ include!(concat!(env!("OUT_DIR"), "/notifications.rs"));
 
 // And the Idol bits
 mod idl {
-    use task_jefe_api::{DumpAgentError, ResetReason};
+    use task_jefe_api::{
+        DumpAgentError, DumpEvictions, LifecycleEvents, ResetReason,
+        RestartStats, RestartStatsError, StackUsage, SubscribeError,
+    };
     include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
 }
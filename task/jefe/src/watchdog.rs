@@ -0,0 +1,107 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Liveness checking for the hardware watchdog.
+//!
+//! If the kernel has armed a watchdog (`watchdog-ms` in the app.toml's
+//! `[kernel]` section), we pet it from our periodic timer. That alone catches
+//! a wedged kernel, or a supervisor that has stopped getting CPU time. To also
+//! catch a wedged task, the app.toml can name tasks to watch in our
+//! `watchdog` config; each of them must then call `watchdog_checkin` at least
+//! once per `timeout-ms`, and we stop petting the watchdog as soon as any of
+//! them is overdue.
+//!
+//! A watched task isn't supervised until it first checks in, so tasks that
+//! only get going once something else is ready (such as power sequencing)
+//! don't need to account for that in their timeout. From then on, a watched
+//! task that we've deliberately stopped -- because it's being held in its
+//! faulted state, or waiting out a restart backoff -- is excused, and one
+//! that's been restarted gets a fresh `timeout-ms` to check in, which we
+//! notice by its generation changing.
+
+use ringbuf::*;
+use userlib::*;
+
+use crate::generated::{WATCHDOG_TASKS, WATCHDOG_TIMEOUT};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Trace {
+    Overdue { task: usize, last_checkin: u64 },
+    None,
+}
+
+ringbuf!(Trace, 4, Trace::None);
+
+#[derive(Copy, Clone, Debug)]
+struct Checkin {
+    generation: Generation,
+    /// Time of the most recent check-in, or `None` if the task hasn't checked
+    /// in since boot.
+    time: Option<u64>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Watchdog {
+    /// Most recent check-in for each of `WATCHDOG_TASKS`, in the same order.
+    checkins: [Checkin; WATCHDOG_TASKS.len()],
+}
+
+impl Watchdog {
+    /// Starts watching. No task is supervised until it first checks in.
+    pub fn new() -> Self {
+        let boot = Checkin {
+            generation: Generation::ZERO,
+            time: None,
+        };
+        Self {
+            checkins: [boot; WATCHDOG_TASKS.len()],
+        }
+    }
+
+    /// Records a check-in from `sender`. Returns `false` if the sender isn't
+    /// one of the tasks we watch.
+    pub fn checkin(&mut self, sender: TaskId, now: u64) -> bool {
+        let Some(i) = WATCHDOG_TASKS.iter().position(|&t| t == sender.index())
+        else {
+            return false;
+        };
+        self.checkins[i] = Checkin {
+            generation: sender.generation(),
+            time: Some(now),
+        };
+        true
+    }
+
+    /// Pets the watchdog if every watched task is either live or excused.
+    /// `excused` is called with the index of each task that's overdue.
+    pub fn check(&mut self, now: u64, excused: impl Fn(usize) -> bool) {
+        for (checkin, &task) in self.checkins.iter_mut().zip(&WATCHDOG_TASKS) {
+            let Some(time) = checkin.time else {
+                // Not yet supervised.
+                continue;
+            };
+
+            let id = TaskId::for_index_and_gen(task, Generation::ZERO);
+            let generation = sys_refresh_task_id(id).generation();
+            if generation != checkin.generation {
+                // Restarted since it last checked in; start the clock over.
+                *checkin = Checkin {
+                    generation,
+                    time: Some(now),
+                };
+                continue;
+            }
+
+            if now.saturating_sub(time) > WATCHDOG_TIMEOUT && !excused(task) {
+                ringbuf_entry!(Trace::Overdue {
+                    task,
+                    last_checkin: time,
+                });
+                return;
+            }
+        }
+
+        kipc::pet_watchdog();
+    }
+}
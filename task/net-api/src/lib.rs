@@ -30,6 +30,10 @@ pub enum SendError {
 
     #[idol(server_death)]
     ServerRestarted = 5,
+
+    /// The destination's address family isn't enabled for this socket (e.g.
+    /// an IPv4 destination from a socket without `ipv4` set in its config)
+    Unaddressable = 6,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive, IdolError)]
@@ -47,6 +51,54 @@ pub enum RecvError {
     ServerRestarted = 4,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive, IdolError)]
+#[repr(u32)]
+pub enum TcpError {
+    /// The selected socket is not owned by this task
+    NotYours = 1,
+
+    /// The operation can't make progress yet; wait for the socket's
+    /// notification and try again
+    WouldBlock = 2,
+
+    /// The socket has no connection, and isn't trying to make one
+    NotConnected = 3,
+
+    /// The socket is in the wrong state for this operation (e.g. listening
+    /// on a socket that's already open)
+    InvalidState = 4,
+
+    /// The remote endpoint given to `tcp_connect` can't be reached
+    Unaddressable = 5,
+
+    #[idol(server_death)]
+    ServerRestarted = 6,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive, IdolError)]
+#[repr(u32)]
+pub enum CountersError {
+    /// The caller neither owns the socket nor is one of the `counter-readers`
+    /// in the net config
+    NotAllowed = 1,
+
+    #[idol(server_death)]
+    ServerRestarted = 2,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive, IdolError)]
+#[repr(u32)]
+pub enum Ipv4Error {
+    /// The net task wasn't built with IPv4 support
+    NotAvailable = 1,
+
+    /// We're waiting on a DHCP server for an address
+    Unconfigured = 2,
+
+    #[idol(server_death)]
+    ServerRestarted = 3,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive, IdolError)]
 #[repr(u32)]
 pub enum PhyError {
@@ -149,6 +201,51 @@ pub struct ManagementCounters {
     pub vsc85x2_mac_valid: bool,
 }
 
+/// Traffic through a single socket, summed across VLANs, along with counts of
+/// the ways that traffic can go missing. All counts wrap on overflow.
+#[derive(
+    Copy, Clone, Debug, Default, Serialize, SerializedSize, Deserialize,
+)]
+pub struct SocketCounters {
+    /// Packets (or, for TCP, successful `tcp_recv` calls) handed to the owner
+    pub rx_packets: u32,
+    pub rx_bytes: u64,
+    /// Packets (or, for TCP, successful `tcp_send` calls) queued by the owner
+    pub tx_packets: u32,
+    pub tx_bytes: u64,
+
+    /// Incoming packets discarded because they didn't fit in the owner's
+    /// buffer
+    pub rx_too_large: u32,
+    /// Incoming UDP packets that the IP stack dropped because the socket's rx
+    /// queue was full. `smoltcp` doesn't report these, so we infer them from
+    /// the datagrams we see arriving for the socket's port; this only counts
+    /// drops due to the `packets` limit, not the `bytes` one.
+    pub rx_buffer_full: u32,
+    /// Sends that failed with `QueueFull` (or, for TCP, `WouldBlock`)
+    pub tx_queue_full: u32,
+    /// Sends that failed with `InvalidVLan`
+    pub tx_invalid_vlan: u32,
+    /// Calls naming this socket from a task that doesn't own it
+    pub not_yours: u32,
+    /// Times we reset the socket because its tx queue appeared stuck
+    pub watchdog_resets: u32,
+}
+
+/// Received frames that the Ethernet driver dropped before they reached the
+/// IP stack, and so can't be attributed to any socket. These wrap on
+/// overflow.
+#[derive(
+    Copy, Clone, Debug, Default, Serialize, SerializedSize, Deserialize,
+)]
+pub struct EthernetDropCounters {
+    /// Frames with a bad checksum (CRC) or another receive error
+    pub rx_errors: u32,
+    /// Frames without a VLAN tag, or with a VID outside our range (only
+    /// counted when VLANs are enabled)
+    pub rx_bad_vid: u32,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive, IdolError)]
 #[repr(u32)]
 pub enum MgmtError {
@@ -191,13 +288,36 @@ pub struct UdpMetadata {
     pub vid: u16,
 }
 
+/// Remote end of a TCP connection.
+#[derive(
+    Copy, Clone, Debug, Serialize, SerializedSize, Deserialize, PartialEq, Eq,
+)]
+pub struct TcpEndpoint {
+    pub addr: Address,
+    pub port: u16,
+}
+
 #[cfg(feature = "use-smoltcp")]
-impl From<UdpMetadata> for smoltcp::wire::IpEndpoint {
-    fn from(m: UdpMetadata) -> Self {
-        Self {
-            addr: m.addr.into(),
+impl TryFrom<TcpEndpoint> for smoltcp::wire::IpEndpoint {
+    type Error = AddressUnsupported;
+
+    fn try_from(e: TcpEndpoint) -> Result<Self, Self::Error> {
+        Ok(Self {
+            addr: e.addr.try_into()?,
+            port: e.port,
+        })
+    }
+}
+
+#[cfg(feature = "use-smoltcp")]
+impl TryFrom<UdpMetadata> for smoltcp::wire::IpEndpoint {
+    type Error = AddressUnsupported;
+
+    fn try_from(m: UdpMetadata) -> Result<Self, Self::Error> {
+        Ok(Self {
+            addr: m.addr.try_into()?,
             port: m.port,
-        }
+        })
     }
 }
 
@@ -209,17 +329,29 @@ impl From<UdpMetadata> for smoltcp::wire::IpEndpoint {
 #[repr(C)]
 pub enum Address {
     Ipv6(Ipv6Address),
+    Ipv4(Ipv4Address),
 }
 
+/// Conversion to `smoltcp`'s address type fails for IPv4 addresses if the net
+/// task was built without IPv4 support.
 #[cfg(feature = "use-smoltcp")]
-impl From<Address> for smoltcp::wire::IpAddress {
-    fn from(a: Address) -> Self {
+impl TryFrom<Address> for smoltcp::wire::IpAddress {
+    type Error = AddressUnsupported;
+
+    fn try_from(a: Address) -> Result<Self, Self::Error> {
         match a {
-            Address::Ipv6(a) => Self::Ipv6(a.into()),
+            Address::Ipv6(a) => Ok(Self::Ipv6(a.into())),
+            #[cfg(feature = "ipv4")]
+            Address::Ipv4(a) => Ok(Self::Ipv4(a.into())),
+            #[cfg(not(feature = "ipv4"))]
+            Address::Ipv4(_) => Err(AddressUnsupported),
         }
     }
 }
 
+#[cfg(feature = "use-smoltcp")]
+pub struct AddressUnsupported;
+
 #[cfg(feature = "use-smoltcp")]
 impl TryFrom<smoltcp::wire::IpAddress> for Address {
     type Error = AddressUnspecified;
@@ -229,6 +361,8 @@ impl TryFrom<smoltcp::wire::IpAddress> for Address {
 
         match a {
             IpAddress::Ipv6(a) => Ok(Self::Ipv6(a.into())),
+            #[cfg(feature = "ipv4")]
+            IpAddress::Ipv4(a) => Ok(Self::Ipv4(a.into())),
         }
     }
 }
@@ -256,5 +390,42 @@ impl From<Ipv6Address> for smoltcp::wire::Ipv6Address {
     }
 }
 
+#[derive(
+    Copy, Clone, Debug, Serialize, SerializedSize, Deserialize, PartialEq, Eq,
+)]
+#[serde(transparent)]
+pub struct Ipv4Address(pub [u8; 4]);
+
+#[cfg(all(feature = "use-smoltcp", feature = "ipv4"))]
+impl From<smoltcp::wire::Ipv4Address> for Ipv4Address {
+    fn from(a: smoltcp::wire::Ipv4Address) -> Self {
+        Self(a.0)
+    }
+}
+
+#[cfg(all(feature = "use-smoltcp", feature = "ipv4"))]
+impl From<Ipv4Address> for smoltcp::wire::Ipv4Address {
+    fn from(a: Ipv4Address) -> Self {
+        Self(a.0)
+    }
+}
+
+/// Current IPv4 configuration of the net task, as reported by
+/// `get_ipv4_status`.
+#[derive(
+    Copy, Clone, Debug, Serialize, SerializedSize, Deserialize, PartialEq, Eq,
+)]
+pub struct Ipv4Status {
+    pub address: Ipv4Address,
+    pub prefix_len: u8,
+    pub gateway: Option<Ipv4Address>,
+
+    /// Server that leased us `address`, or `None` if it's static.
+    pub dhcp_server: Option<Ipv4Address>,
+
+    /// Time (per `sys_get_timer`) at which this configuration took effect.
+    pub since: u64,
+}
+
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
 include!(concat!(env!("OUT_DIR"), "/net_config.rs"));
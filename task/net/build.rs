@@ -3,7 +3,7 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use anyhow::{anyhow, bail, Result};
-use build_net::{BufSize, NetConfig, SocketConfig};
+use build_net::{BufSize, Ipv4Config, NetConfig, SocketConfig};
 use proc_macro2::TokenStream;
 use std::io::Write;
 
@@ -17,6 +17,7 @@ fn main() -> Result<()> {
 
     let net_config = build_net::load_net_config()?;
 
+    check_buffer_sizes(&net_config)?;
     generate_net_config(&net_config)?;
     build_util::expose_target_board();
     build_util::build_notifications()?;
@@ -24,6 +25,35 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// Checks that our socket buffers, which are statically allocated in our own
+/// RAM, leave room for our stack. (They need to leave room for a good deal
+/// more than that, but the rest is harder to predict; the linker will catch
+/// it, just with a less helpful message.)
+fn check_buffer_sizes(config: &NetConfig) -> Result<()> {
+    let me = build_util::task_full_config_toml()?;
+    let Some(&ram) = me.max_sizes.get("ram") else {
+        return Ok(());
+    };
+    let vlan_count = config.vlan.map(|v| v.count).unwrap_or(1);
+
+    let mut total = 0;
+    for socket in config.sockets.values() {
+        // UDP sockets are replicated on each VLAN; TCP sockets are never
+        // used with VLANs.
+        let copies = if socket.kind == "tcp" { 1 } else { vlan_count };
+        total += (socket.tx.bytes + socket.rx.bytes) * copies;
+    }
+    let stack = me.stacksize.unwrap_or(0) as usize;
+
+    if total + stack > ram as usize {
+        bail!(
+            "net socket buffers ({total} bytes) and stack ({stack} bytes) \
+             don't fit in the net task's RAM ({ram} bytes)"
+        );
+    }
+    Ok(())
+}
+
 fn generate_net_config(config: &NetConfig) -> Result<()> {
     let out_dir = build_util::out_dir();
     let dest_path = out_dir.join("net_config.rs");
@@ -37,6 +67,8 @@ fn generate_net_config(config: &NetConfig) -> Result<()> {
         quote::quote! {
             use core::sync::atomic::{AtomicBool, Ordering};
             use smoltcp::socket::udp;
+            #[cfg(feature = "tcp")]
+            use smoltcp::socket::tcp;
 
             pub const SOCKET_COUNT: usize = #socket_count;
         }
@@ -61,6 +93,27 @@ fn generate_net_config(config: &NetConfig) -> Result<()> {
     writeln!(out, "{}", generate_constructor(config)?)?;
     writeln!(out, "{}", generate_owner_info(config)?)?;
     writeln!(out, "{}", generate_port_table(config)?)?;
+    writeln!(out, "{}", generate_kind_table(config)?)?;
+    writeln!(out, "{}", generate_ipv4_table(config)?)?;
+    writeln!(out, "{}", generate_counter_readers(config)?)?;
+
+    match (build_util::has_feature("ipv4"), &config.ipv4) {
+        (true, Some(ipv4)) => {
+            // Like TCP, IPv4 would need an address per VLAN, which we have no
+            // way to configure yet.
+            if config.vlan.is_some() {
+                bail!("IPv4 is not supported with VLANs");
+            }
+            writeln!(out, "{}", generate_ipv4_config(ipv4)?)?;
+        }
+        (true, None) => {
+            bail!("ipv4 feature is enabled, but ipv4 is missing from config")
+        }
+        (false, Some(_)) => {
+            bail!("ipv4 feature is disabled, but ipv4 is present in config")
+        }
+        (false, None) => (),
+    }
 
     build_net::generate_socket_enum(config, &mut out)?;
 
@@ -71,6 +124,62 @@ fn generate_net_config(config: &NetConfig) -> Result<()> {
     Ok(())
 }
 
+fn generate_ipv4_config(config: &Ipv4Config) -> Result<TokenStream> {
+    let parse_addr = |s: &str| -> Result<TokenStream> {
+        let a: std::net::Ipv4Addr = s
+            .parse()
+            .map_err(|e| anyhow!("bad IPv4 address {s:?}: {e}"))?;
+        let [a, b, c, d] = a.octets();
+        Ok(quote::quote! {
+            smoltcp::wire::Ipv4Address::new(#a, #b, #c, #d)
+        })
+    };
+
+    match (&config.address, config.dhcp) {
+        (Some(cidr), false) => {
+            // The DHCP client would fight us over the address.
+            if build_util::has_feature("dhcp") {
+                bail!("dhcp feature is enabled, but ipv4 has a static address");
+            }
+            let Some((addr, prefix_len)) = cidr.split_once('/') else {
+                bail!("IPv4 address {cidr:?} must have a prefix length");
+            };
+            let addr = parse_addr(addr)?;
+            let prefix_len: u8 =
+                prefix_len.parse().ok().filter(|&n| n <= 32).ok_or_else(
+                    || anyhow!("bad IPv4 prefix length in {cidr:?}"),
+                )?;
+            let gateway = match &config.gateway {
+                Some(g) => {
+                    let g = parse_addr(g)?;
+                    quote::quote! { Some(#g) }
+                }
+                None => quote::quote! { None },
+            };
+            Ok(quote::quote! {
+                pub(crate) const IPV4_CONFIG: crate::server::Ipv4Config =
+                    crate::server::Ipv4Config::Static {
+                        cidr: smoltcp::wire::Ipv4Cidr::new(#addr, #prefix_len),
+                        gateway: #gateway,
+                    };
+            })
+        }
+        (None, true) => {
+            if !build_util::has_feature("dhcp") {
+                bail!("IPv4 over DHCP requires the net task's dhcp feature");
+            }
+            if config.gateway.is_some() {
+                bail!("IPv4 gateway can't be set when using DHCP");
+            }
+            Ok(quote::quote! {
+                pub(crate) const IPV4_CONFIG: crate::server::Ipv4Config =
+                    crate::server::Ipv4Config::Dhcp;
+            })
+        }
+        _ => bail!("IPv4 config must have exactly one of address and dhcp"),
+    }
+}
+
 fn generate_port_table(config: &NetConfig) -> Result<TokenStream> {
     let consts = config.sockets.values().map(|socket| {
         let port = socket.port;
@@ -86,6 +195,65 @@ fn generate_port_table(config: &NetConfig) -> Result<TokenStream> {
     })
 }
 
+fn generate_kind_table(config: &NetConfig) -> Result<TokenStream> {
+    let consts = config.sockets.values().map(|socket| {
+        let kind = match socket.kind.as_str() {
+            "tcp" => quote::quote! { Tcp },
+            _ => quote::quote! { Udp },
+        };
+        quote::quote! { crate::server::SocketKind::#kind }
+    });
+
+    let n = config.sockets.len();
+
+    Ok(quote::quote! {
+        pub(crate) const SOCKET_KINDS: [crate::server::SocketKind; #n] = [
+            #( #consts ),*
+        ];
+    })
+}
+
+fn generate_ipv4_table(config: &NetConfig) -> Result<TokenStream> {
+    let consts = config
+        .sockets
+        .iter()
+        .map(|(name, socket)| {
+            if socket.ipv4 && !build_util::has_feature("ipv4") {
+                bail!("socket {name} sets ipv4, but the ipv4 feature is off");
+            }
+            let ipv4 = socket.ipv4;
+            Ok(quote::quote! { #ipv4 })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let n = config.sockets.len();
+
+    Ok(quote::quote! {
+        pub(crate) const SOCKET_IPV4: [bool; #n] = [
+            #( #consts ),*
+        ];
+    })
+}
+
+fn generate_counter_readers(config: &NetConfig) -> Result<TokenStream> {
+    let consts = config
+        .counter_readers
+        .iter()
+        .map(|name| {
+            let task: syn::Ident = syn::parse_str(name)?;
+            Ok(quote::quote! { hubris_num_tasks::Task::#task as usize })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let n = config.counter_readers.len();
+
+    Ok(quote::quote! {
+        pub(crate) const COUNTER_READERS: [usize; #n] = [
+            #( #consts ),*
+        ];
+    })
+}
+
 fn generate_owner_info(config: &NetConfig) -> Result<TokenStream> {
     let consts: Vec<_> = config
         .sockets
@@ -123,16 +291,51 @@ fn generate_socket_state(
     config: &SocketConfig,
     vlan_count: usize,
 ) -> Result<TokenStream> {
-    if config.kind != "udp" {
-        bail!("unsupported socket kind");
+    match config.kind.as_str() {
+        "udp" => {
+            if config.tx.packets == 0 || config.rx.packets == 0 {
+                bail!("UDP socket {name} must buffer at least one packet");
+            }
+            let tx = generate_buffers(name, "TX", &config.tx, vlan_count);
+            let rx = generate_buffers(name, "RX", &config.rx, vlan_count);
+            Ok(quote::quote! {
+                #tx
+                #rx
+            })
+        }
+        "tcp" => {
+            if !build_util::has_feature("tcp") {
+                bail!("TCP socket {name} requires the net task's tcp feature");
+            }
+            // TCP connections are per-interface, and we don't yet have a way
+            // to tell clients which VLAN a connection arrived on. This holds
+            // even for a single VLAN, whose sockets wouldn't be bound to it.
+            if build_util::has_feature("vlan") {
+                bail!("TCP socket {name} is not supported with VLANs");
+            }
+            let tx = generate_stream_buffer(name, "TX", &config.tx);
+            let rx = generate_stream_buffer(name, "RX", &config.rx);
+            Ok(quote::quote! {
+                #tx
+                #rx
+            })
+        }
+        kind => bail!("socket {name} has unsupported kind {kind:?}"),
     }
+}
 
-    let tx = generate_buffers(name, "TX", &config.tx, vlan_count);
-    let rx = generate_buffers(name, "RX", &config.rx, vlan_count);
-    Ok(quote::quote! {
-        #tx
-        #rx
-    })
+fn generate_stream_buffer(
+    name: &str,
+    dir: &str,
+    config: &BufSize,
+) -> TokenStream {
+    let bytecnt = config.bytes;
+    let upname = name.to_ascii_uppercase();
+    let bufname: syn::Ident =
+        syn::parse_str(&format!("SOCK_{}_DAT_{}", dir, upname)).unwrap();
+    quote::quote! {
+        static mut #bufname: [u8; #bytecnt] = [0u8; #bytecnt];
+    }
 }
 
 fn generate_buffers(
@@ -159,12 +362,14 @@ fn generate_buffers(
 fn generate_state_struct(config: &NetConfig) -> TokenStream {
     let n = config.sockets.len();
     quote::quote! {
-        pub(crate) struct Sockets<'a, const N: usize>(pub [[udp::Socket<'a>; #n]; N]);
+        pub(crate) struct Sockets<'a, const N: usize>(
+            pub [[smoltcp::socket::Socket<'a>; #n]; N]
+        );
     }
 }
 
 fn generate_constructor(config: &NetConfig) -> Result<TokenStream> {
-    let name_to_sockets = |name: &String, i: usize| {
+    let name_to_sockets = |name: &String, kind: &str, i: usize| {
         let upname = name.to_ascii_uppercase();
         let rxhdrs: syn::Ident =
             syn::parse_str(&format!("SOCK_RX_HDR_{}", upname)).unwrap();
@@ -175,8 +380,18 @@ fn generate_constructor(config: &NetConfig) -> Result<TokenStream> {
         let txbytes: syn::Ident =
             syn::parse_str(&format!("SOCK_TX_DAT_{}", upname)).unwrap();
 
+        if kind == "tcp" {
+            // TCP sockets aren't replicated across VLANs, so `i` is always 0.
+            return quote::quote! {
+                smoltcp::socket::Socket::Tcp(tcp::Socket::new(
+                    tcp::SocketBuffer::new(unsafe { &mut #rxbytes[..] }),
+                    tcp::SocketBuffer::new(unsafe { &mut #txbytes[..] }),
+                ))
+            };
+        }
+
         quote::quote! {
-            udp::Socket::new(
+            smoltcp::socket::Socket::Udp(udp::Socket::new(
                 udp::PacketBuffer::new(
                     unsafe { &mut #rxhdrs[#i][..] },
                     unsafe { &mut #rxbytes[#i][..] },
@@ -185,7 +400,7 @@ fn generate_constructor(config: &NetConfig) -> Result<TokenStream> {
                     unsafe { &mut #txhdrs[#i][..] },
                     unsafe { &mut #txbytes[#i][..] },
                 ),
-            )
+            ))
         }
     };
     let vlan_count = config.vlan.map(|v| v.count).unwrap_or(1);
@@ -193,8 +408,8 @@ fn generate_constructor(config: &NetConfig) -> Result<TokenStream> {
         .map(|i| {
             let s = config
                 .sockets
-                .keys()
-                .map(|n| name_to_sockets(n, i))
+                .iter()
+                .map(|(n, s)| name_to_sockets(n, &s.kind, i))
                 .collect::<Vec<_>>();
             quote::quote! {
                 [
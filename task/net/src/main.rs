@@ -47,6 +47,10 @@ mod server;
 )]
 mod bsp;
 
+// TCP connections can't yet be tied to a VLAN; see the net task's build.rs.
+#[cfg(all(feature = "tcp", feature = "vlan"))]
+compile_error!("the `tcp` and `vlan` features can't be used together");
+
 #[cfg_attr(feature = "vlan", path = "server_vlan.rs")]
 #[cfg_attr(not(feature = "vlan"), path = "server_basic.rs")]
 mod server_impl;
@@ -56,9 +60,11 @@ pub(crate) mod mgmt;
 
 mod idl {
     use task_net_api::{
-        KszError, KszMacTableEntry, LargePayloadBehavior, MacAddress,
-        MacAddressBlock, ManagementCounters, ManagementLinkStatus, MgmtError,
-        PhyError, RecvError, SendError, SocketName, UdpMetadata,
+        CountersError, EthernetDropCounters, Ipv4Error, Ipv4Status, KszError,
+        KszMacTableEntry, LargePayloadBehavior, MacAddress, MacAddressBlock,
+        ManagementCounters, ManagementLinkStatus, MgmtError, PhyError,
+        RecvError, SendError, SocketCounters, SocketName, TcpEndpoint,
+        TcpError, UdpMetadata,
     };
     include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
 }
@@ -221,11 +227,15 @@ fn main() -> ! {
     // Turn on our IRQ.
     userlib::sys_irq_control(notifications::ETH_IRQ_MASK, true);
 
-    // We use two timers:
+    // We use up to three timers:
     #[derive(Copy, Clone, Enum)]
     enum Timers {
         Wake,
         Watchdog,
+        /// Time at which the IP stack next needs attention (e.g. TCP
+        /// retransmission or DHCP renewal), even if nothing else happens.
+        #[cfg(any(feature = "tcp", feature = "dhcp"))]
+        Poll,
     }
     let mut multitimer =
         Multitimer::<Timers>::new(notifications::WAKE_TIMER_BIT);
@@ -253,6 +263,11 @@ fn main() -> ! {
         let now = sys_get_timer().now;
         let activity = server.poll(now);
 
+        #[cfg(any(feature = "tcp", feature = "dhcp"))]
+        if let Some(delay) = server.poll_delay(now) {
+            multitimer.set_timer(Timers::Poll, now + delay, None);
+        }
+
         if activity.mac_rx {
             // Whenever we observe activity we bump the timer forward. Because
             // we're going to poll the timer below (after doing this) and we
@@ -279,6 +294,10 @@ fn main() -> ! {
                     Timers::Watchdog => {
                         jefe.restart_me();
                     }
+                    #[cfg(any(feature = "tcp", feature = "dhcp"))]
+                    Timers::Poll => {
+                        // We'll poll the stack at the top of the loop.
+                    }
                 }
             }
             let mut msgbuf = [0u8; idl::INCOMING_SIZE];
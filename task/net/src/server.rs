@@ -13,16 +13,28 @@ use crate::generated::VLAN_RANGE;
 use drv_stm32h7_eth as eth;
 use idol_runtime::{ClientError, RequestError};
 use task_net_api::{
+    Address, CountersError, EthernetDropCounters, Ipv4Error, Ipv4Status,
     KszError, KszMacTableEntry, LargePayloadBehavior, MacAddress,
     ManagementCounters, ManagementLinkStatus, MgmtError, PhyError, RecvError,
-    SendError, SocketName, UdpMetadata,
+    SendError, SocketCounters, SocketName, TcpEndpoint, TcpError, UdpMetadata,
 };
 
+use core::cell::Cell;
 use core::iter::zip;
 use heapless::Vec;
 use smoltcp::iface::{Interface, SocketHandle, SocketStorage};
-use smoltcp::socket::udp;
-use smoltcp::wire::{EthernetAddress, Ipv6Cidr};
+#[cfg(feature = "dhcp")]
+use smoltcp::socket::dhcpv4;
+#[cfg(feature = "tcp")]
+use smoltcp::socket::tcp;
+use smoltcp::socket::{udp, Socket};
+#[cfg(feature = "dhcp")]
+use smoltcp::wire::IpCidr;
+use smoltcp::wire::{
+    EthernetAddress, IpEndpoint, IpListenEndpoint, Ipv6Address, Ipv6Cidr,
+};
+#[cfg(feature = "ipv4")]
+use smoltcp::wire::{Ipv4Address, Ipv4Cidr};
 use userlib::{sys_post, sys_refresh_task_id, UnwrapLite};
 use zerocopy::byteorder::U16;
 
@@ -52,6 +64,125 @@ where
         self.net_send_packet(msg, socket, metadata, payload)
     }
 
+    ////////////////////////////////////////////////////////////////////////////
+    // TCP functions
+    #[cfg(feature = "tcp")]
+    fn tcp_listen(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+    ) -> Result<(), RequestError<TcpError>> {
+        self.net_tcp_listen(msg, socket)
+    }
+
+    #[cfg(feature = "tcp")]
+    fn tcp_accept(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+    ) -> Result<TcpEndpoint, RequestError<TcpError>> {
+        self.net_tcp_accept(msg, socket)
+    }
+
+    #[cfg(feature = "tcp")]
+    fn tcp_connect(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+        remote: TcpEndpoint,
+    ) -> Result<(), RequestError<TcpError>> {
+        self.net_tcp_connect(msg, socket, remote)
+    }
+
+    #[cfg(feature = "tcp")]
+    fn tcp_recv(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+        payload: idol_runtime::Leased<idol_runtime::W, [u8]>,
+    ) -> Result<u32, RequestError<TcpError>> {
+        self.net_tcp_recv(msg, socket, payload)
+    }
+
+    #[cfg(feature = "tcp")]
+    fn tcp_send(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+        payload: idol_runtime::Leased<idol_runtime::R, [u8]>,
+    ) -> Result<u32, RequestError<TcpError>> {
+        self.net_tcp_send(msg, socket, payload)
+    }
+
+    #[cfg(feature = "tcp")]
+    fn tcp_close(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+    ) -> Result<(), RequestError<TcpError>> {
+        self.net_tcp_close(msg, socket)
+    }
+
+    ////////////////////////////////////////////////////////////////////////////
+    // Stubs for TCP functions when TCP is disabled. Without it there are no
+    // TCP sockets, so whichever socket the caller named is the wrong kind.
+    #[cfg(not(feature = "tcp"))]
+    fn tcp_listen(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        _socket: SocketName,
+    ) -> Result<(), RequestError<TcpError>> {
+        Err(RequestError::Fail(ClientError::BadMessageContents))
+    }
+
+    #[cfg(not(feature = "tcp"))]
+    fn tcp_accept(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        _socket: SocketName,
+    ) -> Result<TcpEndpoint, RequestError<TcpError>> {
+        Err(RequestError::Fail(ClientError::BadMessageContents))
+    }
+
+    #[cfg(not(feature = "tcp"))]
+    fn tcp_connect(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        _socket: SocketName,
+        _remote: TcpEndpoint,
+    ) -> Result<(), RequestError<TcpError>> {
+        Err(RequestError::Fail(ClientError::BadMessageContents))
+    }
+
+    #[cfg(not(feature = "tcp"))]
+    fn tcp_recv(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        _socket: SocketName,
+        _payload: idol_runtime::Leased<idol_runtime::W, [u8]>,
+    ) -> Result<u32, RequestError<TcpError>> {
+        Err(RequestError::Fail(ClientError::BadMessageContents))
+    }
+
+    #[cfg(not(feature = "tcp"))]
+    fn tcp_send(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        _socket: SocketName,
+        _payload: idol_runtime::Leased<idol_runtime::R, [u8]>,
+    ) -> Result<u32, RequestError<TcpError>> {
+        Err(RequestError::Fail(ClientError::BadMessageContents))
+    }
+
+    #[cfg(not(feature = "tcp"))]
+    fn tcp_close(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        _socket: SocketName,
+    ) -> Result<(), RequestError<TcpError>> {
+        Err(RequestError::Fail(ClientError::BadMessageContents))
+    }
+
     fn smi_read(
         &mut self,
         _msg: &userlib::RecvMessage,
@@ -119,6 +250,49 @@ where
         Ok(self.spare_macs)
     }
 
+    fn get_socket_counters(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+    ) -> Result<SocketCounters, RequestError<CountersError>> {
+        let socket_index = socket as usize;
+        let caller = msg.sender.index();
+        if generated::SOCKET_OWNERS[socket_index].0.index() != caller
+            && !generated::COUNTER_READERS.contains(&caller)
+        {
+            return Err(CountersError::NotAllowed.into());
+        }
+        Ok(self.counters[socket_index])
+    }
+
+    fn get_ethernet_drop_counters(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+    ) -> Result<EthernetDropCounters, RequestError<core::convert::Infallible>>
+    {
+        let drops = self.eth.rx_drop_counts();
+        Ok(EthernetDropCounters {
+            rx_errors: drops.errors,
+            rx_bad_vid: drops.bad_vid,
+        })
+    }
+
+    #[cfg(feature = "ipv4")]
+    fn get_ipv4_status(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+    ) -> Result<Ipv4Status, RequestError<Ipv4Error>> {
+        Ok(self.ipv4.ok_or(Ipv4Error::Unconfigured)?)
+    }
+
+    #[cfg(not(feature = "ipv4"))]
+    fn get_ipv4_status(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+    ) -> Result<Ipv4Status, RequestError<Ipv4Error>> {
+        Err(Ipv4Error::NotAvailable.into())
+    }
+
     ////////////////////////////////////////////////////////////////////////////
     // Stubs for KSZ8463 functions when it's not present
     #[cfg(not(feature = "ksz8463"))]
@@ -244,6 +418,10 @@ where
 pub trait DeviceExt: smoltcp::phy::Device {
     fn read_and_clear_activity_flag(&self) -> bool;
 
+    /// Returns the number of UDP datagrams that arrived for each socket's port
+    /// since the last call, and resets the counts.
+    fn take_udp_arrivals(&self) -> [u16; SOCKET_COUNT];
+
     fn make_meta(
         &self,
         port: u16,
@@ -261,10 +439,37 @@ where
 
     vlan_state: [VLanState<E>; N],
     client_waiting_to_send: [bool; SOCKET_COUNT],
+    counters: [SocketCounters; SOCKET_COUNT],
     bsp: B,
 
     mac: EthernetAddress,
     spare_macs: MacAddressBlock,
+
+    /// Our current IPv4 configuration, if we have one.
+    #[cfg(feature = "ipv4")]
+    ipv4: Option<Ipv4Status>,
+}
+
+/// How we get our IPv4 address, as configured in the app.toml.
+#[cfg(feature = "ipv4")]
+pub(crate) enum Ipv4Config {
+    Static {
+        cidr: Ipv4Cidr,
+        gateway: Option<Ipv4Address>,
+    },
+    #[cfg(feature = "dhcp")]
+    Dhcp,
+}
+
+/// Number of sockets we add to each interface beyond those in the app.toml.
+const DHCP_SOCKET_COUNT: usize = if cfg!(feature = "dhcp") { 1 } else { 0 };
+
+/// Kind of socket, as configured in the app.toml.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum SocketKind {
+    Udp,
+    #[cfg(feature = "tcp")]
+    Tcp,
 }
 
 struct VLanState<E>
@@ -276,8 +481,55 @@ where
     iface: &'static mut Interface,
     device: E,
 
+    /// This interface's link-local address, to which TCP sockets that don't
+    /// talk IPv4 are bound when they listen or connect.
+    #[cfg(feature = "tcp")]
+    ipv6_addr: Ipv6Address,
+
     /// Used to detect stuck queues (due to smoltcp#594)
     queue_watchdog: [QueueWatchdog; SOCKET_COUNT],
+
+    /// Our estimate of how many packets are waiting in each UDP socket's rx
+    /// queue, which `smoltcp` doesn't tell us. (Entries for TCP sockets are
+    /// unused.)
+    rx_queued: [u16; SOCKET_COUNT],
+
+    /// State of each TCP socket when its owner was last woken, so that we can
+    /// wake it again when the connection changes state. (Entries for UDP
+    /// sockets are unused.)
+    #[cfg(feature = "tcp")]
+    tcp_states: [tcp::State; SOCKET_COUNT],
+
+    /// DHCP client socket, which lives alongside the configured sockets.
+    #[cfg(feature = "dhcp")]
+    dhcp_handle: SocketHandle,
+}
+
+/// Checks whether socket `index` has opted into IPv4 in the app.toml.
+fn socket_allows_ipv4(index: usize) -> bool {
+    cfg!(feature = "ipv4") && generated::SOCKET_IPV4[index]
+}
+
+/// Returns the local endpoint for socket `index` on an interface with
+/// link-local address `ipv6_addr`. Sockets that talk IPv4 accept packets to
+/// any of the interface's addresses, since the IPv4 one may come and go with a
+/// DHCP lease; the rest only hear IPv6 traffic to the link-local address.
+fn listen_endpoint(ipv6_addr: Ipv6Address, index: usize) -> IpListenEndpoint {
+    let port = generated::SOCKET_PORTS[index];
+    if socket_allows_ipv4(index) {
+        IpListenEndpoint::from(port)
+    } else {
+        IpListenEndpoint::from((ipv6_addr, port))
+    }
+}
+
+/// Checks whether socket `index` may talk to `addr`: IPv4 addresses are only
+/// reachable from sockets that have opted in.
+fn can_reach(index: usize, addr: Address) -> bool {
+    match addr {
+        Address::Ipv6(_) => true,
+        Address::Ipv4(_) => socket_allows_ipv4(index),
+    }
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -300,21 +552,116 @@ impl<E: DeviceExt> VLanState<E> {
         self.socket_handles.get(index).cloned()
     }
 
-    /// Gets the socket `index`. If `index` is out of range, returns
-    /// `None`.
-    ///
-    /// Sockets are currently assumed to be UDP.
+    /// Gets the UDP socket `index`. If `index` is out of range, or isn't a
+    /// UDP socket, returns `None`.
     pub(crate) fn get_socket_mut(
         &mut self,
         index: usize,
     ) -> Option<&mut udp::Socket<'static>> {
+        if generated::SOCKET_KINDS.get(index)? != &SocketKind::Udp {
+            return None;
+        }
         Some(
             self.socket_set
                 .get_mut::<udp::Socket<'_>>(self.get_handle(index)?),
         )
     }
 
-    pub(crate) fn check_socket_watchdog(&mut self) -> bool {
+    /// Gets the TCP socket `index`. If `index` is out of range, or isn't a
+    /// TCP socket, returns `None`.
+    #[cfg(feature = "tcp")]
+    pub(crate) fn get_tcp_socket_mut(
+        &mut self,
+        index: usize,
+    ) -> Option<&mut tcp::Socket<'static>> {
+        if generated::SOCKET_KINDS.get(index)? != &SocketKind::Tcp {
+            return None;
+        }
+        Some(
+            self.socket_set
+                .get_mut::<tcp::Socket<'_>>(self.get_handle(index)?),
+        )
+    }
+
+    /// Checks whether the owner of socket `index` has something to receive:
+    /// a packet or bytes, or (for TCP) a change in connection state.
+    fn recv_ready(&mut self, index: usize) -> bool {
+        match generated::SOCKET_KINDS[index] {
+            SocketKind::Udp => {
+                self.get_socket_mut(index).unwrap_lite().can_recv()
+            }
+            #[cfg(feature = "tcp")]
+            SocketKind::Tcp => {
+                let s = self.get_tcp_socket_mut(index).unwrap_lite();
+                let (can_recv, state) = (s.can_recv(), s.state());
+                let changed = state != self.tcp_states[index];
+                self.tcp_states[index] = state;
+                can_recv || changed
+            }
+        }
+    }
+
+    /// Checks whether socket `index` has room to send.
+    fn send_ready(&mut self, index: usize) -> bool {
+        match generated::SOCKET_KINDS[index] {
+            SocketKind::Udp => {
+                self.get_socket_mut(index).unwrap_lite().can_send()
+            }
+            #[cfg(feature = "tcp")]
+            SocketKind::Tcp => {
+                self.get_tcp_socket_mut(index).unwrap_lite().can_send()
+            }
+        }
+    }
+
+    /// Applies any change in our DHCP lease to the interface. Returns
+    /// `Some(status)` if the IPv4 configuration changed, where `status` is
+    /// the new configuration (or `None` if we've lost our lease).
+    #[cfg(feature = "dhcp")]
+    fn poll_dhcp(&mut self, now: u64) -> Option<Option<Ipv4Status>> {
+        let event = self
+            .socket_set
+            .get_mut::<dhcpv4::Socket<'_>>(self.dhcp_handle)
+            .poll()?;
+
+        // Whether we're replacing our lease or losing it, the old address and
+        // route have to go.
+        self.iface.update_ip_addrs(|addrs| {
+            if let Some(i) =
+                addrs.iter().position(|a| matches!(a, IpCidr::Ipv4(_)))
+            {
+                addrs.swap_remove(i);
+            }
+        });
+        self.iface.routes_mut().remove_default_ipv4_route();
+
+        match event {
+            dhcpv4::Event::Deconfigured => Some(None),
+            dhcpv4::Event::Configured(config) => {
+                self.iface.update_ip_addrs(|addrs| {
+                    addrs.push(config.address.into()).unwrap_lite()
+                });
+                if let Some(router) = config.router {
+                    self.iface
+                        .routes_mut()
+                        .add_default_ipv4_route(router)
+                        .unwrap_lite();
+                }
+                Some(Some(Ipv4Status {
+                    address: config.address.address().into(),
+                    prefix_len: config.address.prefix_len(),
+                    gateway: config.router.map(Into::into),
+                    dhcp_server: Some(config.server.address.into()),
+                    since: now,
+                }))
+            }
+        }
+    }
+
+    pub(crate) fn check_socket_watchdog(
+        &mut self,
+        counters: &mut [SocketCounters; SOCKET_COUNT],
+    ) -> bool {
         let mut changed = false;
         for socket_index in 0..SOCKET_COUNT {
             if self.queue_watchdog[socket_index]
@@ -328,7 +675,9 @@ impl<E: DeviceExt> VLanState<E> {
                 let e = s.endpoint();
                 s.close();
                 s.bind(e).unwrap_lite();
+                self.rx_queued[socket_index] = 0;
                 changed = true;
+                bump(&mut counters[socket_index].watchdog_resets);
 
                 // Reset the watchdog, so it doesn't fire right away
                 self.queue_watchdog[socket_index] = QueueWatchdog::Nominal;
@@ -336,6 +685,95 @@ impl<E: DeviceExt> VLanState<E> {
         }
         changed
     }
+
+    /// Works out how many datagrams `smoltcp` dropped during the last poll
+    /// because a UDP socket's rx queue was full, and adds them to `counters`.
+    ///
+    /// Packets only leave the queues between polls, so during a poll each
+    /// socket can take as many of its arrivals as it has free slots. Our count
+    /// of queued packets can drift if `smoltcp` rejects a datagram for some
+    /// other reason (e.g. it's for an address we don't have), so we resync it
+    /// whenever the queue turns out to be empty.
+    fn count_rx_buffer_full(
+        &mut self,
+        counters: &mut [SocketCounters; SOCKET_COUNT],
+    ) {
+        let arrivals = self.device.take_udp_arrivals();
+        for (socket_index, arrived) in arrivals.into_iter().enumerate() {
+            let Some(s) = self.get_socket_mut(socket_index) else {
+                continue;
+            };
+            if !s.can_recv() {
+                // Nothing got in, so nothing was turned away for lack of room.
+                self.rx_queued[socket_index] = 0;
+                continue;
+            }
+            let capacity =
+                u16::try_from(s.packet_recv_capacity()).unwrap_or(u16::MAX);
+            let queued = &mut self.rx_queued[socket_index];
+            let taken = arrived.min(capacity.saturating_sub(*queued));
+            *queued += taken;
+            let dropped = &mut counters[socket_index].rx_buffer_full;
+            *dropped = dropped.wrapping_add(u32::from(arrived - taken));
+        }
+    }
+}
+
+/// Per-socket counts of UDP datagrams that a device has received, noted
+/// before `smoltcp` sees them. This is the only way to tell when `smoltcp`
+/// drops datagrams for a full rx queue, since it doesn't count them.
+pub(crate) struct UdpArrivals([Cell<u16>; SOCKET_COUNT]);
+
+impl UdpArrivals {
+    pub(crate) fn new() -> Self {
+        Self([(); SOCKET_COUNT].map(|_| Cell::new(0)))
+    }
+
+    /// Counts `frame` if it's a UDP datagram for one of our UDP sockets.
+    pub(crate) fn note(&self, frame: &[u8]) {
+        if let Some(socket_index) = udp_socket_for_frame(frame) {
+            let n = &self.0[socket_index];
+            n.set(n.get().saturating_add(1));
+        }
+    }
+
+    pub(crate) fn take(&self) -> [u16; SOCKET_COUNT] {
+        core::array::from_fn(|i| self.0[i].take())
+    }
+}
+
+/// Finds the UDP socket that an (untagged) Ethernet frame is for, going by its
+/// destination port alone. IPv6 packets with extension headers aren't
+/// recognized.
+fn udp_socket_for_frame(frame: &[u8]) -> Option<usize> {
+    use smoltcp::wire::{
+        EthernetFrame, EthernetProtocol, IpProtocol, Ipv6Packet, UdpPacket,
+    };
+
+    let frame = EthernetFrame::new_checked(frame).ok()?;
+    let port = match frame.ethertype() {
+        EthernetProtocol::Ipv6 => {
+            let ip = Ipv6Packet::new_checked(frame.payload()).ok()?;
+            if ip.next_header() != IpProtocol::Udp {
+                return None;
+            }
+            UdpPacket::new_checked(ip.payload()).ok()?.dst_port()
+        }
+        #[cfg(feature = "ipv4")]
+        EthernetProtocol::Ipv4 => {
+            let ip =
+                smoltcp::wire::Ipv4Packet::new_checked(frame.payload()).ok()?;
+            if ip.next_header() != IpProtocol::Udp {
+                return None;
+            }
+            UdpPacket::new_checked(ip.payload()).ok()?.dst_port()
+        }
+        _ => return None,
+    };
+    (0..SOCKET_COUNT).find(|&i| {
+        generated::SOCKET_KINDS[i] == SocketKind::Udp
+            && generated::SOCKET_PORTS[i] == port
+    })
 }
 
 impl<'a, B, E, const N: usize> GenServerImpl<'a, B, E, N>
@@ -355,6 +793,9 @@ where
         // Local storage; this will end up owned by the returned ServerImpl.
         let mut vlan_state: Vec<VLanState<E>, N> = Vec::new();
 
+        #[cfg(feature = "ipv4")]
+        let mut ipv4 = None;
+
         // Did you bring enough MAC addresses for everyone?
         assert!(mac_address_block.count.get() as usize >= N);
         let mut mac: [u8; 6] = mac_address_block.base_mac;
@@ -365,7 +806,7 @@ where
             let ipv6_addr = link_local_iface_addr(mac_addr);
 
             // Make some types explicit to try and make this clearer.
-            let sockets: [udp::Socket<'_>; SOCKET_COUNT] = sockets;
+            let sockets: [Socket<'_>; SOCKET_COUNT] = sockets;
 
             let mut config = smoltcp::iface::Config::new();
             config.hardware_addr = Some(mac_addr.into());
@@ -375,17 +816,55 @@ where
             iface.update_ip_addrs(|ip_addrs| {
                 ip_addrs.push(Ipv6Cidr::new(ipv6_addr, 64).into()).unwrap()
             });
+            // (The build script only allows IPv4 without VLANs, so this only
+            // happens once.)
+            #[cfg(feature = "ipv4")]
+            match generated::IPV4_CONFIG {
+                Ipv4Config::Static { cidr, gateway } => {
+                    iface.update_ip_addrs(|ip_addrs| {
+                        ip_addrs.push(cidr.into()).unwrap_lite()
+                    });
+                    if let Some(gateway) = gateway {
+                        iface
+                            .routes_mut()
+                            .add_default_ipv4_route(gateway)
+                            .unwrap_lite();
+                    }
+                    ipv4 = Some(Ipv4Status {
+                        address: cidr.address().into(),
+                        prefix_len: cidr.prefix_len(),
+                        gateway: gateway.map(Into::into),
+                        dhcp_server: None,
+                        since: userlib::sys_get_timer().now,
+                    });
+                }
+                // We'll hear about our address from `poll_dhcp`.
+                #[cfg(feature = "dhcp")]
+                Ipv4Config::Dhcp => (),
+            }
 
             // Associate sockets with this interface.
             let mut socket_set =
                 smoltcp::iface::SocketSet::new(storage.sockets.as_mut_slice());
-            let socket_handles = sockets.map(|s| socket_set.add(s));
-            // Bind sockets to their ports.
-            for (&h, port) in zip(&socket_handles, generated::SOCKET_PORTS) {
-                socket_set
-                    .get_mut::<udp::Socket<'_>>(h)
-                    .bind((ipv6_addr, port))
-                    .unwrap_lite();
+            let socket_handles = sockets.map(|s| match s {
+                Socket::Udp(s) => socket_set.add(s),
+                #[cfg(feature = "tcp")]
+                Socket::Tcp(s) => socket_set.add(s),
+            });
+            #[cfg(feature = "dhcp")]
+            let dhcp_handle = socket_set.add(dhcpv4::Socket::new());
+
+            // Bind UDP sockets to their ports. TCP sockets wait until their
+            // owners ask them to listen or connect.
+            for (i, (&h, kind)) in
+                zip(&socket_handles, generated::SOCKET_KINDS).enumerate()
+            {
+                if kind == SocketKind::Udp {
+                    socket_set
+                        .get_mut::<udp::Socket<'_>>(h)
+                        .bind(listen_endpoint(ipv6_addr, i))
+                        .unwrap_lite();
+                }
             }
 
             vlan_state
@@ -393,8 +872,15 @@ where
                     socket_handles,
                     iface,
                     device,
+                    #[cfg(feature = "tcp")]
+                    ipv6_addr,
                     socket_set,
                     queue_watchdog: [QueueWatchdog::Nominal; SOCKET_COUNT],
+                    rx_queued: [0; SOCKET_COUNT],
+                    #[cfg(feature = "tcp")]
+                    tcp_states: [tcp::State::Closed; SOCKET_COUNT],
+                    #[cfg(feature = "dhcp")]
+                    dhcp_handle,
                 })
                 .unwrap_lite();
 
@@ -428,6 +914,7 @@ where
         Self {
             eth,
             client_waiting_to_send: [false; SOCKET_COUNT],
+            counters: [SocketCounters::default(); SOCKET_COUNT],
             vlan_state: vlan_state.into_array().unwrap_lite(),
             bsp,
             mac: EthernetAddress::from_bytes(&mac_address_block.base_mac),
@@ -436,6 +923,8 @@ where
                 count: U16::new(mac_address_block.count.get() - N as u16),
                 stride: mac_address_block.stride,
             },
+            #[cfg(feature = "ipv4")]
+            ipv4,
         }
     }
 
@@ -451,19 +940,41 @@ where
                 &mut vlan.device,
                 &mut vlan.socket_set,
             );
+            vlan.count_rx_buffer_full(&mut self.counters);
+            #[cfg(feature = "dhcp")]
+            if let Some(status) = vlan.poll_dhcp(t) {
+                self.ipv4 = status;
+                ip = true;
+            }
             // Test and clear our receive activity flag.
             mac_rx |= vlan.device.read_and_clear_activity_flag();
-            ip |= vlan.check_socket_watchdog();
+            ip |= vlan.check_socket_watchdog(&mut self.counters);
         }
 
         crate::Activity { ip, mac_rx }
     }
 
+    /// Returns how long (in ms) after `t` the IP stack next needs to be
+    /// polled even if nothing else happens, e.g. to retransmit TCP segments or
+    /// renew a DHCP lease.
+    #[cfg(any(feature = "tcp", feature = "dhcp"))]
+    pub(crate) fn poll_delay(&mut self, t: u64) -> Option<u64> {
+        let instant = smoltcp::time::Instant::from_millis(t as i64);
+        self.vlan_state
+            .iter_mut()
+            .filter_map(|v| v.iface.poll_delay(instant, &v.socket_set))
+            .map(|d| d.total_millis())
+            .min()
+    }
+
     /// Iterate over sockets, waking any that can do work.
     ///
     /// A task can do work if...
     ///
-    /// - any of its sockets (on any VLAN) have incoming packets waiting, or
+    /// - any of its sockets (on any VLAN) have incoming packets or bytes
+    ///   waiting, or
+    ///
+    /// - any of its TCP sockets has changed connection state, or
     ///
     /// - it is waiting to send on some socket S, and _all_ of the copies of S
     ///   across all VLANs can accept an outgoing packet. (The "all" is
@@ -472,16 +983,10 @@ where
     pub fn wake_sockets(&mut self) {
         for i in 0..SOCKET_COUNT {
             // recv wake depends only on the state of the sockets.
-            let recv_wake = self
-                .vlan_state
-                .iter_mut()
-                .any(|v| v.get_socket_mut(i).unwrap().can_recv());
+            let recv_wake = self.vlan_state.iter_mut().any(|v| v.recv_ready(i));
             // send wake only happens if the wait flag is set.
             let send_wake = self.client_waiting_to_send[i]
-                && self
-                    .vlan_state
-                    .iter_mut()
-                    .all(|v| v.get_socket_mut(i).unwrap().can_send());
+                && self.vlan_state.iter_mut().all(|v| v.send_ready(i));
 
             if recv_wake || send_wake {
                 let (task_id, notification) = generated::SOCKET_OWNERS[i];
@@ -517,10 +1022,12 @@ where
         payload: idol_runtime::Leased<idol_runtime::W, [u8]>,
     ) -> Result<UdpMetadata, RequestError<RecvError>> {
         let socket_index = socket as usize;
+        let counters = &mut self.counters[socket_index];
 
         if generated::SOCKET_OWNERS[socket_index].0.index()
             != msg.sender.index()
         {
+            bump(&mut counters.not_yours);
             return Err(RecvError::NotYours.into());
         }
 
@@ -530,16 +1037,22 @@ where
             let socket = vlan
                 .get_socket_mut(socket_index)
                 .ok_or(RequestError::Fail(ClientError::BadMessageContents))?;
+            // Packets we've taken off the queue and discarded
+            let mut discarded = 0;
             #[allow(clippy::while_let_loop)]
             loop {
                 match socket.recv() {
                     Ok((body, endp)) => {
                         if payload.len() < body.len() {
                             match large_payload_behavior {
-                                LargePayloadBehavior::Discard => continue,
                                 // If we add a `::Fail` case, we will need to
                                 // allow for caller retries (possibly by peeking
                                 // on the socket instead of recving)
+                                LargePayloadBehavior::Discard => {
+                                    bump(&mut counters.rx_too_large);
+                                    discarded += 1;
+                                    continue;
+                                }
                             }
                         }
                         payload
@@ -548,6 +1061,11 @@ where
 
                         // Release borrow on self/socket
                         let body_len = body.len();
+                        bump(&mut counters.rx_packets);
+                        counters.rx_bytes =
+                            counters.rx_bytes.wrapping_add(body_len as u64);
+                        let queued = &mut vlan.rx_queued[socket_index];
+                        *queued = queued.saturating_sub(discarded + 1);
 
                         return Ok(vlan.device.make_meta(
                             endp.port,
@@ -557,6 +1075,7 @@ where
                     }
                     Err(udp::RecvError::Exhausted) => {
                         // Move on to next vid
+                        vlan.rx_queued[socket_index] = 0;
                         break;
                     }
                 }
@@ -575,9 +1094,11 @@ where
         payload: idol_runtime::Leased<idol_runtime::R, [u8]>,
     ) -> Result<(), RequestError<SendError>> {
         let socket_index = socket as usize;
+        let counters = &mut self.counters[socket_index];
         if generated::SOCKET_OWNERS[socket_index].0.index()
             != msg.sender.index()
         {
+            bump(&mut counters.not_yours);
             return Err(SendError::NotYours.into());
         }
 
@@ -585,6 +1106,7 @@ where
         let vlan_index = {
             // Convert from absolute VID to an index in our VLAN array
             if !VLAN_RANGE.contains(&metadata.vid) {
+                bump(&mut counters.tx_invalid_vlan);
                 return Err(SendError::InvalidVLan.into());
             }
             usize::from(metadata.vid - VLAN_RANGE.start)
@@ -592,17 +1114,26 @@ where
         #[cfg(not(feature = "vlan"))]
         let vlan_index = 0;
 
+        if !can_reach(socket_index, metadata.addr) {
+            return Err(SendError::Unaddressable.into());
+        }
+        let remote = IpEndpoint::try_from(metadata)
+            .map_err(|_| SendError::Unaddressable)?;
+
         let vlan = &mut self.vlan_state[vlan_index];
         let socket = vlan
             .get_socket_mut(socket_index)
             .ok_or(RequestError::Fail(ClientError::BadMessageContents))?;
-        match socket.send(payload.len(), metadata.into()) {
+        match socket.send(payload.len(), remote) {
             Ok(buf) => {
                 payload
                     .read_range(0..payload.len(), buf)
                     .map_err(|_| RequestError::went_away())?;
                 self.client_waiting_to_send[socket_index] = false;
                 vlan.queue_watchdog[socket_index] = QueueWatchdog::Nominal;
+                bump(&mut counters.tx_packets);
+                counters.tx_bytes =
+                    counters.tx_bytes.wrapping_add(payload.len() as u64);
                 Ok(())
             }
             Err(udp::SendError::BufferFull) => {
@@ -626,6 +1157,7 @@ where
                     QueueWatchdog::QueueFullTimeout => (),
                 }
                 self.client_waiting_to_send[socket_index] = true;
+                bump(&mut counters.tx_queue_full);
                 Err(SendError::QueueFull.into())
             }
             Err(_e) => {
@@ -636,6 +1168,229 @@ where
     }
 }
 
+/// TCP operations. TCP sockets aren't replicated across VLANs (the build
+/// refuses to configure them alongside VLANs), so these all use the first and
+/// only interface.
+#[cfg(feature = "tcp")]
+impl<B, E, const N: usize> GenServerImpl<'_, B, E, N>
+where
+    B: bsp_support::Bsp,
+    E: DeviceExt,
+{
+    /// Checks that `socket` is a TCP socket owned by the caller, returning
+    /// its index.
+    fn tcp_socket_index(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+    ) -> Result<usize, RequestError<TcpError>> {
+        let socket_index = socket as usize;
+        if generated::SOCKET_OWNERS[socket_index].0.index()
+            != msg.sender.index()
+        {
+            bump(&mut self.counters[socket_index].not_yours);
+            return Err(TcpError::NotYours.into());
+        }
+        if generated::SOCKET_KINDS[socket_index] != SocketKind::Tcp {
+            return Err(RequestError::Fail(ClientError::BadMessageContents));
+        }
+        Ok(socket_index)
+    }
+
+    fn net_tcp_listen(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+    ) -> Result<(), RequestError<TcpError>> {
+        let socket_index = self.tcp_socket_index(msg, socket)?;
+        let vlan = &mut self.vlan_state[0];
+        let endpoint = listen_endpoint(vlan.ipv6_addr, socket_index);
+        let socket = vlan.get_tcp_socket_mut(socket_index).unwrap_lite();
+        // A connection that we closed first lingers in TIME-WAIT for a while;
+        // rather than make the owner wait that out, let a new listen
+        // supersede it (as with `SO_REUSEADDR`).
+        if socket.state() == tcp::State::TimeWait {
+            socket.abort();
+        }
+        socket.listen(endpoint).map_err(|e| match e {
+            tcp::ListenError::InvalidState => TcpError::InvalidState,
+            tcp::ListenError::Unaddressable => TcpError::Unaddressable,
+        })?;
+        Ok(())
+    }
+
+    fn net_tcp_accept(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+    ) -> Result<TcpEndpoint, RequestError<TcpError>> {
+        let socket_index = self.tcp_socket_index(msg, socket)?;
+        let socket = self.vlan_state[0]
+            .get_tcp_socket_mut(socket_index)
+            .unwrap_lite();
+        match socket.state() {
+            tcp::State::Listen
+            | tcp::State::SynSent
+            | tcp::State::SynReceived => Err(TcpError::WouldBlock.into()),
+            tcp::State::Closed | tcp::State::TimeWait => {
+                Err(TcpError::NotConnected.into())
+            }
+            _ => {
+                let endp =
+                    socket.remote_endpoint().ok_or(TcpError::NotConnected)?;
+                Ok(TcpEndpoint {
+                    addr: endp
+                        .addr
+                        .try_into()
+                        .map_err(|_| TcpError::NotConnected)?,
+                    port: endp.port,
+                })
+            }
+        }
+    }
+
+    fn net_tcp_connect(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+        remote: TcpEndpoint,
+    ) -> Result<(), RequestError<TcpError>> {
+        let socket_index = self.tcp_socket_index(msg, socket)?;
+        if !can_reach(socket_index, remote.addr) {
+            return Err(TcpError::Unaddressable.into());
+        }
+        let remote = IpEndpoint::try_from(remote)
+            .map_err(|_| TcpError::Unaddressable)?;
+        let vlan = &mut self.vlan_state[0];
+        let handle = vlan.get_handle(socket_index).unwrap_lite();
+        let socket = vlan.socket_set.get_mut::<tcp::Socket<'_>>(handle);
+
+        // Connections go out from the socket's configured port, and from the
+        // same address it would listen on.
+        socket
+            .connect(
+                vlan.iface.context(),
+                remote,
+                listen_endpoint(vlan.ipv6_addr, socket_index),
+            )
+            .map_err(|e| match e {
+                tcp::ConnectError::InvalidState => TcpError::InvalidState,
+                tcp::ConnectError::Unaddressable => TcpError::Unaddressable,
+            })?;
+        Ok(())
+    }
+
+    /// Copies as many received bytes as are available (and fit) into
+    /// `payload`, returning the count. Returns zero once the peer has closed
+    /// its side of the connection and everything it sent has been read.
+    fn net_tcp_recv(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+        payload: idol_runtime::Leased<idol_runtime::W, [u8]>,
+    ) -> Result<u32, RequestError<TcpError>> {
+        let socket_index = self.tcp_socket_index(msg, socket)?;
+        let socket = self.vlan_state[0]
+            .get_tcp_socket_mut(socket_index)
+            .unwrap_lite();
+        match socket.state() {
+            tcp::State::Listen
+            | tcp::State::SynSent
+            | tcp::State::SynReceived => {
+                return Err(TcpError::WouldBlock.into())
+            }
+            tcp::State::Closed => return Err(TcpError::NotConnected.into()),
+            _ => (),
+        }
+        if !socket.can_recv() {
+            return if socket.may_recv() {
+                Err(TcpError::WouldBlock.into())
+            } else {
+                Ok(0)
+            };
+        }
+
+        // The socket hands us one contiguous chunk of its buffer; that's
+        // plenty, since the caller will come back for more.
+        let mut copied = Ok(());
+        let n = socket
+            .recv(|buf| {
+                let n = buf.len().min(payload.len());
+                copied = payload.write_range(0..n, &buf[..n]);
+                // If the caller went away, leave the bytes where they are.
+                let n = if copied.is_ok() { n } else { 0 };
+                (n, n)
+            })
+            .map_err(|_| TcpError::NotConnected)?;
+        copied.map_err(|_| RequestError::went_away())?;
+
+        let counters = &mut self.counters[socket_index];
+        bump(&mut counters.rx_packets);
+        counters.rx_bytes = counters.rx_bytes.wrapping_add(n as u64);
+        Ok(n as u32)
+    }
+
+    /// Queues as many bytes from `payload` as there's room for, returning the
+    /// count.
+    fn net_tcp_send(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+        payload: idol_runtime::Leased<idol_runtime::R, [u8]>,
+    ) -> Result<u32, RequestError<TcpError>> {
+        let socket_index = self.tcp_socket_index(msg, socket)?;
+        let socket = self.vlan_state[0]
+            .get_tcp_socket_mut(socket_index)
+            .unwrap_lite();
+        match socket.state() {
+            tcp::State::Listen
+            | tcp::State::SynSent
+            | tcp::State::SynReceived => {
+                return Err(TcpError::WouldBlock.into())
+            }
+            _ => (),
+        }
+        if !socket.may_send() {
+            return Err(TcpError::NotConnected.into());
+        }
+        if !socket.can_send() {
+            self.client_waiting_to_send[socket_index] = true;
+            bump(&mut self.counters[socket_index].tx_queue_full);
+            return Err(TcpError::WouldBlock.into());
+        }
+
+        let mut copied = Ok(());
+        let n = socket
+            .send(|buf| {
+                let n = buf.len().min(payload.len());
+                copied = payload.read_range(0..n, &mut buf[..n]);
+                let n = if copied.is_ok() { n } else { 0 };
+                (n, n)
+            })
+            .map_err(|_| TcpError::NotConnected)?;
+        copied.map_err(|_| RequestError::went_away())?;
+        self.client_waiting_to_send[socket_index] = false;
+
+        let counters = &mut self.counters[socket_index];
+        bump(&mut counters.tx_packets);
+        counters.tx_bytes = counters.tx_bytes.wrapping_add(n as u64);
+        Ok(n as u32)
+    }
+
+    fn net_tcp_close(
+        &mut self,
+        msg: &userlib::RecvMessage,
+        socket: SocketName,
+    ) -> Result<(), RequestError<TcpError>> {
+        let socket_index = self.tcp_socket_index(msg, socket)?;
+        self.vlan_state[0]
+            .get_tcp_socket_mut(socket_index)
+            .unwrap_lite()
+            .close();
+        Ok(())
+    }
+}
+
 impl<B, E, const N: usize> idol_runtime::NotificationHandler
     for GenServerImpl<'_, B, E, N>
 where
@@ -655,8 +1410,13 @@ where
     }
 }
 
+/// Bumps one of our `SocketCounters`, wrapping on overflow.
+fn bump(counter: &mut u32) {
+    *counter = counter.wrapping_add(1);
+}
+
 pub struct Storage {
-    sockets: [SocketStorage<'static>; SOCKET_COUNT],
+    sockets: [SocketStorage<'static>; SOCKET_COUNT + DHCP_SOCKET_COUNT],
     iface: core::mem::MaybeUninit<Interface>,
 }
 
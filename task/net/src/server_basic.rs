@@ -9,9 +9,9 @@
 use drv_stm32h7_eth as eth;
 
 use crate::bsp_support;
-use crate::generated;
+use crate::generated::{self, SOCKET_COUNT};
 use crate::{
-    server::{DeviceExt, GenServerImpl, Storage},
+    server::{DeviceExt, GenServerImpl, Storage, UdpArrivals},
     MacAddressBlock,
 };
 use core::cell::Cell;
@@ -56,6 +56,7 @@ where
 pub struct Smol<'d> {
     eth: &'d eth::Ethernet,
     mac_rx: Cell<bool>,
+    udp_arrivals: UdpArrivals,
 }
 
 impl<'d> From<&'d eth::Ethernet> for Smol<'d> {
@@ -63,17 +64,21 @@ impl<'d> From<&'d eth::Ethernet> for Smol<'d> {
         Self {
             eth,
             mac_rx: Cell::new(false),
+            udp_arrivals: UdpArrivals::new(),
         }
     }
 }
 
-pub struct OurRxToken<'d>(&'d eth::Ethernet);
+pub struct OurRxToken<'d>(&'d eth::Ethernet, &'d UdpArrivals);
 impl<'d> smoltcp::phy::RxToken for OurRxToken<'d> {
     fn consume<R, F>(self, f: F) -> R
     where
         F: FnOnce(&mut [u8]) -> R,
     {
-        self.0.recv(f)
+        self.0.recv(|frame| {
+            self.1.note(frame);
+            f(frame)
+        })
     }
 }
 
@@ -96,7 +101,7 @@ impl<'a> smoltcp::phy::Device for Smol<'a> {
     fn receive(
         &mut self,
         _timestamp: smoltcp::time::Instant,
-    ) -> Option<(Self::RxToken<'a>, Self::TxToken<'a>)> {
+    ) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
         // Note: smoltcp wants a transmit token every time it receives a
         // packet. This is because it automatically handles stuff like
         // NDP by itself, but means that if the tx queue fills up, we stop
@@ -111,7 +116,10 @@ impl<'a> smoltcp::phy::Device for Smol<'a> {
             // for some reason (that'd be a software bug instead).
             self.mac_rx.set(true);
 
-            Some((OurRxToken(self.eth), OurTxToken(self.eth)))
+            Some((
+                OurRxToken(self.eth, &self.udp_arrivals),
+                OurTxToken(self.eth),
+            ))
         } else {
             None
         }
@@ -138,6 +146,10 @@ impl DeviceExt for Smol<'_> {
         self.mac_rx.take()
     }
 
+    fn take_udp_arrivals(&self) -> [u16; SOCKET_COUNT] {
+        self.udp_arrivals.take()
+    }
+
     fn make_meta(
         &self,
         port: u16,
@@ -14,9 +14,9 @@ use mutable_statics::mutable_statics;
 use task_net_api::UdpMetadata;
 
 use crate::bsp_support;
-use crate::generated::{self, VLAN_COUNT, VLAN_RANGE};
+use crate::generated::{self, SOCKET_COUNT, VLAN_COUNT, VLAN_RANGE};
 use crate::{
-    server::{DeviceExt, GenServerImpl, Storage},
+    server::{DeviceExt, GenServerImpl, Storage, UdpArrivals},
     MacAddressBlock,
 };
 
@@ -33,20 +33,21 @@ pub struct VLanEthernet<'a> {
     pub eth: &'a eth::Ethernet,
     pub vid: u16,
     mac_rx: Cell<bool>,
+    udp_arrivals: UdpArrivals,
 }
 
 impl<'a> smoltcp::phy::Device for VLanEthernet<'a> {
-    type RxToken<'b> = VLanRxToken<'a> where Self: 'b;
+    type RxToken<'b> = VLanRxToken<'b> where Self: 'b;
     type TxToken<'b> = VLanTxToken<'a> where Self: 'b;
 
     fn receive(
         &mut self,
         _timestamp: smoltcp::time::Instant,
-    ) -> Option<(Self::RxToken<'a>, Self::TxToken<'a>)> {
+    ) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
         if self.eth.vlan_can_recv(self.vid, VLAN_RANGE) && self.eth.can_send() {
             self.mac_rx.set(true);
             Some((
-                VLanRxToken(self.eth, self.vid),
+                VLanRxToken(self.eth, self.vid, &self.udp_arrivals),
                 VLanTxToken(self.eth, self.vid),
             ))
         } else {
@@ -73,6 +74,10 @@ impl DeviceExt for VLanEthernet<'_> {
         self.mac_rx.take()
     }
 
+    fn take_udp_arrivals(&self) -> [u16; SOCKET_COUNT] {
+        self.udp_arrivals.take()
+    }
+
     fn make_meta(
         &self,
         port: u16,
@@ -90,13 +95,16 @@ impl DeviceExt for VLanEthernet<'_> {
 
 ////////////////////////////////////////////////////////////////////////////////
 
-pub struct VLanRxToken<'a>(&'a eth::Ethernet, u16);
+pub struct VLanRxToken<'a>(&'a eth::Ethernet, u16, &'a UdpArrivals);
 impl<'a> smoltcp::phy::RxToken for VLanRxToken<'a> {
     fn consume<R, F>(self, f: F) -> R
     where
         F: FnOnce(&mut [u8]) -> R,
     {
-        self.0.vlan_recv(self.1, f)
+        self.0.vlan_recv(self.1, |frame| {
+            self.2.note(frame);
+            f(frame)
+        })
     }
 }
 
@@ -134,6 +142,7 @@ where
             eth,
             vid: generated::VLAN_RANGE.start + i as u16,
             mac_rx: Cell::new(false),
+            udp_arrivals: UdpArrivals::new(),
         },
     )
 }
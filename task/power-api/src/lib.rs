@@ -187,4 +187,85 @@ impl RenesasBlackbox {
     }
 }
 
+/// Why a fault log capture was taken
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, SerializedSize,
+)]
+pub enum FaultCause {
+    /// Taken when the `power` task started
+    Boot,
+    /// Taken when a new fault appeared on the device at the given index
+    Fault { index: u32 },
+}
+
+/// Status registers read from a PMBus device
+///
+/// Each of the more specific registers is only read if its summary bit is set
+/// in `STATUS_WORD` (reading a register that a device doesn't implement would
+/// itself raise a CML fault), and is zero otherwise.  (These aren't `Option`s
+/// because the `power` task keeps several captures of every device, and would
+/// need nearly twice the RAM for them.)
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Deserialize,
+    Serialize,
+    SerializedSize,
+)]
+pub struct PmbusStatus {
+    pub word: u16,
+    pub vout: u8,
+    pub iout: u8,
+    pub input: u8,
+    pub temperature: u8,
+    pub cml: u8,
+    pub mfr_specific: u8,
+    pub fans_1_2: u8,
+}
+
+/// Fault status of a single device within a capture
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, SerializedSize,
+)]
+pub enum FaultStatus {
+    /// The device wasn't read because its rail is off in this power state
+    Off,
+    /// The device didn't respond
+    Unreadable(ResponseCode),
+    Pmbus(PmbusStatus),
+    Ltc4282 {
+        status: u16,
+        fault_log: u8,
+    },
+    Max5970 {
+        status0: u8,
+        status1: u8,
+        status3: u8,
+        fault0: u8,
+        fault1: u8,
+        fault2: u8,
+    },
+}
+
+/// One device's entry in a fault log capture
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, SerializedSize)]
+pub struct FaultLogEntry {
+    /// Sequence number of the capture; the boot capture is always 0
+    pub seq: u32,
+    /// Time at which the capture was taken, in milliseconds since boot
+    pub timestamp: u64,
+    pub cause: FaultCause,
+    /// Number of devices in the capture
+    pub devices: u32,
+    /// Index of this device in the BSP (as used by the `raw_pmbus_*` ops)
+    pub index: u32,
+    /// Voltage sensor of this device's rail, to identify it
+    pub rail: SensorId,
+    pub status: FaultStatus,
+}
+
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
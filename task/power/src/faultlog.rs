@@ -0,0 +1,345 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Fault log
+//!
+//! When the task starts, and whenever a new fault shows up on any device, we
+//! capture the fault status of *every* device in the BSP: the device that
+//! faulted is rarely the only one with something to say about it.  The boot
+//! capture is kept for the life of the task; fault captures go into a small
+//! ring, with the oldest discarded to make room.
+//!
+//! Faults are found by polling every `FAULT_POLL_TICKS` timer ticks, looking
+//! at `STATUS_WORD` for PMBus devices and at the fault registers of the
+//! others.  A capture is only taken when a fault bit goes from clear to set,
+//! so a latched fault produces a single capture rather than one per poll.
+//!
+//! We never clear faults on the devices themselves, which is what makes the
+//! log useful despite living only in our RAM (and so being lost when we
+//! restart, or the SP resets).  Faults stay latched in the devices until
+//! they're power cycled, so the boot capture of our next incarnation records
+//! whatever a lost capture would have shown.  There's nowhere better to keep
+//! the log: the SP's flash is given over to its image banks, and a power
+//! fault, when rails may be collapsing, is the worst time to be erasing and
+//! writing flash.
+
+use crate::{bsp, Device, DeviceType, PowerControllerConfig, PowerState};
+use drv_i2c_api::{I2cDevice, ResponseCode};
+use pmbus::commands::CommandCode;
+use ringbuf::*;
+use task_power_api::{FaultCause, FaultLogEntry, FaultStatus, PmbusStatus};
+use userlib::*;
+use zerocopy::{AsBytes, FromBytes};
+
+/// Number of fault captures retained, in addition to the boot capture.  Each
+/// capture holds every device's status, so this is kept small to fit in the
+/// task's RAM on boards with many devices.
+const FAULT_LOG_DEPTH: usize = 2;
+
+/// Timer ticks between polls for new faults.  Since faults latch in the
+/// devices, polling slowly only delays a capture (and blurs its timestamp),
+/// while sparing the I2C buses a `STATUS_WORD` read of every rail each tick.
+const FAULT_POLL_TICKS: u32 = 5;
+
+/// `STATUS_WORD` bits that don't indicate a fault: NONE_OF_THE_ABOVE (which
+/// only summarizes the upper byte), OFF, BUSY and POWER_GOOD#
+const STATUS_WORD_NOT_FAULTS: u16 = (1 << 0) | (1 << 6) | (1 << 7) | (1 << 11);
+
+#[derive(Copy, Clone, PartialEq)]
+enum Trace {
+    Fault { index: usize, bits: u32 },
+    Captured(u32),
+    None,
+}
+
+ringbuf!(Trace, 8, Trace::None);
+
+struct Capture {
+    seq: u32,
+    timestamp: u64,
+    cause: FaultCause,
+    status: [FaultStatus; bsp::CONTROLLER_CONFIG_LEN],
+}
+
+impl Capture {
+    const EMPTY: Self = Self {
+        seq: 0,
+        timestamp: 0,
+        cause: FaultCause::Boot,
+        status: [FaultStatus::Off; bsp::CONTROLLER_CONFIG_LEN],
+    };
+}
+
+pub(crate) struct FaultLog {
+    /// The boot capture, followed by the ring of fault captures
+    captures: &'static mut [Capture; FAULT_LOG_DEPTH + 1],
+    /// Index of the oldest fault capture within the ring
+    head: usize,
+    /// Number of valid fault captures in the ring
+    len: usize,
+    next_seq: u32,
+    /// Fault bits seen on each device at the last poll
+    last: [u32; bsp::CONTROLLER_CONFIG_LEN],
+    /// Ticks until the next poll
+    countdown: u32,
+}
+
+impl FaultLog {
+    /// Claims the log's storage and takes the boot capture.
+    ///
+    /// This function can only be called once, and will panic otherwise!
+    pub(crate) fn init(
+        i2c_task: TaskId,
+        devices: &[Device],
+        state: PowerState,
+    ) -> Self {
+        let captures = mutable_statics::mutable_statics!(
+            static mut CAPTURES: [Capture; FAULT_LOG_DEPTH + 1] =
+                [|| Capture::EMPTY; _];
+        );
+        let mut log = Self {
+            captures,
+            head: 0,
+            len: 0,
+            next_seq: 0,
+            last: [0; bsp::CONTROLLER_CONFIG_LEN],
+            countdown: FAULT_POLL_TICKS,
+        };
+        log.capture(0, FaultCause::Boot, i2c_task, devices, state);
+
+        // Faults that were already there at boot are in the boot capture, so
+        // don't take another capture for them on the first poll.
+        log.check(i2c_task, devices, state);
+        log
+    }
+
+    /// Called on each timer tick.  Every `FAULT_POLL_TICKS` ticks, polls
+    /// every powered device for faults, taking a capture if any device has a
+    /// fault that it didn't have at the last poll.
+    pub(crate) fn tick(
+        &mut self,
+        i2c_task: TaskId,
+        devices: &[Device],
+        state: PowerState,
+    ) {
+        self.countdown -= 1;
+        if self.countdown > 0 {
+            return;
+        }
+        self.countdown = FAULT_POLL_TICKS;
+
+        if let Some(index) = self.check(i2c_task, devices, state) {
+            let slot = 1 + (self.head + self.len) % FAULT_LOG_DEPTH;
+            if self.len < FAULT_LOG_DEPTH {
+                self.len += 1;
+            } else {
+                self.head = (self.head + 1) % FAULT_LOG_DEPTH;
+            }
+            let cause = FaultCause::Fault {
+                index: index as u32,
+            };
+            self.capture(slot, cause, i2c_task, devices, state);
+        }
+    }
+
+    /// Reads the fault bits of every powered device, returning the index of
+    /// the first with a fault that it didn't have last time
+    fn check(
+        &mut self,
+        i2c_task: TaskId,
+        devices: &[Device],
+        state: PowerState,
+    ) -> Option<usize> {
+        let mut first = None;
+        for (index, ((c, dev), last)) in bsp::CONTROLLER_CONFIG
+            .iter()
+            .zip(devices.iter())
+            .zip(self.last.iter_mut())
+            .enumerate()
+        {
+            // A device that's off (or unreadable) can't tell us anything; by
+            // forgetting its faults, we'll capture any that are still there
+            // once it's back.
+            let bits = if is_powered(c, state) {
+                fault_bits(c, dev, i2c_task).unwrap_or(0)
+            } else {
+                0
+            };
+            if bits & !*last != 0 {
+                ringbuf_entry!(Trace::Fault { index, bits });
+                first.get_or_insert(index);
+            }
+            *last = bits;
+        }
+        first
+    }
+
+    fn capture(
+        &mut self,
+        slot: usize,
+        cause: FaultCause,
+        i2c_task: TaskId,
+        devices: &[Device],
+        state: PowerState,
+    ) {
+        let capture = &mut self.captures[slot];
+        capture.seq = self.next_seq;
+        capture.timestamp = sys_get_timer().now;
+        capture.cause = cause;
+        for ((c, dev), status) in bsp::CONTROLLER_CONFIG
+            .iter()
+            .zip(devices.iter())
+            .zip(capture.status.iter_mut())
+        {
+            *status = if is_powered(c, state) {
+                read_status(c, dev, i2c_task)
+                    .unwrap_or_else(FaultStatus::Unreadable)
+            } else {
+                FaultStatus::Off
+            };
+        }
+        ringbuf_entry!(Trace::Captured(self.next_seq));
+        self.next_seq = self.next_seq.wrapping_add(1);
+    }
+
+    /// Returns the given device's entry from the oldest capture with a
+    /// sequence number of at least `seq`
+    pub(crate) fn read(
+        &self,
+        seq: u32,
+        index: u32,
+    ) -> Result<FaultLogEntry, ResponseCode> {
+        let capture = core::iter::once(0)
+            .chain((0..self.len).map(|i| 1 + (self.head + i) % FAULT_LOG_DEPTH))
+            .map(|slot| &self.captures[slot])
+            .find(|c| c.seq >= seq)
+            .ok_or(ResponseCode::NoRegister)?;
+        let i = index as usize;
+        let status = *capture.status.get(i).ok_or(ResponseCode::BadArg)?;
+
+        Ok(FaultLogEntry {
+            seq: capture.seq,
+            timestamp: capture.timestamp,
+            cause: capture.cause,
+            devices: bsp::CONTROLLER_CONFIG_LEN as u32,
+            index,
+            rail: bsp::CONTROLLER_CONFIG[i].voltage,
+            status,
+        })
+    }
+}
+
+fn is_powered(c: &PowerControllerConfig, state: PowerState) -> bool {
+    c.state != PowerState::A0 || state == PowerState::A0
+}
+
+/// Returns whether a device speaks PMBus and, if so, whether its registers
+/// are behind a PAGE
+fn pmbus_paged(device: &DeviceType) -> Option<bool> {
+    match device {
+        DeviceType::IBC
+        | DeviceType::Sys
+        | DeviceType::HotSwap(..)
+        | DeviceType::Fan(..) => Some(false),
+        DeviceType::Core
+        | DeviceType::Mem
+        | DeviceType::MemVpp
+        | DeviceType::SerDes
+        | DeviceType::PowerShelf => Some(true),
+        DeviceType::HotSwapIO(..) | DeviceType::HotSwapQSFP(..) => None,
+    }
+}
+
+fn pmbus_read<T: AsBytes + FromBytes>(
+    dev: &I2cDevice,
+    page: Option<u8>,
+    cmd: CommandCode,
+) -> Result<T, ResponseCode> {
+    match page {
+        Some(rail) => dev.write_read_reg(
+            cmd as u8,
+            &[pmbus::commands::PAGE::CommandData::code(), rail],
+        ),
+        None => dev.read_reg(cmd as u8),
+    }
+}
+
+/// Returns the device's current fault bits, whose meaning depends on the
+/// kind of device
+fn fault_bits(
+    c: &PowerControllerConfig,
+    dev: &Device,
+    i2c_task: TaskId,
+) -> Result<u32, ResponseCode> {
+    use drv_i2c_devices::{ltc4282, max5970};
+
+    if let Some(paged) = pmbus_paged(&c.device) {
+        let (i2c, rail) = (c.builder)(i2c_task);
+        let word: u16 =
+            pmbus_read(&i2c, paged.then_some(rail), CommandCode::STATUS_WORD)?;
+        return Ok((word & !STATUS_WORD_NOT_FAULTS).into());
+    }
+
+    match dev {
+        Device::Ltc4282(dev) => {
+            Ok(dev.read_reg(ltc4282::Register::FAULT_LOG)?.into())
+        }
+        Device::Max5970(dev) => {
+            let f0 = dev.read_reg(max5970::Register::fault0)?;
+            let f1 = dev.read_reg(max5970::Register::fault1)?;
+            let f2 = dev.read_reg(max5970::Register::fault2)?;
+            Ok(u32::from_le_bytes([f0, f1, f2, 0]))
+        }
+        _ => Err(ResponseCode::OperationNotSupported),
+    }
+}
+
+fn read_status(
+    c: &PowerControllerConfig,
+    dev: &Device,
+    i2c_task: TaskId,
+) -> Result<FaultStatus, ResponseCode> {
+    use drv_i2c_devices::{ltc4282, max5970};
+
+    if let Some(paged) = pmbus_paged(&c.device) {
+        let (i2c, rail) = (c.builder)(i2c_task);
+        let page = paged.then_some(rail);
+        let word: u16 = pmbus_read(&i2c, page, CommandCode::STATUS_WORD)?;
+
+        // Only read the registers that STATUS_WORD says have something set
+        let read = |bit: u16, cmd| -> Result<u8, ResponseCode> {
+            if word & (1 << bit) != 0 {
+                pmbus_read(&i2c, page, cmd)
+            } else {
+                Ok(0)
+            }
+        };
+        return Ok(FaultStatus::Pmbus(PmbusStatus {
+            word,
+            vout: read(15, CommandCode::STATUS_VOUT)?,
+            iout: read(14, CommandCode::STATUS_IOUT)?,
+            input: read(13, CommandCode::STATUS_INPUT)?,
+            mfr_specific: read(12, CommandCode::STATUS_MFR_SPECIFIC)?,
+            fans_1_2: read(10, CommandCode::STATUS_FANS_1_2)?,
+            temperature: read(2, CommandCode::STATUS_TEMPERATURE)?,
+            cml: read(1, CommandCode::STATUS_CML)?,
+        }));
+    }
+
+    match dev {
+        Device::Ltc4282(dev) => Ok(FaultStatus::Ltc4282 {
+            status: dev.read_reg16(ltc4282::Register::STATUS)?,
+            fault_log: dev.read_reg(ltc4282::Register::FAULT_LOG)?,
+        }),
+        Device::Max5970(dev) => Ok(FaultStatus::Max5970 {
+            status0: dev.read_reg(max5970::Register::status0)?,
+            status1: dev.read_reg(max5970::Register::status1)?,
+            status3: dev.read_reg(max5970::Register::status3)?,
+            fault0: dev.read_reg(max5970::Register::fault0)?,
+            fault1: dev.read_reg(max5970::Register::fault1)?,
+            fault2: dev.read_reg(max5970::Register::fault2)?,
+        }),
+        _ => Err(ResponseCode::OperationNotSupported),
+    }
+}
@@ -21,7 +21,8 @@ use drv_i2c_devices::tps546b24a::*;
 use pmbus::Phase;
 use ringbuf::*;
 use task_power_api::{
-    Bmr491Event, PmbusValue, RawPmbusBlock, RenesasBlackbox, MAX_BLOCK_LEN,
+    Bmr491Event, FaultLogEntry, PmbusValue, RawPmbusBlock, RenesasBlackbox,
+    MAX_BLOCK_LEN,
 };
 use task_sensor_api as sensor_api;
 use userlib::units::*;
@@ -405,17 +406,23 @@ macro_rules! mwocp68_controller {
 #[cfg_attr(target_board = "gimletlet-2", path = "bsp/gimletlet_2.rs")]
 mod bsp;
 
+mod faultlog;
+
 ////////////////////////////////////////////////////////////////////////////////
 
 #[export_name = "main"]
 fn main() -> ! {
     let i2c_task = I2C.get_task_id();
+    let devices = claim_devices(i2c_task);
+    let fault_log =
+        faultlog::FaultLog::init(i2c_task, devices, bsp::get_state());
 
     let mut server = ServerImpl {
         i2c_task,
         sensor: sensor_api::Sensor::from(SENSOR.get_task_id()),
-        devices: claim_devices(i2c_task),
+        devices,
         bsp: bsp::State::init(),
+        fault_log,
     };
     let mut buffer = [0; idl::INCOMING_SIZE];
 
@@ -433,6 +440,7 @@ struct ServerImpl {
     sensor: sensor_api::Sensor,
     devices: &'static mut [Device; bsp::CONTROLLER_CONFIG_LEN],
     bsp: bsp::State,
+    fault_log: faultlog::FaultLog,
 }
 
 impl ServerImpl {
@@ -507,6 +515,7 @@ impl ServerImpl {
             }
         }
 
+        self.fault_log.tick(self.i2c_task, self.devices, state);
         self.bsp.handle_timer_fired(self.devices, state);
     }
 
@@ -1007,6 +1016,15 @@ impl idl::InOrderPowerImpl for ServerImpl {
         Ok(out)
     }
 
+    fn read_fault_log(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        seq: u32,
+        index: u32,
+    ) -> Result<FaultLogEntry, idol_runtime::RequestError<ResponseCode>> {
+        Ok(self.fault_log.read(seq, index)?)
+    }
+
     fn rendmp_dma_read(
         &mut self,
         _msg: &userlib::RecvMessage,
@@ -30,6 +30,36 @@ impl Sensor {
     ) -> Result<(), SensorApiError> {
         self.nodata(id, nodata, sys_get_timer().now)
     }
+
+    /// Fetch the samples in the given sensor's history that were taken in
+    /// `[start, end)`, oldest first, returning the part of `buf` they fill
+    #[inline]
+    pub fn get_history_samples<'a>(
+        &self,
+        id: SensorId,
+        start: u64,
+        end: u64,
+        buf: &'a mut [HistorySample],
+    ) -> Result<&'a [HistorySample], HistoryError> {
+        use zerocopy::AsBytes;
+
+        let n = self.get_history(id, start, end, buf.as_bytes_mut())?;
+        Ok(&buf[..n as usize])
+    }
+
+    /// Fetch the currently active alarms into `buf`, returning the part of
+    /// `buf` they fill along with the total number active (which may be more
+    /// than fit)
+    #[inline]
+    pub fn get_alarm_entries<'a>(
+        &self,
+        buf: &'a mut [AlarmEntry],
+    ) -> (&'a [AlarmEntry], usize) {
+        use zerocopy::AsBytes;
+
+        let total = self.get_alarms(buf.as_bytes_mut()) as usize;
+        (&buf[..total.min(buf.len())], total)
+    }
 }
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
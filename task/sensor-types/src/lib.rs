@@ -156,3 +156,292 @@ impl From<SensorApiError> for SensorError {
         }
     }
 }
+
+/// An error fetching a sensor's history
+#[derive(Copy, Clone, Debug, FromPrimitive, Eq, PartialEq, IdolError)]
+pub enum HistoryError {
+    InvalidSensor = 1,
+    /// This image keeps no sensor history
+    NotConfigured = 2,
+}
+
+/// One entry in a sensor's history, as written into the lease given to
+/// `Sensor::get_history`
+///
+/// A reading that failed (i.e. that was posted with `nodata`) is recorded
+/// with a value of NaN.
+#[derive(
+    zerocopy::AsBytes, zerocopy::FromBytes, Copy, Clone, Debug, Default,
+)]
+#[repr(C)]
+pub struct HistorySample {
+    pub timestamp: zerocopy::U64<zerocopy::LittleEndian>,
+    pub value: zerocopy::F32<zerocopy::LittleEndian>,
+}
+
+/// Which of a sensor's thresholds it is past
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    FromPrimitive,
+    Eq,
+    PartialEq,
+    Serialize,
+    Deserialize,
+    SerializedSize,
+)]
+#[repr(u8)]
+pub enum AlarmLevel {
+    WarningLow = 1,
+    CriticalLow = 2,
+    WarningHigh = 3,
+    CriticalHigh = 4,
+}
+
+impl AlarmLevel {
+    pub fn is_critical(self) -> bool {
+        matches!(self, AlarmLevel::CriticalLow | AlarmLevel::CriticalHigh)
+    }
+}
+
+fn severity(level: Option<AlarmLevel>) -> u8 {
+    match level {
+        None => 0,
+        Some(AlarmLevel::WarningLow | AlarmLevel::WarningHigh) => 1,
+        Some(AlarmLevel::CriticalLow | AlarmLevel::CriticalHigh) => 2,
+    }
+}
+
+/// Alarm thresholds for one sensor, as configured for the sensor task.
+/// Missing thresholds are infinite.
+///
+/// Only the sensor task evaluates these; they live here so that they can be
+/// tested on the host.
+#[derive(Copy, Clone, Debug)]
+pub struct Thresholds {
+    pub critical_low: f32,
+    pub warning_low: f32,
+    pub warning_high: f32,
+    pub critical_high: f32,
+    /// How far back past a threshold a reading must go to clear its alarm
+    pub hysteresis: f32,
+}
+
+impl Thresholds {
+    fn classify(&self, value: f32) -> Option<AlarmLevel> {
+        if value >= self.critical_high {
+            Some(AlarmLevel::CriticalHigh)
+        } else if value >= self.warning_high {
+            Some(AlarmLevel::WarningHigh)
+        } else if value <= self.critical_low {
+            Some(AlarmLevel::CriticalLow)
+        } else if value <= self.warning_low {
+            Some(AlarmLevel::WarningLow)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the alarm level for a sensor that was at `current` and has
+    /// now read `value`.
+    ///
+    /// Alarms are raised as soon as a threshold is reached, but are only
+    /// lowered once the reading has gone back past the threshold by the
+    /// hysteresis, so a reading that dithers around a threshold doesn't
+    /// produce a storm of notifications.
+    ///
+    /// A NaN `value`, which is what the sensor task records when a sensor
+    /// has no data, leaves the level unchanged: not being able to read a
+    /// sensor tells us nothing about whether it's still out of range.
+    pub fn evaluate(
+        &self,
+        current: Option<AlarmLevel>,
+        value: f32,
+    ) -> Option<AlarmLevel> {
+        if value.is_nan() {
+            return current;
+        }
+        let new = self.classify(value);
+        let Some(current) = current else {
+            return new;
+        };
+        if severity(new) >= severity(Some(current)) {
+            return new;
+        }
+
+        // We're on our way back down; go only as far as the hysteresis
+        // allows.
+        let held = match current {
+            AlarmLevel::WarningHigh | AlarmLevel::CriticalHigh => {
+                self.classify(value + self.hysteresis)
+            }
+            AlarmLevel::WarningLow | AlarmLevel::CriticalLow => {
+                self.classify(value - self.hysteresis)
+            }
+        };
+        if severity(held) >= severity(Some(current)) {
+            Some(current)
+        } else {
+            held
+        }
+    }
+}
+
+/// An active alarm, as written into the lease given to `Sensor::get_alarms`
+#[derive(
+    zerocopy::AsBytes, zerocopy::FromBytes, Copy, Clone, Debug, Default,
+)]
+#[repr(C)]
+pub struct AlarmEntry {
+    pub sensor: zerocopy::U32<zerocopy::LittleEndian>,
+    /// An `AlarmLevel`
+    pub level: u8,
+    pub _reserved: [u8; 3],
+    /// When the sensor reached this level
+    pub since: zerocopy::U64<zerocopy::LittleEndian>,
+    /// The sensor's most recent value
+    pub value: zerocopy::F32<zerocopy::LittleEndian>,
+}
+
+impl AlarmEntry {
+    pub fn sensor(&self) -> SensorId {
+        SensorId(self.sensor.get())
+    }
+
+    pub fn level(&self) -> Option<AlarmLevel> {
+        AlarmLevel::from_u8(self.level)
+    }
+}
+
+/// An error subscribing to sensor alarms
+#[derive(Copy, Clone, Debug, FromPrimitive, Eq, PartialEq, IdolError)]
+pub enum SubscribeError {
+    TooManySubscribers = 1,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const T: Thresholds = Thresholds {
+        critical_low: 11.0,
+        warning_low: 11.4,
+        warning_high: 12.6,
+        critical_high: 13.0,
+        hysteresis: 0.1,
+    };
+
+    /// Feeds `values` through `T` in turn, starting with no alarm, and
+    /// returns the level after each.
+    fn run<const N: usize>(values: [f32; N]) -> [Option<AlarmLevel>; N] {
+        let mut level = None;
+        values.map(|v| {
+            level = T.evaluate(level, v);
+            level
+        })
+    }
+
+    #[test]
+    fn raises_immediately() {
+        use AlarmLevel::*;
+        assert_eq!(
+            run([12.0, 12.6, 13.0, 12.0, 11.4, 11.0]),
+            [
+                None,
+                Some(WarningHigh),
+                Some(CriticalHigh),
+                None,
+                Some(WarningLow),
+                Some(CriticalLow),
+            ]
+        );
+    }
+
+    #[test]
+    fn dithering_is_held() {
+        use AlarmLevel::*;
+        // Dithering just under the threshold doesn't clear the alarm...
+        assert_eq!(
+            run([12.6, 12.55, 12.6, 12.52, 12.45]),
+            [
+                Some(WarningHigh),
+                Some(WarningHigh),
+                Some(WarningHigh),
+                Some(WarningHigh),
+                None,
+            ]
+        );
+        // ...and the same on the low side.
+        assert_eq!(
+            run([11.4, 11.45, 11.4, 11.48, 11.55]),
+            [
+                Some(WarningLow),
+                Some(WarningLow),
+                Some(WarningLow),
+                Some(WarningLow),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn steps_down_through_levels() {
+        use AlarmLevel::*;
+        assert_eq!(
+            run([13.5, 12.95, 12.85, 12.55, 12.45]),
+            [
+                Some(CriticalHigh),
+                Some(CriticalHigh),
+                Some(WarningHigh),
+                Some(WarningHigh),
+                None,
+            ]
+        );
+        assert_eq!(
+            run([10.0, 11.05, 11.15, 11.45, 11.55]),
+            [
+                Some(CriticalLow),
+                Some(CriticalLow),
+                Some(WarningLow),
+                Some(WarningLow),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn crossing_straight_over() {
+        use AlarmLevel::*;
+        // A reading that swings from one side to the other moves the alarm
+        // with it, however far it is past the old threshold.
+        assert_eq!(
+            run([13.5, 10.5, 13.5]),
+            [Some(CriticalHigh), Some(CriticalLow), Some(CriticalHigh)]
+        );
+        assert_eq!(run([12.7, 11.3]), [Some(WarningHigh), Some(WarningLow)]);
+    }
+
+    #[test]
+    fn nan_is_no_change() {
+        use AlarmLevel::*;
+        assert_eq!(run([f32::NAN]), [None]);
+        assert_eq!(
+            run([13.5, f32::NAN, 12.0]),
+            [Some(CriticalHigh), Some(CriticalHigh), None]
+        );
+        assert_eq!(run([11.2, f32::NAN]), [Some(WarningLow), Some(WarningLow)]);
+    }
+
+    #[test]
+    fn missing_thresholds() {
+        let t = Thresholds {
+            critical_low: f32::NEG_INFINITY,
+            warning_low: f32::NEG_INFINITY,
+            critical_high: f32::INFINITY,
+            ..T
+        };
+        assert_eq!(t.evaluate(None, -1000.0), None);
+        assert_eq!(t.evaluate(None, 1000.0), Some(AlarmLevel::WarningHigh));
+    }
+}
@@ -2,13 +2,168 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Config {
+    history: Option<HistoryConfig>,
+    #[serde(default)]
+    thresholds: Vec<ThresholdConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct HistoryConfig {
+    /// Number of samples to keep for each sensor
+    depth: usize,
+    /// Record only one in this many readings of each sensor
+    #[serde(default = "default_decimation")]
+    decimation: u16,
+}
+
+fn default_decimation() -> u16 {
+    1
+}
+
+/// Alarm thresholds for a set of I2C sensors, all of the same kind
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct ThresholdConfig {
+    kind: build_i2c::Sensor,
+    /// Sensors to which these thresholds apply, each given by the sensor's
+    /// own name (e.g. its rail), or by its device's name or refdes
+    sensors: Vec<String>,
+    warning_low: Option<f32>,
+    warning_high: Option<f32>,
+    critical_low: Option<f32>,
+    critical_high: Option<f32>,
+    /// How far back past a threshold a reading must go to clear its alarm
+    #[serde(default)]
+    hysteresis: f32,
+}
+
+fn main() -> Result<()> {
     build_util::expose_target_board();
     build_util::build_notifications()?;
     idol::server::build_server_support(
         "../../idl/sensor.idol",
         "server_stub.rs",
         idol::server::ServerStyle::InOrder,
+    )
+    .map_err(|e| anyhow!(e))?;
+
+    let cfg = build_util::task_maybe_config::<Config>()?.unwrap_or_default();
+    let (depth, decimation) = match cfg.history {
+        Some(h) => {
+            if h.depth == 0 || h.depth > usize::from(u16::MAX) {
+                bail!("sensor history depth must be between 1 and 65535");
+            }
+            if h.decimation == 0 {
+                bail!("sensor history decimation must be at least 1");
+            }
+            (h.depth, h.decimation)
+        }
+        None => (0, 1),
+    };
+
+    let out_dir = build_util::out_dir();
+    let dest_path = out_dir.join("server_config.rs");
+    let mut out = std::fs::File::create(dest_path)?;
+    writeln!(out, "pub(crate) const HISTORY_DEPTH: usize = {depth};")?;
+    writeln!(
+        out,
+        "pub(crate) const HISTORY_DECIMATION: u16 = {decimation};"
     )?;
+
+    write_thresholds(&mut out, &cfg.thresholds)?;
+
+    Ok(())
+}
+
+fn write_thresholds(
+    out: &mut impl Write,
+    thresholds: &[ThresholdConfig],
+) -> Result<()> {
+    // Not every board with a sensor task has I2C devices to look through.
+    let devices = if thresholds.is_empty() {
+        vec![]
+    } else {
+        build_i2c::device_descriptions().collect::<Vec<_>>()
+    };
+
+    let mut by_id = BTreeMap::new();
+    for t in thresholds {
+        let lows = [t.critical_low, t.warning_low];
+        let highs = [t.warning_high, t.critical_high];
+        let mut limits = lows.iter().chain(&highs).flatten().peekable();
+        while let Some(a) = limits.next() {
+            if limits.peek().map(|b| a >= *b).unwrap_or(false) {
+                bail!(
+                    "{} thresholds for {:?} must be in order: critical-low, \
+                     warning-low, warning-high, critical-high",
+                    t.kind,
+                    t.sensors
+                );
+            }
+        }
+        if t.hysteresis < 0.0 {
+            bail!("hysteresis for {:?} can't be negative", t.sensors);
+        }
+
+        for name in &t.sensors {
+            let mut found = false;
+            for d in &devices {
+                let device_match = d.name.as_ref() == Some(name)
+                    || d.refdes.as_ref() == Some(name);
+                for s in d.sensors.iter().filter(|s| s.kind == t.kind) {
+                    if device_match || s.name.as_ref() == Some(name) {
+                        found = true;
+                        if by_id.insert(s.id, t).is_some() {
+                            bail!(
+                                "sensor {name} ({}) has two thresholds",
+                                s.id
+                            );
+                        }
+                    }
+                }
+            }
+            if !found {
+                bail!("no {} sensor matches {name:?}", t.kind);
+            }
+        }
+    }
+
+    writeln!(
+        out,
+        "pub(crate) const THRESHOLDS: [(u32, Thresholds); {}] = [",
+        by_id.len()
+    )?;
+    for (id, t) in by_id {
+        let f = |v: Option<f32>, default: &str| match v {
+            Some(v) => format!("{v:?}"),
+            None => default.to_string(),
+        };
+        writeln!(
+            out,
+            "    ({id}, Thresholds {{
+        critical_low: {},
+        warning_low: {},
+        warning_high: {},
+        critical_high: {},
+        hysteresis: {:?},
+    }}),",
+            f(t.critical_low, "f32::NEG_INFINITY"),
+            f(t.warning_low, "f32::NEG_INFINITY"),
+            f(t.warning_high, "f32::INFINITY"),
+            f(t.critical_high, "f32::INFINITY"),
+            t.hysteresis,
+        )?;
+    }
+    writeln!(out, "];")?;
+
     Ok(())
 }
@@ -0,0 +1,96 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Threshold alarms
+//!
+//! Sensors may be given warning and critical thresholds in the `app.toml`;
+//! each reading posted for such a sensor is checked against them. Tasks that
+//! care (through the `subscribe_alarms` operation) are sent a notification of
+//! their choosing whenever any sensor's alarm level changes, and can then
+//! call `get_alarms` to see which sensors are in trouble. Readings are
+//! classified by `task_sensor_api::Thresholds`; posting `nodata` (or a NaN)
+//! leaves a sensor's alarm level as it was.
+//!
+//! Subscriptions don't survive a restart of the subscribing task: a new
+//! incarnation must subscribe again. Nor do they survive a restart of this
+//! task, so subscribers should also resubscribe from time to time.
+
+use task_sensor_api::{AlarmLevel, SubscribeError};
+use userlib::*;
+
+/// Alarm state of a sensor that has thresholds
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct AlarmState {
+    pub level: Option<AlarmLevel>,
+    /// When `level` was reached
+    pub since: u64,
+}
+
+/// Maximum number of simultaneous subscribers. We expect this to be a handful
+/// of servers.
+const MAX_SUBSCRIBERS: usize = 4;
+
+#[derive(Copy, Clone, Debug)]
+struct Subscription {
+    /// The subscribing task, including its generation, so that we can tell
+    /// when it has restarted.
+    task: TaskId,
+    /// Notification bits to post.
+    mask: u32,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct Subscribers {
+    subs: [Option<Subscription>; MAX_SUBSCRIBERS],
+}
+
+impl Subscribers {
+    pub fn subscribe(
+        &mut self,
+        task: TaskId,
+        mask: u32,
+    ) -> Result<(), SubscribeError> {
+        let new = Some(Subscription { task, mask });
+
+        // Resubscribing replaces any existing subscription, including one
+        // left behind by a previous incarnation of the same task.
+        if let Some(slot) = self.find(task) {
+            *slot = new;
+        } else if let Some(slot) = self.subs.iter_mut().find(|s| s.is_none()) {
+            *slot = new;
+        } else {
+            return Err(SubscribeError::TooManySubscribers);
+        }
+        Ok(())
+    }
+
+    pub fn unsubscribe(&mut self, task: TaskId) {
+        if let Some(slot) = self.find(task) {
+            *slot = None;
+        }
+    }
+
+    /// Tells every subscriber that an alarm level has changed.
+    pub fn notify(&mut self) {
+        for slot in &mut self.subs {
+            let Some(sub) = slot else {
+                continue;
+            };
+            let current = sys_refresh_task_id(sub.task);
+            if current.generation() != sub.task.generation() {
+                // The subscriber has restarted since subscribing; the new
+                // incarnation may not be expecting this notification.
+                *slot = None;
+                continue;
+            }
+            sys_post(current, sub.mask);
+        }
+    }
+
+    fn find(&mut self, task: TaskId) -> Option<&mut Option<Subscription>> {
+        self.subs.iter_mut().find(
+            |s| matches!(s, Some(sub) if sub.task.index() == task.index()),
+        )
+    }
+}
@@ -3,13 +3,46 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 //! Sensor management
+//!
+//! Besides the most recent reading and the extremes of each sensor, this task
+//! can keep a short history of readings, so that an excursion can be looked
+//! at after the fact. This is configured in the `app.toml`:
+//!
+//! ```toml
+//! [tasks.sensor.config.history]
+//! depth = 16      # samples kept per sensor
+//! decimation = 4  # record one in every 4 readings
+//! ```
+//!
+//! Each sample costs 12 bytes per sensor, so mind the task's RAM.
+//!
+//! I2C sensors may also be given alarm thresholds, by kind and name (that of
+//! the sensor or its rail, or its device's name or refdes):
+//!
+//! ```toml
+//! [[tasks.sensor.config.thresholds]]
+//! kind = "voltage"
+//! sensors = ["V12_PSU0", "V12_PSU1"]
+//! warning-low = 11.4
+//! warning-high = 12.6
+//! hysteresis = 0.1
+//! ```
+//!
+//! See the `alarm` module for how these are evaluated and reported.
 
 #![no_std]
 #![no_main]
 
-use idol_runtime::{NotificationHandler, RequestError};
-use task_sensor_api::{NoData, Reading, SensorApiError, SensorError, SensorId};
+use core::convert::Infallible;
+use idol_runtime::{Leased, NotificationHandler, RequestError, W};
+use task_sensor_api::{
+    AlarmEntry, HistoryError, HistorySample, NoData, Reading, SensorApiError,
+    SensorError, SensorId, SubscribeError, Thresholds,
+};
 use userlib::*;
+use zerocopy::AsBytes;
+
+mod alarm;
 
 use task_sensor_api::config::NUM_SENSORS;
 
@@ -69,6 +102,20 @@ struct ServerImpl {
     err_time: SensorArray<u64>,
 
     nerrors: SensorArray<u32>,
+
+    // History is kept in a ring per sensor; `history_head` is the slot that
+    // will be written next.
+    history_time: SensorArray<[u64; HISTORY_DEPTH]>,
+    history_value: SensorArray<[f32; HISTORY_DEPTH]>,
+    history_head: SensorArray<u16>,
+    history_len: SensorArray<u16>,
+    /// Readings to skip before we next record one, for decimation
+    history_skip: SensorArray<u16>,
+
+    /// Alarm state of each sensor in `THRESHOLDS`, in the same order
+    alarms: &'static mut [alarm::AlarmState; THRESHOLDS.len()],
+    subscribers: alarm::Subscribers,
+
     deadline: u64,
 }
 
@@ -204,6 +251,9 @@ impl idl::InOrderSensorImpl for ServerImpl {
             self.max_time[id] = timestamp;
         }
 
+        self.record(id, value, timestamp);
+        self.check_thresholds(id, value, timestamp);
+
         Ok(())
     }
 
@@ -225,6 +275,8 @@ impl idl::InOrderSensorImpl for ServerImpl {
         self.err_value[id] = nodata;
         self.err_time[id] = timestamp;
 
+        self.record(id, f32::NAN, timestamp);
+
         //
         // We pack per-`NoData` counters into a u32.
         //
@@ -254,9 +306,165 @@ impl idl::InOrderSensorImpl for ServerImpl {
             .cloned()
             .ok_or_else(|| SensorApiError::InvalidSensor.into())
     }
+
+    fn get_history(
+        &mut self,
+        _: &RecvMessage,
+        id: SensorId,
+        start: u64,
+        end: u64,
+        samples: Leased<W, [u8]>,
+    ) -> Result<u32, RequestError<HistoryError>> {
+        if HISTORY_DEPTH == 0 {
+            return Err(HistoryError::NotConfigured.into());
+        }
+        let len = *self
+            .history_len
+            .get(id)
+            .ok_or(HistoryError::InvalidSensor)? as usize;
+
+        const SAMPLE_SIZE: usize = core::mem::size_of::<HistorySample>();
+        let room = samples.len() / SAMPLE_SIZE;
+        let head = self.history_head[id] as usize;
+        let mut i = if head >= len {
+            head - len
+        } else {
+            head + HISTORY_DEPTH - len
+        };
+
+        let mut count = 0;
+        for _ in 0..len {
+            if count == room {
+                break;
+            }
+            let timestamp = self.history_time[id][i];
+            let value = self.history_value[id][i];
+            i += 1;
+            if i == HISTORY_DEPTH {
+                i = 0;
+            }
+            if timestamp < start || timestamp >= end {
+                continue;
+            }
+            let sample = HistorySample {
+                timestamp: timestamp.into(),
+                value: value.into(),
+            };
+            let offset = count * SAMPLE_SIZE;
+            samples
+                .write_range(offset..offset + SAMPLE_SIZE, sample.as_bytes())
+                .map_err(|()| RequestError::went_away())?;
+            count += 1;
+        }
+
+        Ok(count as u32)
+    }
+
+    fn get_alarms(
+        &mut self,
+        _: &RecvMessage,
+        alarms: Leased<W, [u8]>,
+    ) -> Result<u32, RequestError<Infallible>> {
+        const ENTRY_SIZE: usize = core::mem::size_of::<AlarmEntry>();
+        let room = alarms.len() / ENTRY_SIZE;
+
+        let mut count = 0;
+        for (&(id, _), state) in THRESHOLDS.iter().zip(self.alarms.iter()) {
+            let Some(level) = state.level else {
+                continue;
+            };
+            if count < room {
+                let entry = AlarmEntry {
+                    sensor: id.into(),
+                    level: level as u8,
+                    since: state.since.into(),
+                    value: self.data_value[SensorId(id)].into(),
+                    ..Default::default()
+                };
+                let offset = count * ENTRY_SIZE;
+                alarms
+                    .write_range(offset..offset + ENTRY_SIZE, entry.as_bytes())
+                    .map_err(|()| RequestError::went_away())?;
+            }
+            count += 1;
+        }
+
+        Ok(count as u32)
+    }
+
+    fn subscribe_alarms(
+        &mut self,
+        msg: &RecvMessage,
+        notification_mask: u32,
+    ) -> Result<(), RequestError<SubscribeError>> {
+        if notification_mask == 0 {
+            return Err(RequestError::Fail(ClientError::BadMessageContents));
+        }
+        self.subscribers
+            .subscribe(msg.sender, notification_mask)
+            .map_err(RequestError::from)
+    }
+
+    fn unsubscribe_alarms(
+        &mut self,
+        msg: &RecvMessage,
+    ) -> Result<(), RequestError<Infallible>> {
+        self.subscribers.unsubscribe(msg.sender);
+        Ok(())
+    }
 }
 
 impl ServerImpl {
+    /// Adds a reading to the sensor's history, subject to decimation.
+    ///
+    /// `id` must already have been checked.
+    fn record(&mut self, id: SensorId, value: f32, timestamp: u64) {
+        if HISTORY_DEPTH == 0 {
+            return;
+        }
+
+        let skip = &mut self.history_skip[id];
+        if *skip > 0 {
+            *skip -= 1;
+            return;
+        }
+        *skip = HISTORY_DECIMATION - 1;
+
+        let head = self.history_head[id] as usize;
+        self.history_time[id][head] = timestamp;
+        self.history_value[id][head] = value;
+        self.history_head[id] = if head + 1 == HISTORY_DEPTH {
+            0
+        } else {
+            head as u16 + 1
+        };
+
+        let len = &mut self.history_len[id];
+        if (*len as usize) < HISTORY_DEPTH {
+            *len += 1;
+        }
+    }
+
+    /// Checks a new reading against the sensor's thresholds, if it has any,
+    /// and tells subscribers if its alarm level has changed.
+    ///
+    /// `id` must already have been checked.
+    fn check_thresholds(&mut self, id: SensorId, value: f32, timestamp: u64) {
+        let Ok(i) = THRESHOLDS.binary_search_by_key(&id.0, |&(id, _)| id)
+        else {
+            return;
+        };
+        let state = &mut self.alarms[i];
+        let level = THRESHOLDS[i].1.evaluate(state.level, value);
+        if level != state.level {
+            *state = alarm::AlarmState {
+                level,
+                since: timestamp,
+            };
+            self.subscribers.notify();
+        }
+    }
+
     #[inline(always)]
     fn last_reading(
         &self,
@@ -289,6 +497,11 @@ fn main() -> ! {
     //
     sys_set_timer(Some(deadline), notifications::TIMER_MASK);
 
+    let alarms = mutable_statics::mutable_statics! {
+        static mut ALARMS: [alarm::AlarmState; THRESHOLDS.len()] =
+            [alarm::AlarmState::default; _];
+    };
+
     macro_rules! declare_server {
         ($($name:ident: $t:ty = $n:expr;)*) => {{
             paste::paste! {
@@ -299,6 +512,8 @@ fn main() -> ! {
                 };
                 let ($($name),*) = ($(SensorArray($name)),*);
                 ServerImpl {
+                    alarms,
+                    subscribers: Default::default(),
                     deadline,
                     $($name),*
                 }
@@ -317,6 +532,11 @@ fn main() -> ! {
         err_value: NoData = NoData::DeviceUnavailable;
         err_time: u64 = 0;
         nerrors: u32 = 0;
+        history_time: [u64; HISTORY_DEPTH] = [0; HISTORY_DEPTH];
+        history_value: [f32; HISTORY_DEPTH] = [f32::NAN; HISTORY_DEPTH];
+        history_head: u16 = 0;
+        history_len: u16 = 0;
+        history_skip: u16 = 0;
     );
 
     let mut buffer = [0; idl::INCOMING_SIZE];
@@ -327,9 +547,13 @@ fn main() -> ! {
 }
 
 mod idl {
-    use super::{NoData, Reading, SensorApiError, SensorError, SensorId};
+    use super::{
+        HistoryError, NoData, Reading, SensorApiError, SensorError, SensorId,
+        SubscribeError,
+    };
 
     include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
 }
 
+include!(concat!(env!("OUT_DIR"), "/server_config.rs"));
 include!(concat!(env!("OUT_DIR"), "/notifications.rs"));
@@ -0,0 +1,92 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! TCP echo server: accepts one connection at a time, and sends back
+//! everything it receives until the peer closes its side.
+
+#![no_std]
+#![no_main]
+
+use task_net_api::*;
+use userlib::*;
+
+task_slot!(NET, net);
+
+const SOCKET: SocketName = SocketName::tcpecho;
+
+#[export_name = "main"]
+fn main() -> ! {
+    let net = NET.get_task_id();
+    let net = Net::from(net);
+
+    loop {
+        match serve(&net) {
+            Ok(()) => (),
+            // The peer reset the connection, or `net` restarted (probably due
+            // to its watchdog) and took the connection with it. Either way,
+            // the socket is closed and we can start over.
+            Err(TcpError::NotConnected | TcpError::ServerRestarted) => (),
+            Err(
+                TcpError::NotYours
+                | TcpError::WouldBlock
+                | TcpError::InvalidState
+                | TcpError::Unaddressable,
+            ) => panic!(),
+        }
+    }
+}
+
+/// Waits for a connection and echoes it until the peer is done.
+fn serve(net: &Net) -> Result<(), TcpError> {
+    // Listening fails until the previous connection has finished closing,
+    // which we'll be notified of.
+    loop {
+        match net.tcp_listen(SOCKET) {
+            Err(TcpError::InvalidState) => wait(),
+            r => break r?,
+        }
+    }
+    retry(|| net.tcp_accept(SOCKET))?;
+
+    // Tiiiiiny payload buffer
+    let mut buf = [0u8; 64];
+    loop {
+        let n = retry(|| net.tcp_recv(SOCKET, &mut buf))? as usize;
+        if n == 0 {
+            // The peer has closed its side, and we've sent everything back.
+            break;
+        }
+        let mut sent = 0;
+        while sent < n {
+            sent += retry(|| net.tcp_send(SOCKET, &buf[sent..n]))? as usize;
+        }
+        TCP_ECHO_COUNT
+            .fetch_add(n as u32, core::sync::atomic::Ordering::Relaxed);
+    }
+    net.tcp_close(SOCKET)
+}
+
+/// Calls `op` until it stops asking us to wait.
+fn retry<T>(
+    mut op: impl FnMut() -> Result<T, TcpError>,
+) -> Result<T, TcpError> {
+    loop {
+        match op() {
+            Err(TcpError::WouldBlock) => wait(),
+            r => break r,
+        }
+    }
+}
+
+/// Waits for `net` to tell us something has changed on our socket.
+fn wait() {
+    sys_recv_closed(&mut [], notifications::SOCKET_MASK, TaskId::KERNEL)
+        .unwrap();
+}
+
+/// Total bytes echoed, for inspection through a debugger.
+static TCP_ECHO_COUNT: core::sync::atomic::AtomicU32 =
+    core::sync::atomic::AtomicU32::new(0);
+
+include!(concat!(env!("OUT_DIR"), "/notifications.rs"));
@@ -24,6 +24,7 @@ pub enum ThermalError {
     InvalidWatchdogTime = 7,
     InvalidParameter = 8,
     InvalidIndex = 9,
+    TelemetryDisabled = 10,
 
     #[idol(server_death)]
     ServerDeath,
@@ -2,6 +2,22 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use serde::Deserialize;
+use std::io::Write;
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Config {
+    telemetry: Option<TelemetryConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct TelemetryConfig {
+    /// Number of control loop iterations to keep
+    depth: usize,
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     build_util::expose_target_board();
     build_util::build_notifications()?;
@@ -13,5 +29,18 @@ fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         idol::server::ServerStyle::InOrder,
     )?;
 
+    let cfg = build_util::task_maybe_config::<Config>()?.unwrap_or_default();
+    let depth = match cfg.telemetry {
+        Some(t) if t.depth == 0 => {
+            return Err("thermal telemetry depth must be at least 1".into());
+        }
+        Some(t) => t.depth,
+        None => 0,
+    };
+
+    let out_dir = build_util::out_dir();
+    let mut out = std::fs::File::create(out_dir.join("thermal_config.rs"))?;
+    writeln!(out, "pub(crate) const TELEMETRY_DEPTH: usize = {depth};")?;
+
     Ok(())
 }
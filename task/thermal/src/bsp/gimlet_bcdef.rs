@@ -6,8 +6,8 @@
 
 use crate::{
     control::{
-        ChannelType, Device, FanControl, Fans, InputChannel, PidConfig,
-        TemperatureSensor,
+        ChannelType, Device, FanControl, Fans, InputChannel, TemperatureSensor,
+        Zone,
     },
     i2c_config::{devices, sensors},
 };
@@ -17,6 +17,7 @@ use drv_gimlet_seq_api::{PowerState, Sequencer};
 use drv_i2c_devices::max31790::Max31790;
 use task_sensor_api::SensorId;
 use task_thermal_api::ThermalProperties;
+use thermal_control::{ControllerConfig, PidConfig};
 use userlib::{task_slot, units::Celsius, TaskId};
 
 task_slot!(SEQ, gimlet_seq);
@@ -51,6 +52,9 @@ pub const NUM_DYNAMIC_TEMPERATURE_INPUTS: usize = 0;
 // We've got 6 fans, driven from a single MAX31790 IC
 pub const NUM_FANS: usize = drv_i2c_devices::max31790::MAX_FANS as usize;
 
+// All of the fans blow through the whole sled, so they're driven together
+pub const NUM_ZONES: usize = 1;
+
 /// This controller is tuned and ready to go
 pub const USE_CONTROLLER: bool = true;
 
@@ -71,8 +75,8 @@ pub(crate) struct Bsp {
     /// Id of the I2C task, to query MAX5970 status
     i2c_task: TaskId,
 
-    /// Tuning for the PID controller
-    pub pid_config: PidConfig,
+    /// Fan zones, and how each is controlled
+    pub zones: &'static [Zone; NUM_ZONES],
 }
 
 bitflags::bitflags! {
@@ -180,13 +184,7 @@ impl Bsp {
             i2c_task,
             fctrl,
 
-            // Based on experimental tuning!
-            pid_config: PidConfig {
-                zero: 35.0,
-                gain_p: 1.75,
-                gain_i: 0.0135,
-                gain_d: 0.4,
-            },
+            zones: &ZONES,
 
             inputs: &INPUTS,
             dynamic_inputs: &[],
@@ -197,6 +195,19 @@ impl Bsp {
     }
 }
 
+const ZONES: [Zone; NUM_ZONES] = [Zone {
+    fans: &[0, 1, 2, 3, 4, 5],
+    inputs: None,
+    power_sensors: &[],
+    // Based on experimental tuning!
+    controller: ControllerConfig::Pid(PidConfig {
+        zero: 35.0,
+        gain_p: 1.75,
+        gain_i: 0.0135,
+        gain_d: 0.4,
+    }),
+}];
+
 // In general, see RFD 276 Detailed Thermal Loop Design for references.
 // TODO: temperature_slew_deg_per_sec is made up.
 
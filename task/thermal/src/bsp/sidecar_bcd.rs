@@ -5,8 +5,8 @@
 //! BSP for Sidecar
 
 use crate::control::{
-    ChannelType, Device, FanControl, Fans, InputChannel, PidConfig,
-    TemperatureSensor,
+    ChannelType, Device, FanControl, Fans, InputChannel, TemperatureSensor,
+    Zone,
 };
 use core::convert::TryInto;
 use drv_i2c_devices::max31790::Max31790;
@@ -15,6 +15,7 @@ pub use drv_sidecar_seq_api::SeqError;
 use drv_sidecar_seq_api::{Sequencer, TofinoSeqState, TofinoSequencerPolicy};
 use task_sensor_api::SensorId;
 use task_thermal_api::ThermalProperties;
+use thermal_control::{ControllerConfig, PidConfig};
 use userlib::{task_slot, units::Celsius, TaskId};
 
 include!(concat!(env!("OUT_DIR"), "/i2c_config.rs"));
@@ -41,6 +42,9 @@ pub const NUM_DYNAMIC_TEMPERATURE_INPUTS: usize =
 // Number of individual fans
 pub const NUM_FANS: usize = sensors::NUM_MAX31790_SPEED_SENSORS;
 
+// East and west fans are controlled independently
+pub const NUM_ZONES: usize = 2;
+
 // Run the PID loop on startup
 pub const USE_CONTROLLER: bool = true;
 
@@ -71,7 +75,8 @@ pub(crate) struct Bsp {
 
     seq: Sequencer,
 
-    pub pid_config: PidConfig,
+    /// Fan zones, and how each is controlled
+    pub zones: &'static [Zone; NUM_ZONES],
 }
 
 impl Bsp {
@@ -171,13 +176,7 @@ impl Bsp {
             fctrl_east,
             fctrl_west,
 
-            // TODO: this is all made up, copied from tuned Gimlet values
-            pid_config: PidConfig {
-                zero: 35.0,
-                gain_p: 1.75,
-                gain_i: 0.0135,
-                gain_d: 0.4,
-            },
+            zones: &ZONES,
 
             inputs: &INPUTS,
             dynamic_inputs:
@@ -189,6 +188,38 @@ impl Bsp {
     }
 }
 
+// TODO: this is all made up, copied from tuned Gimlet values
+const PID_CONFIG: PidConfig = PidConfig {
+    zero: 35.0,
+    gain_p: 1.75,
+    gain_i: 0.0135,
+    gain_d: 0.4,
+};
+
+// The Tofino is by far the largest load, so it drives both zones; the
+// VSC7448 additionally drives the west zone.  This split is a first guess,
+// pending airflow measurements.  The transceivers aren't listed, so they
+// drive both zones (see `Zone::inputs`).
+const ZONES: [Zone; NUM_ZONES] = [
+    // East fan modules 0/1
+    Zone {
+        fans: &[0, 1, 2, 3],
+        inputs: Some(&[sensors::TMP451_TF2_TEMPERATURE_SENSOR]),
+        power_sensors: &[],
+        controller: ControllerConfig::Pid(PID_CONFIG),
+    },
+    // West fan modules 2/3
+    Zone {
+        fans: &[4, 5, 6, 7],
+        inputs: Some(&[
+            sensors::TMP451_TF2_TEMPERATURE_SENSOR,
+            sensors::TMP451_VSC7448_TEMPERATURE_SENSOR,
+        ]),
+        power_sensors: &[],
+        controller: ControllerConfig::Pid(PID_CONFIG),
+    },
+];
+
 //
 // Guessing, big time
 //
@@ -4,7 +4,7 @@
 
 use crate::{
     bsp::{self, Bsp, PowerBitmask},
-    Fan, ThermalError, Trace,
+    Fan, ThermalError, Trace, TELEMETRY_DEPTH,
 };
 use drv_i2c_api::ResponseCode;
 use drv_i2c_devices::{
@@ -20,11 +20,17 @@ use drv_i2c_devices::{
 use ringbuf::ringbuf_entry_root as ringbuf_entry;
 use task_sensor_api::{Reading, Sensor as SensorApi, SensorError, SensorId};
 use task_thermal_api::{SensorReadError, ThermalAutoState, ThermalProperties};
+use thermal_control::{
+    telemetry::{RecordHeader, ZoneRecord},
+    Controller, ControllerConfig, PidConfig, PidTerms, ZoneController,
+    ZoneInput,
+};
 use userlib::{
     sys_get_timer,
     units::{Celsius, PWMDuty, Rpm},
     TaskId,
 };
+use zerocopy::{AsBytes, FromBytes, LittleEndian, F32};
 
 ////////////////////////////////////////////////////////////////////////////////
 
@@ -224,6 +230,40 @@ impl InputChannel {
 
 ////////////////////////////////////////////////////////////////////////////////
 
+/// A set of fans that are driven together, and the temperatures that drive
+/// them
+pub struct Zone {
+    /// Fans in this zone, by system index.  Fans that aren't in any zone are
+    /// driven at the highest PWM of all zones.
+    pub fans: &'static [u8],
+
+    /// Temperature inputs (static or dynamic) that drive this zone, or `None`
+    /// for all of them.  Inputs that no zone lists drive every zone, so that
+    /// a part left out of the BSP's zones still gets cooled.
+    pub inputs: Option<&'static [SensorId]>,
+
+    /// Power sensors whose readings are summed into `ZoneInput::power`
+    pub power_sensors: &'static [SensorId],
+
+    /// Control algorithm and its default tuning
+    pub controller: ControllerConfig,
+}
+
+impl Zone {
+    pub fn has_input(&self, id: SensorId) -> bool {
+        match self.inputs {
+            Some(ids) => ids.contains(&id),
+            None => true,
+        }
+    }
+
+    pub fn has_fan(&self, fan: Fan) -> bool {
+        self.fans.contains(&fan.0)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
 /// A `DynamicInputChannel` represents a temperature input channel with thermal
 /// properties that are chosen at runtime, rather than baked into the BSP.
 ///
@@ -313,8 +353,9 @@ pub(crate) struct ThermalControl<'a> {
     /// Most recent power mode mask
     power_mode: PowerBitmask,
 
-    /// PID parameters, pulled from the BSP by default but user-modifiable
-    pid_config: PidConfig,
+    /// One controller per fan zone in the BSP, in the same order.  Their
+    /// tuning is pulled from the BSP by default but user-modifiable.
+    controllers: [ZoneController; bsp::NUM_ZONES],
 
     /// Dynamic inputs are fixed in number but configured at runtime.
     ///
@@ -337,6 +378,9 @@ pub(crate) struct ThermalControl<'a> {
 
     /// Last group PWM control value
     last_pwm: PWMDuty,
+
+    /// Recent iterations of the control loop, if configured
+    telemetry: Telemetry,
 }
 
 /// Represents the state of a temperature sensor, which either has a valid
@@ -379,75 +423,97 @@ impl TimestampedTemperatureReading {
     }
 }
 
-/// Configuration for a PID controller
-#[derive(Copy, Clone)]
-pub struct PidConfig {
-    pub zero: f32,
-    pub gain_p: f32,
-    pub gain_i: f32,
-    pub gain_d: f32,
+const TEMPERATURE_ARRAY_SIZE: usize =
+    bsp::NUM_TEMPERATURE_INPUTS + bsp::NUM_DYNAMIC_TEMPERATURE_INPUTS;
+
+// Telemetry records count inputs and zones in a byte apiece
+const _: () = assert!(TEMPERATURE_ARRAY_SIZE <= u8::MAX as usize);
+const _: () = assert!(bsp::NUM_ZONES <= u8::MAX as usize);
+
+/// Worst-case margins seen on one iteration of the control loop
+struct Margins {
+    /// Margin of each input, in the same order as our temperature arrays, or
+    /// NaN for inputs without a valid reading
+    inputs: [f32; TEMPERATURE_ARRAY_SIZE],
+
+    /// Smallest margin across each zone's inputs
+    zones: [f32; bsp::NUM_ZONES],
 }
 
-/// Represents a PID controller that can only push in one direction (i.e. the
-/// output must always be positive).
-struct OneSidedPidState {
-    /// Previous (time, input) tuple, for derivative term
-    prev_error: Option<f32>,
+impl Margins {
+    fn new() -> Self {
+        Self {
+            inputs: [f32::NAN; TEMPERATURE_ARRAY_SIZE],
+            zones: [f32::MAX; bsp::NUM_ZONES],
+        }
+    }
 
-    /// Accumulated integral term, pre-multiplied by gain
-    integral: f32,
+    /// Records the margin of input `index`, lowering the worst margin of each
+    /// zone that it drives (or of every zone, if no zone claims it)
+    fn update(
+        &mut self,
+        zones: &[Zone],
+        index: usize,
+        id: SensorId,
+        margin: Celsius,
+    ) {
+        self.inputs[index] = margin.0;
+        let unzoned = !zones.iter().any(|zone| zone.has_input(id));
+        for (m, zone) in self.zones.iter_mut().zip(zones) {
+            if unzoned || zone.has_input(id) {
+                *m = m.min(margin.0);
+            }
+        }
+    }
 }
 
-impl OneSidedPidState {
-    /// Attempts to drive the error to zero.
-    ///
-    /// The error and output are expected to have the same signs, i.e. a large
-    /// positive error will produce a large positive output.
-    fn run(&mut self, cfg: &PidConfig, error: f32, output_limit: f32) -> f32 {
-        let p_contribution = cfg.gain_p * error;
-
-        // Pre-multiply accumulated integral by gain, to make clamping easier
-        // (this also means we can change the gain_i without glitches)
-        self.integral += error * cfg.gain_i;
-
-        // Calculate the derivative term if there was a previous error
-        let d_contribution = if let Some(prev_error) = self.prev_error {
-            (error - prev_error) * cfg.gain_d
-        } else {
-            0.0
-        };
-        self.prev_error = Some(error);
-
-        // To prevent integral windup, integral term needs to be clamped to values
-        // can effect the output.
-        let out_pd = cfg.zero + p_contribution + d_contribution;
-        let (integral_min, integral_max) = if out_pd > output_limit {
-            (-out_pd, 0.0)
-        } else if out_pd < 0.0 {
-            (0.0, -out_pd + output_limit)
+/// One iteration of the control loop, as laid out in
+/// `thermal_control::telemetry`
+#[derive(AsBytes, FromBytes)]
+#[repr(C)]
+pub(crate) struct TelemetryRecord {
+    header: RecordHeader,
+    margins: [F32<LittleEndian>; TEMPERATURE_ARRAY_SIZE],
+    zones: [ZoneRecord; bsp::NUM_ZONES],
+}
+
+/// Ring of the most recent `TelemetryRecord`s
+struct Telemetry {
+    records: &'static mut [TelemetryRecord; TELEMETRY_DEPTH],
+    /// Index at which the next record will be written
+    head: usize,
+    /// Number of valid records
+    len: usize,
+    /// Sequence number of the next record
+    seq: u32,
+}
+
+impl Telemetry {
+    /// Returns the slot for the next record, making room if need be
+    fn next(&mut self) -> Option<&mut TelemetryRecord> {
+        let slot = self.records.get_mut(self.head)?;
+        self.head = if self.head + 1 == TELEMETRY_DEPTH {
+            0
         } else {
-            (-out_pd, output_limit - out_pd)
+            self.head + 1
         };
-        self.integral = self.integral.clamp(integral_min, integral_max);
-
-        // Clamp output values to valid range.
-        let out = out_pd + self.integral;
-        out.clamp(0.0, output_limit)
+        self.len = (self.len + 1).min(TELEMETRY_DEPTH);
+        self.seq = self.seq.wrapping_add(1);
+        Some(slot)
     }
-}
 
-impl Default for OneSidedPidState {
-    fn default() -> Self {
-        Self {
-            prev_error: None,
-            integral: 0.0,
-        }
+    /// Iterates over the records we hold, oldest first
+    fn iter(&self) -> impl Iterator<Item = &TelemetryRecord> {
+        let oldest = if self.head >= self.len {
+            self.head - self.len
+        } else {
+            self.head + TELEMETRY_DEPTH - self.len
+        };
+        let (newer, older) = self.records.split_at(oldest);
+        older.iter().chain(newer).take(self.len)
     }
 }
 
-const TEMPERATURE_ARRAY_SIZE: usize =
-    bsp::NUM_TEMPERATURE_INPUTS + bsp::NUM_DYNAMIC_TEMPERATURE_INPUTS;
-
 /// This corresponds to states shown in RFD 276
 ///
 /// All of our temperature arrays contain, in order
@@ -468,7 +534,6 @@ enum ThermalControlState {
     /// Normal happy control loop
     Running {
         values: [TemperatureReading; TEMPERATURE_ARRAY_SIZE],
-        pid: OneSidedPidState,
     },
 
     /// In the overheated state, one or more components has entered their
@@ -486,7 +551,10 @@ enum ThermalControlState {
 }
 
 enum ControlResult {
+    /// Drive every fan at the same PWM
     Pwm(PWMDuty),
+    /// Drive each zone's fans at its own PWM
+    Zones([PWMDuty; bsp::NUM_ZONES]),
     PowerDown,
 }
 
@@ -535,6 +603,10 @@ impl<'a> ThermalControl<'a> {
             static mut ERR_BLACKBOX: [ThermalSensorErrors; 2] =
                 [Default::default; _];
         };
+        let records = mutable_statics! {
+            static mut TELEMETRY: [TelemetryRecord; TELEMETRY_DEPTH] =
+                [TelemetryRecord::new_zeroed; _];
+        };
         Self {
             bsp,
             i2c_task,
@@ -543,7 +615,7 @@ impl<'a> ThermalControl<'a> {
             state: ThermalControlState::Boot {
                 values: [None; TEMPERATURE_ARRAY_SIZE],
             },
-            pid_config: bsp.pid_config,
+            controllers: Self::controllers_from_bsp(bsp),
 
             overheat_hysteresis: Celsius(1.0),
             overheat_timeout_ms: 60_000,
@@ -557,6 +629,13 @@ impl<'a> ThermalControl<'a> {
 
             err_blackbox,
             prev_err_blackbox,
+
+            telemetry: Telemetry {
+                records,
+                head: 0,
+                len: 0,
+                seq: 0,
+            },
         }
     }
 
@@ -577,21 +656,23 @@ impl<'a> ThermalControl<'a> {
             return Err(ThermalError::InvalidParameter);
         }
 
-        // If the incoming integral gain is zero, then it will never be able
-        // to wind down the integral accumulator (which is pre-multiplied),
-        // so clear it here.
-        if let ThermalControlState::Running { pid, .. } = &mut self.state {
-            if i == 0.0 {
-                pid.integral = 0.0;
-            }
+        // This applies to every zone with a PID loop; if there are none,
+        // there's nothing for these parameters to mean.
+        let config = PidConfig {
+            zero: z,
+            gain_p: p,
+            gain_i: i,
+            gain_d: d,
+        };
+        let mut any = false;
+        for c in self.controllers.iter_mut() {
+            any |= c.set_pid(config);
+        }
+        if any {
+            Ok(())
+        } else {
+            Err(ThermalError::InvalidParameter)
         }
-
-        self.pid_config.zero = z;
-        self.pid_config.gain_p = p;
-        self.pid_config.gain_i = i;
-        self.pid_config.gain_d = d;
-
-        Ok(())
     }
 
     pub fn set_margin(&mut self, margin: f32) -> Result<(), ThermalError> {
@@ -606,17 +687,21 @@ impl<'a> ThermalControl<'a> {
         self.target_margin.0
     }
 
-    /// Resets the control state and the PID configuration
+    /// Resets the control state and the controllers' configuration
     pub fn reset(&mut self) {
         self.reset_state();
 
-        // Reset the PID configuration from the BSP
-        self.pid_config = self.bsp.pid_config;
+        // Reset the controllers (and their tuning) from the BSP
+        self.controllers = Self::controllers_from_bsp(self.bsp);
 
         // Set the target_margin to 0, indicating no overcooling
         self.target_margin = Celsius(0.0f32);
     }
 
+    fn controllers_from_bsp(bsp: &Bsp) -> [ZoneController; bsp::NUM_ZONES] {
+        core::array::from_fn(|i| ZoneController::new(&bsp.zones[i].controller))
+    }
+
     /// Resets the control state
     fn reset_state(&mut self) {
         self.state = ThermalControlState::Boot {
@@ -734,7 +819,8 @@ impl<'a> ThermalControl<'a> {
         // they are, so someone else has to do that.
     }
 
-    /// Returns an iterator over tuples of `(value, thermal model)`
+    /// Returns an iterator over tuples of `(index, value, thermal model,
+    /// sensor)`
     ///
     /// The `values` array must contain `static_inputs.len()` +
     /// `dynamic_inputs.len()` values, in that order; this function will panic
@@ -744,21 +830,68 @@ impl<'a> ThermalControl<'a> {
     /// the array), the iterator will skip that entire tuple.
     fn zip_temperatures<'b, T>(
         values: &'b [T],
-        (static_inputs, dynamic_inputs): (
+        (static_inputs, dynamic_ids, dynamic_inputs): (
             &'b [InputChannel],
+            &'b [SensorId],
             &'b [Option<DynamicInputChannel>],
         ),
-    ) -> impl Iterator<Item = (&'b T, ThermalProperties)> {
+    ) -> impl Iterator<Item = (usize, &'b T, ThermalProperties, SensorId)> {
         assert_eq!(values.len(), static_inputs.len() + dynamic_inputs.len());
         values
             .iter()
+            .enumerate()
             .zip(
                 static_inputs
                     .iter()
-                    .map(|i| Some(i.model))
-                    .chain(dynamic_inputs.iter().map(|i| i.map(|i| i.model))),
+                    .map(|i| Some((i.model, i.sensor.sensor_id)))
+                    .chain(
+                        dynamic_inputs
+                            .iter()
+                            .zip(dynamic_ids)
+                            .map(|(i, id)| i.map(|i| (i.model, *id))),
+                    ),
             )
-            .filter_map(|(v, model)| model.map(|t| (v, t)))
+            .filter_map(|((i, v), m)| m.map(|(model, id)| (i, v, model, id)))
+    }
+
+    /// Runs every zone's controller, given the zones' worst margins and power
+    /// draws
+    fn run_zones(
+        controllers: &mut [ZoneController; bsp::NUM_ZONES],
+        target_margin: Celsius,
+        margins: &Margins,
+        powers: &[Option<f32>; bsp::NUM_ZONES],
+    ) -> [PWMDuty; bsp::NUM_ZONES] {
+        core::array::from_fn(|i| {
+            // We adjust the worst component margin by our target margin,
+            // which must be > 0.  This effectively tells the control loop to
+            // overcool the system.
+            //
+            // Controllers expect the sign of the input and output to match,
+            // so we negate things here: if the worst margin is negative (i.e.
+            // the system is overheating), then the error is positive, because
+            // we want a positive fan speed.
+            let input = ZoneInput {
+                error: target_margin.0 - margins.zones[i],
+                power: powers[i],
+            };
+            PWMDuty(controllers[i].run(&input) as u8)
+        })
+    }
+
+    /// Reads the total power draw of each zone from the `sensors` task
+    fn read_zone_powers(&self) -> [Option<f32>; bsp::NUM_ZONES] {
+        core::array::from_fn(|i| {
+            let ids = self.bsp.zones[i].power_sensors;
+            if ids.is_empty() {
+                return None;
+            }
+            let mut total = 0.0;
+            for id in ids {
+                total += self.sensor_api.get_reading(*id).ok()?.value;
+            }
+            Some(total)
+        })
     }
 
     /// An extremely simple thermal control loop.
@@ -827,24 +960,38 @@ impl<'a> ThermalControl<'a> {
             }
         }
 
+        // Power draw is only used by some controllers, but is cheap enough to
+        // read for every zone that has power sensors.
+        let powers = self.read_zone_powers();
+
         // A bit awkward, but we have to borrow these explicitly to work around
         // the lifetime checker, which won't let us call a &self function when
         // self.state is mutably borrowed.
-        let inputs = (self.bsp.inputs, self.dynamic_inputs.as_slice());
+        let inputs = (
+            self.bsp.inputs,
+            self.bsp.dynamic_inputs,
+            self.dynamic_inputs.as_slice(),
+        );
+        let zones = self.bsp.zones.as_slice();
+        let mut margins = Margins::new();
 
         let control_result = match &mut self.state {
             ThermalControlState::Boot { values } => {
                 let mut all_some = true;
                 let mut any_power_down = false;
-                let mut worst_margin = f32::MAX;
-                for (v, model) in Self::zip_temperatures(values, inputs) {
+                for (i, v, model, id) in Self::zip_temperatures(values, inputs)
+                {
                     match v {
                         Some(TemperatureReading::Valid(v)) => {
                             let temperature = v.worst_case(now_ms, &model);
                             any_power_down |=
                                 model.should_power_down(temperature);
-                            worst_margin =
-                                worst_margin.min(model.margin(temperature).0);
+                            margins.update(
+                                zones,
+                                i,
+                                id,
+                                model.margin(temperature),
+                            );
                         }
                         Some(TemperatureReading::Inactive) => {
                             // Inactive sensors are ignored, but do not gate us
@@ -862,41 +1009,42 @@ impl<'a> ThermalControl<'a> {
                     ControlResult::PowerDown
                 } else if all_some {
                     // Transition to the Running state and run a single
-                    // iteration of the PID control loop.
-                    let mut pid = OneSidedPidState::default();
-                    let pwm = pid.run(
-                        &self.pid_config,
-                        self.target_margin.0 - worst_margin,
-                        100.0,
+                    // iteration of each zone's control loop.
+                    for c in self.controllers.iter_mut() {
+                        c.reset();
+                    }
+                    let pwms = Self::run_zones(
+                        &mut self.controllers,
+                        self.target_margin,
+                        &margins,
+                        &powers,
                     );
                     self.state = ThermalControlState::Running {
                         values: values.map(Option::unwrap),
-                        pid,
                     };
                     ringbuf_entry!(Trace::AutoState(self.get_state()));
 
-                    ControlResult::Pwm(PWMDuty(pwm as u8))
+                    ControlResult::Zones(pwms)
                 } else {
                     ControlResult::Pwm(PWMDuty(100))
                 }
             }
-            ThermalControlState::Running { values, pid } => {
+            ThermalControlState::Running { values } => {
                 let mut any_power_down = false;
                 let mut any_critical = false;
-                let mut worst_margin = f32::MAX;
 
                 // Remember, positive margin means that all parts are happily
                 // below their max temperature; negative means someone is
-                // overheating.  We want to pick the _smallest_ margin, since
-                // that's the part which is most overheated.
-                for (v, model) in Self::zip_temperatures(values, inputs) {
+                // overheating.  We want to pick the _smallest_ margin in each
+                // zone, since that's the part which is most overheated.
+                for (i, v, model, id) in Self::zip_temperatures(values, inputs)
+                {
                     if let TemperatureReading::Valid(v) = v {
                         let temperature = v.worst_case(now_ms, &model);
                         any_power_down |= model.should_power_down(temperature);
                         any_critical |= model.is_critical(temperature);
 
-                        worst_margin =
-                            worst_margin.min(model.margin(temperature).0);
+                        margins.update(zones, i, id, model.margin(temperature));
                     }
                 }
 
@@ -914,29 +1062,20 @@ impl<'a> ThermalControl<'a> {
 
                     ControlResult::Pwm(PWMDuty(100))
                 } else {
-                    // We adjust the worst component margin by our target
-                    // margin, which must be > 0.  This effectively tells the
-                    // control loop to overcool the system.
-                    //
-                    // `PidControl::run` expects the sign of the input and
-                    // output to match, so we negate things here: if the worst
-                    // margin is negative (i.e. the system is overheating), then
-                    // the input to `run` is positive, because we want a
-                    // positive fan speed.
-                    let pwm = pid.run(
-                        &self.pid_config,
-                        self.target_margin.0 - worst_margin,
-                        100.0,
-                    );
-                    ControlResult::Pwm(PWMDuty(pwm as u8))
+                    ControlResult::Zones(Self::run_zones(
+                        &mut self.controllers,
+                        self.target_margin,
+                        &margins,
+                        &powers,
+                    ))
                 }
             }
             ThermalControlState::Overheated { values, start_time } => {
                 let mut all_subcritical = true;
                 let mut any_power_down = false;
-                let mut worst_margin = f32::MAX;
 
-                for (v, model) in Self::zip_temperatures(values, inputs) {
+                for (i, v, model, id) in Self::zip_temperatures(values, inputs)
+                {
                     if let TemperatureReading::Valid(v) = v {
                         let temperature = v.worst_case(now_ms, &model);
                         all_subcritical &= model.is_sub_critical(
@@ -944,8 +1083,7 @@ impl<'a> ThermalControl<'a> {
                             self.overheat_hysteresis,
                         );
                         any_power_down |= model.should_power_down(temperature);
-                        worst_margin =
-                            worst_margin.min(model.margin(temperature).0);
+                        margins.update(zones, i, id, model.margin(temperature));
                     }
                 }
 
@@ -956,20 +1094,21 @@ impl<'a> ThermalControl<'a> {
                     ControlResult::PowerDown
                 } else if all_subcritical {
                     // Transition to the Running state and run a single
-                    // iteration of the PID control loop.
-                    let mut pid = OneSidedPidState::default();
-                    let pwm = pid.run(
-                        &self.pid_config,
-                        self.target_margin.0 - worst_margin,
-                        100.0,
+                    // iteration of each zone's control loop.
+                    for c in self.controllers.iter_mut() {
+                        c.reset();
+                    }
+                    let pwms = Self::run_zones(
+                        &mut self.controllers,
+                        self.target_margin,
+                        &margins,
+                        &powers,
                     );
-                    self.state = ThermalControlState::Running {
-                        values: *values,
-                        pid,
-                    };
+                    self.state =
+                        ThermalControlState::Running { values: *values };
                     ringbuf_entry!(Trace::AutoState(self.get_state()));
 
-                    ControlResult::Pwm(PWMDuty(pwm as u8))
+                    ControlResult::Zones(pwms)
                 } else if now_ms > *start_time + self.overheat_timeout_ms {
                     // If blasting the fans hasn't cooled us down in this amount
                     // of time, then something is terribly wrong - abort!
@@ -984,12 +1123,20 @@ impl<'a> ThermalControl<'a> {
             ThermalControlState::Uncontrollable => ControlResult::PowerDown,
         };
 
+        self.record_telemetry(now_ms, &margins, &powers, &control_result);
+
         match control_result {
             ControlResult::Pwm(target_pwm) => {
                 // Send the new RPM to all of our fans
                 ringbuf_entry!(Trace::ControlPwm(target_pwm.0));
                 self.set_pwm(target_pwm)?;
             }
+            ControlResult::Zones(pwms) => {
+                for (zone, pwm) in pwms.iter().enumerate() {
+                    ringbuf_entry!(Trace::ZonePwm(zone as u8, pwm.0));
+                }
+                self.set_zone_pwms(&pwms)?;
+            }
             ControlResult::PowerDown => {
                 ringbuf_entry!(Trace::PowerDownAt(sys_get_timer().now));
                 *self.prev_err_blackbox = *self.err_blackbox;
@@ -1004,6 +1151,78 @@ impl<'a> ThermalControl<'a> {
         Ok(())
     }
 
+    /// Records an iteration of the control loop, if telemetry is enabled
+    fn record_telemetry(
+        &mut self,
+        now_ms: u64,
+        margins: &Margins,
+        powers: &[Option<f32>; bsp::NUM_ZONES],
+        result: &ControlResult,
+    ) {
+        let seq = self.telemetry.seq;
+        let state = self.get_state() as u8;
+        let target_margin = self.target_margin.0;
+        let Some(r) = self.telemetry.next() else {
+            return;
+        };
+
+        r.header = RecordHeader {
+            seq: seq.into(),
+            time: now_ms.into(),
+            state,
+            ninputs: TEMPERATURE_ARRAY_SIZE as u8,
+            nzones: bsp::NUM_ZONES as u8,
+            _reserved: 0,
+            target_margin: target_margin.into(),
+        };
+        for (m, v) in r.margins.iter_mut().zip(&margins.inputs) {
+            *m = (*v).into();
+        }
+        for (zone, z) in r.zones.iter_mut().enumerate() {
+            let (error, pwm, terms) = match result {
+                ControlResult::Zones(pwms) => (
+                    target_margin - margins.zones[zone],
+                    pwms[zone],
+                    self.controllers[zone].pid_terms(),
+                ),
+                ControlResult::Pwm(pwm) => {
+                    (f32::NAN, *pwm, PidTerms::default())
+                }
+                ControlResult::PowerDown => {
+                    (f32::NAN, PWMDuty(0), PidTerms::default())
+                }
+            };
+            *z = ZoneRecord {
+                error: error.into(),
+                power: powers[zone].unwrap_or(f32::NAN).into(),
+                p: terms.p.into(),
+                i: terms.i.into(),
+                d: terms.d.into(),
+                pwm: pwm.0,
+                _reserved: [0; 3],
+            };
+        }
+    }
+
+    /// Iterates over the telemetry records with sequence numbers of at least
+    /// `start`, oldest first, as bytes
+    ///
+    /// Returns `None` if telemetry isn't enabled.
+    pub fn telemetry_since(
+        &self,
+        start: u32,
+    ) -> Option<impl Iterator<Item = &[u8]>> {
+        if TELEMETRY_DEPTH == 0 {
+            return None;
+        }
+        Some(
+            self.telemetry
+                .iter()
+                .filter(move |r| r.header.seq.get() >= start)
+                .map(|r| r.as_bytes()),
+        )
+    }
+
     /// Attempts to set the PWM duty cycle of every fan in this group.
     ///
     /// For fans that are present, set to `pwm`. For fans that are not present,
@@ -1030,6 +1249,43 @@ impl<'a> ThermalControl<'a> {
         last_err.map_err(|_| ThermalError::DeviceError)
     }
 
+    /// Attempts to set the PWM duty cycle of each zone's fans.
+    ///
+    /// A fan that's in more than one zone is driven at the highest of their
+    /// PWMs; a fan that's in none is driven at the highest PWM of all.  As
+    /// with `set_pwm`, missing fans are kept low, and the last error (if any)
+    /// is returned after attempting every fan.
+    fn set_zone_pwms(
+        &mut self,
+        pwms: &[PWMDuty; bsp::NUM_ZONES],
+    ) -> Result<(), ThermalError> {
+        let max = pwms.iter().map(|p| p.0).max().unwrap_or(100);
+        self.last_pwm = PWMDuty(max);
+
+        let mut last_err = Ok(());
+        for (index, sensor_id) in self.fans.enumerate() {
+            let fan = Fan::from(index);
+            let mut zoned = false;
+            let mut pwm = 0;
+            for (zone, p) in self.bsp.zones.iter().zip(pwms) {
+                if zone.has_fan(fan) {
+                    zoned = true;
+                    pwm = pwm.max(p.0);
+                }
+            }
+            // If a fan is missing, keep its PWM signal low
+            let pwm = match (sensor_id, zoned) {
+                (None, _) => PWMDuty(0),
+                (Some(_), true) => PWMDuty(pwm),
+                (Some(_), false) => PWMDuty(max),
+            };
+            if let Err(e) = self.bsp.fan_control(fan).set_pwm(pwm) {
+                last_err = Err(e);
+            }
+        }
+        last_err.map_err(|_| ThermalError::DeviceError)
+    }
+
     /// Sets the PWM for a single fan
     ///
     /// If the fan is present, set to `pwm`. if it is not present, set to zero.
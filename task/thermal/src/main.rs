@@ -40,7 +40,7 @@ use crate::{
 use core::convert::TryFrom;
 use drv_i2c_api::ResponseCode;
 use drv_i2c_devices::max31790::I2cWatchdog;
-use idol_runtime::{NotificationHandler, RequestError};
+use idol_runtime::{Leased, NotificationHandler, RequestError, W};
 use ringbuf::*;
 use task_sensor_api::{Sensor as SensorApi, SensorApiError, SensorId};
 use task_thermal_api::{
@@ -75,6 +75,7 @@ enum Trace {
     SensorReadFailed(SensorId, SensorReadError),
     PostFailed(SensorId, SensorApiError),
     ControlPwm(u8),
+    ZonePwm(u8, u8),
     PowerModeChanged(PowerBitmask),
     PowerDownFailed(SeqError),
     ControlError(ThermalError),
@@ -282,6 +283,31 @@ impl<'a> idl::InOrderThermalImpl for ServerImpl<'a> {
             .map_err(RequestError::from)
     }
 
+    fn get_telemetry(
+        &mut self,
+        _: &RecvMessage,
+        start: u32,
+        records: Leased<W, [u8]>,
+    ) -> Result<u32, RequestError<ThermalError>> {
+        let Some(iter) = self.control.telemetry_since(start) else {
+            return Err(ThermalError::TelemetryDisabled.into());
+        };
+        let mut offset = 0;
+        let mut count = 0;
+        for r in iter {
+            let end = offset + r.len();
+            if end > records.len() {
+                break;
+            }
+            records
+                .write_range(offset..end, r)
+                .map_err(|()| RequestError::went_away())?;
+            offset = end;
+            count += 1;
+        }
+        Ok(count)
+    }
+
     fn get_runtime(
         &mut self,
         _: &RecvMessage,
@@ -391,3 +417,5 @@ mod idl {
 include!(concat!(env!("OUT_DIR"), "/notifications.rs"));
 
 include!(concat!(env!("OUT_DIR"), "/i2c_config.rs"));
+
+include!(concat!(env!("OUT_DIR"), "/thermal_config.rs"));
@@ -49,6 +49,7 @@ fn main() -> ! {
                             SendError::ServerRestarted
                             | SendError::NotYours
                             | SendError::InvalidVLan
+                            | SendError::Unaddressable
                             | SendError::Other,
                         ) => panic!(),
                     }
@@ -2,7 +2,34 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+use anyhow::{Context, Result};
+use build_util::{AllowSend, HmacKey};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Config {
+    /// Keys with which clients may authenticate requests. A request names
+    /// its key by index in this list.
+    keys: Vec<HmacKey>,
+
+    /// Tasks that requests may be sent to. Anything not listed is refused.
+    #[serde(default)]
+    allow: Vec<AllowSend>,
+}
+
+fn main() -> Result<()> {
     build_util::build_notifications()?;
+
+    let cfg = build_util::task_config::<Config>()?;
+
+    let out_dir = build_util::out_dir();
+    let dest_path = out_dir.join("udprpc_config.rs");
+    let mut out = std::fs::File::create(dest_path)
+        .context("creating udprpc_config.rs")?;
+    build_util::write_net_auth_config(
+        &mut out, "udprpc", &cfg.keys, &cfg.allow,
+    )?;
+
     Ok(())
 }
@@ -2,14 +2,41 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+//! Remote procedure calls over UDP.
+//!
+//! This task lets a host on the management network (i.e. `humility rpc`)
+//! send raw messages to tasks, as if with `sys_send`. Since that's a lot of
+//! power, every request must be authenticated with HMAC-SHA256, using one of
+//! the keys in our `app.toml` config, and may only go to the tasks (and
+//! operations) that the config allows.
+//!
+//! A request is an `RpcHeader`, then `nbytes` of message, then the HMAC of
+//! both under the key that the header names. Requests are authenticated, and
+//! protected against replay, as described in `net-auth`.
+//!
+//! A client that doesn't know the session (e.g. because we've restarted)
+//! can send any properly signed request, and learn the session from the
+//! `BadSession` reply. Each key should be used by only one client at a time,
+//! since clients sharing a key would have to agree on sequence numbers.
+//!
+//! Every reply starts with a one-byte `RpcReply`; see the loop below for what
+//! follows it. Successful replies end with a tag binding them to the request,
+//! per `net_auth::Verified::sign`.
+
 #![no_std]
 #![no_main]
 
+use drv_rng_api::Rng;
+use net_auth::{AuthError, Authenticator, Credentials, TAG_SIZE};
 use task_net_api::*;
 use userlib::*;
 use zerocopy::{AsBytes, FromBytes, LittleEndian, U16, U64};
 
 task_slot!(NET, net);
+task_slot!(RNG, rng_driver);
+
+/// Version of the wire protocol described above.
+const RPC_VERSION: u8 = 2;
 
 #[derive(Copy, Clone, Debug)]
 #[repr(u8)]
@@ -24,6 +51,18 @@ enum RpcReply {
     NBytesMismatch,
     /// The output would overflow `tx_data_buf`
     NReplyOverflow,
+    /// The RPC packet's protocol version does not match ours
+    BadVersion,
+    /// The RPC packet names a key that we don't have
+    BadKey,
+    /// The RPC packet's HMAC is wrong
+    BadMac,
+    /// The RPC packet's session ID does not match ours
+    BadSession,
+    /// The RPC packet's sequence number has already been used
+    Replayed,
+    /// The target task or operation isn't in our allow-list
+    NotAllowed,
 }
 
 /// Header for an RPC request
@@ -32,13 +71,121 @@ enum RpcReply {
 #[derive(Copy, Clone, Debug, FromBytes)]
 #[repr(C)]
 struct RpcHeader {
+    version: u8,
+    key: u8,
+    _reserved: [u8; 6],
     image_id: U64<LittleEndian>,
+    session: U64<LittleEndian>,
+    sequence: U64<LittleEndian>,
     task: U16<LittleEndian>,
     op: U16<LittleEndian>,
     nreply: U16<LittleEndian>,
     nbytes: U16<LittleEndian>,
 }
 
+const HEADER_SIZE: usize = core::mem::size_of::<RpcHeader>();
+const REPLY_PREFIX_SIZE: usize = 5;
+
+impl From<AuthError> for RpcReply {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::BadKey => Self::BadKey,
+            AuthError::BadMac => Self::BadMac,
+            AuthError::BadImageId => Self::BadImageId,
+            AuthError::BadSession => Self::BadSession,
+            AuthError::Replayed => Self::Replayed,
+        }
+    }
+}
+
+struct Server {
+    auth: Authenticator<{ generated::KEY_COUNT }>,
+}
+
+impl Server {
+    /// Checks and (if it passes) executes the request in `rx`. Fills out
+    /// everything in `tx` after the `RpcReply` byte, and returns the reply
+    /// code along with the number of bytes of reply from the target task.
+    fn handle(&mut self, rx: &[u8], tx: &mut [u8]) -> (RpcReply, usize) {
+        if rx.len() < HEADER_SIZE + TAG_SIZE {
+            return (RpcReply::TooShort, 0);
+        }
+
+        // We can always read the header, since it's raw data
+        let header = RpcHeader::read_from(&rx[..HEADER_SIZE]).unwrap_lite();
+        let nbytes = header.nbytes.get() as usize;
+        let nreply = header.nreply.get() as usize;
+
+        if header.version != RPC_VERSION {
+            tx[1] = RPC_VERSION;
+            return (RpcReply::BadVersion, 0);
+        }
+        if rx.len() != HEADER_SIZE + nbytes + TAG_SIZE {
+            return (RpcReply::NBytesMismatch, 0);
+        }
+
+        let (signed, tag) = rx.split_at(HEADER_SIZE + nbytes);
+        let credentials = Credentials {
+            key: header.key,
+            image_id: header.image_id.get(),
+            session: header.session.get(),
+            sequence: header.sequence.get(),
+        };
+        let verified = match self.auth.check(&credentials, signed, tag) {
+            Ok(verified) => verified,
+            Err(e) => {
+                match e {
+                    AuthError::BadImageId => tx[1..9]
+                        .copy_from_slice(self.auth.image_id().as_bytes()),
+                    AuthError::BadSession => {
+                        tx[1..9].copy_from_slice(self.auth.session().as_bytes())
+                    }
+                    _ => (),
+                }
+                return (e.into(), 0);
+            }
+        };
+
+        let task_id = TaskId(header.task.get());
+        let op = header.op.get();
+        if !net_auth::is_allowed(&generated::ALLOWED, task_id.index(), op) {
+            return (RpcReply::NotAllowed, 0);
+        }
+        if nreply + REPLY_PREFIX_SIZE + TAG_SIZE > tx.len() {
+            return (RpcReply::NReplyOverflow, 0);
+        }
+
+        // This is the happy path: unpack the data and execute the sys_send
+        // which actually calls the target.
+        let rx_data = &signed[HEADER_SIZE..];
+
+        // The returned data is stored after the reply prefix, which consists
+        // of a one-byte `RpcReply` then a u32 return code from the `sys_send`
+        // call.
+        let tx_data = &mut tx[REPLY_PREFIX_SIZE..][..nreply];
+
+        let task_id = sys_refresh_task_id(task_id);
+        let (rc, len) = sys_send(task_id, op, rx_data, tx_data, &[]);
+
+        // Store the return code
+        tx[1..5].copy_from_slice(&rc.to_be_bytes());
+
+        // For idol calls with ssmarshal or hubpack encoding, the actual reply
+        // len may be less than `nreply` (the max possible encoding length).
+        // We know `len` is at most `nreply`: if it weren't, `sys_send()` would
+        // have faulted us for providing a too-short buffer.
+        let nreply = len;
+
+        // Sign the reply, binding it to the request.
+        tx[0] = RpcReply::Ok as u8;
+        let end = REPLY_PREFIX_SIZE + nreply;
+        let reply_tag = verified.sign(&tx[..end]);
+        tx[end..][..TAG_SIZE].copy_from_slice(&reply_tag);
+
+        (RpcReply::Ok, nreply)
+    }
+}
+
 #[export_name = "main"]
 fn main() -> ! {
     let net = NET.get_task_id();
@@ -46,19 +193,33 @@ fn main() -> ! {
 
     const SOCKET: SocketName = SocketName::rpc;
 
-    // We use the image id to make sure that we're compatible, since we're
-    // sending raw bytes using `sys_send`.  This isn't robust against malicious
-    // behavior, but prevents basic user error.
-    let image_id = kipc::read_image_id();
+    // Picking the session at random means that requests captured before we
+    // (or the whole SP) restarted can't be replayed afterwards.
+    let mut session = 0u64;
+    Rng::from(RNG.get_task_id())
+        .fill(session.as_bytes_mut())
+        .unwrap_lite();
+
+    let mut server = Server {
+        // We use the image id to make sure that we're compatible, since we're
+        // sending raw bytes using `sys_send`.
+        auth: Authenticator::new(
+            &generated::KEYS,
+            kipc::read_image_id(),
+            session,
+        ),
+    };
 
     // The output format is dependent on status code.  The first byte is always
     // a member of `RpcReply` as a `u8`.
-    // - `NBytesMismatch`, `NReplyOverflow` return nothing else (so the reply is
-    //   1 byte)
+    // - `BadVersion` is followed by our version, as a `u8`
     // - `BadImageId` is followed by the *actual* 64-bit image id as a
     //   little-endian value
+    // - `BadSession` is followed by our session ID, as a 64-bit little-endian
+    //   value
     // - `Ok` is followed by the return code as a 32-bit, little-endian value,
-    //   then by `nreply` bytes of reply.
+    //   then by `nreply` bytes of reply, then by the reply's HMAC.
+    // - Everything else returns nothing else (so the reply is 1 byte)
     loop {
         let mut rx_data_buf = [0u8; 1024];
         let mut tx_data_buf = [0u8; 1024];
@@ -68,77 +229,31 @@ fn main() -> ! {
             &mut rx_data_buf,
         ) {
             Ok(mut meta) => {
-                const HEADER_SIZE: usize = core::mem::size_of::<RpcHeader>();
-                const REPLY_PREFIX_SIZE: usize = 5;
-
                 // We deliberately assign to `r` here then manipulate it;
                 // otherwise, the compiler won't include `RpcReply` in DWARF
                 // data.
-                let (r, nreply) = if (meta.size as usize) < HEADER_SIZE {
-                    (RpcReply::TooShort, 0)
-                } else {
-                    // We can always read the header, since it's raw data
-                    let header =
-                        RpcHeader::read_from(&rx_data_buf[..HEADER_SIZE])
-                            .unwrap_lite();
-
-                    let nbytes = header.nbytes.get() as usize;
-                    let mut nreply = header.nreply.get() as usize;
-
-                    let r = if image_id != header.image_id.get() {
-                        tx_data_buf[1..9].copy_from_slice(image_id.as_bytes());
-                        RpcReply::BadImageId
-                    } else if meta.size as usize != HEADER_SIZE + nbytes {
-                        RpcReply::NBytesMismatch
-                    } else if nreply + REPLY_PREFIX_SIZE > tx_data_buf.len() {
-                        RpcReply::NReplyOverflow
-                    } else {
-                        // This is the happy path: unpack the data and execute
-                        // the sys_send which actually calls the target.
-                        let rx_data = &rx_data_buf[HEADER_SIZE..][..nbytes];
-
-                        // The returned data is stored after the reply prefix,
-                        // which consists of a one-byte `RpcReply` then a
-                        // u32 return code from the `sys_send` call.
-                        let tx_data =
-                            &mut tx_data_buf[REPLY_PREFIX_SIZE..][..nreply];
-
-                        let task_id =
-                            sys_refresh_task_id(TaskId(header.task.get()));
-                        let (rc, len) = sys_send(
-                            task_id,
-                            header.op.get(),
-                            rx_data,
-                            tx_data,
-                            &[],
-                        );
-
-                        // Store the return code
-                        tx_data_buf[1..5].copy_from_slice(&rc.to_be_bytes());
-
-                        // For idol calls with ssmarshal or hubpack encoding,
-                        // the actual reply len may be less than `nreply` (the
-                        // max possible encoding length); fill in the
-                        // possibly-truncated length here. We know `len` is at
-                        // most `nreply`: if it weren't, `sys_send()` would have
-                        // faulted us for providing a too-short buffer.
-                        nreply = len;
-
-                        RpcReply::Ok
-                    };
-                    (r, nreply)
-                };
+                let (r, nreply) = server.handle(
+                    &rx_data_buf[..meta.size as usize],
+                    &mut tx_data_buf,
+                );
 
                 // Store the `RpcReply` return code and return size
                 tx_data_buf[0] = r as u8;
                 meta.size = match r {
                     RpcReply::TooShort
                     | RpcReply::NBytesMismatch
-                    | RpcReply::NReplyOverflow => 1,
-                    RpcReply::BadImageId => {
-                        (1 + core::mem::size_of_val(&image_id)) as u32
+                    | RpcReply::NReplyOverflow
+                    | RpcReply::BadKey
+                    | RpcReply::BadMac
+                    | RpcReply::Replayed
+                    | RpcReply::NotAllowed => 1,
+                    RpcReply::BadVersion => 2,
+                    RpcReply::BadImageId | RpcReply::BadSession => {
+                        (1 + core::mem::size_of::<u64>()) as u32
+                    }
+                    RpcReply::Ok => {
+                        (nreply + REPLY_PREFIX_SIZE + TAG_SIZE) as u32
                     }
-                    RpcReply::Ok => (nreply + REPLY_PREFIX_SIZE) as u32,
                 };
 
                 loop {
@@ -167,10 +282,14 @@ fn main() -> ! {
                             .unwrap_lite();
                         }
                         // These errors should be impossible if we're configured
-                        // correctly.
-                        Err(SendError::NotYours | SendError::InvalidVLan) => {
-                            unreachable!()
-                        }
+                        // correctly. (We only ever reply to the address a
+                        // request came from, which the socket must be able to
+                        // reach.)
+                        Err(
+                            SendError::NotYours
+                            | SendError::InvalidVLan
+                            | SendError::Unaddressable,
+                        ) => unreachable!(),
                         // Unclear under what conditions we could se `Other` -
                         // just panic for now? At the time of this writing
                         // `Other` should only come back if the destination
@@ -198,4 +317,8 @@ fn main() -> ! {
     }
 }
 
+mod generated {
+    include!(concat!(env!("OUT_DIR"), "/udprpc_config.rs"));
+}
+
 include!(concat!(env!("OUT_DIR"), "/notifications.rs"));
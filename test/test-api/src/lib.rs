@@ -36,6 +36,24 @@ pub enum AssistOp {
     RefreshTaskIdOffByOne = 21,
     RefreshTaskIdOffByMany = 22,
     ReadNotifications = 23,
+    /// Tries to take the `test` kernel lock, replying 1 if it did (and then
+    /// releasing it) or 0 if it's held.
+    TryLock = 24,
+    /// Replies, then takes the `test` kernel lock (blocking if need be) and
+    /// holds it until `ReleaseLock`.
+    HoldLock = 25,
+    ReleaseLock = 26,
+    /// Replies, then releases the `test` kernel lock without holding it.
+    BadUnlock = 27,
+    /// Replies, then makes a syscall with a buffer that runs off the end of
+    /// the address space.
+    BadSlice = 28,
+    /// Replies, then asks the kernel for a task's status with the response
+    /// buffer at the given address, which the assistant can't access.
+    BadKipcBuffer = 29,
+    /// Like `SendBack`, but the message carries a lease of four bytes at the
+    /// given address, which the assistant can't access.
+    SendBackWithBadLease = 30,
 }
 
 /// Operations that are performed by the test-suite
@@ -43,6 +61,12 @@ pub enum AssistOp {
 pub enum SuiteOp {
     /// Run a case, replying before it starts (`usize -> ()`).
     RunCase = 3,
+    /// Returns the number of cases (`() -> usize`).
+    CaseCount = 4,
+    /// Writes the name of a case into the lease, truncating it to fit, and
+    /// returns the name's full length (`usize -> usize`, with one writable
+    /// lease).
+    CaseName = 5,
 }
 
 /// Operations that are performed by the test-runner
@@ -4,5 +4,6 @@
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     build_util::expose_m_profile();
+    build_util::build_locks()?;
     Ok(())
 }
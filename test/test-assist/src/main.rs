@@ -7,6 +7,7 @@
 #![no_std]
 #![no_main]
 
+#[cfg(not(target_arch = "x86"))]
 use core::arch::asm;
 use hubris_num_tasks::NUM_TASKS;
 use test_api::*;
@@ -14,6 +15,7 @@ use userlib::*;
 use zerocopy::AsBytes;
 
 #[inline(never)]
+#[cfg(not(target_arch = "x86"))]
 fn badread(arg: u32) {
     unsafe {
         (arg as *const u8).read_volatile();
@@ -25,12 +27,14 @@ fn panic(_arg: u32) {
 }
 
 #[inline(never)]
+#[cfg(not(target_arch = "x86"))]
 fn stackblow(_arg: u32) {
     let c = [0xdeu8; 8192];
     panic!("val is {}", c[2000]);
 }
 
 #[inline(never)]
+#[cfg(not(target_arch = "x86"))]
 fn execdata(_arg: u32) {
     unsafe {
         let c = [0x4770u16]; // bx lr
@@ -45,9 +49,11 @@ fn execdata(_arg: u32) {
     }
 }
 
+#[cfg(not(target_arch = "x86"))]
 static BXLR: [u16; 1] = [0x4770u16];
 
 #[inline(never)]
+#[cfg(not(target_arch = "x86"))]
 fn illop(_arg: u32) {
     unsafe {
         // This should attempt to execute with the Thumb bit clear, so
@@ -58,6 +64,7 @@ fn illop(_arg: u32) {
 }
 
 #[inline(never)]
+#[cfg(not(target_arch = "x86"))]
 fn badexec(arg: u32) {
     unsafe {
         let val: u32 = arg | 1;
@@ -67,6 +74,7 @@ fn badexec(arg: u32) {
 }
 
 #[inline(never)]
+#[cfg(not(target_arch = "x86"))]
 fn textoob(_arg: u32) {
     unsafe {
         // fly off the end of our text -- which will either induce
@@ -82,6 +90,7 @@ fn textoob(_arg: u32) {
 }
 
 #[inline(never)]
+#[cfg(not(target_arch = "x86"))]
 fn stackoob(_arg: u32) {
     let c = [0xdeu8; 16];
 
@@ -97,6 +106,7 @@ fn stackoob(_arg: u32) {
 }
 
 #[inline(never)]
+#[cfg(not(target_arch = "x86"))]
 fn busfault(_arg: u32) {
     unsafe {
         // unprivileged software reading CSFR is a bus error
@@ -105,6 +115,7 @@ fn busfault(_arg: u32) {
 }
 
 #[inline(never)]
+#[cfg(not(target_arch = "x86"))]
 fn illinst(_arg: u32) {
     unsafe {
         // an illegal instruction
@@ -112,6 +123,29 @@ fn illinst(_arg: u32) {
     }
 }
 
+fn badunlock(_arg: u32) {
+    sys_unlock(locks::TEST);
+}
+
+fn badslice(_arg: u32) {
+    // The kernel must reject this before it even looks up the lender.
+    let buf =
+        unsafe { core::slice::from_raw_parts_mut(usize::MAX as *mut u8, 2) };
+    sys_borrow_read(TaskId::KERNEL, 0, 0, buf);
+}
+
+fn badkipcbuffer(arg: u32) {
+    let response =
+        unsafe { core::slice::from_raw_parts_mut(arg as *mut u8, 16) };
+    sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadTaskStatus as u16,
+        0u32.as_bytes(),
+        response,
+        &[],
+    );
+}
+
 #[inline(never)]
 #[cfg(any(armv7m, armv8m))]
 fn divzero(_arg: u32) {
@@ -149,19 +183,34 @@ fn main() -> ! {
     let mut stored_value = 0;
     let mut borrow_buffer = [0u8; 16];
     let mut posted_bits = 0;
+    let mut held_lock = None;
 
+    // The simulator can't turn bad accesses or instructions into task faults,
+    // so it only gets the faults that go through the kernel.
     let fatalops = [
-        (AssistOp::BadMemory, badread as fn(u32)),
-        (AssistOp::Panic, panic),
+        (AssistOp::Panic, panic as fn(u32)),
+        (AssistOp::BadUnlock, badunlock),
+        (AssistOp::BadSlice, badslice),
+        (AssistOp::BadKipcBuffer, badkipcbuffer),
+        #[cfg(not(target_arch = "x86"))]
+        (AssistOp::BadMemory, badread),
         #[cfg(any(armv7m, armv8m))]
         (AssistOp::DivZero, divzero),
+        #[cfg(not(target_arch = "x86"))]
         (AssistOp::StackOverflow, stackblow),
+        #[cfg(not(target_arch = "x86"))]
         (AssistOp::ExecData, execdata),
+        #[cfg(not(target_arch = "x86"))]
         (AssistOp::IllegalOperation, illop),
+        #[cfg(not(target_arch = "x86"))]
         (AssistOp::BadExec, badexec),
+        #[cfg(not(target_arch = "x86"))]
         (AssistOp::TextOutOfBounds, textoob),
+        #[cfg(not(target_arch = "x86"))]
         (AssistOp::StackOutOfBounds, stackoob),
+        #[cfg(not(target_arch = "x86"))]
         (AssistOp::BusError, busfault),
+        #[cfg(not(target_arch = "x86"))]
         (AssistOp::IllegalInstruction, illinst),
     ];
 
@@ -228,6 +277,23 @@ fn main() -> ! {
                         );
                         // Ignore the result.
                     }
+                    AssistOp::SendBackWithBadLease => {
+                        let task_id = caller.task_id();
+                        caller.reply(*msg);
+                        let bad = unsafe {
+                            core::slice::from_raw_parts(*msg as *const u8, 4)
+                        };
+                        // The kernel should fault us when the caller tries to
+                        // borrow from this.
+                        sys_send(
+                            task_id,
+                            42,
+                            &msg.to_le_bytes(),
+                            last_reply.as_bytes_mut(),
+                            &[Lease::from(bad)],
+                        );
+                        panic!("unexpectedly survived {:?}", op);
+                    }
                     #[cfg(any(armv7m, armv8m))]
                     AssistOp::EatSomePi => {
                         eat_some_pi(*msg > 0);
@@ -277,6 +343,18 @@ fn main() -> ! {
                     AssistOp::ReadNotifications => {
                         caller.reply(core::mem::replace(posted_bits, 0));
                     }
+                    AssistOp::TryLock => {
+                        let guard = hl::try_lock(locks::TEST);
+                        caller.reply(guard.is_some() as u32);
+                    }
+                    AssistOp::HoldLock => {
+                        caller.reply(0);
+                        held_lock = Some(hl::lock(locks::TEST));
+                    }
+                    AssistOp::ReleaseLock => {
+                        held_lock = None;
+                        caller.reply(0);
+                    }
                     _ => {
                         // Anything else should be fatal
                         for (which, func) in &fatalops {
@@ -298,3 +376,5 @@ fn main() -> ! {
         );
     }
 }
+
+include!(concat!(env!("OUT_DIR"), "/locks.rs"));
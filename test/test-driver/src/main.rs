@@ -0,0 +1,178 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Test driver for the hosted simulator.
+//!
+//! On hardware, `humility test` runs the test suite by way of hiffy. There's
+//! no debugger attached to the simulator, so this task does that job from
+//! inside the image instead: it runs each case in the suite in turn, asks the
+//! runner for the result, and prints one line per case to the simulator's
+//! console. Once every case has run, it ends the simulation, with a nonzero
+//! exit status if any case failed.
+//!
+//! Like hiffy, this needs task slots for the `suite` and the `runner`.
+
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+
+use test_api::*;
+use userlib::*;
+use zerocopy::AsBytes;
+
+task_slot!(SUITE, suite);
+task_slot!(RUNNER, runner);
+
+/// How long a case may run before we give up on it, in ticks.
+const CASE_TIMEOUT: u64 = 10_000;
+
+enum Outcome {
+    Pass,
+    Fail,
+    Timeout,
+}
+
+#[export_name = "main"]
+fn main() -> ! {
+    let mut failed = 0;
+    let count = case_count();
+    for idx in 0..count {
+        let mut name = [0; 64];
+        let name = case_name(idx, &mut name);
+
+        let outcome = run_case(idx);
+        let mut line = Line::default();
+        let _ = write!(line, "test {name} ... ");
+        match outcome {
+            Outcome::Pass => {
+                let _ = writeln!(line, "ok");
+            }
+            Outcome::Fail | Outcome::Timeout => {
+                failed += 1;
+                let why = if let Outcome::Timeout = outcome {
+                    "timed out"
+                } else {
+                    "FAILED"
+                };
+                let _ = write!(line, "{why}");
+                let suite = SUITE.get_task_index().into();
+                if let TaskState::Faulted { fault, .. } =
+                    kipc::read_task_status(suite)
+                {
+                    let _ = write!(line, " ({fault:?})");
+                }
+                let _ = writeln!(line);
+            }
+        }
+        sim::write(line.as_bytes());
+    }
+
+    let mut line = Line::default();
+    let _ = writeln!(
+        line,
+        "\ntest result: {}. {} passed; {failed} failed",
+        if failed == 0 { "ok" } else { "FAILED" },
+        count - failed,
+    );
+    sim::write(line.as_bytes());
+    sim::exit(if failed == 0 { 0 } else { 1 })
+}
+
+/// Asks the suite how many cases it has.
+fn case_count() -> usize {
+    let mut count = 0usize;
+    let (rc, _) = sys_send(
+        SUITE.get_task_id(),
+        SuiteOp::CaseCount as u16,
+        &[],
+        count.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    count
+}
+
+/// Reads the name of case `idx` into `buf`, truncating it if need be.
+fn case_name(idx: usize, buf: &mut [u8]) -> &str {
+    let mut len = 0usize;
+    let (rc, _) = sys_send(
+        SUITE.get_task_id(),
+        SuiteOp::CaseName as u16,
+        idx.as_bytes(),
+        len.as_bytes_mut(),
+        &[Lease::from(&mut *buf)],
+    );
+    assert_eq!(rc, 0);
+    let name = &buf[..len.min(buf.len())];
+    core::str::from_utf8(name).unwrap_or("<unprintable>")
+}
+
+/// Runs case `idx` on a freshly restarted suite, and waits for the runner to
+/// report how it went.
+fn run_case(idx: usize) -> Outcome {
+    kipc::restart_task(SUITE.get_task_index().into(), true);
+
+    let (rc, _) = sys_send(
+        SUITE.get_task_id(),
+        SuiteOp::RunCase as u16,
+        idx.as_bytes(),
+        &mut [],
+        &[],
+    );
+    assert_eq!(rc, 0);
+
+    let deadline = sys_get_timer().now + CASE_TIMEOUT;
+    loop {
+        let mut result = 0u32;
+        let (rc, _) = sys_send(
+            RUNNER.get_task_id(),
+            RunnerOp::TestResult as u16,
+            &[],
+            result.as_bytes_mut(),
+            &[],
+        );
+        assert_eq!(rc, 0);
+        match TestResult::try_from(result) {
+            Ok(TestResult::Success) => return Outcome::Pass,
+            Ok(TestResult::Failure) => return Outcome::Fail,
+            Ok(TestResult::NotDone) => (),
+            Err(x) => panic!("bad test result {x}"),
+        }
+        if sys_get_timer().now >= deadline {
+            return Outcome::Timeout;
+        }
+        hl::sleep_for(1);
+    }
+}
+
+/// A line of console output. Anything that doesn't fit is dropped.
+struct Line {
+    buf: [u8; 160],
+    len: usize,
+}
+
+impl Default for Line {
+    fn default() -> Self {
+        Self {
+            buf: [0; 160],
+            len: 0,
+        }
+    }
+}
+
+impl Line {
+    fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl Write for Line {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let n = s.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
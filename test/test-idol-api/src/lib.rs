@@ -18,6 +18,11 @@ pub enum IdolTestError {
     RipServer = 3,
 }
 
+/// Notification that makes the server take the `test-witness` kernel lock.
+pub const WITNESS_TAKE: u32 = 1 << 0;
+/// Notification that makes the server release the `test-witness` kernel lock.
+pub const WITNESS_RELEASE: u32 = 1 << 1;
+
 #[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct FancyTestType {
     pub u: u32,
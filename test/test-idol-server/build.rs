@@ -4,6 +4,7 @@
 
 fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     build_util::expose_target_board();
+    build_util::build_locks()?;
 
     idol::server::build_server_support(
         "../test-idol-api/api.idol",
@@ -6,10 +6,17 @@
 #![no_main]
 
 use idol_runtime::RequestError;
-use test_idol_api::{FancyTestType, IdolTestError, SocketName, UdpMetadata};
+use test_idol_api::{
+    FancyTestType, IdolTestError, SocketName, UdpMetadata, WITNESS_RELEASE,
+    WITNESS_TAKE,
+};
 use userlib::*;
 
-struct ServerImpl;
+struct ServerImpl {
+    /// The `test-witness` lock, which the test suite has us take when poked,
+    /// so it can tell whether we ran
+    witness: Option<hl::LockGuard>,
+}
 
 impl idl::InOrderIdolTestImpl for ServerImpl {
     fn increment(
@@ -81,13 +88,28 @@ impl idl::InOrderIdolTestImpl for ServerImpl {
     }
 }
 
+impl idol_runtime::NotificationHandler for ServerImpl {
+    fn current_notification_mask(&self) -> u32 {
+        WITNESS_TAKE | WITNESS_RELEASE
+    }
+
+    fn handle_notification(&mut self, bits: u32) {
+        if bits & WITNESS_RELEASE != 0 {
+            self.witness = None;
+        }
+        if bits & WITNESS_TAKE != 0 && self.witness.is_none() {
+            self.witness = Some(hl::lock(locks::TEST_WITNESS));
+        }
+    }
+}
+
 #[export_name = "main"]
 fn main() -> ! {
     // Handle messages.
     let mut incoming = [0u8; idl::INCOMING_SIZE];
-    let mut serverimpl = ServerImpl;
+    let mut serverimpl = ServerImpl { witness: None };
     loop {
-        idol_runtime::dispatch(&mut incoming, &mut serverimpl);
+        idol_runtime::dispatch_n(&mut incoming, &mut serverimpl);
     }
 }
 
@@ -96,3 +118,5 @@ mod idl {
 
     include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
 }
+
+include!(concat!(env!("OUT_DIR"), "/locks.rs"));
@@ -4,6 +4,7 @@
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     build_util::expose_m_profile();
+    build_util::build_locks()?;
 
     #[cfg(feature = "i2c-devices")]
     build_i2c::codegen(build_i2c::Disposition::Devices)?;
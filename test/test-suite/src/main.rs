@@ -4,7 +4,8 @@
 
 //! Test suite.
 //!
-//! This task is driven by another entity (currently hiffy)
+//! This task is driven by another entity (currently hiffy, or `test-driver`
+//! in the simulator)
 //!
 //! Any test case that fails should indicate this by `panic!` (or equivalent,
 //! like failing an `assert!`).
@@ -40,8 +41,14 @@ ringbuf!(Trace, 64, Trace::None);
 /// A constant that is known to be in a region we can't access to induce a
 /// memory fault. (Note that if TZ is enabled, this will in fact induce a
 /// secure fault, and a different constant will be required.)
+#[cfg(not(target_arch = "x86"))]
 const BAD_ADDRESS: u32 = 0x0;
 
+/// An address in the kernel's null region, which no task may access. Unlike
+/// `BAD_ADDRESS`, this isn't null, since the assistant makes slices of it;
+/// and since only the kernel ever tries to access it, it works without an MPU.
+const NO_ACCESS_ADDRESS: u32 = 0x10;
+
 /// Helper macro for building a list of functions with their names.
 /// We use the humility debug processing to get the name of each
 /// test case and the total number of tests. The #[used(linker)]
@@ -79,14 +86,25 @@ test_cases! {
     test_floating_point_highregs,
     #[cfg(any(armv7m, armv8m))]
     test_floating_point_fault,
+    // These rely on the MPU and M-profile fault reporting, which the
+    // simulator doesn't model.
+    #[cfg(not(target_arch = "x86"))]
     test_fault_badmem,
+    #[cfg(not(target_arch = "x86"))]
     test_fault_stackoverflow,
+    #[cfg(not(target_arch = "x86"))]
     test_fault_execdata,
+    #[cfg(not(target_arch = "x86"))]
     test_fault_illop,
+    #[cfg(not(target_arch = "x86"))]
     test_fault_nullexec,
+    #[cfg(not(target_arch = "x86"))]
     test_fault_textoob,
+    #[cfg(not(target_arch = "x86"))]
     test_fault_stackoob,
+    #[cfg(not(target_arch = "x86"))]
     test_fault_buserror,
+    #[cfg(not(target_arch = "x86"))]
     test_fault_illinst,
     #[cfg(any(armv7m, armv8m))]
     test_fault_divzero,
@@ -98,6 +116,8 @@ test_cases! {
     test_fault_badinjection,
     test_fault_superinjection,
     test_fault_selfinjection,
+    test_fault_badslice,
+    test_fault_badkipcbuffer,
     test_panic,
     test_restart,
     test_restart_taskgen,
@@ -105,6 +125,7 @@ test_cases! {
     test_borrow_read,
     test_borrow_write,
     test_borrow_without_peer_waiting,
+    test_borrow_bad_lease,
     test_supervisor_fault_notification,
     test_timer_advance,
     test_timer_notify,
@@ -116,6 +137,11 @@ test_cases! {
     test_refresh_task_id_off_by_one,
     test_refresh_task_id_off_by_many,
     test_post,
+    test_lock_acquire,
+    test_lock_try_busy,
+    test_lock_release_unheld,
+    test_lock_priority_inheritance,
+    test_lock_released_on_fault,
     test_idol_basic,
     test_idol_bool_arg,
     test_idol_bool_ret,
@@ -282,7 +308,7 @@ cfg_if::cfg_if! {
                 assert_eq!($name, FaultInfo::InvalidOperation(0));
             };
         }
-    } else {
+    } else if #[cfg(not(target_arch = "x86"))] {
         macro_rules! assert_fault_eq {
             ($name:expr, $expected:expr) => {
                 assert_eq!($name, $expected);
@@ -293,6 +319,7 @@ cfg_if::cfg_if! {
 
 /// Tests a memory fault, which ensures that the address reporting is correct,
 /// and that the MPU is on.
+#[cfg(not(target_arch = "x86"))]
 fn test_fault_badmem() {
     let bad_address = BAD_ADDRESS;
     let fault = test_fault(AssistOp::BadMemory, bad_address);
@@ -306,6 +333,7 @@ fn test_fault_badmem() {
     );
 }
 
+#[cfg(not(target_arch = "x86"))]
 fn test_fault_stackoverflow() {
     let fault = test_fault(AssistOp::StackOverflow, 0);
 
@@ -319,10 +347,12 @@ fn test_fault_stackoverflow() {
     }
 }
 
+#[cfg(not(target_arch = "x86"))]
 fn test_fault_execdata() {
     assert_fault_eq!(test_fault(AssistOp::ExecData, 0), FaultInfo::IllegalText);
 }
 
+#[cfg(not(target_arch = "x86"))]
 fn test_fault_illop() {
     let fault = test_fault(AssistOp::IllegalOperation, 0);
 
@@ -334,6 +364,7 @@ fn test_fault_illop() {
     }
 }
 
+#[cfg(not(target_arch = "x86"))]
 fn test_fault_nullexec() {
     assert_fault_eq!(
         test_fault(AssistOp::BadExec, BAD_ADDRESS),
@@ -341,6 +372,7 @@ fn test_fault_nullexec() {
     );
 }
 
+#[cfg(not(target_arch = "x86"))]
 fn test_fault_textoob() {
     let fault = test_fault(AssistOp::TextOutOfBounds, BAD_ADDRESS);
 
@@ -354,6 +386,7 @@ fn test_fault_textoob() {
     }
 }
 
+#[cfg(not(target_arch = "x86"))]
 fn test_fault_stackoob() {
     let fault = test_fault(AssistOp::StackOutOfBounds, 0);
     match fault {
@@ -366,6 +399,7 @@ fn test_fault_stackoob() {
     }
 }
 
+#[cfg(not(target_arch = "x86"))]
 fn test_fault_buserror() {
     let fault = test_fault(AssistOp::BusError, 0);
 
@@ -379,6 +413,7 @@ fn test_fault_buserror() {
     }
 }
 
+#[cfg(not(target_arch = "x86"))]
 fn test_fault_illinst() {
     assert_fault_eq!(
         test_fault(AssistOp::IllegalInstruction, 0),
@@ -446,6 +481,26 @@ fn test_fault_selfinjection() {
     );
 }
 
+/// Tests that the kernel faults a task that hands it a malformed slice.
+fn test_fault_badslice() {
+    assert_eq!(
+        test_fault(AssistOp::BadSlice, 0),
+        FaultInfo::SyscallUsage(UsageError::InvalidSlice)
+    );
+}
+
+/// Tests that the kernel won't write, on a task's behalf, to memory that the
+/// task can't access.
+fn test_fault_badkipcbuffer() {
+    assert_eq!(
+        test_fault(AssistOp::BadKipcBuffer, NO_ACCESS_ADDRESS),
+        FaultInfo::MemoryAccess {
+            address: Some(NO_ACCESS_ADDRESS),
+            source: FaultSource::Kernel,
+        }
+    );
+}
+
 /// Tests that a `panic!` in a task is recorded as a fault.
 fn test_panic() {
     let assist = assist_task_id();
@@ -1116,6 +1171,61 @@ fn test_borrow_without_peer_waiting() {
     assert_eq!(initial_id, new_id, "id should not change");
 }
 
+/// Tests that borrowing from a lease of memory the lender can't access fails,
+/// and faults the lender rather than the borrower.
+fn test_borrow_bad_lease() {
+    let assist = assist_task_id();
+
+    let mut response = 0_u32;
+    let (rc, len) = sys_send(
+        assist,
+        AssistOp::SendBackWithBadLease as u16,
+        &NO_ACCESS_ADDRESS.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 4);
+
+    hl::recv_without_notification(
+        response.as_bytes_mut(),
+        |_op: u32, msg| -> Result<(), u32> {
+            let (_msg, caller) = msg.fixed::<u32, u32>().unwrap();
+
+            // The lease itself is well-formed, so we can learn about it...
+            let info = caller.borrow(0).info().unwrap();
+            assert_eq!(info.attributes, LeaseAttributes::READ);
+            assert_eq!(info.len, 4);
+
+            // ...but not read from it.
+            let mut dest = [0; 4];
+            assert!(caller.borrow(0).read_fully_at(0, &mut dest).is_none());
+
+            // The assistant is dead, so there's nobody to reply to.
+            Ok(())
+        },
+    );
+
+    let status = kipc::read_task_status(ASSIST.get_task_index().into());
+    match status {
+        TaskState::Faulted {
+            fault,
+            original_state: SchedState::InReply(_),
+        } => {
+            assert_eq!(
+                fault,
+                FaultInfo::MemoryAccess {
+                    address: Some(NO_ACCESS_ADDRESS),
+                    source: FaultSource::Kernel,
+                }
+            );
+        }
+        _ => {
+            panic!("expected fault");
+        }
+    }
+}
+
 /// Tests that faults in tasks are reported to the supervisor.
 ///
 /// NOTE: this test depends on the supervisor fault mask, set in the test's
@@ -1409,6 +1519,97 @@ fn test_post() {
     assert_eq!(response, ARBITRARY_MASK);
 }
 
+/// Sends a kernel lock operation to the assistant, returning its response.
+fn assist_lock_op(op: AssistOp) -> u32 {
+    let mut response = 0_u32;
+    let (rc, len) = sys_send(
+        assist_task_id(),
+        op as u16,
+        &0_u32.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 4);
+    response
+}
+
+/// Tests that a kernel lock excludes other tasks while it's held, and only
+/// then.
+fn test_lock_acquire() {
+    let guard = hl::lock(locks::TEST);
+    assert_eq!(assist_lock_op(AssistOp::TryLock), 0);
+    drop(guard);
+    assert_eq!(assist_lock_op(AssistOp::TryLock), 1);
+
+    // The assistant let go of it again.
+    let guard = hl::lock(locks::TEST);
+    drop(guard);
+}
+
+/// Tests that a non-blocking acquire of a lock held by another task returns
+/// `LOCK_BUSY` rather than blocking.
+fn test_lock_try_busy() {
+    assist_lock_op(AssistOp::HoldLock);
+    assert!(hl::try_lock(locks::TEST).is_none());
+
+    assist_lock_op(AssistOp::ReleaseLock);
+    assert!(hl::try_lock(locks::TEST).is_some());
+}
+
+/// Tests that releasing a lock held by another task is a fault.
+fn test_lock_release_unheld() {
+    let _guard = hl::lock(locks::TEST);
+    assert_eq!(
+        test_fault(AssistOp::BadUnlock, 0),
+        FaultInfo::SyscallUsage(UsageError::BadLockOperation)
+    );
+    restart_assistant();
+}
+
+/// Tests that a lock holder inherits the priority of a more important waiter.
+///
+/// The assistant is more important than we are, and the Idol server is as
+/// important as the assistant. We have the Idol server take the
+/// `test-witness` lock when it next runs, so that we can tell whether it has.
+fn test_lock_priority_inheritance() {
+    let idol = IDOL.get_task_id();
+
+    let guard = hl::lock(locks::TEST);
+    // The assistant replies, then waits for the lock, boosting us.
+    assist_lock_op(AssistOp::HoldLock);
+
+    // If we'd kept our own priority, this would switch to the Idol server,
+    // which would take the witness lock before we got to check it.
+    assert_eq!(sys_post(idol, test_idol_api::WITNESS_TAKE), 0);
+    assert!(
+        hl::try_lock(locks::TEST_WITNESS).is_some(),
+        "preempted while holding a lock that a more important task wants"
+    );
+
+    // Handing the lock to the assistant drops us back to our own priority,
+    // and the Idol server runs (and takes the witness lock) at last.
+    drop(guard);
+    assert!(hl::try_lock(locks::TEST).is_none());
+    assert!(hl::try_lock(locks::TEST_WITNESS).is_none());
+
+    // Now a notification does preempt us.
+    assert_eq!(sys_post(idol, test_idol_api::WITNESS_RELEASE), 0);
+    assert!(hl::try_lock(locks::TEST_WITNESS).is_some());
+
+    assist_lock_op(AssistOp::ReleaseLock);
+}
+
+/// Tests that the kernel releases the locks of a task that faults.
+fn test_lock_released_on_fault() {
+    assist_lock_op(AssistOp::HoldLock);
+    assert!(hl::try_lock(locks::TEST).is_none());
+
+    assert_eq!(test_fault(AssistOp::Panic, 0), FaultInfo::Panic);
+    assert!(hl::try_lock(locks::TEST).is_some());
+    restart_assistant();
+}
+
 ///////////////////////////////////////////////////////////////////////////////
 // Frameworky bits follow
 
@@ -1498,9 +1699,28 @@ fn main() -> ! {
                         assert_eq!(rc, 0);
                         assert_eq!(len, 0);
                     }
+                    SuiteOp::CaseCount => {
+                        let (_, caller) =
+                            msg.fixed::<(), usize>().ok_or(2u32)?;
+                        caller.reply(TESTS.len());
+                    }
+                    SuiteOp::CaseName => {
+                        let (&idx, caller) = msg
+                            .fixed_with_leases::<usize, usize>(1)
+                            .ok_or(2u32)?;
+                        let name = TESTS.get(idx).ok_or(3u32)?.0;
+                        let buf = caller.borrow(0);
+                        let info = buf.info().ok_or(4u32)?;
+                        let n = name.len().min(info.len);
+                        buf.write_fully_at(0, &name.as_bytes()[..n])
+                            .ok_or(4u32)?;
+                        caller.reply(name.len());
+                    }
                 }
                 Ok(())
             },
         )
     }
 }
+
+include!(concat!(env!("OUT_DIR"), "/locks.rs"));